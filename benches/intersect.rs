@@ -0,0 +1,104 @@
+// Intersection tests on their own: one sphere, one box, each accelerator over all of
+// final_scene, and building the BVH over a large sphere field with and without instancing.
+//     cargo bench --bench intersect
+// Add `--features simd` (or f32) to compare against the default build.
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use ray_tracer::aabb::AABB;
+use ray_tracer::accelerator::Accelerator;
+use ray_tracer::hittable::{HitRecord, Hittable};
+use ray_tracer::interval::Interval;
+use ray_tracer::material::Lambertian;
+use ray_tracer::ray::Ray;
+use ray_tracer::scene::{final_scene, SphereField};
+use ray_tracer::sphere::Sphere;
+use ray_tracer::util;
+use ray_tracer::vec3::{Float, Vec3};
+
+const RAYS: usize = 1024;
+
+// Rays from `origin` to random points of the box around `target`, `spread` from its center
+// in every direction; the same ones on every run.
+fn rays_towards(origin: Vec3, target: Vec3, spread: Float) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(1);
+    (0..RAYS)
+        .map(|_| {
+            let offset = Vec3::new(
+                rng.gen_range(-spread..spread),
+                rng.gen_range(-spread..spread),
+                rng.gen_range(-spread..spread),
+            );
+            Ray::new(origin, target + offset - origin, 0.0)
+        })
+        .collect()
+}
+
+fn sphere_hit(c: &mut Criterion) {
+    let sphere = Sphere::new(
+        Vec3::zero(),
+        1.0,
+        Arc::new(Lambertian::from_color(Vec3::ones())),
+    );
+    // about half of them hit
+    let rays = rays_towards(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), 1.5);
+    let mut rec = HitRecord::new();
+    c.bench_function("sphere hit", |b| {
+        b.iter(|| {
+            for r in &rays {
+                black_box(sphere.hit(r, Interval::with_bounds(0.001, Float::INFINITY), &mut rec));
+            }
+        })
+    });
+}
+
+fn aabb_hit(c: &mut Criterion) {
+    let bbox = AABB::new_two_points(Vec3::new(-1.0, -1.0, -1.0), Vec3::ones());
+    let rays = rays_towards(Vec3::new(2.0, 3.0, 5.0), Vec3::zero(), 1.5);
+    c.bench_function("aabb hit", |b| {
+        b.iter(|| {
+            for r in &rays {
+                black_box(bbox.hit(r, Interval::with_bounds(0.001, Float::INFINITY)));
+            }
+        })
+    });
+}
+
+fn traversal(c: &mut Criterion) {
+    for accelerator in [Accelerator::Bvh, Accelerator::KdTree, Accelerator::Grid] {
+        util::seed_rng(1);
+        let (cam, world) = final_scene(64, 1, 1, 1);
+        let tree = accelerator.build(world);
+        let rays = rays_towards(cam.lookfrom, cam.lookat, 300.0);
+        let mut rec = HitRecord::new();
+        c.bench_function(&format!("final_scene {}", accelerator.name()), |b| {
+            b.iter(|| {
+                for r in &rays {
+                    black_box(tree.hit(r, Interval::with_bounds(0.001, Float::INFINITY), &mut rec));
+                }
+            })
+        });
+    }
+}
+
+fn field_build(c: &mut Criterion) {
+    // 88 by 88 cells, about 7700 spheres
+    for instanced in [false, true] {
+        let field = SphereField {
+            extent: 22.0,
+            density: 4.0,
+            instanced,
+        };
+        let name = if instanced { "instanced" } else { "plain" };
+        c.bench_function(&format!("sphere_field {} build", name), |b| {
+            b.iter(|| black_box(field.build(1)))
+        });
+    }
+}
+
+criterion_group!(benches, sphere_hit, aabb_hit, traversal, field_build);
+criterion_main!(benches);
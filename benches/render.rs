@@ -0,0 +1,37 @@
+// A whole render of a small Cornell box, camera rays to the final pixels, with a fixed seed.
+//     cargo bench --bench render
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use ray_tracer::progress::{Progress, ProgressSink};
+use ray_tracer::scene::cornell_box;
+use ray_tracer::util;
+
+// the terminal progress bar would clear the screen after every iteration
+struct Quiet;
+
+impl ProgressSink for Quiet {
+    fn update(&self, _progress: &Progress) {}
+}
+
+fn cornell(c: &mut Criterion) {
+    util::seed_rng(1);
+    let (mut cam, world) = cornell_box();
+    cam.image_width = 100;
+    cam.sample_per_pixel = 16;
+    cam.part_num_x = 4;
+    cam.part_num_y = 4;
+    cam.seed = Some(1);
+    cam.progress = Arc::new(Quiet);
+
+    let mut group = c.benchmark_group("render");
+    // a render takes a while, criterion's default of 100 samples would take minutes
+    group.sample_size(10);
+    group.bench_function("cornell_box 100px 16spp", |b| b.iter(|| cam.render(&world)));
+    group.finish();
+}
+
+criterion_group!(benches, cornell);
+criterion_main!(benches);
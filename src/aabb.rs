@@ -1,5 +1,8 @@
+#[cfg(feature = "simd")]
+use crate::simd::{slab_hit, F4};
 use crate::{
     interval::Interval,
+    stats::{self, Counter},
     util::{Ray, Vec3},
 };
 
@@ -85,18 +88,45 @@ impl AABB {
             }
         }
     }
+    #[cfg(feature = "simd")]
+    pub fn hit(&self, r: &Ray, ray_t: Interval) -> bool {
+        stats::count(Counter::AabbTest, 1);
+        if r.has_cone() {
+            return self.cone_clip(r, ray_t).is_some();
+        }
+        let (o, inv) = (r.a_origin, r.inv_direction);
+        slab_hit(
+            F4::new([self.x.min, self.y.min, self.z.min, 0.0]),
+            F4::new([self.x.max, self.y.max, self.z.max, 0.0]),
+            F4::new([o.x, o.y, o.z, 0.0]),
+            F4::new([inv.x, inv.y, inv.z, 1.0]),
+            ray_t.min,
+            ray_t.max,
+        )
+    }
+
+    #[cfg(not(feature = "simd"))]
     pub fn hit(&self, r: &Ray, mut ray_t: Interval) -> bool {
+        stats::count(Counter::AabbTest, 1);
+        if r.has_cone() {
+            return self.cone_clip(r, ray_t).is_some();
+        }
         let ray_orig: &Vec3 = &r.a_origin;
-        let ray_dir: &Vec3 = &r.b_direction;
 
         for axis in 0..3 {
             let ax: Interval = self.axis_interval(axis);
-            let adinv = 1.0 / ray_dir.lp(axis as u8);
+            let adinv = r.inv_direction.lp(axis as u8);
+            // the side the ray enters by first
+            let (near, far) = if r.negative[axis as usize] {
+                (ax.max, ax.min)
+            } else {
+                (ax.min, ax.max)
+            };
 
-            let t0 = (ax.min - ray_orig.lp(axis as u8)) * adinv;
-            let t1 = (ax.max - ray_orig.lp(axis as u8)) * adinv;
+            let t0 = (near - ray_orig.lp(axis as u8)) * adinv;
+            let t1 = (far - ray_orig.lp(axis as u8)) * adinv;
 
-            ray_t = ray_t.intersect(Interval::with_orderless_bounds(t0, t1));
+            ray_t = ray_t.intersect(Interval::with_bounds(t0, t1));
 
             if ray_t.max <= ray_t.min {
                 if self.x.size() <= 0.0 || self.y.size() <= 0.0 || self.z.size() <= 0.0 {
@@ -109,6 +139,56 @@ impl AABB {
         true
     }
 
+    // The part of `ray_t` where `r` is inside the box, or where its cone reaches into it.
+    pub fn clip(&self, r: &Ray, ray_t: Interval) -> Option<Interval> {
+        if r.has_cone() {
+            return self.cone_clip(r, ray_t);
+        }
+        let mut t = ray_t;
+        for axis in 0..3 {
+            let interval = self.axis_interval(axis);
+            let inv = r.inv_direction.lp(axis as u8);
+            let origin = r.a_origin.lp(axis as u8);
+            let t0 = (interval.min - origin) * inv;
+            let t1 = (interval.max - origin) * inv;
+            t = t.intersect(Interval::with_orderless_bounds(t0, t1));
+            if t.max < t.min {
+                return None;
+            }
+        }
+        Some(t)
+    }
+
+    // The part of `ray_t` where the cone of `r` touches the box: the slab test with every slab
+    // widened by the ray's footprint, which grows along the ray, so that geometry widened to
+    // the footprint (see HitRecord::coverage) is found outside its own box.
+    fn cone_clip(&self, r: &Ray, mut ray_t: Interval) -> Option<Interval> {
+        let spread = r.cone_spread * r.b_direction.length();
+        for axis in 0..3 {
+            let ax: Interval = self.axis_interval(axis);
+            let o = r.a_origin.lp(axis as u8);
+            let d = r.b_direction.lp(axis as u8);
+            // min - footprint(t) <= o + t d and o + t d <= max + footprint(t), each a bound
+            // on t from one side or the other
+            for (slope, bound) in [
+                (d + spread, ax.min - r.cone_width - o),
+                (spread - d, o - ax.max - r.cone_width),
+            ] {
+                if slope > 0.0 {
+                    ray_t.min = ray_t.min.max(bound / slope);
+                } else if slope < 0.0 {
+                    ray_t.max = ray_t.max.min(bound / slope);
+                } else if bound > 0.0 {
+                    return None;
+                }
+            }
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+        }
+        Some(ray_t)
+    }
+
     pub const EMPTY: AABB = AABB {
         x: Interval::EMPTY,
         y: Interval::EMPTY,
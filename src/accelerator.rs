@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use crate::bvh::BVHNode;
+use crate::grid::Grid;
+use crate::hittable::{Hittable, HittableList};
+use crate::kdtree::KdTree;
+
+// The structure that finds what a ray hits among the objects of a scene. Which is faster
+// depends on the scene: the BVH builds quickly and copes with objects of any size, the
+// kd-tree takes longer to build and often traces faster through dense meshes, and the grid
+// suits many objects of about the same size spread evenly.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Accelerator {
+    #[default]
+    Bvh,
+    KdTree,
+    Grid,
+}
+
+impl Accelerator {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "bvh" => Ok(Accelerator::Bvh),
+            "kdtree" => Ok(Accelerator::KdTree),
+            "grid" => Ok(Accelerator::Grid),
+            _ => Err(format!(
+                "unknown accelerator \"{}\" (bvh, kdtree, grid)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Accelerator::Bvh => "bvh",
+            Accelerator::KdTree => "kdtree",
+            Accelerator::Grid => "grid",
+        }
+    }
+
+    pub fn build(&self, list: HittableList) -> Arc<dyn Hittable> {
+        match self {
+            Accelerator::Bvh => Arc::new(BVHNode::new(list)),
+            Accelerator::KdTree => Arc::new(KdTree::new(list)),
+            Accelerator::Grid => Arc::new(Grid::new(list)),
+        }
+    }
+}
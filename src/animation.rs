@@ -0,0 +1,136 @@
+use crate::vec3::consts::PI;
+
+use image::RgbImage;
+
+use crate::camera::Camera;
+use crate::camera_path::CameraKey;
+use crate::hittable::HittableList;
+use crate::vec3::{Float, Vec3};
+
+// Renders a frame sequence with a keyframed camera. The keys need not cover every frame:
+// frames between two keys get a linear blend of them, frames outside the keys hold the
+// nearest one. Without keys every frame keeps the camera of its scene.
+pub struct Animation {
+    pub first_frame: u32,
+    pub last_frame: u32,
+    keys: Vec<CameraKey>,
+}
+
+impl Animation {
+    pub fn new(first_frame: u32, last_frame: u32, mut keys: Vec<CameraKey>) -> Self {
+        keys.sort_by_key(|k| k.frame);
+        Self {
+            first_frame,
+            last_frame,
+            keys,
+        }
+    }
+
+    // The frames of the keys, first to last.
+    pub fn from_keys(keys: Vec<CameraKey>) -> Self {
+        let first = keys.iter().map(|k| k.frame).min().unwrap_or(1);
+        let last = keys.iter().map(|k| k.frame).max().unwrap_or(1);
+        Self::new(first, last, keys)
+    }
+
+    // Turntable: `lookfrom` orbits `lookat` once over frames 1 to `frames`, `radius` away and
+    // `elevation` degrees above the horizon. It starts `azimuth` degrees from the +Z side
+    // towards +X and turns that way, counterclockwise seen from above. Frame `frames` + 1
+    // would repeat frame 1, so the sequence loops without a stutter.
+    pub fn turntable(
+        lookat: Vec3,
+        radius: Float,
+        elevation: Float,
+        azimuth: Float,
+        frames: u32,
+    ) -> Self {
+        let frames = frames.max(1);
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let keys = (1..=frames)
+            .map(|frame| {
+                let angle = azimuth + 2.0 * PI * (frame - 1) as Float / frames as Float;
+                let dir = Vec3::new(
+                    angle.sin() * elevation.cos(),
+                    elevation.sin(),
+                    angle.cos() * elevation.cos(),
+                );
+                CameraKey {
+                    frame,
+                    lookfrom: lookat + dir * radius,
+                    lookat,
+                    vup: Vec3::new(0.0, 1.0, 0.0),
+                    vfov: None,
+                }
+            })
+            .collect();
+        Self::new(1, frames, keys)
+    }
+
+    // The turntable at the distance and height of the camera, starting where it stands.
+    pub fn turntable_from(cam: &Camera, frames: u32) -> Self {
+        let offset = cam.lookfrom - cam.lookat;
+        let radius = offset.length();
+        let elevation = (offset.y / radius).clamp(-1.0, 1.0).asin();
+        let azimuth = offset.x.atan2(offset.z);
+        Self::turntable(
+            cam.lookat,
+            radius,
+            elevation.to_degrees(),
+            azimuth.to_degrees(),
+            frames,
+        )
+    }
+
+    pub fn camera_at(&self, frame: u32) -> Option<CameraKey> {
+        let next = self.keys.partition_point(|k| k.frame <= frame);
+        let (a, b) = match (
+            next.checked_sub(1).map(|i| &self.keys[i]),
+            self.keys.get(next),
+        ) {
+            (Some(a), Some(b)) => (a, b),
+            (Some(k), None) | (None, Some(k)) => (k, k),
+            (None, None) => return None,
+        };
+        let t = if b.frame > a.frame {
+            (frame - a.frame) as Float / (b.frame - a.frame) as Float
+        } else {
+            0.0
+        };
+        Some(CameraKey {
+            frame,
+            lookfrom: a.lookfrom + (b.lookfrom - a.lookfrom) * t,
+            lookat: a.lookat + (b.lookat - a.lookat) * t,
+            vup: (a.vup + (b.vup - a.vup) * t).unit(),
+            vfov: match (a.vfov, b.vfov) {
+                (Some(fa), Some(fb)) => Some(fa + (fb - fa) * t),
+                (fa, fb) => fa.or(fb),
+            },
+        })
+    }
+
+    // Renders every frame in order. `scene` gives the camera and world of a frame, so objects
+    // can move from frame to frame; the keyed camera is then applied, `prepare` gets the last
+    // word on the camera (quality settings and such) and `save` receives the image, with the
+    // camera that rendered it.
+    pub fn render<S, P, F>(&self, mut scene: S, mut prepare: P, mut save: F)
+    where
+        S: FnMut(u32) -> (Camera, HittableList),
+        P: FnMut(&mut Camera, &HittableList),
+        F: FnMut(u32, &Camera, RgbImage),
+    {
+        for frame in self.first_frame..=self.last_frame {
+            let (mut cam, world) = scene(frame);
+            if let Some(key) = self.camera_at(frame) {
+                key.apply(&mut cam);
+            }
+            prepare(&mut cam, &world);
+            let img = cam.render(&world);
+            save(frame, &cam, img);
+        }
+    }
+}
+
+// "<name>_0001.png" and so on, in the order encoders expect.
+pub fn frame_file_name(name: &str, frame: u32) -> String {
+    format!("{}_{:04}.png", name, frame)
+}
@@ -0,0 +1,143 @@
+use crate::vec3::consts::PI;
+
+use rand::Rng;
+
+use crate::util::{random_in_unit_disk, rng};
+use crate::vec3::{Float, Vec3};
+
+// Shape of the lens opening. Defocused highlights (bokeh) take this shape, scaled by the
+// defocus angle. Samples are points on the lens in units of the defocus radius, x right and
+// y up.
+pub enum Aperture {
+    Circle,
+    // regular polygon of `blades` sides, the first corner `rotation` degrees from the top
+    Polygon { blades: u32, rotation: Float },
+    // grayscale image stretched over the square around the unit circle: white is open,
+    // black is closed
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    pub fn polygon(blades: u32, rotation: Float) -> Self {
+        Aperture::Polygon {
+            blades: blades.max(3),
+            rotation,
+        }
+    }
+
+    pub fn mask(filename: &str) -> Result<Self, String> {
+        Ok(Aperture::Mask(ApertureMask::load(filename)?))
+    }
+
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circle => random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                let mut rng = rng();
+                // a random wedge of the polygon, then a uniform point in its triangle
+                let wedge = rng.gen_range(0..*blades);
+                polygon_point(*blades, *rotation, wedge, rng.gen(), rng.gen())
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+
+    // The point of the lens for the pair (s, t) in [0, 1) x [0, 1): pairs spread evenly over
+    // the square, such as the Halton points, give points spread evenly over the opening.
+    // Masks are sampled at random whatever the pair.
+    pub fn sample_at(&self, (s, t): (Float, Float)) -> Vec3 {
+        match self {
+            Aperture::Circle => concentric_disk(s, t),
+            Aperture::Polygon { blades, rotation } => {
+                // the wedge from the whole part of s times the blades, the rest is the point
+                let scaled = s * *blades as Float;
+                let wedge = (scaled as u32).min(blades - 1);
+                polygon_point(*blades, *rotation, wedge, scaled - wedge as Float, t)
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+// A uniform point of the triangle from the center to side `wedge` of the polygon, for (s, t)
+// uniform in the unit square.
+fn polygon_point(blades: u32, rotation: Float, wedge: u32, mut s: Float, mut t: Float) -> Vec3 {
+    let step = 2.0 * PI / blades as Float;
+    let start = 0.5 * PI + rotation.to_radians() + step * wedge as Float;
+    let a = Vec3::new(start.cos(), start.sin(), 0.0);
+    let b = Vec3::new((start + step).cos(), (start + step).sin(), 0.0);
+    if s + t > 1.0 {
+        (s, t) = (1.0 - s, 1.0 - t);
+    }
+    a * s + b * t
+}
+
+// Shirley and Chiu's concentric map of the unit square onto the unit disk, which keeps
+// neighbouring points together and evenly spread points evenly spread.
+fn concentric_disk(s: Float, t: Float) -> Vec3 {
+    let (a, b) = (2.0 * s - 1.0, 2.0 * t - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zero();
+    }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, 0.25 * PI * (b / a))
+    } else {
+        (b, 0.5 * PI - 0.25 * PI * (a / b))
+    };
+    Vec3::new(r * phi.cos(), r * phi.sin(), 0.0)
+}
+
+// Point `index` of the van der Corput sequence in `base`, the digits of `index` mirrored
+// about the radix point. Bases 2 and 3 together make the Halton sequence, whose first n
+// points cover the unit square evenly for any n.
+pub fn halton(mut index: u32, base: u32) -> Float {
+    let mut result = 0.0;
+    let mut digit_value = 1.0;
+    while index > 0 {
+        digit_value /= base as Float;
+        result += digit_value * (index % base) as Float;
+        index /= base;
+    }
+    result
+}
+
+pub struct ApertureMask {
+    width: u32,
+    height: u32,
+    // pixel values in [0, 1], rows from the top
+    values: Vec<Float>,
+    max: Float,
+}
+
+impl ApertureMask {
+    fn load(filename: &str) -> Result<Self, String> {
+        let image = image::open(filename)
+            .map_err(|e| format!("{}: {}", filename, e))?
+            .to_luma8();
+        let values: Vec<Float> = image.pixels().map(|p| p.0[0] as Float / 255.0).collect();
+        let max = values.iter().cloned().fold(0.0, Float::max);
+        if max <= 0.0 {
+            return Err(format!("{}: aperture mask is all black", filename));
+        }
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            values,
+            max,
+        })
+    }
+
+    // rejection sampling, so gray pixels let through a share of the light
+    fn sample(&self) -> Vec3 {
+        let mut rng = rng();
+        loop {
+            let (x, y) = (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            let i = (((x + 1.0) * 0.5 * self.width as Float) as u32).min(self.width - 1);
+            let j = (((1.0 - y) * 0.5 * self.height as Float) as u32).min(self.height - 1);
+            let value = self.values[(j * self.width + i) as usize];
+            if rng.gen::<Float>() * self.max < value {
+                return Vec3::new(x, y, 0.0);
+            }
+        }
+    }
+}
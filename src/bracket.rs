@@ -0,0 +1,72 @@
+use image::RgbImage;
+
+use crate::camera::Camera;
+use crate::contact_sheet::contact_sheet;
+use crate::hittable::HittableList;
+use crate::vec3::Float;
+use crate::wedge::{trim_number, WedgeAxis};
+
+// The camera settings a bracket can sweep.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Bracketed {
+    // focus_dist, in scene units
+    Focus,
+    // defocus_angle, in degrees
+    Defocus,
+    // exposure_compensation, in stops
+    Exposure,
+}
+
+impl Bracketed {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "focus" => Ok(Bracketed::Focus),
+            "defocus" => Ok(Bracketed::Defocus),
+            "exposure" => Ok(Bracketed::Exposure),
+            _ => Err(format!(
+                "can't bracket \"{}\" (focus, defocus, exposure)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bracketed::Focus => "focus",
+            Bracketed::Defocus => "defocus",
+            Bracketed::Exposure => "exposure",
+        }
+    }
+
+    fn set(&self, cam: &mut Camera, value: Float) {
+        match self {
+            Bracketed::Focus => cam.focus_dist = value,
+            Bracketed::Defocus => cam.defocus_angle = value,
+            Bracketed::Exposure => cam.exposure_compensation = value,
+        }
+    }
+}
+
+// Renders `world` through `cam` once per value of `axis`, a camera setting such as
+// "focus=8:12:5", and lays the labelled renders out in a contact sheet, so focus, blur and
+// exposure can be picked side by side instead of by editing and rendering again. The scene
+// is built once; only the camera changes between the renders.
+pub fn render_bracket(
+    cam: &mut Camera,
+    world: &HittableList,
+    axis: &WedgeAxis,
+) -> Result<RgbImage, String> {
+    let setting = Bracketed::named(&axis.name)?;
+    if setting == Bracketed::Defocus && cam.physical.is_some() {
+        return Err("the f-number of a physical camera sets its defocus".to_owned());
+    }
+    let mut cells = vec![];
+    for value in &axis.values {
+        let label = format!("{}={}", setting.name(), trim_number(*value));
+        println!("bracket: {}", label);
+        setting.set(cam, *value);
+        cells.push((cam.render(world), label));
+    }
+    let columns = (cells.len() as f64).sqrt().ceil() as usize;
+    Ok(contact_sheet(&cells, columns))
+}
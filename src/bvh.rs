@@ -1,16 +1,68 @@
-use std::{cmp::Ordering, sync::Arc};
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
 
 use crate::{
     aabb::AABB,
     hittable::{HitRecord, Hittable, HittableList},
     interval::Interval,
+    normal_cone::NormalCone,
     ray::Ray,
+    scene_export::SceneWriter,
+    stats,
+    util::random_f64_0_1,
+    vec3::{Float, Vec3},
 };
 
+// Bounding volume hierarchy, flattened into one array of nodes so that meshes of millions of
+// triangles stay small and close together in memory: a node is 20 bytes, its children are
+// 32-bit indices of nodes or objects, and their boxes are kept in 8 bits per side relative
+// to the node's own box (rounded outwards, so a little larger than what is in them). Only
+// the box around everything is stored in full.
+//
+// Each node also has the normal cone of everything under it, so that over a BVH of emitters
+// light sampling (random and pdf_value) walks down only into the groups of lights that can
+// face the point it samples them from.
+//
+// With a cache directory set (set_cache_dir) BVHs over many objects are kept there once
+// built, and a later BVH over objects with the same boxes, in the same order, is read back
+// instead of built again: what the build does depends on nothing else.
 pub struct BVHNode {
+    // depth first, the root first and the left child of a node right after it
+    nodes: Vec<Node>,
+    // in the order of the leaves, left to right
+    objects: Vec<Arc<dyn Hittable>>,
     bounding_box: AABB,
-    left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
+    // normals of everything under each node, in the order of nodes
+    cones: Vec<NormalCone>,
+}
+
+struct Node {
+    // boxes of the children: min x, y, z, then max x, y, z, in 255ths of this node's box
+    bounds: [[u8; 6]; 2],
+    // index into nodes, or into objects with LEAF set; NONE for no child
+    children: [u32; 2],
+}
+
+const LEAF: u32 = 1 << 31;
+// the right child of the root of a BVH over a single object
+const NONE: u32 = u32::MAX;
+
+// Directory of cached BVHs, see set_cache_dir().
+static CACHE_DIR: RwLock<Option<String>> = RwLock::new(None);
+// fewer objects are built faster than read from a file
+const CACHE_MIN_OBJECTS: usize = 4096;
+// first bytes of a cache file; the last one is the version of the format
+const CACHE_MAGIC: &[u8; 8] = b"RTBVH\0\0\x02";
+
+// order of the objects and nodes of a BVH, see BVHNode::build_all
+type Built = (Vec<u32>, Vec<Node>);
+
+// Keeps BVHs built from now on in `dir` (created when first written to), or none with None.
+pub fn set_cache_dir(dir: Option<String>) {
+    *CACHE_DIR.write().unwrap() = dir;
 }
 
 impl BVHNode {
@@ -23,62 +75,424 @@ impl BVHNode {
             .unwrap()
     }
 
-    pub fn new(mut list: HittableList) -> Self {
-        let length = list.objects.len();
-        BVHNode::init_from_list(list.objects.as_mut(), length)
-    }
-    fn init_from_list(vec: &mut [Arc<dyn Hittable>], object_span: usize) -> Self {
+    pub fn new(list: HittableList) -> Self {
+        let start = std::time::Instant::now();
+        let objects = list.objects;
         let mut bounding_box = AABB::EMPTY;
-        for i in 0..object_span {
-            bounding_box = bounding_box.union(vec[i].bounding_box());
+        for object in &objects {
+            bounding_box = bounding_box.union(object.bounding_box());
+        }
+        let cache = cache_path(&objects);
+        let cached = cache
+            .as_deref()
+            .and_then(|path| read_cache(path, objects.len()));
+        let (order, nodes) = cached.unwrap_or_else(|| {
+            let built = BVHNode::build_all(&objects, bounding_box);
+            if let Some(path) = &cache {
+                if let Err(e) = write_cache(path, &built) {
+                    println!("Cannot write BVH cache {}: {}", path.display(), e);
+                }
+            }
+            built
+        });
+        let objects: Vec<_> = order.iter().map(|&i| objects[i as usize].clone()).collect();
+        let cones = BVHNode::node_cones(&nodes, &objects);
+        stats::add_bvh_build_time(start.elapsed());
+        Self {
+            nodes,
+            objects,
+            bounding_box,
+            cones,
         }
+    }
 
-        let axis = bounding_box.longest_axis();
+    // The nodes over `objects` and the order of the objects in the leaves (as indices into
+    // `objects`).
+    fn build_all(objects: &[Arc<dyn Hittable>], bounding_box: AABB) -> Built {
+        let mut order: Vec<u32> = (0..objects.len() as u32).collect();
+        let mut nodes = vec![];
+        match objects.len() {
+            0 => {}
+            1 => nodes.push(Node {
+                bounds: [encode(bounding_box, bounding_box), [0; 6]],
+                children: [LEAF, NONE],
+            }),
+            _ => {
+                let (bbox, quantized) = (bounding_box, bounding_box);
+                BVHNode::build(objects, &mut order, 0, bbox, quantized, &mut nodes);
+            }
+        }
+        (order, nodes)
+    }
 
-        let left: Arc<dyn Hittable>;
-        let right: Arc<dyn Hittable>;
+    // The normal cone of every node. Children come after their parents, so going backwards
+    // every child's cone is known before its parent's.
+    fn node_cones(nodes: &[Node], objects: &[Arc<dyn Hittable>]) -> Vec<NormalCone> {
+        let mut cones = vec![NormalCone::everywhere(); nodes.len()];
+        for (index, node) in nodes.iter().enumerate().rev() {
+            let cone = |child: u32| match child {
+                NONE => None,
+                _ if child & LEAF != 0 => Some(objects[(child & !LEAF) as usize].normal_bounds()),
+                _ => Some(cones[child as usize]),
+            };
+            cones[index] = match (cone(node.children[0]), cone(node.children[1])) {
+                (Some(left), Some(right)) => left.union(right),
+                (Some(cone), None) | (None, Some(cone)) => cone,
+                (None, None) => NormalCone::everywhere(),
+            };
+        }
+        cones
+    }
 
-        if object_span == 1 {
-            left = vec[0].clone();
-            right = vec[0].clone();
-        } else if object_span == 2 {
-            left = vec[0].clone();
-            right = vec[1].clone();
-        } else {
-            vec[..object_span].sort_by(|a, b| Self::box_compare(a, b, axis));
+    // Builds the subtree over the objects of `order`, which start at `first` in the whole
+    // order and lie in `bbox`, into `nodes`, and gives its child index. `quantized` is the
+    // box the parent's node gives it, around `bbox`; the boxes of the children are stored
+    // relative to it.
+    fn build(
+        objects: &[Arc<dyn Hittable>],
+        order: &mut [u32],
+        first: u32,
+        bbox: AABB,
+        quantized: AABB,
+        nodes: &mut Vec<Node>,
+    ) -> u32 {
+        if order.len() == 1 {
+            return first | LEAF;
+        }
+        if order.len() > 2 {
+            let axis = bbox.longest_axis();
+            order.sort_by(|&a, &b| {
+                Self::box_compare(&objects[a as usize], &objects[b as usize], axis)
+            });
+        }
 
-            let mid = object_span / 2;
-            left = Arc::from(BVHNode::init_from_list(vec[..mid].as_mut(), mid));
-            right = Arc::from(BVHNode::init_from_list(
-                vec[mid..object_span].as_mut(),
-                object_span - mid,
-            ));
+        let index = nodes.len();
+        nodes.push(Node {
+            bounds: [[0; 6]; 2],
+            children: [NONE; 2],
+        });
+        let mid = order.len() / 2;
+        let (left, right) = order.split_at_mut(mid);
+        BVHNode::build_child(objects, left, first, quantized, index, 0, nodes);
+        let first = first + mid as u32;
+        BVHNode::build_child(objects, right, first, quantized, index, 1, nodes);
+        index as u32
+    }
+
+    // Builds child `k` of node `index`, whose box is `quantized`, over the objects of `order`.
+    fn build_child(
+        objects: &[Arc<dyn Hittable>],
+        order: &mut [u32],
+        first: u32,
+        quantized: AABB,
+        index: usize,
+        k: usize,
+        nodes: &mut Vec<Node>,
+    ) {
+        let mut bbox = AABB::EMPTY;
+        for &o in order.iter() {
+            bbox = bbox.union(objects[o as usize].bounding_box());
         }
+        let bounds = encode(quantized, bbox);
+        let quantized = decode(quantized, &bounds);
+        let child = BVHNode::build(objects, order, first, bbox, quantized, nodes);
+        nodes[index].bounds[k] = bounds;
+        nodes[index].children[k] = child;
+    }
 
-        Self {
-            left,
-            right,
-            bounding_box,
+    // Searches node `index`, whose box is `bbox`, like BVHNode::hit.
+    fn hit_node<'a>(
+        &'a self,
+        index: u32,
+        bbox: AABB,
+        r: &Ray,
+        ray_t: Interval,
+        rec: &mut HitRecord<'a>,
+    ) -> bool {
+        if !bbox.hit(r, ray_t) {
+            return false;
         }
+        let node = &self.nodes[index as usize];
+        let hit_left = self.hit_child(node, 0, bbox, r, ray_t, rec);
+        let ray_t = Interval::with_bounds(ray_t.min, if hit_left { rec.t } else { ray_t.max });
+        let hit_right = self.hit_child(node, 1, bbox, r, ray_t, rec);
+        hit_left || hit_right
     }
-}
 
-impl Hittable for BVHNode {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        if !self.bounding_box.hit(r, ray_t) {
+    fn hit_child<'a>(
+        &'a self,
+        node: &Node,
+        k: usize,
+        bbox: AABB,
+        r: &Ray,
+        ray_t: Interval,
+        rec: &mut HitRecord<'a>,
+    ) -> bool {
+        let child = node.children[k];
+        if child == NONE {
             return false;
         }
+        let bbox = decode(bbox, &node.bounds[k]);
+        if child & LEAF == 0 {
+            return self.hit_node(child, bbox, r, ray_t, rec);
+        }
+        // not every object tests its own box first (lists don't)
+        bbox.hit(r, ray_t) && self.objects[(child & !LEAF) as usize].hit(r, ray_t, rec)
+    }
+
+    // The chances light sampling from `origin` goes down into each child of `node`, whose box
+    // is `bbox`: even between the children whose normal cones can face `origin`, or between
+    // all of them when none can, since then nothing under the node lights `origin` anyway.
+    fn child_chances(&self, node: &Node, bbox: AABB, origin: Vec3) -> [Float; 2] {
+        let faces = |k: usize| {
+            let child = node.children[k];
+            let cone = match child {
+                NONE => return false,
+                _ if child & LEAF != 0 => self.objects[(child & !LEAF) as usize].normal_bounds(),
+                _ => self.cones[child as usize],
+            };
+            cone.may_face(decode(bbox, &node.bounds[k]), origin)
+        };
+        match (faces(0), faces(1)) {
+            (true, false) => [1.0, 0.0],
+            (false, true) => [0.0, 1.0],
+            _ if node.children[1] == NONE => [1.0, 0.0],
+            _ => [0.5, 0.5],
+        }
+    }
+
+    // pdf_value below node `index`, whose box is `bbox`, which random() reaches with
+    // probability `chance`. Only children whose boxes the direction passes through can have
+    // picked it.
+    fn node_pdf(&self, index: u32, bbox: AABB, r: &Ray, chance: Float) -> Float {
+        let node = &self.nodes[index as usize];
+        let chances = self.child_chances(node, bbox, r.a_origin);
+        let mut pdf = 0.0;
+        for (k, &child_chance) in chances.iter().enumerate() {
+            let child = node.children[k];
+            if child_chance == 0.0 {
+                continue;
+            }
+            let chance = chance * child_chance;
+            if child & LEAF != 0 {
+                let object = &self.objects[(child & !LEAF) as usize];
+                pdf += chance * object.pdf_value(r.a_origin, r.b_direction);
+                continue;
+            }
+            let bbox = decode(bbox, &node.bounds[k]);
+            if bbox.hit(r, Interval::with_bounds(0.0, Float::INFINITY)) {
+                pdf += self.node_pdf(child, bbox, r, chance);
+            }
+        }
+        pdf
+    }
+}
 
-        let hit_left = self.left.hit(r, ray_t, rec);
-        let hit_right = self.right.hit(
-            r,
-            Interval::with_bounds(ray_t.min, if hit_left { rec.t } else { ray_t.max }),
-            rec,
+// The side of `axis` at `q` 255ths along it; the ends exactly.
+fn plane(axis: Interval, q: u8) -> Float {
+    match q {
+        0 => axis.min,
+        255 => axis.max,
+        _ => axis.min + axis.size() * q as Float / 255.0,
+    }
+}
+
+fn decode(parent: AABB, bounds: &[u8; 6]) -> AABB {
+    let axis = |a: usize| {
+        let interval = parent.axis_interval(a as i32);
+        Interval::with_bounds(plane(interval, bounds[a]), plane(interval, bounds[a + 3]))
+    };
+    AABB::new(axis(0), axis(1), axis(2))
+}
+
+// `child` in 255ths of `parent`, rounded outwards so that decode() gives a box around it.
+fn encode(parent: AABB, child: AABB) -> [u8; 6] {
+    let mut bounds = [0, 0, 0, 255, 255, 255];
+    for a in 0..3 {
+        let (p, c) = (
+            parent.axis_interval(a as i32),
+            child.axis_interval(a as i32),
         );
-        hit_left || hit_right
+        let step = p.size() / 255.0;
+        if !(step.is_finite() && step > 0.0) {
+            continue;
+        }
+        let mut low = ((c.min - p.min) / step).floor().clamp(0.0, 255.0) as u8;
+        while low > 0 && plane(p, low) > c.min {
+            low -= 1;
+        }
+        let mut high = ((c.max - p.min) / step).ceil().clamp(0.0, 255.0) as u8;
+        while high < 255 && plane(p, high) < c.max {
+            high += 1;
+        }
+        (bounds[a], bounds[a + 3]) = (low, high);
+    }
+    bounds
+}
+
+// The cache file of a BVH over `objects`, None if there is no cache directory or too few
+// objects. It is named by a hash of everything the BVH depends on.
+fn cache_path(objects: &[Arc<dyn Hittable>]) -> Option<PathBuf> {
+    let dir = CACHE_DIR.read().unwrap().clone()?;
+    if objects.len() < CACHE_MIN_OBJECTS {
+        return None;
+    }
+    // FNV-1a, which unlike the std hashers is the same in every build
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |value: u64| {
+        for byte in value.to_le_bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    };
+    feed(std::mem::size_of::<Float>() as u64);
+    feed(objects.len() as u64);
+    for object in objects {
+        let bbox = object.bounding_box();
+        for value in [
+            bbox.x.min,
+            bbox.x.max,
+            bbox.y.min,
+            bbox.y.max,
+            bbox.z.min,
+            bbox.z.max,
+        ] {
+            feed(f64::from(value).to_bits());
+        }
+    }
+    Some(Path::new(&dir).join(format!("bvh_{:016x}.bin", hash)))
+}
+
+// The cached BVH over `count` objects at `path`; None if there is none or it doesn't fit.
+fn read_cache(path: &Path, count: usize) -> Option<Built> {
+    let data = std::fs::read(path).ok()?;
+    let (magic, mut rest) = data.split_at_checked(CACHE_MAGIC.len())?;
+    let mut take = |n: usize| {
+        let (head, tail) = rest.split_at_checked(n)?;
+        rest = tail;
+        Some(head)
+    };
+    if magic != CACHE_MAGIC {
+        return None;
+    }
+    let u32_at = |b: &[u8]| u32::from_le_bytes(b.try_into().unwrap());
+    let objects = u32_at(take(4)?) as usize;
+    let node_count = u32_at(take(4)?) as usize;
+    if objects != count {
+        return None;
+    }
+    let order: Vec<u32> = take(4 * count)?.chunks(4).map(u32_at).collect();
+    let mut nodes = Vec::with_capacity(node_count);
+    for node in take(20 * node_count)?.chunks(20) {
+        let mut bounds = [[0; 6]; 2];
+        bounds[0].copy_from_slice(&node[0..6]);
+        bounds[1].copy_from_slice(&node[6..12]);
+        let children = [u32_at(&node[12..16]), u32_at(&node[16..20])];
+        nodes.push(Node { bounds, children });
+    }
+
+    // every object once, every child in range
+    let mut seen = vec![false; count];
+    for &o in &order {
+        if o as usize >= count || std::mem::replace(&mut seen[o as usize], true) {
+            return None;
+        }
+    }
+    let valid = |child: u32| {
+        child == NONE
+            || (child & LEAF != 0 && ((child & !LEAF) as usize) < count)
+            || (child & LEAF == 0 && (child as usize) < node_count)
+    };
+    if !rest.is_empty()
+        || !nodes
+            .iter()
+            .all(|node| node.children.iter().all(|&c| valid(c)))
+    {
+        return None;
+    }
+    Some((order, nodes))
+}
+
+// Writes `built` to `path` through a temporary file, so that a render reading the cache
+// never sees half of it.
+fn write_cache(path: &Path, built: &Built) -> std::io::Result<()> {
+    let (order, nodes) = built;
+    let mut data = Vec::with_capacity(16 + 4 * order.len() + 20 * nodes.len());
+    data.extend_from_slice(CACHE_MAGIC);
+    data.extend_from_slice(&(order.len() as u32).to_le_bytes());
+    data.extend_from_slice(&(nodes.len() as u32).to_le_bytes());
+    for o in order {
+        data.extend_from_slice(&o.to_le_bytes());
+    }
+    for node in nodes {
+        data.extend_from_slice(&node.bounds[0]);
+        data.extend_from_slice(&node.bounds[1]);
+        data.extend_from_slice(&node.children[0].to_le_bytes());
+        data.extend_from_slice(&node.children[1].to_le_bytes());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&temporary, &data)?;
+    std::fs::rename(&temporary, path)
+}
+
+impl Hittable for BVHNode {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        !self.nodes.is_empty() && self.hit_node(0, self.bounding_box, r, ray_t, rec)
     }
 
     fn bounding_box(&self) -> AABB {
         self.bounding_box
     }
+
+    fn normal_bounds(&self) -> NormalCone {
+        self.cones.first().copied().unwrap_or_else(NormalCone::everywhere)
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        for object in &self.objects {
+            object.export(out)?;
+        }
+        Ok(())
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        for object in &self.objects {
+            object.collect_lights(lights);
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        for object in &self.objects {
+            object.collect_surfaces(surfaces);
+        }
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        if self.nodes.is_empty() {
+            return 0.0;
+        }
+        let r = Ray::new(origin, direction, 0.0);
+        self.node_pdf(0, self.bounding_box, &r, 1.0)
+    }
+
+    // Walks down from the root into a child picked by child_chances until it gets to an
+    // object, which picks the direction.
+    fn random(&self, origin: Vec3) -> Vec3 {
+        if self.nodes.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let (mut index, mut bbox) = (0, self.bounding_box);
+        loop {
+            let node = &self.nodes[index as usize];
+            let chances = self.child_chances(node, bbox, origin);
+            let k = if random_f64_0_1() < chances[0] { 0 } else { 1 };
+            let child = node.children[k];
+            if child & LEAF != 0 {
+                return self.objects[(child & !LEAF) as usize].random(origin);
+            }
+            (index, bbox) = (child, decode(bbox, &node.bounds[k]));
+        }
+    }
 }
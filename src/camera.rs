@@ -1,14 +1,249 @@
+use crate::aperture::{halton, Aperture};
+use crate::bvh::BVHNode;
 use crate::color::write_color;
-use crate::hittable::{HitRecord, Hittable};
+use crate::denoise::{Denoiser, GuideBuffers};
+use crate::exposure::PhysicalCamera;
+use crate::film::{Film, Filter};
+use crate::histogram::ExposureReport;
+use crate::hittable::{HitRecord, Hittable, HittableList};
 use crate::interval::Interval;
+use crate::irradiance_cache::IrradianceCache;
+use crate::lightmap::Lightmap;
+use crate::material::{Estimator, Lambertian};
+use crate::progress::{Progress, ProgressSink, TerminalProgress};
 use crate::ray::Ray;
-use crate::util::random_in_unit_disk;
-use crate::vec3::Vec3;
+use crate::ray_paths::{PathEnd, PathVertex, TracedPath};
+use crate::stats::{self, Counter, RenderStats, TileTime};
+use crate::texture::Gradient;
+use crate::util;
+use crate::vec3::consts::PI;
+use crate::vec3::{Float, Vec3};
 use image::{ImageBuffer, RgbImage}; //接收render传回来的图片，在main中文件输出
-use indicatif::ProgressBar;
 use rand::Rng;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+// Outcome of Camera::probe.
+pub struct ProbeReport {
+    // samples per pixel needed in each tile, row by row (part_num_x per row)
+    pub tile_spp: Vec<u32>,
+    // spp for the whole image, covering 90% of the probed pixels
+    pub suggested_spp: u32,
+}
+
+// How rays leave the camera. The fisheyes fit an image circle of `vfov` degrees across into
+// the image height (180 is a full hemisphere) and render black outside it; equidistant keeps
+// angles evenly spaced, equisolid keeps areas in proportion. Equirectangular is the full
+// sphere, longitude across and latitude down, centered on `lookat`: at an aspect ratio of 2
+// it is an environment map or VR panorama. Only the perspective projection has defocus blur.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    FisheyeEquidistant,
+    FisheyeEquisolid,
+    Equirectangular,
+}
+
+impl Projection {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "perspective" => Ok(Projection::Perspective),
+            "fisheye" => Ok(Projection::FisheyeEquidistant),
+            "equisolid" => Ok(Projection::FisheyeEquisolid),
+            "equirect" => Ok(Projection::Equirectangular),
+            _ => Err(format!(
+                "unknown projection \"{}\" (perspective, fisheye, equisolid, equirect)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::FisheyeEquidistant => "fisheye",
+            Projection::FisheyeEquisolid => "equisolid",
+            Projection::Equirectangular => "equirect",
+        }
+    }
+}
+
+// Where focus_dist is taken from when the camera is about to render, instead of being set
+// by hand: the distance to `lookat`, or the depth of whatever the camera sees at the center
+// of the image or at a pixel (x, y) from the top left, along the view axis. Where the ray
+// hits nothing focus_dist stays as it is. Perspective only, like defocus blur.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Autofocus {
+    Lookat,
+    Center,
+    Pixel(Float, Float),
+}
+
+impl Autofocus {
+    // "lookat", "center" or "<x>,<y>"
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "lookat" => Ok(Autofocus::Lookat),
+            "center" => Ok(Autofocus::Center),
+            _ => match name.split_once(',').map(|(x, y)| (x.parse(), y.parse())) {
+                Some((Ok(x), Ok(y))) => Ok(Autofocus::Pixel(x, y)),
+                _ => Err(format!(
+                    "unknown autofocus \"{}\" (lookat, center or <x>,<y>)",
+                    name
+                )),
+            },
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Autofocus::Lookat => "lookat".to_owned(),
+            Autofocus::Center => "center".to_owned(),
+            Autofocus::Pixel(x, y) => format!("{},{}", x, y),
+        }
+    }
+}
+
+// What the camera renders. Path is the image; the others are quick views for checking the
+// geometry, which shade the first hit without following the path any further: the shading
+// normal (each axis from -1 to 1 as 0 to 1 in a channel), the distance (white at the camera,
+// half grey at the lookat distance), the texture coordinates (u red, v green) and the
+// unlit colour of the material. They come out the same on every render, a few samples per
+// pixel only smooth the edges.
+//
+// Direct is a fast preview of the lighting for look-dev: paths end at the first diffuse
+// surface with the light reaching it straight from the emissive shapes (one shadow ray to
+// each, see Hittable::collect_lights) and the sky, so there is no bounce light. Mirrors and
+// glass are followed as in Path.
+//
+// Preview is a quick look at the whole lighting for checking the composition: one camera ray
+// per pixel, and the light arriving at the diffuse surfaces it hits is gathered from
+// sample_per_pixel paths once per block of preview_block by preview_block pixels, for all
+// hits of the block that face the same way at about the same distance. Each pixel takes that
+// times its own albedo, so edges and textures stay sharp while the lighting is blotchy.
+// Mirrors, glass and the sky are followed per pixel as in Path, with the one sample.
+//
+// Bounces traces the paths as Path does and shows how many times they bounced, on average
+// per pixel, from black (none, the ray escaped or hit a light) through blue, green, yellow
+// and red to white at max_depth, in steps that shrink as the count grows (see
+// bounce_color): where the paths run long, and whether they are cut at max_depth.
+#[derive(Clone, Copy, PartialEq)]
+pub enum RenderMode {
+    Path,
+    Normals,
+    Depth,
+    Uv,
+    Albedo,
+    Direct,
+    Preview,
+    Bounces,
+}
+
+impl RenderMode {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "path" => Ok(RenderMode::Path),
+            "normals" => Ok(RenderMode::Normals),
+            "depth" => Ok(RenderMode::Depth),
+            "uv" => Ok(RenderMode::Uv),
+            "albedo" => Ok(RenderMode::Albedo),
+            "direct" => Ok(RenderMode::Direct),
+            "preview" => Ok(RenderMode::Preview),
+            "bounces" => Ok(RenderMode::Bounces),
+            _ => Err(format!(
+                "unknown render mode \"{}\" (path, normals, depth, uv, albedo, direct, preview, \
+                 bounces)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderMode::Path => "path",
+            RenderMode::Normals => "normals",
+            RenderMode::Depth => "depth",
+            RenderMode::Uv => "uv",
+            RenderMode::Albedo => "albedo",
+            RenderMode::Direct => "direct",
+            RenderMode::Preview => "preview",
+            RenderMode::Bounces => "bounces",
+        }
+    }
+
+    // false for the debug views, whose colours are not light
+    fn is_lit(&self) -> bool {
+        matches!(
+            self,
+            RenderMode::Path | RenderMode::Direct | RenderMode::Preview
+        )
+    }
+
+    // true where paths go on past the first diffuse hit as in Path, with light_sampling
+    fn follows_paths(&self) -> bool {
+        matches!(
+            self,
+            RenderMode::Path | RenderMode::Preview | RenderMode::Bounces
+        )
+    }
+
+    // true for the views of the first hit alone, see Camera::debug_color
+    fn shows_first_hit(&self) -> bool {
+        matches!(
+            self,
+            RenderMode::Normals | RenderMode::Depth | RenderMode::Uv | RenderMode::Albedo
+        )
+    }
+}
+
+// How paths in Path mode find the lights from diffuse surfaces (Material::is_diffuse). Bsdf
+// only bounces the way the surface scatters and hits lights by chance, which is fine under a
+// sky or large lights and noisy with small ones. Mixture aims the bounce at a light with
+// probability `weight` (0 to 1) and scatters it as the surface does otherwise, weighted by
+// the mixed density: raise the weight for diffuse scenes lit by small lights, lower it when
+// most of the light comes from glossy reflections or the sky. Mis adds a shadow ray to a
+// light at every diffuse hit and weighs it against the bounce by the power heuristic, which
+// keeps the better of both without tuning. Scenes without lights the camera can sample
+// (Hittable::collect_lights) render as with Bsdf. Materials can leave out one of the two
+// where it only costs rays (Material::estimator).
+#[derive(Clone, Copy, PartialEq)]
+pub enum LightSampling {
+    Bsdf,
+    Mixture(Float),
+    Mis,
+}
+
+impl LightSampling {
+    // "bsdf", "mis", "mixture" (half and half) or "mixture:<weight>"
+    pub fn named(name: &str) -> Result<Self, String> {
+        let (name, weight) = match name.split_once(':') {
+            Some((name, weight)) => (name, Some(weight)),
+            None => (name, None),
+        };
+        match (name, weight) {
+            ("bsdf", None) => Ok(LightSampling::Bsdf),
+            ("mis", None) => Ok(LightSampling::Mis),
+            ("mixture", None) => Ok(LightSampling::Mixture(0.5)),
+            ("mixture", Some(weight)) => match weight.parse::<Float>() {
+                Ok(weight) if (0.0..=1.0).contains(&weight) => Ok(LightSampling::Mixture(weight)),
+                _ => Err(format!("mixture weight \"{}\" is not in [0, 1]", weight)),
+            },
+            _ => Err(format!(
+                "unknown light sampling \"{}\" (bsdf, mixture[:<weight>], mis)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LightSampling::Bsdf => "bsdf",
+            LightSampling::Mixture(_) => "mixture",
+            LightSampling::Mis => "mis",
+        }
+    }
+}
 
 pub struct Camera {
     pub image_width: u32,
@@ -19,31 +254,105 @@ pub struct Camera {
     pixel00_loc: Vec3,
     pub sample_per_pixel: u32,
     pub max_depth: u32,
-    pub vfov: f64,
+    // bounces before Russian roulette starts; max_depth or more turns it off
+    pub roulette_depth: u32,
+    pub vfov: Float,
     pub lookfrom: Vec3, // Point camera is looking from
     pub lookat: Vec3,   // Point camera is looking at
     pub vup: Vec3,      // Camera-relative "up" direction
+    pub projection: Projection,
+    // lens shift: moves the film window off the lens axis, in fractions of the frame width
+    // and height (right and up). With a level camera, shifting up takes in a tall building
+    // while its verticals stay parallel. Perspective only.
+    pub shift_x: Float,
+    pub shift_y: Float,
     u: Vec3,
     v: Vec3,
     w: Vec3, // orthonormal basis
-    pub defocus_angle: f64,
-    pub focus_dist: f64,
+    pub defocus_angle: Float,
+    pub focus_dist: Float,
+    // when set, replaces focus_dist before rendering
+    pub autofocus: Option<Autofocus>,
     defocus_disk_u: Vec3, // Defocus disk horizontal radius
     defocus_disk_v: Vec3, // Defocus disk vertical radius
+    pub aperture: Aperture,
+    // samples the lens with the Halton points, offset at random per pixel, rather than at
+    // random: smoother defocus blur at few samples per pixel
+    pub halton_lens: bool,
+    // focus assist: tints what the camera sees in focus, see focus_weight, and samples the
+    // lens as with halton_lens; for setting focus_dist and defocus_angle from a quick render
+    pub focus_assist: bool,
+    // when set, scales the image by the exposure and replaces defocus_angle
+    pub physical: Option<PhysicalCamera>,
+    // stops brighter (negative: darker) than the image would be otherwise
+    pub exposure_compensation: Float,
+    exposure_scale: Float,
 
     pub part_num_y: u32,
     pub part_num_x: u32,
     part_height: u32,
     part_width: u32,
-    thread_limit: u32,
+    pub thread_limit: u32,
 
-    bar: ProgressBar,
-    pub aspect_ratio: f64,
+    // told of the render's progress from the render threads; a terminal progress bar unless
+    // replaced
+    pub progress: Arc<dyn ProgressSink>,
+    pixels_done: AtomicU64,
+    tiles_done: AtomicU32,
+    render_start: Instant,
+    pub aspect_ratio: Float,
 
     pub background: Vec3,
+    // rays sample times in [shutter_open, shutter_close]; motion is defined over [0, 1]
+    pub shutter_open: Float,
+    pub shutter_close: Float,
+    // replaces the background colour for rays that escape, when set
+    pub sky: Option<Gradient>,
 
     sub_pixel_cnt: u32,
     pub enable_ssaa: bool,
+
+    // matte pass: only the object with this id (see hittable::Named) is seen by the camera,
+    // fully shaded, over `matte_key`; everything else is held out but still casts shadows
+    // and shows in reflections
+    pub matte: Option<u32>,
+    pub matte_key: Vec3,
+
+    pub render_mode: RenderMode,
+    // side of the blocks of pixels that share the light gathered at their diffuse hits in
+    // RenderMode::Preview
+    pub preview_block: u32,
+    // when set, camera rays hitting diffuse surfaces take the light there from the cache
+    // instead of following the path further
+    pub irradiance_cache: Option<IrradianceCache>,
+    pub light_sampling: LightSampling,
+    // camera rays carry a cone as wide as their pixel, kept through mirrors and glass, and
+    // geometry thinner than the cone where it is hit is widened to it and partly covers it
+    // (see HitRecord::coverage): thin and far away things no longer flicker between samples
+    pub ray_cones: bool,
+    // how samples are shared among the pixels around them, see film::Filter
+    pub filter: Filter,
+    // when set, the finished image is filtered with the denoiser, guided by the normals,
+    // albedos and depths of a quick pass of its own (see guide_buffers); not in the debug
+    // views
+    pub denoise: Option<Denoiser>,
+    cone_spread: Float,
+    // the emissive surfaces of the world: one by one for RenderMode::Direct, which lights
+    // with each of them, and in a BVH for light_sampling, which picks one of those that can
+    // face the point it lights (BVHNode::random)
+    lights: HittableList,
+
+    // makes the render repeatable: every tile starts its random numbers from this and its
+    // position, whichever thread renders it
+    pub seed: Option<u64>,
+
+    // filled in by render()
+    pub stats: RenderStats,
+    pub exposure: Option<ExposureReport>,
+    ray_count: AtomicU64,
+    tile_times: Mutex<Vec<TileTime>>,
+    // paths by the bounces they made, see RenderStats::path_depths
+    path_depths: Mutex<Vec<u64>>,
 }
 
 impl Camera {
@@ -57,38 +366,72 @@ impl Camera {
             pixel00_loc: Vec3::zero(),
             sample_per_pixel: 100,
             max_depth: 50,
+            roulette_depth: 5,
             vfov: 90.0,
             lookfrom: Vec3::zero(),
             lookat: Vec3::new(0.0, 0.0, -1.0),
             vup: Vec3::new(0.0, 1.0, 0.0),
+            projection: Projection::Perspective,
+            shift_x: 0.0,
+            shift_y: 0.0,
             u: Vec3::zero(),
             v: Vec3::zero(),
             w: Vec3::zero(),
             defocus_angle: 0.0, // Variation angle of rays through each pixel
             focus_dist: 10.0,   // Distance from camera lookfrom point to plane of perfect focus
+            autofocus: None,
             defocus_disk_u: Vec3::zero(),
             defocus_disk_v: Vec3::zero(),
+            aperture: Aperture::Circle,
+            halton_lens: false,
+            focus_assist: false,
+            physical: None,
+            exposure_compensation: 0.0,
+            exposure_scale: 1.0,
             part_num_y: 20,
             part_num_x: 20,
             part_height: 0,
             part_width: 0,
             thread_limit: 16,
-            bar: ProgressBar::new(1),
+            progress: Arc::new(TerminalProgress::new()),
+            pixels_done: AtomicU64::new(0),
+            tiles_done: AtomicU32::new(0),
+            render_start: Instant::now(),
             aspect_ratio: 16.0 / 9.0,
             background: Vec3::zero(),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            sky: None,
             sub_pixel_cnt: 1,
             enable_ssaa: true,
+            matte: None,
+            matte_key: Vec3::new(0.0, 1.0, 0.0),
+            render_mode: RenderMode::Path,
+            preview_block: 4,
+            irradiance_cache: None,
+            light_sampling: LightSampling::Bsdf,
+            ray_cones: false,
+            filter: Filter::default(),
+            denoise: None,
+            cone_spread: 0.0,
+            lights: HittableList::new(),
+            seed: None,
+            stats: RenderStats::default(),
+            exposure: None,
+            ray_count: AtomicU64::new(0),
+            tile_times: Mutex::new(vec![]),
+            path_depths: Mutex::new(vec![]),
         }
     }
 
     fn initialize(&mut self) {
-        self.image_height = (self.image_width as f64 / self.aspect_ratio) as u32;
+        self.image_height = (self.image_width as Float / self.aspect_ratio) as u32;
         if self.image_height < 1 {
             self.image_height = 1;
         }
 
         // sub pixel (SSAA)
-        self.sub_pixel_cnt = ((self.sample_per_pixel as f64).sqrt() + 0.999).floor() as u32;
+        self.sub_pixel_cnt = ((self.sample_per_pixel as Float).sqrt() + 0.999).floor() as u32;
         assert!(self.sub_pixel_cnt >= 1);
         println!("sample_per_pixel: {}", self.sample_per_pixel);
         println!("sub_pixel_cnt: {}", self.sub_pixel_cnt);
@@ -99,20 +442,17 @@ impl Camera {
         self.part_height = self.image_height / self.part_num_y;
         self.part_width = self.image_width / self.part_num_x;
 
-        // ProgressBar
-        self.bar = ProgressBar::new(self.image_height as u64 * self.image_width as u64);
-        self.bar.set_style(
-            indicatif::ProgressStyle::default_bar()
-                .template("{msg}  {bar:40.cyan/blue} {pos:>7}/{len:7}  {per_sec}   {eta_precise}"),
-        );
-        self.bar.set_message("|0 threads outstanding|");
+        self.place_viewport();
+    }
 
+    // The camera's frame and the viewport at focus_dist, with what depends on them.
+    fn place_viewport(&mut self) {
         let theta = self.vfov.to_radians();
         let h = (theta / 2.0).tan();
 
         let viewport_height = 2.0 * h * self.focus_dist;
         let viewport_width =
-            viewport_height * (self.image_width as f64) / (self.image_height as f64);
+            viewport_height * (self.image_width as Float) / (self.image_height as Float);
         self.camera_center = self.lookfrom;
 
         self.w = (self.lookfrom - self.lookat).unit();
@@ -124,14 +464,30 @@ impl Camera {
         let viewport_v = -viewport_height * self.v;
 
         // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        self.pixel_delta_u = viewport_u / (self.image_width as f64);
-        self.pixel_delta_v = viewport_v / (self.image_height as f64);
+        self.pixel_delta_u = viewport_u / (self.image_width as Float);
+        self.pixel_delta_v = viewport_v / (self.image_height as Float);
 
-        // Calculate the location of the upper left pixel.
-        let viewport_upper_left =
-            self.camera_center - self.focus_dist * self.w - viewport_u / 2.0 - viewport_v / 2.0;
+        // Calculate the location of the upper left pixel, off center by the lens shift.
+        let viewport_upper_left = self.camera_center - self.focus_dist * self.w
+            + (self.shift_x - 0.5) * viewport_u
+            - (self.shift_y + 0.5) * viewport_v;
         self.pixel00_loc = viewport_upper_left + (self.pixel_delta_u + self.pixel_delta_v) * 0.5;
 
+        // the angle a pixel spans, at the center of the image
+        self.cone_spread = match self.projection {
+            _ if !self.ray_cones => 0.0,
+            Projection::Perspective => self.pixel_delta_v.length() / self.focus_dist,
+            Projection::Equirectangular => PI / self.image_height as Float,
+            _ => self.vfov.to_radians() / self.image_height as Float,
+        };
+
+        self.exposure_scale = 1.0;
+        if let Some(physical) = self.physical {
+            self.exposure_scale = physical.exposure_scale();
+            self.defocus_angle = physical.defocus_angle(self.vfov, self.focus_dist);
+        }
+        self.exposure_scale *= Float::powf(2.0, self.exposure_compensation);
+
         // Calculate the camera defocus disk basis vectors.
         let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
         self.defocus_disk_u = self.u * defocus_radius;
@@ -140,13 +496,36 @@ impl Camera {
 
     pub fn render(&mut self, world: &(impl Hittable + Send + Sync)) -> RgbImage {
         self.initialize();
+        self.prepare_world(world);
+        if self.focus_assist {
+            println!(
+                "focus assist: focus_dist {}, defocus_angle {}",
+                self.focus_dist, self.defocus_angle
+            );
+        }
+        let start = Instant::now();
+        self.render_start = start;
+        self.ray_count.store(0, Ordering::Relaxed);
+        stats::reset_counters();
+        self.tile_times.lock().unwrap().clear();
+        *self.path_depths.lock().unwrap() = vec![0; self.max_depth as usize + 1];
+        self.pixels_done.store(0, Ordering::Relaxed);
+        self.tiles_done.store(0, Ordering::Relaxed);
+        self.progress.start(
+            self.image_height as u64 * self.image_width as u64,
+            self.part_num_x * self.part_num_y,
+        );
 
         // println!("started rendering");
 
         let mut img: RgbImage = ImageBuffer::new(self.image_width, self.image_height);
-        let img_mtx = Arc::new(Mutex::new(&mut img)); // wrap with &mut
+        let image_film = Arc::new(Mutex::new(Film::new(
+            (0, self.image_width),
+            (0, self.image_height),
+        )));
+        let film_mtx = image_film.clone(); // will be moved
 
-        let camera_wrapper1 = Arc::new(self); // Arc<&Camera>，注意内部包装的是 ref
+        let camera_wrapper1 = Arc::new(&*self); // Arc<&Camera>，注意内部包装的是 ref
         let camera_wrapper = camera_wrapper1.clone(); // will be moved
 
         // spawn threads
@@ -174,37 +553,96 @@ impl Camera {
 
                     // move "thread_count++" out of child thread, so that it's sequential with thread number control code
                     thread_count.fetch_add(1, Ordering::SeqCst);
-                    camera_wrapper.bar.set_message(format!(
-                        "|{} threads outstanding|",
-                        thread_count.load(Ordering::SeqCst)
-                    )); // set "thread_count" information to progress bar
 
                     // clone for moving
                     let camera_wrapper = camera_wrapper.clone(); // 每一个子线程需要重新 clone 一个 Arc，相当于引用计数 + 1
-                    let img_mtx = img_mtx.clone();
+                    let film_mtx = film_mtx.clone();
                     let thread_count = thread_count.clone();
                     let thread_number_controller = thread_number_controller.clone();
 
                     let _ = thread_spawner.spawn(move |_| {
-                        camera_wrapper.render_sub(world, ymin, ymax, xmin, xmax, img_mtx);
+                        camera_wrapper.render_sub(world, ymin, ymax, xmin, xmax, film_mtx);
 
                         thread_count.fetch_sub(1, Ordering::SeqCst); // subtract first, then notify.
-                        camera_wrapper.bar.set_message(format!(
-                            "|{} threads outstanding|",
-                            thread_count.load(Ordering::SeqCst)
-                        ));
-                        // NOTIFY
+                                                                     // NOTIFY
                         thread_number_controller.notify_one();
                     });
                 }
             }
         })
         .unwrap();
-        std::process::Command::new("clear").status().unwrap();
-        camera_wrapper1.bar.finish();
+        camera_wrapper1.progress.finish();
+        drop(camera_wrapper1);
+        let film = image_film.lock().unwrap();
+        let mut colors = film.colors();
+        if let Some(denoiser) = self.denoise.filter(|_| self.render_mode.is_lit()) {
+            let denoise_start = Instant::now();
+            colors = denoiser.denoise(&colors, &self.guide_buffers(world));
+            println!("denoised in {:.2}s", denoise_start.elapsed().as_secs_f64());
+        }
+        if self.render_mode == RenderMode::Bounces {
+            colors = colors.iter().map(|c| self.bounce_color(c.x)).collect();
+            println!(
+                "bounces: black 0, blue {}, green {}, yellow {}, red {}, white {} (max_depth)",
+                self.bounces_at(0.2),
+                self.bounces_at(0.4),
+                self.bounces_at(0.6),
+                self.bounces_at(0.8),
+                self.max_depth
+            );
+        }
+        for (index, &color) in colors.iter().enumerate() {
+            let (i, j) = (
+                index % self.image_width as usize,
+                index / self.image_width as usize,
+            );
+            write_color(color, &mut img, i, j);
+        }
+        self.exposure = Some(ExposureReport::new(
+            self.image_width,
+            self.image_height,
+            &colors,
+        ));
+        if let Some(cache) = &self.irradiance_cache {
+            println!("irradiance cache: {} records", cache.record_count());
+        }
+
+        self.stats = RenderStats {
+            rays: self.ray_count.load(Ordering::Relaxed),
+            render_seconds: start.elapsed().as_secs_f64(),
+            bvh_build_seconds: stats::bvh_build_time().as_secs_f64(),
+            tiles: std::mem::take(&mut *self.tile_times.lock().unwrap()),
+            peak_memory: stats::peak_memory(),
+            counters: stats::counters(),
+            path_depths: std::mem::take(&mut *self.path_depths.lock().unwrap()),
+        };
         img
     }
 
+    // Sets up what the render mode needs to know about `world` before tracing it.
+    fn prepare_world(&mut self, world: &impl Hittable) {
+        self.autofocus(world);
+        if let Some(cache) = &mut self.irradiance_cache {
+            cache.prepare(world.bounding_box());
+        }
+        self.lights = HittableList::new();
+        if self.render_mode == RenderMode::Direct {
+            world.collect_lights(&mut self.lights);
+            println!("direct lighting: {} lights", self.lights.objects.len());
+        } else if self.render_mode.follows_paths() && self.light_sampling != LightSampling::Bsdf {
+            let mut lights = HittableList::new();
+            world.collect_lights(&mut lights);
+            println!(
+                "light sampling ({}): {} lights",
+                self.light_sampling.name(),
+                lights.objects.len()
+            );
+            if !lights.objects.is_empty() {
+                self.lights.add(Arc::new(BVHNode::new(lights)));
+            }
+        }
+    }
+
     // ymin..ymax , xmin..xmax
     fn render_sub(
         &self,
@@ -213,110 +651,1357 @@ impl Camera {
         ymax: u32,
         xmin: u32,
         xmax: u32,
-        img_mtx: Arc<Mutex<&mut RgbImage>>,
+        film_mtx: Arc<Mutex<Film>>,
     ) {
         // println!("started thread");
-        // Render
-        let mut buffer =
-            vec![vec![Vec3::zero(); self.image_width as usize]; self.image_height as usize];
-        for j in ymin..ymax {
-            for i in xmin..xmax {
-                if self.enable_ssaa {
-                    for sub_y in 0..self.sub_pixel_cnt {
-                        for sub_x in 0..self.sub_pixel_cnt {
-                            let r = self.get_ray_subpixel(i, j, sub_y, sub_x);
-                            buffer[(j - ymin) as usize][(i - xmin) as usize] +=
-                                self.ray_color(&r, world, self.max_depth);
+        let start = Instant::now();
+        // Render into a film around the tile, row by row; one RNG serves all its rays. The
+        // samples go out in waves of about WAVE_SIZE paths that advance a bounce at a time:
+        // all rays of the wave are intersected, then all hits shaded.
+        let tile_width = (xmax - xmin) as usize;
+        let tile_pixels = tile_width * (ymax - ymin) as usize;
+        let mut film = Film::around(
+            (xmin, xmax),
+            (ymin, ymax),
+            self.filter,
+            self.image_width,
+            self.image_height,
+        );
+        if let Some(seed) = self.seed {
+            util::seed_rng(seed.wrapping_add((ymin as u64) << 32 | xmin as u64));
+        }
+        let mut rng = util::rng();
+        // the debug views are not light, exposure doesn't apply to them
+        let scale = if self.render_mode.is_lit() {
+            self.exposure_scale
+        } else {
+            1.0
+        };
+        let preview = self.render_mode == RenderMode::Preview;
+        let samples = if preview {
+            // all in preview_tile
+            0
+        } else if self.enable_ssaa {
+            self.sub_pixel_cnt * self.sub_pixel_cnt
+        } else {
+            self.sample_per_pixel
+        };
+        if preview {
+            let mut buffer = vec![Vec3::zero(); tile_pixels];
+            self.preview_tile(world, (xmin, xmax), (ymin, ymax), &mut buffer, &mut rng);
+            for j in ymin..ymax {
+                for i in xmin..xmax {
+                    let pixel = (j - ymin) as usize * tile_width + (i - xmin) as usize;
+                    film.add(i, j, buffer[pixel] * scale, 1.0);
+                }
+            }
+            self.report_progress(tile_pixels as u64, true);
+        }
+        // a pixel comes out as bright as the sum of its samples over sample_per_pixel, also
+        // where SSAA's square grid of sub-pixels has more
+        let sample_scale = if self.render_mode == RenderMode::Bounces {
+            // the mean of the counts, with SSAA's extra sub-pixels as well
+            1.0
+        } else {
+            scale * samples as Float / self.sample_per_pixel as Float
+        };
+        let per_wave = (WAVE_SIZE / tile_pixels).clamp(1, samples.max(1) as usize) as u32;
+        let mut pixels_done = 0;
+        for first in (0..samples).step_by(per_wave as usize) {
+            let last = (first + per_wave).min(samples);
+            let mut wave = Wave::default();
+            // the image position of each sample; a path's "pixel" is its sample
+            let mut positions = vec![];
+            for j in ymin..ymax {
+                for i in xmin..xmax {
+                    for sample in first..last {
+                        let (x, y) = if self.enable_ssaa {
+                            let (sub_y, sub_x) =
+                                (sample / self.sub_pixel_cnt, sample % self.sub_pixel_cnt);
+                            self.subpixel_position(i, j, sub_y, sub_x)
+                        } else {
+                            self.jittered_position(i, j, &mut rng)
+                        };
+                        let lens = self.lens_sample(i, j, sample);
+                        if let Some(r) = self.ray_at(x, y, lens, &mut rng) {
+                            wave.push(r, Vec3::ones(), 0.0, positions.len());
                         }
+                        positions.push((x, y));
                     }
-                } else {
-                    for _ in 0..self.sample_per_pixel {
-                        let r = self.get_ray(i, j);
-                        buffer[(j - ymin) as usize][(i - xmin) as usize] +=
-                            self.ray_color(&r, world, self.max_depth);
+                }
+            }
+            let mut radiance = vec![Vec3::zero(); positions.len()];
+            self.trace_wave(wave, 0, world, &mut radiance, &mut rng);
+            for (&(x, y), color) in positions.iter().zip(radiance) {
+                let mut color = color * sample_scale;
+                if self.focus_assist {
+                    let tint = FOCUS_TINT_OPACITY * self.focus_weight(x, y, world);
+                    let tint_scale = samples as Float / self.sample_per_pixel as Float;
+                    color = color * (1.0 - tint) + FOCUS_TINT * (tint * tint_scale);
+                }
+                film.splat(self.filter, x, y, color);
+            }
+
+            let pixels = tile_pixels as u64 * last as u64 / samples as u64;
+            self.report_progress(pixels - pixels_done, last == samples);
+            pixels_done = pixels;
+        }
+
+        self.tile_times.lock().unwrap().push(TileTime {
+            x: xmin / self.part_width,
+            y: ymin / self.part_height,
+            seconds: start.elapsed().as_secs_f64(),
+        });
+        film_mtx.lock().unwrap().merge(&film);
+    }
+
+    // RenderMode::Preview over the tile of pixels `xs.0..xs.1` by `ys.0..ys.1`, into `buffer`.
+    fn preview_tile(
+        &self,
+        world: &impl Hittable,
+        xs: (u32, u32),
+        ys: (u32, u32),
+        buffer: &mut [Vec3],
+        rng: &mut impl Rng,
+    ) {
+        let tile_width = (xs.1 - xs.0) as usize;
+        let block = self.preview_block.max(1);
+        // the camera rays that go on as paths
+        let mut wave = Wave::default();
+        for block_y in (ys.0..ys.1).step_by(block as usize) {
+            for block_x in (xs.0..xs.1).step_by(block as usize) {
+                // the light gathered in this block so far: the normal and distance of the hit
+                // it was gathered at, and the light
+                let mut gathered: Vec<(Vec3, Float, Vec3)> = vec![];
+                for j in block_y..(block_y + block).min(ys.1) {
+                    for i in block_x..(block_x + block).min(xs.1) {
+                        let pixel = (j - ys.0) as usize * tile_width + (i - xs.0) as usize;
+                        let Some(ray) = self.get_ray(i, j, rng) else {
+                            continue;
+                        };
+                        let mut rec = HitRecord::new();
+                        let t = Interval::with_bounds(0.001, Float::INFINITY);
+                        if !world.hit(&ray, t, &mut rec)
+                            || !rec.mat.is_diffuse()
+                            || rec.coverage < 1.0
+                            || self.matte.is_some_and(|id| rec.object_id != id)
+                        {
+                            wave.push(ray, Vec3::ones(), 0.0, pixel);
+                            continue;
+                        }
+                        self.ray_count.fetch_add(1, Ordering::Relaxed);
+                        let distance = rec.t * ray.b_direction.length();
+                        let shared = gathered.iter().find(|(normal, at, _)| {
+                            *normal * rec.normal >= PREVIEW_NORMAL_MATCH
+                                && (distance - at).abs() <= PREVIEW_DISTANCE_MATCH * at
+                        });
+                        let irradiance = match shared {
+                            Some(&(_, _, irradiance)) => irradiance,
+                            None => {
+                                let samples = self.sample_per_pixel.max(1);
+                                let (irradiance, _) = self.gather(&rec, samples, world, rng);
+                                gathered.push((rec.normal, distance, irradiance));
+                                irradiance
+                            }
+                        };
+                        buffer[pixel] +=
+                            rec.mat.emitted(&rec) + rec.mat.albedo(&rec).component_mul(irradiance);
                     }
                 }
-                self.bar.inc(1);
             }
         }
+        self.trace_wave(wave, 0, world, buffer, rng);
+    }
+
+    // Sets focus_dist as `autofocus` asks and moves the viewport there.
+    fn autofocus(&mut self, world: &impl Hittable) {
+        let Some(autofocus) = self.autofocus else {
+            return;
+        };
+        if self.projection != Projection::Perspective {
+            return;
+        }
+        let (x, y) = match autofocus {
+            Autofocus::Lookat => {
+                self.focus_dist = (self.lookat - self.lookfrom).length();
+                self.place_viewport();
+                return;
+            }
+            // between the middle pixels for even sizes
+            Autofocus::Center => (
+                (self.image_width as Float - 1.0) / 2.0,
+                (self.image_height as Float - 1.0) / 2.0,
+            ),
+            Autofocus::Pixel(x, y) => (x, y),
+        };
+        match self.depth_at(x, y, world) {
+            Some(depth) => {
+                self.focus_dist = depth;
+                self.place_viewport();
+                println!("autofocus: focus_dist {}", depth);
+            }
+            None => println!(
+                "autofocus: nothing at ({}, {}), focus_dist stays {}",
+                x, y, self.focus_dist
+            ),
+        }
+    }
+
+    // Adds `pixels` to the pixels done, and a tile when `tile_finished`, and tells the sink.
+    fn report_progress(&self, pixels: u64, tile_finished: bool) {
+        let pixels_done = self.pixels_done.fetch_add(pixels, Ordering::Relaxed) + pixels;
+        let tiles_done = if tile_finished {
+            self.tiles_done.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            self.tiles_done.load(Ordering::Relaxed)
+        };
+        self.progress.update(&Progress::new(
+            pixels_done,
+            self.image_height as u64 * self.image_width as u64,
+            tiles_done,
+            self.part_num_x * self.part_num_y,
+            self.render_start.elapsed(),
+        ));
+    }
+
+    // Aims the camera at the bounding box of `target` from `direction` (pointing from the
+    // target towards the camera) and backs off until the box's bounding sphere fills
+    // `fill_fraction` of the narrower side of the frame. The field of view and aspect ratio
+    // are kept; the focus distance is set to the target's center.
+    pub fn frame(&mut self, target: &dyn Hittable, direction: Vec3, fill_fraction: Float) {
+        let bbox = target.bounding_box();
+        let min = Vec3::new(bbox.x.min, bbox.y.min, bbox.z.min);
+        let max = Vec3::new(bbox.x.max, bbox.y.max, bbox.z.max);
+        let center = (min + max) * 0.5;
+        let radius = ((max - min).length() * 0.5).max(1e-3);
+
+        let tan_half_v = (self.vfov.to_radians() / 2.0).tan();
+        let tan_half = tan_half_v.min(tan_half_v * self.aspect_ratio);
+        // the sphere's silhouette spans `fill_fraction` of the image plane half-width
+        let angle = (fill_fraction.clamp(0.01, 1.0) * tan_half).atan();
+        let distance = radius / angle.sin();
+
+        let direction = if direction.near_zero() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            direction.unit()
+        };
+        self.lookat = center;
+        self.lookfrom = center + direction * distance;
+        self.focus_dist = distance;
+    }
+
+    // Quick pass over every `stride`-th pixel in both directions with `probe_spp` samples.
+    // From the variance of each probed pixel's luminance it estimates how many samples bring
+    // the standard error down to `target_error` of the pixel value (0.02 = 2% noise).
+    // Pixels above the 90th percentile are left out of the suggestion: those are fireflies
+    // that more samples barely help with.
+    pub fn probe(
+        &mut self,
+        world: &(impl Hittable + Send + Sync),
+        stride: u32,
+        probe_spp: u32,
+        target_error: Float,
+    ) -> ProbeReport {
+        self.initialize();
+        self.prepare_world(world);
+        let stride = stride.max(1);
+        let probe_spp = probe_spp.max(2);
+        let rows: Vec<u32> = (0..self.image_height).step_by(stride as usize).collect();
+        let chunk = (rows.len() / self.thread_limit.max(1) as usize).max(1);
+
+        let camera: &Camera = self;
+        let needed: Vec<(u32, u32, Float)> = crossbeam::thread::scope(|s| {
+            let handles: Vec<_> = rows
+                .chunks(chunk)
+                .map(|rows| {
+                    s.spawn(move |_| {
+                        if let Some(seed) = camera.seed {
+                            util::seed_rng(seed.wrapping_add(rows[0] as u64));
+                        }
+                        let mut rng = util::rng();
+                        let mut needed = vec![];
+                        for &j in rows {
+                            for i in (0..camera.image_width).step_by(stride as usize) {
+                                let mut sum = 0.0;
+                                let mut sum_sq = 0.0;
+                                for _ in 0..probe_spp {
+                                    let c = match camera.get_ray(i, j, &mut rng) {
+                                        Some(r) => camera.ray_color(&r, world, &mut rng),
+                                        None => Vec3::zero(),
+                                    };
+                                    let luminance = 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z;
+                                    sum += luminance;
+                                    sum_sq += luminance * luminance;
+                                }
+                                let n = probe_spp as Float;
+                                let mean = sum / n;
+                                let variance = ((sum_sq - sum * mean) / (n - 1.0)).max(0.0);
+                                // near-black pixels are judged against a small absolute floor
+                                let tolerance = target_error * mean.max(0.01);
+                                needed.push((i, j, variance / (tolerance * tolerance)));
+                            }
+                        }
+                        needed
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        })
+        .unwrap();
+
+        let percentile_90 = |mut values: Vec<Float>| -> Option<u32> {
+            if values.is_empty() {
+                return None;
+            }
+            values.sort_by(|a, b| a.total_cmp(b));
+            let spp = values[(values.len() - 1) * 9 / 10].ceil().clamp(1.0, 1e6) as u32;
+            if self.enable_ssaa {
+                // the stratified path renders sub_pixel_cnt^2 samples anyway
+                let side = ((spp as Float).sqrt()).ceil() as u32;
+                Some(side * side)
+            } else {
+                Some(spp)
+            }
+        };
+        let suggested_spp = percentile_90(needed.iter().map(|(_, _, n)| *n).collect())
+            .unwrap_or(self.sample_per_pixel);
+
+        let mut tile_spp = vec![];
+        for ty in 0..self.part_num_y {
+            for tx in 0..self.part_num_x {
+                let in_tile = needed
+                    .iter()
+                    .filter(|(i, j, _)| i / self.part_width == tx && j / self.part_height == ty)
+                    .map(|(_, _, n)| *n)
+                    .collect();
+                // tiles the stride skipped over get the overall suggestion
+                tile_spp.push(percentile_90(in_tile).unwrap_or(suggested_spp));
+            }
+        }
+        ProbeReport {
+            tile_spp,
+            suggested_spp,
+        }
+    }
+
+    // Follows the path of `r` for up to max_depth bounces; a wave of one path.
+    fn ray_color(&self, r: &Ray, world: &impl Hittable, rng: &mut impl Rng) -> Vec3 {
+        let mut color = [Vec3::zero()];
+        let mut wave = Wave::default();
+        wave.push(r.clone(), Vec3::ones(), 0.0, 0);
+        self.trace_wave(wave, 0, world, &mut color, rng);
+        color[0]
+    }
+
+    // Advances all paths of the wave a bounce at a time, adding what they gather to their
+    // pixels, until every path has ended or max_depth bounces are done. The rays of the wave
+    // are bounce `first_bounce` of their paths, 0 for camera rays.
+    fn trace_wave(
+        &self,
+        mut wave: Wave,
+        first_bounce: u32,
+        world: &impl Hittable,
+        pixels: &mut [Vec3],
+        rng: &mut impl Rng,
+    ) {
+        // paths that ended after each number of bounces, and the last for those cut at
+        // max_depth
+        let mut depths = vec![0; self.max_depth as usize + 1];
+        let count_bounces = self.render_mode == RenderMode::Bounces;
+        for bounce in first_bounce..self.max_depth {
+            if wave.rays.is_empty() {
+                break;
+            }
+            self.ray_count
+                .fetch_add(wave.rays.len() as u64, Ordering::Relaxed);
+            let hits: Vec<Option<HitRecord>> = wave
+                .rays
+                .iter()
+                .map(|ray| {
+                    let mut rec = HitRecord::new();
+                    world
+                        .hit(ray, Interval::with_bounds(0.001, Float::INFINITY), &mut rec)
+                        .then_some(rec)
+                })
+                .collect();
 
-        let mut img_guard = img_mtx.lock().unwrap(); // 相当于 lock_guard, 会自动就解锁。
-        for j in ymin..ymax {
-            for i in xmin..xmax {
-                write_color(
-                    buffer[(j - ymin) as usize][(i - xmin) as usize]
-                        / (self.sample_per_pixel as f64),
-                    *img_guard,
-                    i as usize,
-                    j as usize,
+            let mut next = Wave::default();
+            for (k, hit) in hits.iter().enumerate() {
+                let mut throughput = wave.throughput[k];
+                let (color, scattered) = self.step(
+                    &wave.rays[k],
+                    hit.as_ref(),
+                    bounce,
+                    wave.scatter_pdf[k],
+                    world,
+                    &mut throughput,
+                    rng,
                 );
+                match scattered {
+                    Some((scattered, scatter_pdf)) => {
+                        next.push(scattered, throughput, scatter_pdf, wave.pixel[k])
+                    }
+                    None => {
+                        depths[bounce as usize] += 1;
+                        if count_bounces {
+                            pixels[wave.pixel[k]] += Vec3::ones() * bounce as Float;
+                        }
+                    }
+                }
+                if !count_bounces {
+                    pixels[wave.pixel[k]] += color;
+                }
+            }
+            wave = next;
+        }
+        depths[self.max_depth as usize] += wave.rays.len() as u64;
+        if count_bounces {
+            for &pixel in &wave.pixel {
+                pixels[pixel] += Vec3::ones() * self.max_depth as Float;
             }
         }
+        let mut path_depths = self.path_depths.lock().unwrap();
+        if path_depths.len() < depths.len() {
+            path_depths.resize(depths.len(), 0);
+        }
+        for (total, count) in path_depths.iter_mut().zip(depths) {
+            *total += count;
+        }
     }
 
-    fn ray_color(&self, r: &Ray, world: &impl Hittable, depth: u32) -> Vec3 {
-        if depth == 0 {
-            return Vec3::zero();
+    // A path's ray and what it hit, where the path may end as end_at says or bounce on.
+    #[allow(clippy::too_many_arguments)]
+    fn step(
+        &self,
+        ray: &Ray,
+        hit: Option<&HitRecord>,
+        bounce: u32,
+        scatter_pdf: Float,
+        world: &impl Hittable,
+        throughput: &mut Vec3,
+        rng: &mut impl Rng,
+    ) -> (Vec3, Option<(Ray, Float)>) {
+        let ended = hit.and_then(|rec| self.end_at(ray, rec, bounce, world, rng));
+        match ended {
+            Some(color) => (throughput.component_mul(color), None),
+            None => self.bounce(ray, hit, bounce, scatter_pdf, world, throughput, rng),
         }
-        let mut rec = HitRecord::new();
+    }
 
-        // If the ray hits nothing, return the background color.
-        if !world.hit(r, Interval::with_bounds(0.001, f64::INFINITY), &mut rec) {
-            return self.background;
+    // `samples` paths through pixel (i, j), every bounce of them recorded, for finding out
+    // why a pixel is black or much too bright. Rays that escape are drawn as long as the
+    // distance to lookat.
+    pub fn trace_pixel(
+        &mut self,
+        world: &(impl Hittable + Send + Sync),
+        i: u32,
+        j: u32,
+        samples: u32,
+    ) -> Vec<TracedPath> {
+        self.initialize();
+        self.prepare_world(world);
+        if let Some(seed) = self.seed {
+            util::seed_rng(seed);
         }
+        let mut rng = util::rng();
+        let escape = (self.lookat - self.lookfrom).length();
+        (0..samples)
+            .filter_map(|sample| {
+                let (x, y) = self.jittered_position(i, j, &mut rng);
+                let lens = self.lens_sample(i, j, sample);
+                let ray = self.ray_at(x, y, lens, &mut rng)?;
+                Some(self.trace_path(ray, world, escape, &mut rng))
+            })
+            .collect()
+    }
+
+    // The path of `ray` as trace_wave follows it, bounce by bounce.
+    fn trace_path(
+        &self,
+        mut ray: Ray,
+        world: &impl Hittable,
+        escape: Float,
+        rng: &mut impl Rng,
+    ) -> TracedPath {
+        let mut path = TracedPath::new(ray.a_origin);
+        let mut throughput = Vec3::ones();
+        let mut scatter_pdf = 0.0;
+        for bounce in 0..self.max_depth {
+            let mut rec = HitRecord::new();
+            let hit = world
+                .hit(
+                    &ray,
+                    Interval::with_bounds(0.001, Float::INFINITY),
+                    &mut rec,
+                )
+                .then_some(rec);
+            let (light, scattered) = self.step(
+                &ray,
+                hit.as_ref(),
+                bounce,
+                scatter_pdf,
+                world,
+                &mut throughput,
+                rng,
+            );
+            path.vertices.push(PathVertex {
+                point: hit
+                    .as_ref()
+                    .map_or_else(|| ray.at(escape / ray.b_direction.length()), |rec| rec.p),
+                hit: hit.as_ref().map(|rec| (rec.normal, rec.object_id)),
+                light,
+                throughput,
+            });
+            path.radiance += light;
+            match scattered {
+                Some((next, pdf)) => (ray, scatter_pdf) = (next, pdf),
+                None => {
+                    path.end = match hit {
+                        Some(_) => PathEnd::Stopped,
+                        None => PathEnd::Escaped,
+                    };
+                    return path;
+                }
+            }
+        }
+        path
+    }
+
+    // One bounce of a path that carries `throughput`: the light it gathers here and the ray
+    // it goes on with, if any, with the density it was scattered with when a light it hits
+    // must be weighed against a shadow ray (0 otherwise, LEFT_TO_SHADOW_RAYS when the shadow
+    // ray counts in full). `scatter_pdf` is that of `ray`.
+    #[allow(clippy::too_many_arguments)]
+    fn bounce(
+        &self,
+        ray: &Ray,
+        hit: Option<&HitRecord>,
+        bounce: u32,
+        scatter_pdf: Float,
+        world: &impl Hittable,
+        throughput: &mut Vec3,
+        rng: &mut impl Rng,
+    ) -> (Vec3, Option<(Ray, Float)>) {
+        let sample_lights = self.render_mode.follows_paths()
+            && self.light_sampling != LightSampling::Bsdf
+            && !self.lights.objects.is_empty();
+        let Some(rec) = hit.filter(|rec| sample_lights && rec.coverage >= 1.0) else {
+            let (color, scattered) = self.shade(ray, hit, bounce, 1.0, throughput, rng);
+            return (color, scattered.map(|ray| (ray, 0.0)));
+        };
+        let matte = bounce == 0 && self.matte.is_some_and(|id| rec.object_id != id);
+        if rec.mat.is_diffuse() && !matte && rec.mat.estimator() != Estimator::Bsdf {
+            return self.scatter_diffuse(ray, rec, bounce, world, throughput, rng);
+        }
+        // a light found by a bounce that a shadow ray could have found as well
+        let weight = if scatter_pdf != 0.0 && rec.mat.is_emissive() {
+            let light_pdf = self.lights.pdf_value(ray.a_origin, ray.b_direction);
+            if scatter_pdf == LEFT_TO_SHADOW_RAYS {
+                if light_pdf > 0.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            } else {
+                power_heuristic(scatter_pdf, light_pdf)
+            }
+        } else {
+            1.0
+        };
+        let (color, scattered) = self.shade(ray, hit, bounce, weight, throughput, rng);
+        (color, scattered.map(|ray| (ray, 0.0)))
+    }
+
+    // One bounce as the material scatters, see bounce; the emission of the hit counts
+    // `emission_weight` times. Past roulette_depth bounces a path goes on with the
+    // probability of its brightest throughput channel and is boosted to make up for the
+    // ones cut.
+    fn shade(
+        &self,
+        ray: &Ray,
+        hit: Option<&HitRecord>,
+        bounce: u32,
+        emission_weight: Float,
+        throughput: &mut Vec3,
+        rng: &mut impl Rng,
+    ) -> (Vec3, Option<Ray>) {
+        if bounce == 0
+            && self
+                .matte
+                .is_some_and(|id| hit.is_none_or(|rec| rec.object_id != id))
+        {
+            return (self.matte_key, None);
+        }
+        if self.render_mode.shows_first_hit() {
+            return (
+                hit.map_or(Vec3::zero(), |rec| self.debug_color(ray, rec)),
+                None,
+            );
+        }
+
+        // If the ray hits nothing, return the background color.
+        let Some(rec) = hit else {
+            return (throughput.component_mul(self.background_at(ray)), None);
+        };
 
         let mut scattered = Ray::default();
         let mut attenuation = Vec3::zero();
-        let color_from_emission = rec.mat.emitted(rec.u, rec.v, rec.p);
+        let mut color = throughput.component_mul(rec.mat.emitted(rec)) * emission_weight;
+
+        // geometry widened to the ray's cone gives its light by the part of the cone it
+        // covers, and the path goes on past it as often as it doesn't
+        if rec.coverage < 1.0 {
+            color = color * rec.coverage;
+            if rng.gen::<Float>() >= rec.coverage {
+                let origin = rec.spawn_ray(ray.b_direction, ray.time).a_origin;
+                let past = ray.moved(origin, ray.b_direction);
+                return (color, Some(carry_cone(ray, rec, past)));
+            }
+        }
+
+        if !rec.mat.scatter(ray, rec, &mut attenuation, &mut scattered) {
+            return (color, None);
+        }
+        if !rec.mat.is_diffuse() {
+            scattered = carry_cone(ray, rec, scattered);
+        }
+        // once a dispersive material picked a wavelength, the rest of the path keeps it
+        if scattered.wavelength == 0.0 {
+            scattered.wavelength = ray.wavelength;
+        }
+        *throughput = throughput.component_mul(attenuation);
+        if !self.survives(bounce, throughput, rng) {
+            return (color, None);
+        }
+        (color, Some(scattered))
+    }
+
+    // A bounce off a diffuse surface that aims at the lights as light_sampling asks.
+    fn scatter_diffuse(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        bounce: u32,
+        world: &impl Hittable,
+        throughput: &mut Vec3,
+        rng: &mut impl Rng,
+    ) -> (Vec3, Option<(Ray, Float)>) {
+        let albedo = rec.mat.albedo(rec);
+        let mut color = throughput.component_mul(rec.mat.emitted(rec));
+        let cosine_pdf = |direction: Vec3| (rec.normal * direction.unit()).max(0.0) / PI;
+
+        let (direction, pdf, scatter_pdf) = match self.light_sampling {
+            LightSampling::Mixture(weight) => {
+                let direction = if rng.gen::<Float>() < weight {
+                    self.lights.random(rec.p)
+                } else {
+                    cosine_direction(rec)
+                };
+                let pdf = weight * self.lights.pdf_value(rec.p, direction)
+                    + (1.0 - weight) * cosine_pdf(direction);
+                (direction, pdf, 0.0)
+            }
+            _ => {
+                let light = self.light_sample(ray.time, rec, world);
+                color += throughput.component_mul(albedo).component_mul(light);
+                let direction = cosine_direction(rec);
+                let pdf = cosine_pdf(direction);
+                match rec.mat.estimator() {
+                    Estimator::Lights => (direction, pdf, LEFT_TO_SHADOW_RAYS),
+                    _ => (direction, pdf, pdf),
+                }
+            }
+        };
+        // light samples can fall below the surface
+        if pdf <= 0.0 || !rec.is_above(direction) {
+            return (color, None);
+        }
+        *throughput = throughput.component_mul(albedo) * (cosine_pdf(direction) / pdf);
+        if !self.survives(bounce, throughput, rng) {
+            return (color, None);
+        }
+        let mut scattered = rec.spawn_ray(direction, ray.time);
+        scattered.wavelength = ray.wavelength;
+        (color, Some((scattered, scatter_pdf)))
+    }
+
+    // The light from a shadow ray to a random light, as the cosine-weighted average over the
+    // hemisphere that a diffuse surface multiplies its albedo with, weighed against finding
+    // that light by a bounce (LightSampling::Mis).
+    fn light_sample(&self, time: Float, rec: &HitRecord, world: &impl Hittable) -> Vec3 {
+        let direction = self.lights.random(rec.p);
+        let cosine = rec.normal * direction.unit();
+        if cosine <= 0.0 || !rec.is_above(direction) {
+            return Vec3::zero();
+        }
+        let light_pdf = self.lights.pdf_value(rec.p, direction);
+        if light_pdf <= 0.0 {
+            return Vec3::zero();
+        }
+        self.ray_count.fetch_add(1, Ordering::Relaxed);
+        stats::count(Counter::ShadowRay, 1);
+        let mut hit = HitRecord::new();
+        if !world.hit(
+            &rec.spawn_ray(direction, time),
+            Interval::with_bounds(0.001, Float::INFINITY),
+            &mut hit,
+        ) {
+            return Vec3::zero();
+        }
+        let weight = match rec.mat.estimator() {
+            Estimator::Lights => 1.0,
+            _ => power_heuristic(light_pdf, cosine / PI),
+        };
+        hit.mat.emitted(&hit) * (weight * cosine / (PI * light_pdf))
+    }
+
+    // Russian roulette past roulette_depth bounces: whether the path goes on, with the
+    // probability of its brightest throughput channel, boosted to make up for the ones cut.
+    fn survives(&self, bounce: u32, throughput: &mut Vec3, rng: &mut impl Rng) -> bool {
+        if bounce < self.roulette_depth {
+            return true;
+        }
+        let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+        if rng.gen::<Float>() >= survival {
+            return false;
+        }
+        *throughput = *throughput / survival;
+        true
+    }
+
+    fn background_at(&self, ray: &Ray) -> Vec3 {
+        match &self.sky {
+            Some(sky) => sky.at_direction(ray.b_direction),
+            None => self.background,
+        }
+    }
+
+    // The light leaving `rec` towards `ray` where the path ends there without scattering
+    // further: diffuse surfaces in the direct preview, and diffuse surfaces hit by camera
+    // rays with the irradiance cache. None where the path goes on as usual.
+    fn end_at(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        bounce: u32,
+        world: &impl Hittable,
+        rng: &mut impl Rng,
+    ) -> Option<Vec3> {
+        if !rec.mat.is_diffuse()
+            || rec.coverage < 1.0
+            || (bounce == 0 && self.matte.is_some_and(|id| rec.object_id != id))
+        {
+            return None;
+        }
+        let irradiance = match self.render_mode {
+            RenderMode::Direct => self.direct_light(ray.time, rec, world),
+            RenderMode::Path if bounce == 0 => self.cached_irradiance(rec, world, rng)?,
+            _ => return None,
+        };
+        Some(rec.mat.emitted(rec) + rec.mat.albedo(rec).component_mul(irradiance))
+    }
+
+    // The light arriving at a diffuse surface hit by a camera ray, from the irradiance cache.
+    fn cached_irradiance(
+        &self,
+        rec: &HitRecord,
+        world: &impl Hittable,
+        rng: &mut impl Rng,
+    ) -> Option<Vec3> {
+        let cache = self.irradiance_cache.as_ref()?;
+        let irradiance = match cache.lookup(rec.p, rec.normal) {
+            Some(irradiance) => irradiance,
+            None => {
+                let (irradiance, harmonic_distance) = self.gather(rec, cache.samples, world, rng);
+                cache.insert(rec.p, rec.normal, irradiance, harmonic_distance);
+                irradiance
+            }
+        };
+        Some(irradiance)
+    }
+
+    // The light arriving at `rec` straight from the lights and the sky, averaged over the
+    // cosine-weighted hemisphere like the irradiance cache's records: a shadow ray to a random
+    // point of each light, and one in a cosine-weighted direction that counts the sky if it
+    // escapes. Light reflected by other surfaces is left out.
+    fn direct_light(&self, time: Float, rec: &HitRecord, world: &impl Hittable) -> Vec3 {
+        let first_hit = |direction: Vec3| {
+            let mut hit = HitRecord::new();
+            world
+                .hit(
+                    &rec.spawn_ray(direction, time),
+                    Interval::with_bounds(0.001, Float::INFINITY),
+                    &mut hit,
+                )
+                .then_some(hit)
+        };
+        let mut light = Vec3::zero();
+        let mut shadow_rays = 1;
+        for emitter in &self.lights.objects {
+            let direction = emitter.random(rec.p);
+            let cosine = rec.normal * direction.unit();
+            if cosine <= 0.0 || !rec.is_above(direction) {
+                continue;
+            }
+            let pdf = emitter.pdf_value(rec.p, direction);
+            if pdf <= 0.0 {
+                continue;
+            }
+            shadow_rays += 1;
+            // the light of this emitter only, if nothing is in front of it: another light in
+            // the way is an occluder here, its own shadow ray counts what it gives
+            let ray = rec.spawn_ray(direction, time);
+            let mut at_light = HitRecord::new();
+            if !emitter.hit(&ray, Interval::with_bounds(0.001, Float::INFINITY), &mut at_light) {
+                continue;
+            }
+            let in_front = Interval::with_bounds(0.001, at_light.t * (1.0 - 1e-5));
+            if !world.hit(&ray, in_front, &mut HitRecord::new()) {
+                light += at_light.mat.emitted(&at_light) * (cosine / (PI * pdf));
+            }
+        }
+
+        let direction = cosine_direction(rec);
+        if first_hit(direction).is_none() {
+            light += self.background_at(&Ray::new(rec.p, direction, time));
+        }
+        self.ray_count.fetch_add(shadow_rays, Ordering::Relaxed);
+        stats::count(Counter::ShadowRay, shadow_rays);
+        light
+    }
+
+    // Traces `samples` paths leaving `rec` in cosine-weighted directions, as a Lambertian
+    // surface scatters them: their average radiance and the harmonic mean of the distances
+    // to their first hits.
+    fn gather(
+        &self,
+        rec: &HitRecord,
+        samples: u32,
+        world: &impl Hittable,
+        rng: &mut impl Rng,
+    ) -> (Vec3, Float) {
+        let mut radiance = [Vec3::zero()];
+        let mut inverse_distances = 0.0;
+        let mut next = Wave::default();
+        self.ray_count.fetch_add(samples as u64, Ordering::Relaxed);
+        for _ in 0..samples {
+            let ray = rec.spawn_ray(cosine_direction(rec), self.sample_time(rng));
+            let mut hit = HitRecord::new();
+            let hit = world
+                .hit(
+                    &ray,
+                    Interval::with_bounds(0.001, Float::INFINITY),
+                    &mut hit,
+                )
+                .then_some(hit);
+            if let Some(hit) = &hit {
+                inverse_distances += 1.0 / (hit.t * ray.b_direction.length()).max(1e-6);
+            }
+            let mut throughput = Vec3::ones();
+            let (color, scattered) =
+                self.bounce(&ray, hit.as_ref(), 1, 0.0, world, &mut throughput, rng);
+            radiance[0] += color;
+            if let Some((scattered, scatter_pdf)) = scattered {
+                next.push(scattered, throughput, scatter_pdf, 0);
+            }
+        }
+        self.trace_wave(next, 2, world, &mut radiance, rng);
+        (
+            radiance[0] / samples as Float,
+            samples as Float / inverse_distances,
+        )
+    }
+
+    // Bakes the light arriving at `surfaces` (see Hittable::collect_surfaces) in `world` into
+    // a lightmap with cells of `size` texels: per texel, the light a white diffuse surface
+    // would reflect at sample_per_pixel points of it, on the side its normal faces (for a
+    // quad, that of u x v), with the exposure: the irradiance over pi. Times the albedo it is
+    // the colour of a diffuse surface there, which makes the path tracer a light baker for
+    // surfaces that are then drawn without lighting of their own. Light sampling works as
+    // in renders. The surfaces themselves take part in the lighting as they are in `world`.
+    pub fn bake(
+        &mut self,
+        world: &(impl Hittable + Send + Sync),
+        surfaces: &HittableList,
+        size: u32,
+    ) -> Lightmap {
+        self.initialize();
+        self.prepare_world(world);
+        let mut lightmap = Lightmap::new(surfaces.objects.len(), size.max(1));
+        let rows: Vec<u32> = (0..lightmap.height()).collect();
+        let chunk = (rows.len() / self.thread_limit.max(1) as usize).max(1);
+        let camera: &Camera = self;
+        let atlas = &lightmap;
+        let texels: Vec<(u32, u32, Option<Vec3>)> = crossbeam::thread::scope(|s| {
+            let handles: Vec<_> = rows
+                .chunks(chunk)
+                .map(|rows| {
+                    s.spawn(move |_| {
+                        if let Some(seed) = camera.seed {
+                            util::seed_rng(seed.wrapping_add(rows[0] as u64));
+                        }
+                        let mut rng = util::rng();
+                        let mut texels = vec![];
+                        for &y in rows {
+                            for x in 0..atlas.width() {
+                                let value = atlas.texel(x, y).and_then(|(k, u, v)| {
+                                    let surface = &*surfaces.objects[k];
+                                    camera.bake_texel(surface, (u, v), size, world, &mut rng)
+                                });
+                                texels.push((x, y, value));
+                            }
+                        }
+                        texels
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        })
+        .unwrap();
+        for (x, y, value) in texels {
+            lightmap.set(x, y, value);
+        }
+        lightmap
+    }
 
-        if !rec.mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
-            return color_from_emission;
+    // The light baked into the texel of `surface` from (u, v), its texture coordinates at
+    // the top left, to 1 / size right and down; None if no sample falls on the surface.
+    fn bake_texel(
+        &self,
+        surface: &dyn Hittable,
+        (u, v): (Float, Float),
+        size: u32,
+        world: &impl Hittable,
+        rng: &mut impl Rng,
+    ) -> Option<Vec3> {
+        let samples = self.sample_per_pixel.max(1);
+        let white = Lambertian::from_color(Vec3::ones());
+        let mut radiance = [Vec3::zero()];
+        let mut on_surface = 0;
+        let mut next = Wave::default();
+        for _ in 0..samples {
+            let su = u + rng.gen::<Float>() / size as Float;
+            let sv = v - rng.gen::<Float>() / size as Float;
+            let Some((p, normal)) = surface.surface_at(su, sv) else {
+                continue;
+            };
+            on_surface += 1;
+            // a ray arriving head on at a white Lambertian stand-in for the surface's own
+            // material, shaded as a first bounce
+            let ray = Ray::new(p + normal, -normal, self.sample_time(rng));
+            let mut rec = HitRecord::new();
+            rec.p = p;
+            rec.t = 1.0;
+            rec.normal = normal;
+            rec.geometric_normal = normal;
+            rec.front_face = true;
+            rec.mat = &white;
+            let mut throughput = Vec3::ones();
+            let (color, scattered) =
+                self.bounce(&ray, Some(&rec), 1, 0.0, world, &mut throughput, rng);
+            radiance[0] += color;
+            if let Some((scattered, scatter_pdf)) = scattered {
+                next.push(scattered, throughput, scatter_pdf, 0);
+            }
+        }
+        if on_surface == 0 {
+            return None;
+        }
+        self.ray_count
+            .fetch_add(on_surface as u64, Ordering::Relaxed);
+        self.trace_wave(next, 2, world, &mut radiance, rng);
+        Some(radiance[0] * (self.exposure_scale / on_surface as Float))
+    }
+
+    // What the debug views show of a first hit, see RenderMode.
+    fn debug_color(&self, ray: &Ray, rec: &HitRecord) -> Vec3 {
+        match self.render_mode {
+            RenderMode::Path | RenderMode::Direct | RenderMode::Preview | RenderMode::Bounces => {
+                Vec3::zero()
+            }
+            RenderMode::Normals => (rec.normal + Vec3::ones()) * 0.5,
+            RenderMode::Depth => {
+                let distance = rec.t * ray.b_direction.length();
+                let reference = (self.lookat - self.lookfrom).length();
+                Vec3::ones() * (reference / (reference + distance))
+            }
+            RenderMode::Uv => Vec3::new(rec.u, rec.v, 0.0),
+            RenderMode::Albedo => rec.mat.albedo(rec),
         }
+    }
 
-        let color_from_scatter =
-            attenuation.component_mul(self.ray_color(&scattered, world, depth - 1));
+    // The colour of RenderMode::Bounces for a mean of `bounces`: along the ramp of
+    // BOUNCE_RAMP by log(1 + bounces), white at max_depth.
+    fn bounce_color(&self, bounces: Float) -> Vec3 {
+        let t = (1.0 + bounces.max(0.0)).ln() / (1.0 + self.max_depth.max(1) as Float).ln();
+        let t = t.clamp(0.0, 1.0) * (BOUNCE_RAMP.len() - 1) as Float;
+        let k = (t as usize).min(BOUNCE_RAMP.len() - 2);
+        let f = t - k as Float;
+        BOUNCE_RAMP[k] * (1.0 - f) + BOUNCE_RAMP[k + 1] * f
+    }
+
+    // The bounces at `t` (0 to 1) along the ramp of bounce_color, rounded.
+    fn bounces_at(&self, t: Float) -> u32 {
+        ((1.0 + self.max_depth.max(1) as Float).powf(t) - 1.0).round() as u32
+    }
+
+    fn get_ray(&self, i: u32, j: u32, rng: &mut impl Rng) -> Option<Ray> {
+        let (x, y) = self.jittered_position(i, j, rng);
+        self.ray_at(x, y, None, rng)
+    }
 
-        color_from_emission + color_from_scatter
+    // A random image position in pixel (i, j).
+    fn jittered_position(&self, i: u32, j: u32, rng: &mut impl Rng) -> (Float, Float) {
+        let x = i as Float + rng.gen_range(-0.5..0.5);
+        let y = j as Float + rng.gen_range(-0.5..0.5);
+        (x, y)
     }
 
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
-        let mut rng = rand::thread_rng();
+    // The center of sub-pixel (sub_x, sub_y) of pixel (i, j), for SSAA.
+    fn subpixel_position(&self, i: u32, j: u32, sub_y: u32, sub_x: u32) -> (Float, Float) {
+        let x = i as Float + (sub_x * 2 + 1) as Float / self.sub_pixel_cnt as Float / 2.0 - 0.5;
+        let y = j as Float + (sub_y * 2 + 1) as Float / self.sub_pixel_cnt as Float / 2.0 - 0.5;
+        (x, y)
+    }
 
-        let pixel_sample = self.pixel00_loc
-            + ((i as f64 + rng.gen_range(-0.5..0.5)) * self.pixel_delta_u)
-            + ((j as f64 + rng.gen_range(-0.5..0.5)) * self.pixel_delta_v);
+    // The point of the lens for sample `sample` of pixel (i, j) with halton_lens, as for
+    // Aperture::sample_at; None to sample it at random. The Halton points are offset by a
+    // fixed amount per pixel (wrapping around), so that neighbouring pixels don't all use
+    // the same points of the lens.
+    fn lens_sample(&self, i: u32, j: u32, sample: u32) -> Option<(Float, Float)> {
+        if !self.halton_lens && !self.focus_assist {
+            return None;
+        }
+        let hash = mix_bits((j as u64) << 32 | i as u64);
+        let offset_x = (hash >> 40) as Float / (1u64 << 24) as Float;
+        let offset_y = ((hash >> 16) & 0xff_ffff) as Float / (1u64 << 24) as Float;
+        Some((
+            (halton(sample + 1, 2) + offset_x).fract(),
+            (halton(sample + 1, 3) + offset_y).fract(),
+        ))
+    }
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.camera_center
+    // How sharp the first surface seen through image position (x, y) comes out, for the focus
+    // assist: 1 where its circle of confusion is at most a pixel across, down to 0 at two
+    // pixels. Without defocus blur everything is sharp, and depths within 1% of focus_dist
+    // count as in focus instead, fading out at 2%. 0 for the other projections.
+    fn focus_weight(&self, x: Float, y: Float, world: &impl Hittable) -> Float {
+        if self.projection != Projection::Perspective {
+            return 0.0;
+        }
+        let Some(depth) = self.depth_at(x, y, world) else {
+            return 0.0;
+        };
+        let blur = if self.defocus_angle > 0.0 {
+            // the circle of confusion where it crosses the focal plane, in pixels
+            let radius = self.defocus_disk_u.length();
+            2.0 * radius * (depth - self.focus_dist).abs() / depth / self.pixel_delta_u.length()
         } else {
-            self.defocus_disk_sample()
+            (depth - self.focus_dist).abs() / (0.01 * self.focus_dist)
         };
-        let ray_direction = pixel_sample - ray_origin;
+        (2.0 - blur).clamp(0.0, 1.0)
+    }
+
+    // The normal, albedo and distance of the first surface seen through each pixel, from
+    // GUIDE_GRID by GUIDE_GRID rays spread over it and through the center of the lens, so that
+    // the guides are sharp where the image is in focus and the noise alone is told apart.
+    fn guide_buffers(&self, world: &(impl Hittable + Send + Sync)) -> GuideBuffers {
+        let (width, height) = (self.image_width, self.image_height);
+        let rows: Vec<u32> = (0..height).collect();
+        let chunk = (rows.len() / self.thread_limit.max(1) as usize).max(1);
+        let camera: &Camera = self;
+        let pixels: Vec<(Vec3, Vec3, Float)> = crossbeam::thread::scope(|s| {
+            let handles: Vec<_> = rows
+                .chunks(chunk)
+                .map(|rows| {
+                    s.spawn(move |_| {
+                        if let Some(seed) = camera.seed {
+                            util::seed_rng(seed.wrapping_add(!(rows[0] as u64)));
+                        }
+                        let mut rng = util::rng();
+                        let mut pixels = Vec::with_capacity(rows.len() * width as usize);
+                        for &j in rows {
+                            for i in 0..width {
+                                pixels.push(camera.guide_pixel(i, j, world, &mut rng));
+                            }
+                        }
+                        pixels
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        })
+        .unwrap();
+        let mut guides = GuideBuffers::new(width, height);
+        for (index, (normal, albedo, depth)) in pixels.into_iter().enumerate() {
+            guides.normal[index] = normal;
+            guides.albedo[index] = albedo;
+            guides.depth[index] = depth;
+        }
+        guides
+    }
 
-        Ray::new(ray_origin, ray_direction, rng.gen_range(0.0..=1.0))
+    // Mean normal, albedo and distance of the surfaces seen through pixel (i, j) on a grid of
+    // GUIDE_GRID by GUIDE_GRID rays; the distance is infinite when most of them miss.
+    fn guide_pixel(
+        &self,
+        i: u32,
+        j: u32,
+        world: &impl Hittable,
+        rng: &mut impl Rng,
+    ) -> (Vec3, Vec3, Float) {
+        let (mut normal, mut albedo, mut depth) = (Vec3::zero(), Vec3::zero(), 0.0);
+        let grid = GUIDE_GRID;
+        let mut hits = 0;
+        for sy in 0..grid {
+            for sx in 0..grid {
+                let x = i as Float + (sx as Float + rng.gen::<Float>()) / grid as Float - 0.5;
+                let y = j as Float + (sy as Float + rng.gen::<Float>()) / grid as Float - 0.5;
+                let Some(ray) = self.ray_at(x, y, None, rng) else {
+                    continue;
+                };
+                // the same point of the focus plane, seen from the center of the lens
+                let target = ray.a_origin + ray.b_direction;
+                let ray = Ray::new(self.camera_center, target - self.camera_center, ray.time);
+                let mut rec = HitRecord::new();
+                self.ray_count.fetch_add(1, Ordering::Relaxed);
+                if world.hit(
+                    &ray,
+                    Interval::with_bounds(0.001, Float::INFINITY),
+                    &mut rec,
+                ) {
+                    normal += rec.normal;
+                    albedo += rec.mat.albedo(&rec);
+                    depth += (rec.p - self.camera_center).length();
+                    hits += 1;
+                }
+            }
+        }
+        let count = (grid * grid) as Float;
+        if 2 * hits <= grid * grid {
+            return (Vec3::zero(), albedo / count, Float::INFINITY);
+        }
+        let normal = if normal.near_zero() {
+            normal
+        } else {
+            normal.unit()
+        };
+        (normal, albedo / count, depth / hits as Float)
     }
 
-    fn get_ray_subpixel(&self, i: u32, j: u32, sub_y: u32, sub_x: u32) -> Ray {
-        let mut rng = rand::thread_rng();
+    // The depth along the view axis of the first surface seen from the center of the lens
+    // through image position (x, y), if any; perspective only.
+    fn depth_at(&self, x: Float, y: Float, world: &impl Hittable) -> Option<Float> {
+        let pixel_sample = self.pixel00_loc + (x * self.pixel_delta_u) + (y * self.pixel_delta_v);
+        let ray = Ray::new(
+            self.camera_center,
+            pixel_sample - self.camera_center,
+            self.shutter_open,
+        );
+        let mut rec = HitRecord::new();
+        self.ray_count.fetch_add(1, Ordering::Relaxed);
+        if !world.hit(
+            &ray,
+            Interval::with_bounds(0.001, Float::INFINITY),
+            &mut rec,
+        ) {
+            return None;
+        }
+        let depth = (rec.p - self.camera_center) * -self.w;
+        (depth > 0.0).then_some(depth)
+    }
 
-        let pixel_sample = self.pixel00_loc
-            + ((i as f64 + (sub_x * 2 + 1) as f64 / self.sub_pixel_cnt as f64 / 2.0 - 0.5)
-                * self.pixel_delta_u)
-            + ((j as f64 + (sub_y * 2 + 1) as f64 / self.sub_pixel_cnt as f64 / 2.0 - 0.5)
-                * self.pixel_delta_v);
+    // Ray through the image position (x, y), in pixels from the center of the top left pixel,
+    // from the point `lens` of the lens (see lens_sample); None outside the image circle of a
+    // fisheye.
+    fn ray_at(
+        &self,
+        x: Float,
+        y: Float,
+        lens: Option<(Float, Float)>,
+        rng: &mut impl Rng,
+    ) -> Option<Ray> {
+        let time = self.sample_time(rng);
+        if self.projection == Projection::Perspective {
+            let pixel_sample =
+                self.pixel00_loc + (x * self.pixel_delta_u) + (y * self.pixel_delta_v);
+            let ray_origin = if self.defocus_angle <= 0.0 {
+                self.camera_center
+            } else {
+                self.defocus_disk_sample(lens)
+            };
+            let ray_direction = pixel_sample - ray_origin;
+            return Some(self.with_cone(Ray::new(ray_origin, ray_direction, time)));
+        }
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.camera_center
+        let (width, height) = (self.image_width as Float, self.image_height as Float);
+        let direction = if self.projection == Projection::Equirectangular {
+            let longitude = ((x + 0.5) / width - 0.5) * 2.0 * PI;
+            let latitude = (0.5 - (y + 0.5) / height) * PI;
+            latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w)
+                + latitude.sin() * self.v
         } else {
-            self.defocus_disk_sample()
+            // from the image center, y up, 1 at the top and bottom edges
+            let px = (2.0 * (x + 0.5) - width) / height;
+            let py = (height - 2.0 * (y + 0.5)) / height;
+            let r = (px * px + py * py).sqrt();
+            if r > 1.0 {
+                return None;
+            }
+            let half_fov = (0.5 * self.vfov).to_radians();
+            let theta = match self.projection {
+                Projection::FisheyeEquisolid => 2.0 * (r * (0.5 * half_fov).sin()).asin(),
+                _ => r * half_fov,
+            };
+            let phi = py.atan2(px);
+            theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w
         };
-        let ray_direction = pixel_sample - ray_origin;
+        Some(self.with_cone(Ray::new(self.camera_center, direction, time)))
+    }
+
+    fn with_cone(&self, mut ray: Ray) -> Ray {
+        ray.cone_spread = self.cone_spread;
+        ray
+    }
 
-        Ray::new(ray_origin, ray_direction, rng.gen_range(0.0..=1.0))
+    fn sample_time(&self, rng: &mut impl Rng) -> Float {
+        let (open, close) = (self.shutter_open, self.shutter_close);
+        if close <= open {
+            return open;
+        }
+        rng.gen_range(open..=close)
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = random_in_unit_disk();
+    fn defocus_disk_sample(&self, lens: Option<(Float, Float)>) -> Vec3 {
+        let p = match lens {
+            Some(lens) => self.aperture.sample_at(lens),
+            None => self.aperture.sample(),
+        };
         return self.camera_center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v);
     }
 }
+
+// paths per wave in render_sub
+const WAVE_SIZE: usize = 4096;
+
+// black, blue, green, yellow, red and white, evenly spread over the ramp of bounce_color
+const BOUNCE_RAMP: [Vec3; 6] = [
+    Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: 1.0,
+        z: 1.0,
+    },
+];
+
+// rays per pixel, on both axes, for the denoiser's guide buffers
+const GUIDE_GRID: u32 = 2;
+
+// the focus assist's colour for what is in focus, and how much of the image it covers there
+const FOCUS_TINT: Vec3 = Vec3 {
+    x: 0.1,
+    y: 1.0,
+    z: 0.2,
+};
+const FOCUS_TINT_OPACITY: Float = 0.5;
+
+// hits of a preview block share the light gathered at another if their normals are at most
+// about 25 degrees apart and their distances from the camera within 10% of each other
+const PREVIEW_NORMAL_MATCH: Float = 0.9;
+const PREVIEW_DISTANCE_MATCH: Float = 0.1;
+
+// Paths traced together, structure of arrays: ray, throughput, scatter density (see
+// Camera::bounce) and pixel of path k.
+#[derive(Default)]
+struct Wave {
+    rays: Vec<Ray>,
+    throughput: Vec<Vec3>,
+    scatter_pdf: Vec<Float>,
+    pixel: Vec<usize>,
+}
+
+impl Wave {
+    fn push(&mut self, ray: Ray, throughput: Vec3, scatter_pdf: Float, pixel: usize) {
+        self.rays.push(ray);
+        self.throughput.push(throughput);
+        self.scatter_pdf.push(scatter_pdf);
+        self.pixel.push(pixel);
+    }
+}
+
+// A direction off `rec` as a Lambertian surface scatters: cosine-weighted about the shading
+// normal, mirrored back out where that would go through the surface.
+fn cosine_direction(rec: &HitRecord) -> Vec3 {
+    let direction = rec.normal + util::random_in_unit_sphere().unit();
+    if rec.is_above(direction) {
+        direction
+    } else {
+        util::reflect(direction, rec.geometric_normal)
+    }
+}
+
+// `next`, leaving the hit `rec` of `ray`, with the cone of `ray` as wide as it got there and
+// spreading as before: right for rays going straight on or off flat mirrors, which is all
+// ray_cones follows (it is only for the look of thin geometry).
+fn carry_cone(ray: &Ray, rec: &HitRecord, next: Ray) -> Ray {
+    Ray {
+        cone_width: ray.footprint(rec.t),
+        cone_spread: ray.cone_spread,
+        ..next
+    }
+}
+
+// SplitMix64's finalizer: every bit of `x` flips about half the bits of the result.
+fn mix_bits(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// scatter_pdf of a bounce whose material leaves the lights to its shadow rays
+// (Estimator::Lights): the lights it hits count only where shadow rays can't find them
+const LEFT_TO_SHADOW_RAYS: Float = -1.0;
+
+// Weight of a sample drawn with density `a` against another strategy with density `b`.
+fn power_heuristic(a: Float, b: Float) -> Float {
+    let (a, b) = (a * a, b * b);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,123 @@
+use crate::camera::Camera;
+use crate::vec3::{Float, Vec3};
+
+// One frame of an imported camera move.
+pub struct CameraKey {
+    pub frame: u32,
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    pub vfov: Option<Float>,
+}
+
+impl CameraKey {
+    pub fn apply(&self, cam: &mut Camera) {
+        cam.lookfrom = self.lookfrom;
+        cam.lookat = self.lookat;
+        cam.vup = self.vup;
+        if let Some(vfov) = self.vfov {
+            cam.vfov = vfov;
+        }
+    }
+}
+
+// Reads a per-frame camera path from a CSV file with a header row, in scene coordinates:
+//
+//     frame,x,y,z,tx,ty,tz,fov        position and look-at target
+//     frame,x,y,z,rx,ry,rz,fov        position and XYZ Euler angles in degrees
+//
+// `fov` (vertical, degrees) is optional. Positions and targets are Y up, so an exporter from
+// Blender, which is Z up, writes (x, z, -y) for them. Euler angles are Blender's own
+// rotation_euler, left as they are: the camera looks down its local -Z axis with +Y up in
+// Blender's Z-up world, turned to Y up here, so (90, 0, 0) looks along -Z and (0, 0, 0)
+// straight down.
+pub fn load_camera_path(path: &str) -> Result<Vec<CameraKey>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+
+    let (_, header) = lines.next().ok_or(format!("{}: empty camera path", path))?;
+    let header: Vec<&str> = header.split(',').map(|s| s.trim()).collect();
+    let column = |name: &str| header.iter().position(|h| *h == name);
+    let required =
+        |name: &str| column(name).ok_or(format!("{}: missing column \"{}\"", path, name));
+
+    let frame = required("frame")?;
+    let position = [required("x")?, required("y")?, required("z")?];
+    let target = match (column("tx"), column("ty"), column("tz")) {
+        (Some(tx), Some(ty), Some(tz)) => Some([tx, ty, tz]),
+        _ => None,
+    };
+    let rotation = match (column("rx"), column("ry"), column("rz")) {
+        (Some(rx), Some(ry), Some(rz)) => Some([rx, ry, rz]),
+        _ => None,
+    };
+    if target.is_none() && rotation.is_none() {
+        return Err(format!(
+            "{}: need either tx,ty,tz or rx,ry,rz columns",
+            path
+        ));
+    }
+    let fov = column("fov");
+
+    let mut keys = vec![];
+    for (line_no, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
+        let number = |i: usize| -> Result<Float, String> {
+            fields.get(i).and_then(|f| f.parse().ok()).ok_or(format!(
+                "{}:{}: bad or missing value in column {}",
+                path,
+                line_no + 1,
+                i + 1
+            ))
+        };
+        let vec = |cols: [usize; 3]| -> Result<Vec3, String> {
+            Ok(Vec3::new(
+                number(cols[0])?,
+                number(cols[1])?,
+                number(cols[2])?,
+            ))
+        };
+
+        let lookfrom = vec(position)?;
+        let (lookat, vup) = match target {
+            Some(target) => (vec(target)?, Vec3::new(0.0, 1.0, 0.0)),
+            None => {
+                let angles = vec(rotation.unwrap())?;
+                let forward = y_up(rotate_xyz(Vec3::new(0.0, 0.0, -1.0), angles));
+                (
+                    lookfrom + forward,
+                    y_up(rotate_xyz(Vec3::new(0.0, 1.0, 0.0), angles)),
+                )
+            }
+        };
+        keys.push(CameraKey {
+            frame: number(frame)? as u32,
+            lookfrom,
+            lookat,
+            vup,
+            vfov: match fov {
+                Some(i) => Some(number(i)?),
+                None => None,
+            },
+        });
+    }
+    Ok(keys)
+}
+
+// a Z-up direction in Y-up coordinates, a -90 degree turn about X
+fn y_up(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, v.z, -v.y)
+}
+
+// rotates `v` about X, then Y, then Z (angles in degrees)
+fn rotate_xyz(v: Vec3, angles: Vec3) -> Vec3 {
+    let (sx, cx) = angles.x.to_radians().sin_cos();
+    let (sy, cy) = angles.y.to_radians().sin_cos();
+    let (sz, cz) = angles.z.to_radians().sin_cos();
+    let v = Vec3::new(v.x, cx * v.y - sx * v.z, sx * v.y + cx * v.z);
+    let v = Vec3::new(cy * v.x + sy * v.z, v.y, -sy * v.x + cy * v.z);
+    Vec3::new(cz * v.x - sz * v.y, sz * v.x + cz * v.y, v.z)
+}
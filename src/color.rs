@@ -1,6 +1,8 @@
 use crate::interval::Interval;
-use crate::vec3::Vec3;
+use crate::vec3::{Float, Vec3};
 use image::RgbImage;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{OnceLock, RwLock};
 
 /// the multi-sample write_color() function
 // pub fn write_color(pixel_color: [u8; 3], img: &mut RgbImage, i: usize, j: usize) {
@@ -12,11 +14,365 @@ use image::RgbImage;
 /// the multi-sample write_color() function
 pub fn write_color(pixel_color: Vec3, img: &mut RgbImage, i: usize, j: usize) {
     let interval: Interval = Interval::with_bounds(0.0, 255.0);
+    let pixel_color = output_transform().encode(to_rec709(pixel_color));
+    // rounding down after adding a threshold in 0..1 keeps the mean of the plain truncation
+    let threshold = dither_threshold(i, j) - 0.5;
     let pixel = img.get_pixel_mut(i as u32, j as u32);
     *pixel = image::Rgb([
-        interval.clamp(pixel_color.x.sqrt() * 256.0) as u8,
-        interval.clamp(pixel_color.y.sqrt() * 256.0) as u8,
-        interval.clamp(pixel_color.z.sqrt() * 256.0) as u8,
+        interval.clamp((pixel_color.x * 256.0 + threshold).floor()) as u8,
+        interval.clamp((pixel_color.y * 256.0 + threshold).floor()) as u8,
+        interval.clamp((pixel_color.z * 256.0 + threshold).floor()) as u8,
     ]);
     // Write the translated [0,255] value of each color component.
 }
+
+// The linear RGB space that colours are rendered in. Colours given in scene files are taken
+// to be in it as they are; image textures, black body and spectral colours are converted
+// into it. Rec709 has the primaries of sRGB and Rec.709 video, which is what everything
+// was before there was a choice; AcesCg the wider AP1 primaries of ACES, where saturated
+// colours mix and bounce more like they do in reality and which compositing packages work
+// in. Either way the output transform brings the image back to Rec.709 primaries.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WorkingSpace {
+    Rec709,
+    AcesCg,
+}
+
+impl WorkingSpace {
+    // "srgb" is the same space as "rec709"
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "rec709" | "srgb" => Ok(WorkingSpace::Rec709),
+            "acescg" => Ok(WorkingSpace::AcesCg),
+            _ => Err(format!(
+                "unknown working space \"{}\" (rec709, srgb, acescg)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WorkingSpace::Rec709 => "rec709",
+            WorkingSpace::AcesCg => "acescg",
+        }
+    }
+}
+
+// The curve that encodes the image for the 8-bit output, after its primaries are brought to
+// Rec.709. Gamma2 is a plain square root, which the renderer always used and is a little
+// darker than sRGB in the shadows; Srgb is the piecewise sRGB curve that image viewers and
+// compositing packages decode; Rec709 the camera curve of HD video (BT.709).
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputTransform {
+    Gamma2,
+    Srgb,
+    Rec709,
+}
+
+impl OutputTransform {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "gamma2" => Ok(OutputTransform::Gamma2),
+            "srgb" => Ok(OutputTransform::Srgb),
+            "rec709" => Ok(OutputTransform::Rec709),
+            _ => Err(format!(
+                "unknown output transform \"{}\" (gamma2, srgb, rec709)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputTransform::Gamma2 => "gamma2",
+            OutputTransform::Srgb => "srgb",
+            OutputTransform::Rec709 => "rec709",
+        }
+    }
+
+    // Linear Rec.709 values to encoded ones, 0 to 1 over the range of the output.
+    pub fn encode(&self, c: Vec3) -> Vec3 {
+        let curve = |x: Float| match self {
+            OutputTransform::Gamma2 => x.sqrt(),
+            OutputTransform::Srgb if x <= 0.0031308 => 12.92 * x,
+            OutputTransform::Srgb => 1.055 * x.powf(1.0 / 2.4) - 0.055,
+            OutputTransform::Rec709 if x < 0.018 => 4.5 * x,
+            OutputTransform::Rec709 => 1.099 * x.powf(0.45) - 0.099,
+        };
+        Vec3::new(curve(c.x), curve(c.y), curve(c.z))
+    }
+}
+
+// The working space, see set_working_space(); an atomic rather than a lock, as every texture
+// lookup reads it.
+static WORKING_SPACE: AtomicU8 = AtomicU8::new(WorkingSpace::Rec709 as u8);
+static OUTPUT_TRANSFORM: RwLock<OutputTransform> = RwLock::new(OutputTransform::Gamma2);
+
+pub fn set_working_space(space: WorkingSpace) {
+    WORKING_SPACE.store(space as u8, Ordering::Relaxed);
+}
+
+pub fn working_space() -> WorkingSpace {
+    if WORKING_SPACE.load(Ordering::Relaxed) == WorkingSpace::AcesCg as u8 {
+        WorkingSpace::AcesCg
+    } else {
+        WorkingSpace::Rec709
+    }
+}
+
+pub fn set_output_transform(transform: OutputTransform) {
+    *OUTPUT_TRANSFORM.write().unwrap() = transform;
+}
+
+pub fn output_transform() -> OutputTransform {
+    *OUTPUT_TRANSFORM.read().unwrap()
+}
+
+// linear Rec.709 to ACEScg and back, with the Bradford adaptation between their white points
+// (D65 and about D60), as in the ACES OpenColorIO config
+const REC709_TO_ACESCG: [[Float; 3]; 3] = [
+    [0.613097, 0.339523, 0.047379],
+    [0.070194, 0.916354, 0.013452],
+    [0.020616, 0.109570, 0.869815],
+];
+const ACESCG_TO_REC709: [[Float; 3]; 3] = [
+    [1.704859, -0.621715, -0.083299],
+    [-0.130078, 1.140734, -0.010560],
+    [-0.023964, -0.128975, 1.153013],
+];
+// CIE XYZ to linear Rec.709
+const XYZ_TO_REC709: [[Float; 3]; 3] = [
+    [3.2406, -1.5372, -0.4986],
+    [-0.9689, 1.8758, 0.0415],
+    [0.0557, -0.2040, 1.0570],
+];
+
+fn transform(m: &[[Float; 3]; 3], c: Vec3) -> Vec3 {
+    let row = |r: &[Float; 3]| r[0] * c.x + r[1] * c.y + r[2] * c.z;
+    Vec3::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+// A linear Rec.709 (sRGB) colour in the working space.
+pub fn from_rec709(c: Vec3) -> Vec3 {
+    match working_space() {
+        WorkingSpace::Rec709 => c,
+        WorkingSpace::AcesCg => transform(&REC709_TO_ACESCG, c),
+    }
+}
+
+// A colour of the working space in linear Rec.709.
+pub fn to_rec709(c: Vec3) -> Vec3 {
+    match working_space() {
+        WorkingSpace::Rec709 => c,
+        WorkingSpace::AcesCg => transform(&ACESCG_TO_REC709, c),
+    }
+}
+
+// How colours are rounded to the 256 levels of the output images. Without dithering a
+// smooth gradient such as the sky or a soft shadow breaks into bands a level apart; a
+// threshold that varies from pixel to pixel turns them into a fine pattern instead. Ordered
+// repeats an 8 by 8 Bayer matrix, which shows as a faint crosshatch; BlueNoise a 64 by 64
+// tile of blue noise (void and cluster), whose grain has no low frequencies to notice.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dither {
+    None,
+    Ordered,
+    BlueNoise,
+}
+
+impl Dither {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "none" => Ok(Dither::None),
+            "ordered" => Ok(Dither::Ordered),
+            "blue-noise" => Ok(Dither::BlueNoise),
+            _ => Err(format!(
+                "unknown dither \"{}\" (none, ordered, blue-noise)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            Dither::Ordered => "ordered",
+            Dither::BlueNoise => "blue-noise",
+        }
+    }
+}
+
+// The dithering of write_color, see set_dither().
+static DITHER: RwLock<Dither> = RwLock::new(Dither::BlueNoise);
+
+pub fn set_dither(dither: Dither) {
+    *DITHER.write().unwrap() = dither;
+}
+
+// Threshold in 0..1 for rounding pixel (i, j); a half everywhere without dithering.
+fn dither_threshold(i: usize, j: usize) -> Float {
+    match *DITHER.read().unwrap() {
+        Dither::None => 0.5,
+        Dither::Ordered => {
+            // the Bayer matrix grows by putting 4M, 4M + 2, 4M + 3 and 4M + 1 in the
+            // quarters, so each bit of x and y picks a quarter one level down
+            let mut rank = 0;
+            for bit in 0..3 {
+                let (x, y) = ((i >> bit) & 1, (j >> bit) & 1);
+                rank = rank * 4 + 2 * (x ^ y) + y;
+            }
+            (rank as Float + 0.5) / 64.0
+        }
+        Dither::BlueNoise => {
+            let ranks = BLUE_NOISE.get_or_init(blue_noise);
+            let rank = ranks[(j % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + i % BLUE_NOISE_SIZE];
+            (rank as Float + 0.5) / (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as Float
+        }
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+// spread of the Gaussian that measures how crowded the points are, in pixels
+const BLUE_NOISE_SIGMA: Float = 1.5;
+static BLUE_NOISE: OnceLock<Vec<u16>> = OnceLock::new();
+
+// Ranks 0..64 * 64 over a tile of 64 by 64 pixels that wraps around, such that the pixels
+// of rank below any n are spread evenly (Ulichney, "The void-and-cluster method for dither
+// array generation"). The crowding at each pixel is the sum of a Gaussian around every
+// point, so the tightest cluster is the point where that is highest and the largest void
+// the empty pixel where it is lowest.
+fn blue_noise() -> Vec<u16> {
+    let n = BLUE_NOISE_SIZE;
+    let count = n * n;
+    let kernel: Vec<Float> = (0..count)
+        .map(|k| {
+            let wrap = |d: usize| d.min(n - d) as Float;
+            let (dx, dy) = (wrap(k % n), wrap(k / n));
+            (-(dx * dx + dy * dy) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+        })
+        .collect();
+    let mut points = vec![false; count];
+    let mut energy = vec![0.0; count];
+    let toggle = |points: &mut [bool], energy: &mut [Float], p: usize| {
+        points[p] = !points[p];
+        let sign = if points[p] { 1.0 } else { -1.0 };
+        let (px, py) = (p % n, p / n);
+        for (k, e) in energy.iter_mut().enumerate() {
+            let (dx, dy) = ((k % n + n - px) % n, (k / n + n - py) % n);
+            *e += sign * kernel[dy * n + dx];
+        }
+    };
+    let tightest_cluster = |points: &[bool], energy: &[Float]| {
+        (0..count)
+            .filter(|&k| points[k])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |points: &[bool], energy: &[Float]| {
+        (0..count)
+            .filter(|&k| !points[k])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // a tenth of the pixels, scattered by a fixed hash so that every run gets the same
+    // tile, then moved from the tightest cluster to the largest void until that settles
+    let initial = count / 10;
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut placed = 0;
+    while placed < initial {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let p = (state % count as u64) as usize;
+        if !points[p] {
+            toggle(&mut points, &mut energy, p);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&points, &energy);
+        toggle(&mut points, &mut energy, cluster);
+        let void = largest_void(&points, &energy);
+        if void == cluster {
+            toggle(&mut points, &mut energy, cluster);
+            break;
+        }
+        toggle(&mut points, &mut energy, void);
+    }
+
+    // the points rank below the rest: take them away from the tightest cluster down, then
+    // fill the largest voids from the same start
+    let mut ranks = vec![0; count];
+    let (mut prototype, prototype_energy) = (points.clone(), energy.clone());
+    for rank in (0..initial).rev() {
+        let cluster = tightest_cluster(&points, &energy);
+        toggle(&mut points, &mut energy, cluster);
+        ranks[cluster] = rank as u16;
+    }
+    energy = prototype_energy;
+    for rank in initial..count {
+        let void = largest_void(&prototype, &energy);
+        toggle(&mut prototype, &mut energy, void);
+        ranks[void] = rank as u16;
+    }
+    ranks
+}
+
+// Decodes 8-bit sRGB image values (0..1) to linear light, with the exact piecewise curve.
+pub fn srgb_to_linear(c: Vec3) -> Vec3 {
+    let decode = |x: Float| {
+        if x <= 0.04045 {
+            x / 12.92
+        } else {
+            ((x + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    Vec3::new(decode(c.x), decode(c.y), decode(c.z))
+}
+
+// Colour of a black body at `kelvin` (Planck's law seen through the CIE observer of
+// wavelength_to_rgb) in the working space, scaled to unit luminance so that brightness can
+// be set separately.
+pub fn blackbody_color(kelvin: Float) -> Vec3 {
+    // c2 = h * c / k in nm * K
+    let c2 = 1.4388e7;
+    let mut xyz = Vec3::zero();
+    let mut wavelength: Float = 380.0;
+    while wavelength <= 780.0 {
+        let radiance = 1.0 / (wavelength.powi(5) * ((c2 / (wavelength * kelvin)).exp() - 1.0));
+        xyz += wavelength_to_xyz(wavelength) * radiance;
+        wavelength += 5.0;
+    }
+    // Y is the luminance
+    if xyz.y <= 0.0 {
+        return Vec3::zero();
+    }
+    xyz_to_working(xyz / xyz.y)
+}
+
+// Working space response of a single wavelength (nm). Out of gamut values are clipped to 0.
+pub fn wavelength_to_rgb(wavelength: Float) -> Vec3 {
+    xyz_to_working(wavelength_to_xyz(wavelength))
+}
+
+// CIE 1931 observer at a wavelength (nm), from the multi-lobe Gaussian fit by Wyman, Sloan
+// and Shirley (2013).
+fn wavelength_to_xyz(wavelength: Float) -> Vec3 {
+    let g = |mu: Float, sigma1: Float, sigma2: Float| {
+        let t = (wavelength - mu) / if wavelength < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    };
+    let x =
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
+
+// CIE XYZ to the working space, clipped to 0 out of its gamut.
+fn xyz_to_working(xyz: Vec3) -> Vec3 {
+    let c = from_rec709(transform(&XYZ_TO_REC709, xyz));
+    Vec3::new(c.x.max(0.0), c.y.max(0.0), c.z.max(0.0))
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+// Writes a self-contained HTML page showing `after` over `before` with a draggable divider,
+// for sharing before/after results. Both images are embedded, so the page can be mailed or
+// attached on its own.
+pub fn write_ab_slider(before: &Path, after: &Path, out: &Path) -> Result<(), String> {
+    let read = |path: &Path| std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e));
+    let before_data = base64(&read(before)?);
+    let after_data = base64(&read(after)?);
+    let name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{before_name} vs {after_name}</title>
+<style>
+  body {{ background: #222; color: #ddd; font-family: sans-serif; text-align: center; }}
+  #view {{ position: relative; display: inline-block; cursor: ew-resize; user-select: none; }}
+  #view img {{ display: block; image-rendering: pixelated; }}
+  #after {{ position: absolute; top: 0; left: 0; clip-path: inset(0 0 0 50%); }}
+  #bar {{ position: absolute; top: 0; bottom: 0; left: 50%; width: 2px; background: #fff; }}
+  .label {{ display: inline-block; width: 45%; }}
+</style>
+</head>
+<body>
+<p><span class="label">&larr; {before_name}</span><span class="label">{after_name} &rarr;</span></p>
+<div id="view">
+  <img id="before" src="data:image/png;base64,{before_data}">
+  <img id="after" src="data:image/png;base64,{after_data}">
+  <div id="bar"></div>
+</div>
+<script>
+  const view = document.getElementById("view");
+  const move = (e) => {{
+    const rect = view.getBoundingClientRect();
+    const x = Math.min(Math.max((e.clientX - rect.left) / rect.width, 0), 1) * 100;
+    document.getElementById("after").style.clipPath = "inset(0 0 0 " + x + "%)";
+    document.getElementById("bar").style.left = x + "%";
+  }};
+  view.addEventListener("mousemove", move);
+  view.addEventListener("touchmove", (e) => move(e.touches[0]));
+</script>
+</body>
+</html>
+"#,
+        before_name = name(before),
+        after_name = name(after),
+        before_data = before_data,
+        after_data = after_data,
+    );
+    std::fs::write(out, html).map_err(|e| format!("{}: {}", out.display(), e))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+
+use crate::color::{Dither, OutputTransform, WorkingSpace};
+use crate::denoise::Denoiser;
+use crate::texture::Placeholder;
+
+// User-wide defaults, read from ~/.config/raytracer.toml and then overridden by CLI flags.
+//
+// Only a flat subset of TOML is understood:
+//     threads = 16
+//     output_dir = "output"
+//     texture_dirs = ["texture", "/data/hdri"]
+//     bvh_cache = "/tmp/raytracer_bvh"
+//     dither = "ordered"
+//     working_space = "acescg"
+//     output_transform = "srgb"
+//     missing_texture = "magenta"
+//     denoiser = "atrous"
+//     preview = true
+#[derive(Clone)]
+pub struct Config {
+    pub threads: u32,
+    pub texture_dirs: Vec<String>,
+    pub output_dir: String,
+    // where built BVHs are kept for later renders, see bvh::set_cache_dir
+    pub bvh_cache: Option<String>,
+    // how images are rounded to 8 bits, see color::Dither
+    pub dither: Dither,
+    // see color::WorkingSpace and color::OutputTransform
+    pub working_space: WorkingSpace,
+    pub output_transform: OutputTransform,
+    // what image textures that can't be loaded show, see texture::Placeholder
+    pub missing_texture: Placeholder,
+    // filters the finished renders, on top of what scenes ask for; see denoise::Denoiser
+    pub denoiser: Option<Denoiser>,
+    // renders in camera::RenderMode::Preview unless `--render-mode` says otherwise
+    pub preview: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            threads: 16,
+            texture_dirs: vec!["texture".to_owned()],
+            output_dir: "output".to_owned(),
+            bvh_cache: None,
+            dither: Dither::BlueNoise,
+            working_space: WorkingSpace::Rec709,
+            output_transform: OutputTransform::Gamma2,
+            missing_texture: Placeholder::Checker,
+            denoiser: None,
+            preview: false,
+        }
+    }
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/raytracer.toml, falling back to ~/.config/raytracer.toml
+    pub fn user_config_path() -> Option<PathBuf> {
+        if let Ok(dir) = std::env::var("XDG_CONFIG_HOME") {
+            if !dir.is_empty() {
+                return Some(Path::new(&dir).join("raytracer.toml"));
+            }
+        }
+        std::env::var("HOME")
+            .ok()
+            .map(|home| Path::new(&home).join(".config").join("raytracer.toml"))
+    }
+
+    // Defaults, then the user config file (if any), then the command line.
+    pub fn load(args: &[String]) -> Self {
+        let mut config = Self::default();
+
+        // `--config <path>` replaces the user config file instead of layering on top of it
+        let path = match args.iter().position(|a| a == "--config") {
+            Some(i) => args.get(i + 1).map(PathBuf::from),
+            None => Self::user_config_path(),
+        };
+        if let Some(path) = path {
+            if path.exists() {
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => {
+                        if let Err(e) = config.merge_toml(&text) {
+                            println!("Ignoring config file {}: {}", path.display(), e);
+                        }
+                    }
+                    Err(e) => println!("Cannot read config file {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        config.merge_args(args);
+        config
+    }
+
+    // Applies the settings of a config file, all of them or, on an error, none.
+    pub fn merge_toml(&mut self, text: &str) -> Result<(), String> {
+        let mut merged = self.clone();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(format!("line {}: expected `key = value`", line_no + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+            let line_error = |e: String| format!("line {}: {}", line_no + 1, e);
+            match key {
+                "threads" => merged.threads = parse_threads(value).map_err(line_error)?,
+                "texture_dirs" => merged.texture_dirs = parse_string_array(value)?,
+                "output_dir" => merged.output_dir = parse_string(value)?,
+                "bvh_cache" => merged.bvh_cache = Some(parse_string(value)?),
+                "dither" => {
+                    merged.dither = Dither::named(&parse_string(value)?).map_err(line_error)?
+                }
+                "working_space" => {
+                    merged.working_space =
+                        WorkingSpace::named(&parse_string(value)?).map_err(line_error)?
+                }
+                "output_transform" => {
+                    merged.output_transform =
+                        OutputTransform::named(&parse_string(value)?).map_err(line_error)?
+                }
+                "missing_texture" => {
+                    merged.missing_texture =
+                        Placeholder::named(&parse_string(value)?).map_err(line_error)?
+                }
+                "denoiser" => {
+                    merged.denoiser = Denoiser::named(&parse_string(value)?).map_err(line_error)?
+                }
+                "preview" => merged.preview = parse_bool(value).map_err(line_error)?,
+                _ => println!("Unknown config key \"{}\" (line {})", key, line_no + 1),
+            }
+        }
+        *self = merged;
+        Ok(())
+    }
+
+    pub fn merge_args(&mut self, args: &[String]) {
+        let mut cli_texture_dirs = vec![];
+        let mut i = 0;
+        while i < args.len() {
+            let value = args.get(i + 1);
+            match (args[i].as_str(), value) {
+                ("--threads", Some(v)) => self.threads = or_exit(parse_threads(v)),
+                ("--output-dir", Some(v)) => self.output_dir = v.clone(),
+                ("--texture-dir", Some(v)) => cli_texture_dirs.push(v.clone()),
+                ("--bvh-cache", Some(v)) => self.bvh_cache = Some(v.clone()),
+                ("--dither", Some(v)) => self.dither = or_exit(Dither::named(v)),
+                ("--working-space", Some(v)) => {
+                    self.working_space = or_exit(WorkingSpace::named(v))
+                }
+                ("--output-transform", Some(v)) => {
+                    self.output_transform = or_exit(OutputTransform::named(v))
+                }
+                ("--missing-texture", Some(v)) => {
+                    self.missing_texture = or_exit(Placeholder::named(v))
+                }
+                ("--denoiser", Some(v)) => self.denoiser = or_exit(Denoiser::named(v)),
+                ("--denoise", _) => {
+                    self.denoiser = Some(Denoiser::default());
+                    i += 1;
+                    continue;
+                }
+                ("--preview", Some(v)) => self.preview = or_exit(parse_switch(v)),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            }
+            i += 2;
+        }
+        // directories given on the command line are searched first
+        if !cli_texture_dirs.is_empty() {
+            cli_texture_dirs.append(&mut self.texture_dirs);
+            self.texture_dirs = cli_texture_dirs;
+        }
+    }
+
+    pub fn output_path(&self, file_name: &str) -> PathBuf {
+        Path::new(&self.output_dir).join(file_name)
+    }
+
+    // Creates the output directory if needed and checks that files can be written there, so
+    // that a bad path fails before a long render rather than after it.
+    pub fn prepare_output_dir(&self) -> Result<(), String> {
+        let dir = Path::new(&self.output_dir);
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let probe = dir.join(".write_test");
+        std::fs::write(&probe, b"")
+            .map_err(|e| format!("{} is not writable: {}", dir.display(), e))?;
+        let _ = std::fs::remove_file(&probe);
+        Ok(())
+    }
+}
+
+// The command line flags merge_args reads and whether they take a value, for the subcommands
+// that check their own arguments.
+pub const FLAGS: [(&str, bool); 12] = [
+    ("--config", true),
+    ("--threads", true),
+    ("--output-dir", true),
+    ("--texture-dir", true),
+    ("--bvh-cache", true),
+    ("--dither", true),
+    ("--working-space", true),
+    ("--output-transform", true),
+    ("--missing-texture", true),
+    ("--denoiser", true),
+    ("--denoise", false),
+    ("--preview", true),
+];
+
+// The value of a setting given on the command line, or the end with its error.
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+fn parse_threads(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(threads) if threads > 0 => Ok(threads),
+        _ => Err(format!(
+            "the thread count must be a positive integer, got {}",
+            value
+        )),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("expected true or false, got {}", value)),
+    }
+}
+
+// `on` or `off` on the command line
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, got {}", value)),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    // a '#' inside a quoted string is not a comment
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_string(value: &str) -> Result<String, String> {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .map(|v| v.to_owned())
+        .ok_or(format!("expected a quoted string, got {}", value))
+}
+
+fn parse_string_array(value: &str) -> Result<Vec<String>, String> {
+    let inner = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .ok_or(format!("expected an array, got {}", value))?;
+    inner
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(parse_string)
+        .collect()
+}
@@ -0,0 +1,51 @@
+use image::{GenericImage, ImageBuffer, Rgb, RgbImage};
+
+use crate::font::{draw_text, text_width, GLYPH_HEIGHT};
+
+const GAP: u32 = 4;
+const LABEL_HEIGHT: u32 = GLYPH_HEIGHT + 4;
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const TEXT: Rgb<u8> = Rgb([230, 230, 230]);
+
+// Lays `cells` out row by row, `columns` per row, with each label centered under its image.
+// Cells may differ in size; every slot is as large as the largest one.
+pub fn contact_sheet(cells: &[(RgbImage, String)], columns: usize) -> RgbImage {
+    let columns = columns.max(1);
+    let rows = cells.len().div_ceil(columns);
+    let cell_width = cells
+        .iter()
+        .map(|(img, label)| img.width().max(text_width(label)))
+        .max()
+        .unwrap_or(0);
+    let cell_height = cells.iter().map(|(img, _)| img.height()).max().unwrap_or(0) + LABEL_HEIGHT;
+
+    let mut sheet: RgbImage = ImageBuffer::from_pixel(
+        GAP + columns as u32 * (cell_width + GAP),
+        GAP + rows as u32 * (cell_height + GAP),
+        BACKGROUND,
+    );
+    for (n, (img, label)) in cells.iter().enumerate() {
+        let x = GAP + (n % columns) as u32 * (cell_width + GAP);
+        let y = GAP + (n / columns) as u32 * (cell_height + GAP);
+        sheet
+            .copy_from(img, x + (cell_width - img.width()) / 2, y)
+            .unwrap();
+        draw_text(
+            &mut sheet,
+            x + (cell_width - text_width(label)) / 2,
+            y + img.height() + 2,
+            label,
+            TEXT,
+        );
+    }
+    sheet
+}
+
+// The value of the flag at `args[i]` of a sheet subcommand (--size, --spp), which has to be a
+// positive integer.
+pub fn positive_arg(args: &[String], i: usize) -> Result<u32, String> {
+    args.get(i + 1)
+        .and_then(|v| v.parse().ok())
+        .filter(|&v| v > 0)
+        .ok_or(format!("{} expects a positive integer", args[i]))
+}
@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use crate::camera::Camera;
+use crate::hittable::HittableList;
+use crate::material::Material;
+use crate::material_registry;
+use crate::scene_builder::SceneBuilder;
+use crate::vec3::{Float, Vec3};
+
+// The Cornell box, 555 units on each side and open towards the camera, with whatever
+// should be in it:
+//
+//     CornellBox::new()
+//         .light(330.0, 305.0, Vec3::new(7.0, 7.0, 7.0))
+//         .empty()
+//         .tall_block(Fill::Material(metal(Vec3::ones(), 0.0)))
+//         .sphere(Vec3::new(190.0, 90.0, 190.0), 90.0, Fill::Material(dielectric(1.5)))
+//         .build()
+//
+// new() is the classic box: green on the left, red on the right, white elsewhere, a small
+// light in the middle of the ceiling and a tall and a short white block. Each setting
+// replaces the classic one; `empty` clears the contents for `block` and `sphere` to fill.
+#[derive(Clone)]
+pub struct CornellBox {
+    pub left: Arc<dyn Material>,
+    pub right: Arc<dyn Material>,
+    // the floor, the ceiling and the back wall
+    pub white: Arc<dyn Material>,
+    // width (along x) and depth (along z) of the light, centered on the ceiling
+    pub light_size: (Float, Float),
+    pub light: Vec3,
+    pub contents: Vec<Content>,
+}
+
+// What something in the box is made of: a surface or a constant medium of some density and
+// albedo (smoke).
+#[derive(Clone)]
+pub enum Fill {
+    Material(Arc<dyn Material>),
+    Smoke(Float, Vec3),
+}
+
+#[derive(Clone)]
+pub enum Content {
+    // a box of `size` with a corner at the origin, turned `rotate_y` degrees about it, then
+    // moved to `position`
+    Block {
+        size: Vec3,
+        rotate_y: Float,
+        position: Vec3,
+        fill: Fill,
+    },
+    Sphere {
+        center: Vec3,
+        radius: Float,
+        fill: Fill,
+    },
+}
+
+const SIZE: Float = 555.0;
+
+impl CornellBox {
+    pub fn new() -> Self {
+        let standard = material_registry::standard();
+        let white = standard.material("white").unwrap();
+        Self {
+            left: standard.material("green").unwrap(),
+            right: standard.material("red").unwrap(),
+            white: white.clone(),
+            light_size: (130.0, 105.0),
+            light: Vec3::new(15.0, 15.0, 15.0),
+            contents: vec![],
+        }
+        .tall_block(Fill::Material(white.clone()))
+        .short_block(Fill::Material(white))
+    }
+
+    pub fn walls(mut self, left: Arc<dyn Material>, right: Arc<dyn Material>) -> Self {
+        self.left = left;
+        self.right = right;
+        self
+    }
+
+    pub fn white(mut self, white: Arc<dyn Material>) -> Self {
+        self.white = white;
+        self
+    }
+
+    pub fn light(mut self, width: Float, depth: Float, color: Vec3) -> Self {
+        self.light_size = (width, depth);
+        self.light = color;
+        self
+    }
+
+    pub fn empty(mut self) -> Self {
+        self.contents.clear();
+        self
+    }
+
+    pub fn block(mut self, size: Vec3, rotate_y: Float, position: Vec3, fill: Fill) -> Self {
+        self.contents.push(Content::Block {
+            size,
+            rotate_y,
+            position,
+            fill,
+        });
+        self
+    }
+
+    pub fn sphere(mut self, center: Vec3, radius: Float, fill: Fill) -> Self {
+        self.contents.push(Content::Sphere {
+            center,
+            radius,
+            fill,
+        });
+        self
+    }
+
+    // The classic tall block at the back right, made of `fill`, in place of the one there.
+    pub fn tall_block(self, fill: Fill) -> Self {
+        let size = Vec3::new(165.0, 330.0, 165.0);
+        self.replace_block(size, 15.0, Vec3::new(265.0, 0.0, 295.0), fill)
+    }
+
+    // The classic short block at the front left, made of `fill`, in place of the one there.
+    pub fn short_block(self, fill: Fill) -> Self {
+        let size = Vec3::new(165.0, 165.0, 165.0);
+        self.replace_block(size, -18.0, Vec3::new(130.0, 0.0, 65.0), fill)
+    }
+
+    fn replace_block(mut self, size: Vec3, rotate_y: Float, position: Vec3, fill: Fill) -> Self {
+        let same_place = |content: &Content| match content {
+            Content::Block { position: p, .. } => *p == position,
+            Content::Sphere { .. } => false,
+        };
+        match self.contents.iter().position(same_place) {
+            Some(i) => {
+                self.contents[i] = Content::Block {
+                    size,
+                    rotate_y,
+                    position,
+                    fill,
+                }
+            }
+            None => self = self.block(size, rotate_y, position, fill),
+        }
+        self
+    }
+
+    // The box and its contents with the usual camera in front of it.
+    pub fn build(self) -> (Camera, HittableList) {
+        let (width, depth) = self.light_size;
+        let center = Vec3::new(SIZE / 2.0 + 0.5, SIZE - 1.0, SIZE / 2.0 + 2.0);
+        let mut builder = SceneBuilder::new()
+            .quad(
+                Vec3::new(SIZE, 0.0, 0.0),
+                Vec3::new(0.0, SIZE, 0.0),
+                Vec3::new(0.0, 0.0, SIZE),
+            )
+            .material(self.left)
+            .quad(
+                Vec3::zero(),
+                Vec3::new(0.0, SIZE, 0.0),
+                Vec3::new(0.0, 0.0, SIZE),
+            )
+            .material(self.right)
+            // facing down, just below the ceiling
+            .quad(
+                center + Vec3::new(width / 2.0, 0.0, depth / 2.0),
+                Vec3::new(-width, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -depth),
+            )
+            .light(self.light)
+            .quad(
+                Vec3::zero(),
+                Vec3::new(SIZE, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, SIZE),
+            )
+            .material(self.white.clone())
+            .quad(
+                Vec3::new(SIZE, SIZE, SIZE),
+                Vec3::new(-SIZE, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -SIZE),
+            )
+            .material(self.white.clone())
+            .quad(
+                Vec3::new(0.0, 0.0, SIZE),
+                Vec3::new(SIZE, 0.0, 0.0),
+                Vec3::new(0.0, SIZE, 0.0),
+            )
+            .material(self.white);
+        for content in self.contents {
+            let fill = match content {
+                Content::Block {
+                    size,
+                    rotate_y,
+                    position,
+                    fill,
+                } => {
+                    builder = builder
+                        .box_(Vec3::zero(), size)
+                        .rotate_y(rotate_y)
+                        .translate(position);
+                    fill
+                }
+                Content::Sphere {
+                    center,
+                    radius,
+                    fill,
+                } => {
+                    builder = builder.sphere(center, radius);
+                    fill
+                }
+            };
+            builder = match fill {
+                Fill::Material(mat) => builder.material(mat),
+                Fill::Smoke(density, albedo) => builder.smoke(density, albedo),
+            };
+        }
+        builder
+            .camera(|cam| {
+                cam.aspect_ratio = 1.0;
+                cam.image_width = 600;
+                cam.sample_per_pixel = 200;
+                cam.max_depth = 50;
+                cam.background = Vec3::zero();
+
+                cam.vfov = 40.0;
+                cam.lookfrom = Vec3::new(278.0, 278.0, -800.0);
+                cam.lookat = Vec3::new(278.0, 278.0, 0.0);
+                cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+                cam.defocus_angle = 0.0;
+            })
+            .build()
+    }
+}
+
+impl Default for CornellBox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,162 @@
+use crate::vec3::{Float, Vec3};
+
+// What the camera sees first through each pixel, averaged over a few rays: the shading
+// normal, the unlit colour of the material (Material::albedo) and the distance from the
+// camera, infinite where most of the rays escape. The denoiser keeps the edges between them.
+pub struct GuideBuffers {
+    pub width: u32,
+    pub height: u32,
+    // per pixel, row by row
+    pub normal: Vec<Vec3>,
+    pub albedo: Vec<Vec3>,
+    pub depth: Vec<Float>,
+}
+
+impl GuideBuffers {
+    pub fn new(width: u32, height: u32) -> Self {
+        let count = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            normal: vec![Vec3::zero(); count],
+            albedo: vec![Vec3::zero(); count],
+            depth: vec![Float::INFINITY; count],
+        }
+    }
+}
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010), guided by GuideBuffers. Lower
+// sigmas and a higher power keep more detail and more noise.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    pub color_sigma: Float,
+    pub normal_power: Float,
+    pub albedo_sigma: Float,
+    pub depth_sigma: Float,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            color_sigma: 2.0,
+            normal_power: 64.0,
+            albedo_sigma: 0.1,
+            depth_sigma: 0.05,
+        }
+    }
+}
+
+// the B3 spline, 1 4 6 4 1 over 16, at offsets 0, 1 and 2
+const KERNEL: [Float; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// albedos darker than this in every channel are left in, there is no lighting to tell apart
+const MIN_ALBEDO: Float = 0.01;
+
+impl Denoiser {
+    // "atrous" for the default filter, "none" for no filtering.
+    pub fn named(name: &str) -> Result<Option<Self>, String> {
+        match name {
+            "atrous" => Ok(Some(Self::default())),
+            "none" => Ok(None),
+            _ => Err(format!("unknown denoiser \"{}\" (atrous, none)", name)),
+        }
+    }
+
+    // The image of linear `colors` (row by row, as the guides) with the noise filtered out.
+    pub fn denoise(&self, colors: &[Vec3], guides: &GuideBuffers) -> Vec<Vec3> {
+        let (width, height) = (guides.width as i64, guides.height as i64);
+        let demodulate = |albedo: Vec3| albedo.x.max(albedo.y).max(albedo.z) >= MIN_ALBEDO;
+        let divide = |c: Float, a: Float| if a > 0.0 { c / a } else { 0.0 };
+        let mut image: Vec<Vec3> = colors
+            .iter()
+            .zip(&guides.albedo)
+            .map(|(&c, &a)| match demodulate(a) {
+                true => Vec3::new(divide(c.x, a.x), divide(c.y, a.y), divide(c.z, a.z)),
+                false => c,
+            })
+            .collect();
+
+        let mut color_sigma = self.color_sigma;
+        for pass in 0..self.iterations {
+            let step = 1i64 << pass;
+            let mut next = vec![Vec3::zero(); image.len()];
+            for y in 0..height {
+                for x in 0..width {
+                    let p = (y * width + x) as usize;
+                    let mut sum = Vec3::zero();
+                    let mut weights = 0.0;
+                    for dy in -2i64..=2 {
+                        for dx in -2i64..=2 {
+                            let (qx, qy) = (x + dx * step, y + dy * step);
+                            if qx < 0 || qx >= width || qy < 0 || qy >= height {
+                                continue;
+                            }
+                            let q = (qy * width + qx) as usize;
+                            let weight = KERNEL[dx.unsigned_abs() as usize]
+                                * KERNEL[dy.unsigned_abs() as usize]
+                                * self.similarity(&image, guides, p, q, color_sigma);
+                            sum += image[q] * weight;
+                            weights += weight;
+                        }
+                    }
+                    // the center pixel always counts, weights is never 0
+                    next[p] = sum / weights;
+                }
+            }
+            image = next;
+            color_sigma *= 0.5;
+        }
+
+        image
+            .iter()
+            .zip(&guides.albedo)
+            .map(|(&c, &a)| match demodulate(a) {
+                true => c.component_mul(a),
+                false => c,
+            })
+            .collect()
+    }
+
+    // How much pixel q counts towards pixel p, from 0 to 1.
+    fn similarity(
+        &self,
+        image: &[Vec3],
+        guides: &GuideBuffers,
+        p: usize,
+        q: usize,
+        color_sigma: Float,
+    ) -> Float {
+        let (depth_p, depth_q) = (guides.depth[p], guides.depth[q]);
+        // the sky only mixes with the sky
+        if depth_p.is_infinite() || depth_q.is_infinite() {
+            if depth_p.is_infinite() != depth_q.is_infinite() {
+                return 0.0;
+            }
+        } else {
+            let depth = (depth_p - depth_q).abs() / (self.depth_sigma * depth_p.max(1e-6));
+            let normal = (guides.normal[p] * guides.normal[q]).max(0.0);
+            let albedo = (guides.albedo[p] - guides.albedo[q]).squared_length();
+            let weight = (-depth).exp()
+                * normal.powf(self.normal_power)
+                * (-albedo / (self.albedo_sigma * self.albedo_sigma)).exp();
+            if weight == 0.0 {
+                return 0.0;
+            }
+            return weight * color_weight(image[p], image[q], color_sigma);
+        }
+        color_weight(image[p], image[q], color_sigma)
+    }
+}
+
+// Likeness of two colours, compared with their values squashed into 0..1 so that bright
+// fireflies don't stand out of every comparison.
+fn color_weight(a: Vec3, b: Vec3, sigma: Float) -> Float {
+    let squash = |c: Vec3| {
+        let f = |x: Float| x.max(0.0) / (1.0 + x.max(0.0));
+        Vec3::new(f(c.x), f(c.y), f(c.z))
+    };
+    let distance = (squash(a) - squash(b)).squared_length();
+    (-distance / (sigma * sigma)).exp()
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{area_pdf, HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::material::{cut_out, Material};
+use crate::normal_cone::NormalCone;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::stats::{self, Counter};
+use crate::util::random_in_unit_disk;
+use crate::vec3::consts::PI;
+use crate::vec3::{Float, Vec3};
+
+// Flat round shape, e.g. for round softboxes and ring lights. The texture coordinates map
+// the square around the disk to [0, 1]^2.
+#[derive(Clone)]
+pub struct Disk {
+    center: Vec3,
+    // radius vectors spanning the plane
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    mat: Arc<dyn Material>,
+    bounding_box: AABB,
+    normal: Vec3,
+    d: Float,
+}
+
+impl Disk {
+    // The front of the disk faces along `normal`.
+    pub fn new(center: Vec3, normal: Vec3, radius: Float, mat: Arc<dyn Material>) -> Self {
+        let normal = normal.unit();
+        let helper = if normal.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let u = normal.cross(helper).unit() * radius;
+        let v = normal.cross(u);
+        let n = u.cross(v);
+
+        let extent = Vec3::new(
+            radius * (1.0 - normal.x * normal.x).max(0.0).sqrt(),
+            radius * (1.0 - normal.y * normal.y).max(0.0).sqrt(),
+            radius * (1.0 - normal.z * normal.z).max(0.0).sqrt(),
+        );
+        Self {
+            center,
+            u,
+            v,
+            w: n / (n * n),
+            mat,
+            bounding_box: AABB::new_two_points(center - extent, center + extent),
+            normal,
+            d: normal * center,
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        stats::count(Counter::DiskTest, 1);
+        let denom = r.b_direction * self.normal;
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+        let t = (self.d - r.a_origin * self.normal) / denom;
+        if !ray_t.contains(t) {
+            return false;
+        }
+
+        // plane coordinates in units of the radius
+        let intersection = r.at(t);
+        let planar = intersection - self.center;
+        let alpha = self.w * planar.cross(self.v);
+        let beta = self.w * self.u.cross(planar);
+        if alpha * alpha + beta * beta > 1.0 {
+            return false;
+        }
+        let (u, v) = (0.5 * (alpha + 1.0), 0.5 * (beta + 1.0));
+        if cut_out(self.mat.as_ref(), u, v, intersection) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = u;
+        rec.v = v;
+        rec.mat = &*self.mat;
+        rec.set_face_normal(r, &self.normal);
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+
+    fn normal_bounds(&self) -> NormalCone {
+        if self.mat.is_one_sided() {
+            NormalCone::new(self.normal, 0.0)
+        } else {
+            NormalCone::everywhere()
+        }
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        out.disk(self.center, self.normal, self.u.length(), &self.mat)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        if self.mat.is_emissive() {
+            lights.add(Arc::new(self.clone()));
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        surfaces.add(Arc::new(self.clone()));
+    }
+
+    // None in the corners of the square, off the disk
+    fn surface_at(&self, u: Float, v: Float) -> Option<(Vec3, Vec3)> {
+        let (alpha, beta) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if alpha * alpha + beta * beta > 1.0 {
+            return None;
+        }
+        Some((self.center + self.u * alpha + self.v * beta, self.normal))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        area_pdf(self, origin, direction, PI * self.u.squared_length())
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        let p = random_in_unit_disk();
+        self.center + self.u * p.x + self.v * p.y - origin
+    }
+}
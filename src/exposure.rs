@@ -0,0 +1,45 @@
+use crate::vec3::Float;
+
+// The exposure controls of a real camera. With them set, emitters are given in physical units,
+// luminance in cd/m^2 (nits): a sunlit scene wants about ISO 100, 1/125 s at f/16, a lit room
+// ISO 800, 1/60 s at f/2.8. The f-number also sets the defocus blur, through the focal length
+// a full frame (36 x 24 mm) camera needs for the vertical field of view.
+#[derive(Clone, Copy)]
+pub struct PhysicalCamera {
+    pub iso: Float,
+    // seconds
+    pub shutter_speed: Float,
+    pub f_number: Float,
+    // scene units per meter, for the size of the aperture
+    pub units_per_meter: Float,
+}
+
+impl PhysicalCamera {
+    pub fn new(iso: Float, shutter_speed: Float, f_number: Float) -> Self {
+        Self {
+            iso,
+            shutter_speed,
+            f_number,
+            units_per_meter: 1.0,
+        }
+    }
+
+    // Exposure value at ISO 100; one more stop is half the light.
+    pub fn ev100(&self) -> Float {
+        (self.f_number * self.f_number / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    // Multiplier from scene luminance to pixel values, 1 being where the sensor saturates
+    // (the saturation based sensitivity of ISO 12232, with lens losses: 78 / 0.65 = 1.2).
+    pub fn exposure_scale(&self) -> Float {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+
+    // Camera::defocus_angle for an aperture of focal length / f-number.
+    pub fn defocus_angle(&self, vfov: Float, focus_dist: Float) -> Float {
+        // half the sensor height over the tangent of half the field of view, in meters
+        let focal_length = 0.012 / (0.5 * vfov).to_radians().tan();
+        let radius = 0.5 * focal_length / self.f_number * self.units_per_meter;
+        2.0 * (radius / focus_dist).atan().to_degrees()
+    }
+}
@@ -0,0 +1,230 @@
+use crate::color::write_color;
+use crate::vec3::consts::PI;
+use crate::vec3::{Float, Vec3};
+use image::RgbImage;
+
+// How a sample is shared among the pixels within the filter's radius (in pixels). Box with a
+// radius of half a pixel is the plain mean of the samples inside each pixel.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box(Float),
+    Tent(Float),
+    Gaussian(Float),
+    Mitchell(Float),
+    BlackmanHarris(Float),
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box(0.5)
+    }
+}
+
+// falloff of the Gaussian, exp(-alpha * d^2) with d in pixels
+const GAUSSIAN_ALPHA: Float = 2.0;
+
+impl Filter {
+    // "<name>" with its usual radius or "<name>:<radius>", e.g. "gaussian:2"
+    pub fn named(name: &str) -> Result<Self, String> {
+        let (name, radius) = match name.split_once(':') {
+            Some((name, radius)) => (name, Some(radius)),
+            None => (name, None),
+        };
+        let filter: fn(Float) -> Filter = match name {
+            "box" => Filter::Box,
+            "tent" => Filter::Tent,
+            "gaussian" => Filter::Gaussian,
+            "mitchell" => Filter::Mitchell,
+            "blackman-harris" => Filter::BlackmanHarris,
+            _ => {
+                return Err(format!(
+                    "unknown filter \"{}\" (box, tent, gaussian, mitchell, blackman-harris)",
+                    name
+                ))
+            }
+        };
+        let radius = match radius {
+            None => filter(0.0).default_radius(),
+            Some(radius) => match radius.parse::<Float>() {
+                // any less and some pixels may get no samples
+                Ok(radius) if radius >= 0.5 && radius.is_finite() => radius,
+                _ => return Err(format!("filter radius \"{}\" is not 0.5 or more", radius)),
+            },
+        };
+        Ok(filter(radius))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Box(_) => "box",
+            Filter::Tent(_) => "tent",
+            Filter::Gaussian(_) => "gaussian",
+            Filter::Mitchell(_) => "mitchell",
+            Filter::BlackmanHarris(_) => "blackman-harris",
+        }
+    }
+
+    fn default_radius(&self) -> Float {
+        match self {
+            Filter::Box(_) => 0.5,
+            Filter::Tent(_) => 1.0,
+            Filter::Gaussian(_) => 1.5,
+            Filter::Mitchell(_) | Filter::BlackmanHarris(_) => 2.0,
+        }
+    }
+
+    pub fn radius(&self) -> Float {
+        match *self {
+            Filter::Box(r)
+            | Filter::Tent(r)
+            | Filter::Gaussian(r)
+            | Filter::Mitchell(r)
+            | Filter::BlackmanHarris(r) => r,
+        }
+    }
+
+    // Weight of a sample at offset (dx, dy) from a pixel center.
+    pub fn weight(&self, dx: Float, dy: Float) -> Float {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, d: Float) -> Float {
+        let r = self.radius();
+        let d = d.abs();
+        if d > r {
+            return 0.0;
+        }
+        match self {
+            Filter::Box(_) => 1.0,
+            Filter::Tent(_) => 1.0 - d / r,
+            Filter::Gaussian(_) => {
+                ((-GAUSSIAN_ALPHA * d * d).exp() - (-GAUSSIAN_ALPHA * r * r).exp()).max(0.0)
+            }
+            Filter::Mitchell(_) => mitchell(2.0 * d / r),
+            Filter::BlackmanHarris(_) => {
+                let t = 2.0 * PI * (0.5 + 0.5 * d / r);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+}
+
+// Mitchell and Netravali's cubic with B = C = 1/3, over 0..2.
+fn mitchell(x: Float) -> Float {
+    let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+    let x2 = x * x;
+    let x3 = x2 * x;
+    if x > 1.0 {
+        ((-b - 6.0 * c) * x3
+            + (6.0 * b + 30.0 * c) * x2
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        ((12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b))
+            / 6.0
+    }
+}
+
+// Filtered samples over the pixels x0..x1 by y0..y1 of an image: per pixel the sum of the
+// samples times their weights, and the sum of the weights. A tile renders into a film of its
+// own, as wide around it as its samples reach, which is then added to the image's.
+pub struct Film {
+    xs: (u32, u32),
+    ys: (u32, u32),
+    pixels: Vec<(Vec3, Float)>,
+}
+
+impl Film {
+    pub fn new(xs: (u32, u32), ys: (u32, u32)) -> Self {
+        let count = (xs.1 - xs.0) as usize * (ys.1 - ys.0) as usize;
+        Self {
+            xs,
+            ys,
+            pixels: vec![(Vec3::zero(), 0.0); count],
+        }
+    }
+
+    // The film for the samples of the pixels `xs` by `ys`: those pixels and as many around
+    // them as `filter` reaches, within the image of `width` by `height`.
+    pub fn around(xs: (u32, u32), ys: (u32, u32), filter: Filter, width: u32, height: u32) -> Self {
+        let margin = (filter.radius() - 0.5).ceil().max(0.0) as u32;
+        Self::new(
+            (xs.0.saturating_sub(margin), (xs.1 + margin).min(width)),
+            (ys.0.saturating_sub(margin), (ys.1 + margin).min(height)),
+        )
+    }
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        (j - self.ys.0) as usize * (self.xs.1 - self.xs.0) as usize + (i - self.xs.0) as usize
+    }
+
+    // Adds `color`, sampled at image position (x, y) in pixels from the center of the top
+    // left pixel, to the pixels of the film that `filter` reaches from there.
+    pub fn splat(&mut self, filter: Filter, x: Float, y: Float, color: Vec3) {
+        let r = filter.radius();
+        let range = |p: Float, (min, max): (u32, u32)| {
+            let first = (p - r).ceil().max(min as Float) as u32;
+            let last = ((p + r).floor() + 1.0).clamp(min as Float, max as Float) as u32;
+            first..last
+        };
+        for j in range(y, self.ys) {
+            for i in range(x, self.xs) {
+                let weight = filter.weight(i as Float - x, j as Float - y);
+                if weight != 0.0 {
+                    let index = self.index(i, j);
+                    let (sum, weights) = &mut self.pixels[index];
+                    *sum += color * weight;
+                    *weights += weight;
+                }
+            }
+        }
+    }
+
+    // Adds `color` with `weight` to pixel (i, j) alone.
+    pub fn add(&mut self, i: u32, j: u32, color: Vec3, weight: Float) {
+        let index = self.index(i, j);
+        let (sum, weights) = &mut self.pixels[index];
+        *sum += color * weight;
+        *weights += weight;
+    }
+
+    // Adds the pixels of `other`, which must lie within this film.
+    pub fn merge(&mut self, other: &Film) {
+        for j in other.ys.0..other.ys.1 {
+            for i in other.xs.0..other.xs.1 {
+                let (sum, weights) = other.pixels[other.index(i, j)];
+                let index = self.index(i, j);
+                self.pixels[index].0 += sum;
+                self.pixels[index].1 += weights;
+            }
+        }
+    }
+
+    // The weighted mean of the samples of pixel (i, j), black without any.
+    pub fn color(&self, i: u32, j: u32) -> Vec3 {
+        let (sum, weights) = self.pixels[self.index(i, j)];
+        if weights == 0.0 {
+            Vec3::zero()
+        } else {
+            sum / weights
+        }
+    }
+
+    // The colours of all pixels of the film, row by row.
+    pub fn colors(&self) -> Vec<Vec3> {
+        (self.ys.0..self.ys.1)
+            .flat_map(|j| (self.xs.0..self.xs.1).map(move |i| (i, j)))
+            .map(|(i, j)| self.color(i, j))
+            .collect()
+    }
+
+    // Writes the film to the same pixels of `img`.
+    pub fn write(&self, img: &mut RgbImage) {
+        for j in self.ys.0..self.ys.1 {
+            for i in self.xs.0..self.xs.1 {
+                write_color(self.color(i, j), img, i as usize, j as usize);
+            }
+        }
+    }
+}
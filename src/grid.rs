@@ -0,0 +1,334 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable, HittableList},
+    interval::Interval,
+    normal_cone::NormalCone,
+    ray::Ray,
+    scene_export::SceneWriter,
+    stats,
+    vec3::Float,
+};
+
+// Uniform grid over a list of objects, an alternative to BVHNode (see Accelerator) for objects
+// of similar size spread evenly; much larger objects are kept apart and tested on every ray.
+pub struct Grid {
+    objects: Vec<Arc<dyn Hittable>>,
+    // by index into `objects`
+    large: Vec<u32>,
+    // the box the cells divide, around the objects that aren't large
+    grid_box: AABB,
+    resolution: [usize; 3],
+    // the objects of cell i are cell_objects[cell_start[i]..cell_start[i + 1]]; cells go along
+    // x first, then y, then z
+    cell_start: Vec<u32>,
+    cell_objects: Vec<u32>,
+    bounding_box: AABB,
+    normal_cone: NormalCone,
+}
+
+// cells along the longest side per cube root of the number of objects
+const DENSITY: Float = 3.0;
+const MAX_RESOLUTION: usize = 128;
+// an object is large if its box is wider than this part of the box around everything on
+// some axis
+const LARGE: Float = 0.5;
+
+impl Grid {
+    pub fn new(list: HittableList) -> Self {
+        let start = std::time::Instant::now();
+        let objects = list.objects;
+        let boxes: Vec<AABB> = objects.iter().map(|o| o.bounding_box()).collect();
+        let mut bounding_box = AABB::EMPTY;
+        for bbox in &boxes {
+            bounding_box = bounding_box.union(*bbox);
+        }
+        let normal_cone = match objects.split_first() {
+            Some((first, rest)) => rest.iter().fold(first.normal_bounds(), |cone, o| {
+                cone.union(o.normal_bounds())
+            }),
+            None => NormalCone::everywhere(),
+        };
+
+        let is_large = |bbox: &AABB| {
+            (0..3).any(|a| {
+                let size = bbox.axis_interval(a).size();
+                !size.is_finite() || size > LARGE * bounding_box.axis_interval(a).size()
+            })
+        };
+        let (mut large, mut small) = (vec![], vec![]);
+        let mut grid_box = AABB::EMPTY;
+        for (i, bbox) in boxes.iter().enumerate() {
+            if objects.len() > 1 && is_large(bbox) {
+                large.push(i as u32);
+            } else {
+                small.push(i as u32);
+                grid_box = grid_box.union(*bbox);
+            }
+        }
+
+        let size = [grid_box.x.size(), grid_box.y.size(), grid_box.z.size()];
+        let longest = size[0].max(size[1]).max(size[2]);
+        let per_unit = DENSITY * (small.len() as Float).cbrt() / longest;
+        let resolution = size.map(|s| {
+            let cells = (s * per_unit).round();
+            if cells.is_finite() {
+                (cells as usize).clamp(1, MAX_RESOLUTION)
+            } else {
+                1
+            }
+        });
+
+        let mut grid = Self {
+            objects,
+            large,
+            grid_box,
+            resolution,
+            cell_start: vec![],
+            cell_objects: vec![],
+            bounding_box,
+            normal_cone,
+        };
+        if !small.is_empty() {
+            grid.fill(&boxes, &small);
+        }
+        stats::add_bvh_build_time(start.elapsed());
+        grid
+    }
+
+    // Lists `objects` in the cells their boxes overlap: counts them per cell first, so that
+    // cell_objects is allocated once.
+    fn fill(&mut self, boxes: &[AABB], objects: &[u32]) {
+        let [nx, ny, nz] = self.resolution;
+        let mut counts = vec![0u32; nx * ny * nz + 1];
+        for &o in objects {
+            self.for_cells(boxes[o as usize], |cell| counts[cell] += 1);
+        }
+        let mut total = 0;
+        for count in counts.iter_mut() {
+            (*count, total) = (total, total + *count);
+        }
+        let mut next = counts.clone();
+        let mut cell_objects = vec![0; total as usize];
+        for &o in objects {
+            self.for_cells(boxes[o as usize], |cell| {
+                cell_objects[next[cell] as usize] = o;
+                next[cell] += 1;
+            });
+        }
+        self.cell_start = counts;
+        self.cell_objects = cell_objects;
+    }
+
+    // Calls `f` with the index of every cell that `bbox` overlaps.
+    fn for_cells(&self, bbox: AABB, mut f: impl FnMut(usize)) {
+        let range = |a: usize| {
+            let interval = bbox.axis_interval(a as i32);
+            (self.cell(a, interval.min), self.cell(a, interval.max))
+        };
+        let (x, y, z) = (range(0), range(1), range(2));
+        for k in z.0..=z.1 {
+            for j in y.0..=y.1 {
+                for i in x.0..=x.1 {
+                    f(self.index([i, j, k]));
+                }
+            }
+        }
+    }
+
+    // The cell along `axis` that position `p` on it is in, clamped to the grid.
+    fn cell(&self, axis: usize, p: Float) -> usize {
+        let interval = self.grid_box.axis_interval(axis as i32);
+        let n = self.resolution[axis];
+        let cell = ((p - interval.min) / interval.size() * n as Float).floor();
+        cell.clamp(0.0, (n - 1) as Float) as usize
+    }
+
+    fn index(&self, cell: [usize; 3]) -> usize {
+        cell[0] + self.resolution[0] * (cell[1] + self.resolution[1] * cell[2])
+    }
+
+    // The box of a cell.
+    fn cell_box(&self, cell: [usize; 3]) -> AABB {
+        let bounds = |a: usize| {
+            let interval = self.grid_box.axis_interval(a as i32);
+            let width = interval.size() / self.resolution[a] as Float;
+            let min = interval.min + cell[a] as Float * width;
+            Interval::with_bounds(min, min + width)
+        };
+        AABB {
+            x: bounds(0),
+            y: bounds(1),
+            z: bounds(2),
+        }
+    }
+
+    // Searches the cells along `r` in `ray_t`, nearest first.
+    fn hit_cells<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        if r.has_cone() {
+            return self.hit_cone_cells(r, ray_t, rec);
+        }
+        stats::count(stats::Counter::AabbTest, 1);
+        let Some(t) = self.grid_box.clip(r, ray_t) else {
+            return false;
+        };
+        let p = r.at(t.min);
+        let mut cell = [0; 3];
+        // per axis: where the ray crosses into the next cell, how far apart those crossings
+        // are, and which way it steps
+        let mut next_t = [Float::INFINITY; 3];
+        let mut delta_t = [Float::INFINITY; 3];
+        let mut step = [0isize; 3];
+        for a in 0..3 {
+            cell[a] = self.cell(a, p.lp(a as u8));
+            let direction = r.b_direction.lp(a as u8);
+            if direction == 0.0 {
+                continue;
+            }
+            let interval = self.grid_box.axis_interval(a as i32);
+            let width = interval.size() / self.resolution[a] as Float;
+            let (side, sign) = if direction > 0.0 {
+                (cell[a] + 1, 1)
+            } else {
+                (cell[a], -1)
+            };
+            let plane = interval.min + side as Float * width;
+            let inv = r.inv_direction.lp(a as u8);
+            next_t[a] = (plane - r.a_origin.lp(a as u8)) * inv;
+            delta_t[a] = width * inv.abs();
+            step[a] = sign;
+        }
+
+        let mut enter = t.min;
+        loop {
+            let a = if next_t[0] < next_t[1] {
+                if next_t[0] < next_t[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if next_t[1] < next_t[2] {
+                1
+            } else {
+                2
+            };
+            let exit = next_t[a].min(t.max);
+            // a little beyond the cell, for hits on its sides that rounding puts out
+            let slack = 1e-6 * (1.0 + exit.abs());
+            let mut cell_t = Interval::with_bounds(
+                (enter - slack).max(ray_t.min),
+                (exit + slack).min(ray_t.max),
+            );
+            let i = self.index(cell);
+            let (first, end) = (self.cell_start[i] as usize, self.cell_start[i + 1] as usize);
+            let mut hit_anything = false;
+            for &o in &self.cell_objects[first..end] {
+                if self.objects[o as usize].hit(r, cell_t, rec) {
+                    hit_anything = true;
+                    cell_t.max = rec.t;
+                }
+            }
+            if hit_anything {
+                return true;
+            }
+            if next_t[a] > t.max {
+                return false;
+            }
+            let next = cell[a] as isize + step[a];
+            if next < 0 || next >= self.resolution[a] as isize {
+                return false;
+            }
+            cell[a] = next as usize;
+            enter = next_t[a];
+            next_t[a] += delta_t[a];
+        }
+    }
+
+    // The search for a ray with a cone (see Ray::footprint), whose widened hits can be in cells
+    // the ray itself doesn't pass: every cell in reach of the cone, each over the part of the
+    // ray whose cone reaches into it, keeping the closest hit.
+    fn hit_cone_cells<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        stats::count(stats::Counter::AabbTest, 1);
+        let Some(t) = self.grid_box.clip(r, ray_t) else {
+            return false;
+        };
+        let reach = r.footprint(t.max);
+        let widen =
+            |interval: Interval| Interval::with_bounds(interval.min - reach, interval.max + reach);
+        let span = AABB::new_two_points(r.at(t.min), r.at(t.max));
+        let span = AABB::new(widen(span.x), widen(span.y), widen(span.z));
+
+        let [nx, ny, _] = self.resolution;
+        let mut closest = t.max;
+        let mut hit_anything = false;
+        self.for_cells(span, |i| {
+            let cell = [i % nx, i / nx % ny, i / (nx * ny)];
+            let reach = Interval::with_bounds(t.min, closest);
+            let Some(cell_t) = self.cell_box(cell).clip(r, reach) else {
+                return;
+            };
+            // a little beyond the cell, as in hit_cells
+            let slack = 1e-6 * (1.0 + cell_t.max.abs());
+            let mut cell_t = Interval::with_bounds(
+                (cell_t.min - slack).max(ray_t.min),
+                (cell_t.max + slack).min(closest),
+            );
+            let (first, end) = (self.cell_start[i] as usize, self.cell_start[i + 1] as usize);
+            for &o in &self.cell_objects[first..end] {
+                if self.objects[o as usize].hit(r, cell_t, rec) {
+                    hit_anything = true;
+                    closest = rec.t;
+                    cell_t.max = rec.t;
+                }
+            }
+        });
+        hit_anything
+    }
+}
+
+impl Hittable for Grid {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let mut closest = ray_t.max;
+        let mut hit_anything = false;
+        for &o in &self.large {
+            if self.objects[o as usize].hit(r, Interval::with_bounds(ray_t.min, closest), rec) {
+                hit_anything = true;
+                closest = rec.t;
+            }
+        }
+        if !self.cell_start.is_empty()
+            && self.hit_cells(r, Interval::with_bounds(ray_t.min, closest), rec)
+        {
+            hit_anything = true;
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+
+    fn normal_bounds(&self) -> NormalCone {
+        self.normal_cone
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        for object in &self.objects {
+            object.export(out)?;
+        }
+        Ok(())
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        for object in &self.objects {
+            object.collect_lights(lights);
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        for object in &self.objects {
+            object.collect_surfaces(surfaces);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use image::{Rgb, RgbImage};
+
+use crate::color::{output_transform, to_rec709};
+use crate::vec3::{Float, Vec3};
+
+// stops below the clipping point covered by the histogram, one bin each; darker pixels go
+// into the first bin
+const STOPS: usize = 12;
+// share of clipped pixels worth a warning
+const CLIPPED_WARNING: Float = 0.01;
+
+// How the rendered values sit against the range of the output, from the linear colours of
+// the image with the exposure applied: what share of the pixels clips to white (a channel of
+// 1 or more, in Rec.709), what share comes out black (under half a step of the 8-bit output
+// in every channel, after the output transform), and a histogram of luminance in stops below
+// the clipping point. The numbers to go by when turning a light from 7 to 15 or the exposure
+// up and down.
+pub struct ExposureReport {
+    pub width: u32,
+    pub height: u32,
+    // per pixel, row by row
+    luminance: Vec<Float>,
+    clipped: Vec<bool>,
+    black: Vec<bool>,
+}
+
+impl ExposureReport {
+    pub fn new(width: u32, height: u32, colors: &[Vec3]) -> Self {
+        let transform = output_transform();
+        let mut report = Self {
+            width,
+            height,
+            luminance: Vec::with_capacity(colors.len()),
+            clipped: Vec::with_capacity(colors.len()),
+            black: Vec::with_capacity(colors.len()),
+        };
+        for &color in colors {
+            let c = to_rec709(color);
+            report
+                .luminance
+                .push(0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z);
+            report.clipped.push(c.x.max(c.y).max(c.z) >= 1.0);
+            let encoded = transform.encode(c);
+            report
+                .black
+                .push(encoded.x.max(encoded.y).max(encoded.z) < 0.5 / 256.0);
+        }
+        report
+    }
+
+    pub fn clipped_fraction(&self) -> Float {
+        fraction(&self.clipped)
+    }
+
+    pub fn black_fraction(&self) -> Float {
+        fraction(&self.black)
+    }
+
+    // The luminance below which `p` (0 to 1) of the pixels are.
+    pub fn percentile(&self, p: Float) -> Float {
+        if self.luminance.is_empty() {
+            return 0.0;
+        }
+        let mut sorted = self.luminance.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let index = ((sorted.len() - 1) as Float * p.clamp(0.0, 1.0)).round() as usize;
+        sorted[index]
+    }
+
+    // Pixel counts per stop below clipping, darkest first: bin k holds luminances from
+    // 2^(k - STOPS) to 2^(k + 1 - STOPS), the first everything darker and the last everything
+    // brighter.
+    pub fn histogram(&self) -> Vec<u64> {
+        let mut bins = vec![0; STOPS + 1];
+        for &l in &self.luminance {
+            let stop = if l > 0.0 {
+                l.log2().floor()
+            } else {
+                -(STOPS as Float)
+            };
+            let bin = (stop + STOPS as Float).clamp(0.0, STOPS as Float) as usize;
+            bins[bin] += 1;
+        }
+        bins
+    }
+
+    // A line on clipping and black pixels, with a warning line when too much clips.
+    pub fn summary(&self) -> String {
+        let median = self.percentile(0.5);
+        let bright = self.percentile(0.99);
+        // in stops, where the brightest 1% of the image is against the clipping point
+        let headroom = if bright > 0.0 {
+            -bright.log2()
+        } else {
+            STOPS as Float
+        };
+        let mut text = format!(
+            "exposure: {:.2}% clipped, {:.2}% black, median luminance {:.3}, 99th percentile \
+             {:.3} ({:.1} stops {} clipping)",
+            100.0 * self.clipped_fraction(),
+            100.0 * self.black_fraction(),
+            median,
+            bright,
+            headroom.abs(),
+            if headroom >= 0.0 { "below" } else { "over" },
+        );
+        if self.clipped_fraction() > CLIPPED_WARNING {
+            text += &format!(
+                "\nwarning: {:.1}% of the pixels clip to white, turn the lights or the exposure \
+                 down",
+                100.0 * self.clipped_fraction()
+            );
+        }
+        text
+    }
+
+    // The histogram as text, a bar per stop, brightest on top.
+    pub fn chart(&self) -> String {
+        let bins = self.histogram();
+        let most = bins.iter().copied().max().unwrap_or(0).max(1);
+        let mut lines = vec!["luminance, stops below clipping:".to_owned()];
+        for (k, &count) in bins.iter().enumerate().rev() {
+            let label = match k {
+                STOPS => "over".to_owned(),
+                0 => format!("<{}", 1 - STOPS as i32),
+                _ => format!("{}", k as i32 - STOPS as i32),
+            };
+            let bar = "#".repeat((count * 50).div_ceil(most) as usize);
+            lines.push(format!("{:>5} {:>8} {}", label, count, bar));
+        }
+        lines.join("\n")
+    }
+
+    // `img` with zebra stripes over the pixels that clip (red) and those that stay black
+    // (blue, the other way), for finding them in the frame.
+    pub fn zebra(&self, img: &RgbImage) -> RgbImage {
+        let mut out = img.clone();
+        for (i, j, pixel) in out.enumerate_pixels_mut() {
+            let index = (j * self.width + i) as usize;
+            if self.clipped[index] && ((i + j) / 4).is_multiple_of(2) {
+                *pixel = Rgb([255, 0, 0]);
+            } else if self.black[index] && ((i + self.height - j) / 4).is_multiple_of(2) {
+                *pixel = Rgb([0, 0, 255]);
+            }
+        }
+        out
+    }
+}
+
+fn fraction(flags: &[bool]) -> Float {
+    if flags.is_empty() {
+        return 0.0;
+    }
+    flags.iter().filter(|&&f| f).count() as Float / flags.len() as Float
+}
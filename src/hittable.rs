@@ -2,33 +2,60 @@ use std::sync::Arc;
 
 use crate::aabb::AABB;
 use crate::interval::Interval;
-use crate::material::{Isotropic, Lambertian, Material};
+use crate::material::{EmissiveIsotropic, Isotropic, Material};
+use crate::normal_cone::NormalCone;
 use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
 use crate::texture::Texture;
-use crate::util::random_f64_0_1;
-use crate::vec3::Vec3;
+use crate::util::{random_f64_0_1, random_i32_ranged};
+use crate::vec3::{Float, Vec3};
+
+// how far spawn_ray moves ray origins off the surface, relative to the size of the coordinates
+const SPAWN_OFFSET: Float = 1e-5;
 
 #[derive(Clone)]
-pub struct HitRecord {
+pub struct HitRecord<'a> {
     pub p: Vec3,
+    // shading normal, used by the materials; may be interpolated or perturbed
     pub normal: Vec3,
-    pub t: f64,
-    pub u: f64,
-    pub v: f64,
+    // normal of the actual surface, decides which side scattered rays leave on
+    pub geometric_normal: Vec3,
+    pub t: Float,
+    pub u: Float,
+    pub v: Float,
     pub front_face: bool,
-    pub mat: Arc<dyn Material>,
+    // borrowed from the object hit, so that recording a hit, which closer ones may replace,
+    // costs no reference count
+    pub mat: &'a dyn Material,
+    // set by `Named`; 0 for objects without a name
+    pub object_id: u32,
+    // the fraction of the ray's cone the surface covers, below 1.0 where geometry thinner
+    // than the ray's footprint was widened to it (see Ray::footprint)
+    pub coverage: Float,
+    // how far a smooth triangle moves `p` out onto the curved surface its vertex normals
+    // describe, for rays reflected off the front of it (see spawn_ray); zero elsewhere
+    pub lift: Vec3,
 }
 
-impl HitRecord {
+// the material of a record that nothing has been recorded in
+struct NoMaterial;
+
+impl Material for NoMaterial {}
+
+impl<'a> HitRecord<'a> {
     pub fn new() -> Self {
         HitRecord {
             p: Vec3::zero(),
             normal: Vec3::zero(),
+            geometric_normal: Vec3::zero(),
             t: 0.0,
             u: 0.0,
             v: 0.0,
             front_face: true,
-            mat: Arc::from(Lambertian::from_color(Vec3::ones())),
+            mat: &NoMaterial,
+            object_id: 0,
+            coverage: 1.0,
+            lift: Vec3::zero(),
         }
     }
 
@@ -41,14 +68,108 @@ impl HitRecord {
             *outward_normal
         } else {
             -*outward_normal
+        };
+        self.geometric_normal = self.normal;
+        // a new surface hit belongs to no named object until a `Named` around it claims it
+        self.object_id = 0;
+        // and covers all of the ray's cone unless its primitive narrows that after this call
+        self.coverage = 1.0;
+        // and sits on the flat surface until a smooth triangle lifts it
+        self.lift = Vec3::zero();
+    }
+
+    // Replaces the shading normal, keeping it on the same side as the geometric normal.
+    // Call after set_face_normal.
+    pub fn set_shading_normal(&mut self, outward_normal: &Vec3) {
+        self.normal = if self.front_face {
+            *outward_normal
+        } else {
+            -*outward_normal
+        };
+    }
+
+    // Whether `direction` leaves the surface on the side the ray came from, by the
+    // geometric normal: a shading normal alone can send rays through the surface.
+    pub fn is_above(&self, direction: Vec3) -> bool {
+        direction * self.geometric_normal > 0.0
+    }
+
+    // A ray leaving the hit point in `direction`, started a little off the surface along the
+    // geometric normal, on the side it leaves to: off the shading normal it could start
+    // inside the surface and hit it again (acne), or leave on the wrong side of a smooth mesh
+    // and carry light through it. Rays leaving on the side the shading normal faces start from
+    // the lifted point, the rest from the surface itself.
+    pub fn spawn_ray(&self, direction: Vec3, time: Float) -> Ray {
+        let size = self.p.x.abs().max(self.p.y.abs()).max(self.p.z.abs());
+        let mut offset = self.geometric_normal * (SPAWN_OFFSET * (1.0 + size));
+        if !self.is_above(direction) {
+            offset = -offset;
+        } else if direction * self.normal > 0.0 {
+            offset += self.lift;
         }
+        Ray::new(self.p + offset, direction, time)
     }
 }
 
 pub trait Hittable {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool;
 
     fn bounding_box(&self) -> AABB;
+
+    // bounds the directions the surfaces emit light in, the normals of one-sided lights, so
+    // that light sampling skips lights with their backs to the point it samples them from
+    fn normal_bounds(&self) -> NormalCone {
+        NormalCone::everywhere()
+    }
+
+    // Writes the object as scene file statements (scene_export); the default is for objects
+    // the format has no statement for.
+    fn export(&self, _out: &mut SceneWriter) -> Result<(), String> {
+        Err(format!("{} can't be exported", std::any::type_name::<Self>()))
+    }
+
+    // Adds the emissive surfaces among the object's, placed in world space, to `lights` for
+    // the integrators that aim rays at lights. Objects that can't be sampled add nothing and
+    // are only lit by chance.
+    fn collect_lights(&self, _lights: &mut HittableList) {}
+
+    // Adds the surfaces among the object's whose texture coordinates can be turned back into
+    // points (see surface_at), placed in world space, to `surfaces`, for baking light over
+    // them. Triangles, whose texture coordinates are their own barycentric ones, media and
+    // moving objects add nothing.
+    fn collect_surfaces(&self, _surfaces: &mut HittableList) {}
+
+    // The point at texture coordinates (u, v) of a surface that collect_surfaces adds, and
+    // its outward normal there; None where no point of it has them.
+    fn surface_at(&self, _u: Float, _v: Float) -> Option<(Vec3, Vec3)> {
+        None
+    }
+
+    // Density, per unit solid angle, with which random(origin) picks `direction`
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> Float {
+        0.0
+    }
+
+    // A direction from `origin` towards a random point on the object, of any length
+    fn random(&self, _origin: Vec3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+// pdf_value for a flat object whose random() picks points uniformly over its `area`: the
+// density per unit area turned into one per unit solid angle where `direction` meets it.
+pub fn area_pdf(object: &impl Hittable, origin: Vec3, direction: Vec3, area: Float) -> Float {
+    let mut rec = HitRecord::new();
+    if !object.hit(
+        &Ray::new(origin, direction, 0.0),
+        Interval::with_bounds(0.001, Float::INFINITY),
+        &mut rec,
+    ) {
+        return 0.0;
+    }
+    let distance_squared = rec.t * rec.t * direction.squared_length();
+    let cosine = (direction * rec.geometric_normal).abs() / direction.length();
+    distance_squared / (cosine * area)
 }
 
 pub struct HittableList {
@@ -77,10 +198,55 @@ impl HittableList {
         self.objects.push(object.clone());
         self.bounding_box = AABB::new_two_boxes(self.bounding_box, object.bounding_box());
     }
+
+    // Adds all of `objects`, like `add` one at a time.
+    pub fn extend(&mut self, objects: impl IntoIterator<Item = Arc<dyn Hittable>>) {
+        let objects = objects.into_iter();
+        self.objects.reserve(objects.size_hint().0);
+        let mut bbox = self.bounding_box;
+        for object in objects {
+            bbox = bbox.union(object.bounding_box());
+            self.objects.push(object);
+        }
+        self.bounding_box = AABB::new_two_boxes(bbox, bbox);
+    }
+
+    // The list of `make(0)` to `make(count - 1)`, made on all cores; for scenes generated by
+    // the thousands of objects. Each thread makes a run of them into a list of its own, and
+    // those are joined in order, so the result doesn't depend on the number of threads.
+    pub fn par_from_fn(count: usize, make: impl Fn(usize) -> Arc<dyn Hittable> + Sync) -> Self {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let run = count.div_ceil(threads).max(PARALLEL_MIN_RUN);
+        let make = &make;
+        let lists: Vec<HittableList> = crossbeam::thread::scope(|s| {
+            let handles: Vec<_> = (0..count)
+                .step_by(run)
+                .map(|first| {
+                    s.spawn(move |_| {
+                        let mut list = HittableList::new();
+                        list.extend((first..(first + run).min(count)).map(make));
+                        list
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })
+        .unwrap();
+
+        let mut list = HittableList::new();
+        list.objects.reserve(count);
+        for run in lists {
+            list.extend(run.objects);
+        }
+        list
+    }
 }
 
+// fewest objects par_from_fn gives a thread; fewer are made faster than a thread starts
+const PARALLEL_MIN_RUN: usize = 256;
+
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
         let mut temp_rec = HitRecord::new();
         let mut hit_anything: bool = false;
         let mut closest_so_far = ray_t.max;
@@ -103,11 +269,96 @@ impl Hittable for HittableList {
     fn bounding_box(&self) -> AABB {
         self.bounding_box
     }
+
+    fn normal_bounds(&self) -> NormalCone {
+        match self.objects.split_first() {
+            Some((first, rest)) => rest
+                .iter()
+                .fold(first.normal_bounds(), |cone, object| cone.union(object.normal_bounds())),
+            None => NormalCone::everywhere(),
+        }
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        for object in &self.objects {
+            object.export(out)?;
+        }
+        Ok(())
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        for object in &self.objects {
+            object.collect_lights(lights);
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        for object in &self.objects {
+            object.collect_surfaces(surfaces);
+        }
+    }
+
+    // the objects are picked with equal chances
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        let weight = 1.0 / self.objects.len() as Float;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        let last = self.objects.len() as i32 - 1;
+        self.objects[random_i32_ranged(0, last) as usize].random(origin)
+    }
 }
 
 unsafe impl Send for HittableList {}
 unsafe impl Sync for HittableList {}
 
+// Tags the hits on an object (a group, usually) with an id, for per-object render passes
+// such as the matte. The outermost name wins.
+pub struct Named {
+    object: Arc<dyn Hittable>,
+    id: u32,
+}
+
+impl Named {
+    pub fn new(object: Arc<dyn Hittable>, id: u32) -> Self {
+        Self { object, id }
+    }
+}
+
+impl Hittable for Named {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        if !self.object.hit(r, ray_t, rec) {
+            return false;
+        }
+        rec.object_id = self.id;
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.object.bounding_box()
+    }
+
+    fn normal_bounds(&self) -> NormalCone {
+        self.object.normal_bounds()
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        out.named(format!("object{}", self.id), &*self.object)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        self.object.collect_lights(lights);
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        self.object.collect_surfaces(surfaces);
+    }
+}
+
 pub struct Translate {
     object: Arc<dyn Hittable>,
     offset: Vec3,
@@ -126,9 +377,9 @@ impl Translate {
 }
 
 impl Hittable for Translate {
-    fn hit(&self, r: &Ray, t_range: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, r: &Ray, t_range: Interval, rec: &mut HitRecord<'a>) -> bool {
         // Move the ray backwards by the offset
-        let offset_r = Ray::new(r.a_origin - self.offset, r.b_direction, r.time);
+        let offset_r = r.moved(r.a_origin - self.offset, r.b_direction);
 
         // Determine whether an intersection exists along the offset ray (and if so, where)
         if !self.object.hit(&offset_r, t_range, rec) {
@@ -144,24 +395,61 @@ impl Hittable for Translate {
     fn bounding_box(&self) -> AABB {
         self.bounding_box
     }
+
+    fn normal_bounds(&self) -> NormalCone {
+        self.object.normal_bounds()
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        out.transformed(0.0, self.offset, &*self.object)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        let mut inner = HittableList::new();
+        self.object.collect_lights(&mut inner);
+        for light in inner.objects {
+            lights.add(Arc::new(Translate::new(light, self.offset)));
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        let mut inner = HittableList::new();
+        self.object.collect_surfaces(&mut inner);
+        for surface in inner.objects {
+            surfaces.add(Arc::new(Translate::new(surface, self.offset)));
+        }
+    }
+
+    fn surface_at(&self, u: Float, v: Float) -> Option<(Vec3, Vec3)> {
+        let (p, normal) = self.object.surface_at(u, v)?;
+        Some((p + self.offset, normal))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        self.object.pdf_value(origin - self.offset, direction)
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        self.object.random(origin - self.offset)
+    }
 }
 
 pub struct RotateY {
     object: Arc<dyn Hittable>,
-    cos_theta: f64,
-    sin_theta: f64,
+    cos_theta: Float,
+    sin_theta: Float,
     bounding_box: AABB,
 }
 
 impl RotateY {
-    pub fn new(object: Arc<dyn Hittable>, angle: f64) -> Self {
+    pub fn new(object: Arc<dyn Hittable>, angle: Float) -> Self {
         let radians = angle.to_radians();
-        let sin_theta = f64::sin(radians);
-        let cos_theta = f64::cos(radians);
+        let sin_theta = Float::sin(radians);
+        let cos_theta = Float::cos(radians);
         let mut bounding_box = object.bounding_box();
 
-        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
-        let mut max = Vec3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+        let mut min = Vec3::new(Float::INFINITY, Float::INFINITY, Float::INFINITY);
+        let mut max = Vec3::new(-Float::INFINITY, -Float::INFINITY, -Float::INFINITY);
 
         for i in 0..2 {
             for j in 0..2 {
@@ -188,8 +476,8 @@ impl RotateY {
                     let tester = Vec3::new(new_x, y, new_z);
 
                     for c in 0..3 {
-                        *min.mut_lp(c) = f64::min(min.lp(c), tester.lp(c));
-                        *max.mut_lp(c) = f64::max(max.lp(c), tester.lp(c));
+                        *min.mut_lp(c) = Float::min(min.lp(c), tester.lp(c));
+                        *max.mut_lp(c) = Float::max(max.lp(c), tester.lp(c));
                     }
                 }
             }
@@ -203,10 +491,33 @@ impl RotateY {
             bounding_box,
         }
     }
+
+    // in degrees, as given to new
+    fn angle(&self) -> Float {
+        self.sin_theta.atan2(self.cos_theta).to_degrees()
+    }
+
+    // Rotates an object space point or direction into world space.
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x + self.sin_theta * v.z,
+            v.y,
+            -self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+
+    // Rotates a world space point or direction into object space.
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x - self.sin_theta * v.z,
+            v.y,
+            self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
 }
 
 impl Hittable for RotateY {
-    fn hit(&self, r: &Ray, t_range: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, r: &Ray, t_range: Interval, rec: &mut HitRecord<'a>) -> bool {
         // Change the ray from world space to object space
         let mut origin = r.a_origin;
         let mut direction = r.b_direction;
@@ -217,7 +528,7 @@ impl Hittable for RotateY {
         direction.x = self.cos_theta * r.b_direction.x - self.sin_theta * r.b_direction.z;
         direction.z = self.sin_theta * r.b_direction.x + self.cos_theta * r.b_direction.z;
 
-        let rotated_r = Ray::new(origin, direction, r.time);
+        let rotated_r = r.moved(origin, direction);
 
         // Determine whether an intersection exists in object space (and if so, where)
         if !self.object.hit(&rotated_r, t_range, rec) {
@@ -234,8 +545,21 @@ impl Hittable for RotateY {
         normal.x = self.cos_theta * rec.normal.x + self.sin_theta * rec.normal.z;
         normal.z = -self.sin_theta * rec.normal.x + self.cos_theta * rec.normal.z;
 
+        // Same for the geometric normal
+        let mut geometric_normal = rec.geometric_normal;
+        geometric_normal.x =
+            self.cos_theta * rec.geometric_normal.x + self.sin_theta * rec.geometric_normal.z;
+        geometric_normal.z =
+            -self.sin_theta * rec.geometric_normal.x + self.cos_theta * rec.geometric_normal.z;
+
+        // and the lift
+        let lift = rec.lift;
+        rec.lift.x = self.cos_theta * lift.x + self.sin_theta * lift.z;
+        rec.lift.z = -self.sin_theta * lift.x + self.cos_theta * lift.z;
+
         rec.p = p;
         rec.normal = normal;
+        rec.geometric_normal = geometric_normal;
 
         true
     }
@@ -243,33 +567,94 @@ impl Hittable for RotateY {
     fn bounding_box(&self) -> AABB {
         self.bounding_box
     }
+
+    fn normal_bounds(&self) -> NormalCone {
+        let cone = self.object.normal_bounds();
+        let axis = Vec3::new(
+            self.cos_theta * cone.axis.x + self.sin_theta * cone.axis.z,
+            cone.axis.y,
+            -self.sin_theta * cone.axis.x + self.cos_theta * cone.axis.z,
+        );
+        NormalCone::new(axis, cone.theta)
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        out.transformed(self.angle(), Vec3::zero(), &*self.object)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        let mut inner = HittableList::new();
+        self.object.collect_lights(&mut inner);
+        for light in inner.objects {
+            lights.add(Arc::new(RotateY::new(light, self.angle())));
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        let mut inner = HittableList::new();
+        self.object.collect_surfaces(&mut inner);
+        for surface in inner.objects {
+            surfaces.add(Arc::new(RotateY::new(surface, self.angle())));
+        }
+    }
+
+    fn surface_at(&self, u: Float, v: Float) -> Option<(Vec3, Vec3)> {
+        let (p, normal) = self.object.surface_at(u, v)?;
+        Some((self.to_world(p), self.to_world(normal)))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        self.object.pdf_value(self.to_object(origin), self.to_object(direction))
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        let direction = self.object.random(self.to_object(origin));
+        Vec3::new(
+            self.cos_theta * direction.x + self.sin_theta * direction.z,
+            direction.y,
+            -self.sin_theta * direction.x + self.cos_theta * direction.z,
+        )
+    }
 }
 
 pub struct ConstantMedium {
     boundary: Arc<dyn Hittable>,
-    neg_inv_density: f64,
+    neg_inv_density: Float,
     phase_function: Arc<dyn Material>,
 }
 
 impl ConstantMedium {
-    pub fn from_color(boundary: Arc<dyn Hittable>, density: f64, albedo: Vec3) -> Self {
+    pub fn from_color(boundary: Arc<dyn Hittable>, density: Float, albedo: Vec3) -> Self {
         Self {
             boundary,
             neg_inv_density: -1.0 / density,
             phase_function: Arc::from(Isotropic::from_color(albedo)),
         }
     }
-    pub fn from_tex(boundary: Arc<dyn Hittable>, density: f64, tex: Arc<dyn Texture>) -> Self {
+    pub fn from_tex(boundary: Arc<dyn Hittable>, density: Float, tex: Arc<dyn Texture>) -> Self {
         Self {
             boundary,
             neg_inv_density: -1.0 / density,
             phase_function: Arc::from(Isotropic::from_texture(tex)),
         }
     }
+    // `emission` is the light given off per unit distance inside the medium
+    pub fn emissive(
+        boundary: Arc<dyn Hittable>,
+        density: Float,
+        albedo: Vec3,
+        emission: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function: Arc::from(EmissiveIsotropic::new(albedo, emission, density)),
+        }
+    }
 }
 
 impl Hittable for ConstantMedium {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
         let mut rec1 = HitRecord::new();
         let mut rec2 = HitRecord::new();
 
@@ -279,7 +664,7 @@ impl Hittable for ConstantMedium {
 
         if !self.boundary.hit(
             r,
-            Interval::with_bounds(rec1.t + 0.0001, f64::INFINITY),
+            Interval::with_bounds(rec1.t + 0.0001, Float::INFINITY),
             &mut rec2,
         ) {
             return false;
@@ -311,8 +696,11 @@ impl Hittable for ConstantMedium {
         rec.t = rec1.t + hit_distance / ray_length;
         rec.p = r.at(rec.t);
         rec.normal = Vec3::new(1.0,0.0,0.0);  // arbitrary
+        rec.geometric_normal = rec.normal;
         rec.front_face = true;     // also arbitrary
-        rec.mat = self.phase_function.clone();
+        rec.coverage = 1.0;
+        rec.lift = Vec3::zero();
+        rec.mat = &*self.phase_function;
 
         true
     }
@@ -320,4 +708,13 @@ impl Hittable for ConstantMedium {
     fn bounding_box(&self) -> AABB {
         self.boundary.bounding_box()
     }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        let options = format!(
+            "density {} {}",
+            -1.0 / self.neg_inv_density,
+            self.phase_function.export(out)?
+        );
+        out.medium(&*self.boundary, &options)
+    }
 }
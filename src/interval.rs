@@ -1,37 +1,39 @@
+use crate::vec3::Float;
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Interval {
-    pub min: f64,
-    pub max: f64,
+    pub min: Float,
+    pub max: Float,
 }
 
 impl Interval {
     pub fn new() -> Self {
         Interval {
-            min: f64::INFINITY,
-            max: -f64::INFINITY,
+            min: Float::INFINITY,
+            max: -Float::INFINITY,
         }
     }
 
-    pub fn with_bounds(min: f64, max: f64) -> Self {
+    pub fn with_bounds(min: Float, max: Float) -> Self {
         Interval { min, max }
     }
 
-    pub fn with_orderless_bounds(a: f64, b: f64) -> Self {
+    pub fn with_orderless_bounds(a: Float, b: Float) -> Self {
         Interval {
             min: a.min(b),
             max: a.max(b),
         }
     }
 
-    pub fn size(&self) -> f64 {
+    pub fn size(&self) -> Float {
         self.max - self.min
     }
 
-    pub fn contains(&self, x: f64) -> bool {
+    pub fn contains(&self, x: Float) -> bool {
         self.min <= x && x <= self.max
     }
 
-    pub fn clamp(&self, x: f64) -> f64 {
+    pub fn clamp(&self, x: Float) -> Float {
         if x < self.min {
             return self.min;
         }
@@ -41,21 +43,21 @@ impl Interval {
         x
     }
 
-    pub fn surrounds(&self, x: f64) -> bool {
+    pub fn surrounds(&self, x: Float) -> bool {
         self.min < x && x < self.max
     }
 
     pub const EMPTY: Interval = Interval {
-        min: f64::INFINITY,
-        max: -f64::INFINITY,
+        min: Float::INFINITY,
+        max: -Float::INFINITY,
     };
 
     pub const UNIVERSE: Interval = Interval {
-        min: -f64::INFINITY,
-        max: f64::INFINITY,
+        min: -Float::INFINITY,
+        max: Float::INFINITY,
     };
 
-    pub fn expand(&self, delta: f64) -> Self {
+    pub fn expand(&self, delta: Float) -> Self {
         Self::with_bounds(self.min - delta / 2.0, self.max + delta / 2.0)
     }
 
@@ -75,10 +77,10 @@ impl Interval {
 
 }
 
-impl std::ops::Add<f64> for Interval {
+impl std::ops::Add<Float> for Interval {
     type Output = Self;
 
-    fn add(self, rhs: f64) -> Self::Output {
+    fn add(self, rhs: Float) -> Self::Output {
         Self {
             min: self.min + rhs,
             max: self.max + rhs,
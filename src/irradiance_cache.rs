@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+use crate::aabb::AABB;
+use crate::vec3::{Float, Vec3};
+
+// Ward's irradiance cache: the light arriving at diffuse surfaces, gathered at scattered
+// points and interpolated in between. The light reaching a wall changes slowly except near
+// other geometry, so each record holds for a distance proportional to the harmonic mean of
+// the distances its gather rays travelled (a record in a corner covers less than one in the
+// middle of a wall). Records are made on demand by the render threads and shared by all of
+// them; which thread makes a record depends on timing, so seeded renders with the cache
+// don't repeat exactly.
+//
+// What is cached is the average radiance over the cosine-weighted hemisphere, the quantity a
+// Lambertian surface multiplies its albedo with; textures stay sharp as they aren't part of
+// it. The interpolation blurs shadows and smooths over the noise of the gathers a little,
+// that is the bias traded for the speed.
+pub struct IrradianceCache {
+    // larger reuses records further away: faster, blurrier (0.1 to 0.5 are sensible)
+    pub accuracy: Float,
+    // rays gathered per record
+    pub samples: u32,
+    // bounds of the radius of a record, from the size of the scene
+    min_radius: Float,
+    max_radius: Float,
+    // records by grid cell, each in every cell its region of influence touches
+    cell_size: Float,
+    cells: RwLock<HashMap<(i64, i64, i64), Vec<Record>>>,
+    records: AtomicUsize,
+}
+
+#[derive(Clone, Copy)]
+struct Record {
+    p: Vec3,
+    normal: Vec3,
+    irradiance: Vec3,
+    radius: Float,
+}
+
+impl IrradianceCache {
+    pub fn new(accuracy: Float, samples: u32) -> Self {
+        let mut cache = Self {
+            accuracy: accuracy.max(0.01),
+            samples: samples.max(1),
+            min_radius: 0.0,
+            max_radius: 0.0,
+            cell_size: 0.0,
+            cells: RwLock::new(HashMap::new()),
+            records: AtomicUsize::new(0),
+        };
+        cache.prepare(AABB::new_two_points(Vec3::zero(), Vec3::ones()));
+        cache
+    }
+
+    // Empties the cache and sizes it for a scene within `bbox`.
+    pub fn prepare(&mut self, bbox: AABB) {
+        let diagonal = Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size()).length();
+        let diagonal = if diagonal.is_finite() && diagonal > 0.0 {
+            diagonal
+        } else {
+            1.0
+        };
+        self.max_radius = diagonal / 20.0;
+        self.min_radius = diagonal / 500.0;
+        self.cell_size = self.accuracy * self.max_radius;
+        self.cells.get_mut().unwrap().clear();
+        self.records.store(0, Ordering::Relaxed);
+    }
+
+    // Irradiance at `p` on a surface facing `normal`, interpolated from the records that
+    // hold there; None if there are none.
+    pub fn lookup(&self, p: Vec3, normal: Vec3) -> Option<Vec3> {
+        let cells = self.cells.read().unwrap();
+        let records = cells.get(&self.cell(p))?;
+        let mut sum = Vec3::zero();
+        let mut total_weight = 0.0;
+        for record in records {
+            let offset = p - record.p;
+            // a record in front of p sees what p is shadowed from
+            if offset * (normal + record.normal) * 0.5 < -0.01 * record.radius {
+                continue;
+            }
+            let error =
+                offset.length() / record.radius + (1.0 - (normal * record.normal).min(1.0)).sqrt();
+            if error < self.accuracy {
+                let weight = 1.0 / error.max(1e-4);
+                sum += record.irradiance * weight;
+                total_weight += weight;
+            }
+        }
+        (total_weight > 0.0).then(|| sum / total_weight)
+    }
+
+    // Adds the irradiance gathered at `p`, whose gather rays travelled `harmonic_distance`
+    // on average (harmonic mean, infinite if they all escaped).
+    pub fn insert(&self, p: Vec3, normal: Vec3, irradiance: Vec3, harmonic_distance: Float) {
+        let record = Record {
+            p,
+            normal,
+            irradiance,
+            radius: harmonic_distance.clamp(self.min_radius, self.max_radius),
+        };
+        let reach = Vec3::ones() * (self.accuracy * record.radius);
+        let (low, high) = (self.cell(p - reach), self.cell(p + reach));
+        let mut cells = self.cells.write().unwrap();
+        for x in low.0..=high.0 {
+            for y in low.1..=high.1 {
+                for z in low.2..=high.2 {
+                    cells.entry((x, y, z)).or_default().push(record);
+                }
+            }
+        }
+        self.records.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.records.load(Ordering::Relaxed)
+    }
+
+    fn cell(&self, p: Vec3) -> (i64, i64, i64) {
+        let index = |c: Float| (c / self.cell_size).floor() as i64;
+        (index(p.x), index(p.y), index(p.z))
+    }
+}
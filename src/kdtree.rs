@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable, HittableList},
+    interval::Interval,
+    normal_cone::NormalCone,
+    ray::Ray,
+    scene_export::SceneWriter,
+    stats,
+    vec3::Float,
+};
+
+// kd-tree over a list of objects, an alternative to BVHNode (see Accelerator), split by the
+// surface area heuristic; objects crossing a plane are in the leaves on both sides.
+pub struct KdTree {
+    // depth first, the root first and the below child of a node right after it
+    nodes: Vec<KdNode>,
+    // the objects of the leaves, by index into `objects`
+    leaf_objects: Vec<u32>,
+    objects: Vec<Arc<dyn Hittable>>,
+    bounding_box: AABB,
+    normal_cone: NormalCone,
+}
+
+enum KdNode {
+    Interior { axis: u8, split: Float, above: u32 },
+    Leaf { first: u32, count: u32 },
+}
+
+// relative costs of a traversal step and of testing an object, and how much cheaper a split
+// with an empty side counts (Pharr et al., "Physically Based Rendering", 4.4)
+const TRAVERSAL_COST: Float = 1.0;
+const INTERSECT_COST: Float = 80.0;
+const EMPTY_BONUS: Float = 0.5;
+// splits in a row that may cost more than the leaf they replace, as the next ones may pay off
+const BAD_REFINES: u32 = 3;
+const MAX_DEPTH: u32 = 60;
+
+#[derive(Clone, Copy)]
+struct Edge {
+    t: Float,
+    object: u32,
+    start: bool,
+}
+
+impl KdTree {
+    pub fn new(list: HittableList) -> Self {
+        let start = std::time::Instant::now();
+        let objects = list.objects;
+        let boxes: Vec<AABB> = objects.iter().map(|o| o.bounding_box()).collect();
+        let mut bounding_box = AABB::EMPTY;
+        for bbox in &boxes {
+            bounding_box = bounding_box.union(*bbox);
+        }
+        let normal_cone = match objects.split_first() {
+            Some((first, rest)) => rest.iter().fold(first.normal_bounds(), |cone, o| {
+                cone.union(o.normal_bounds())
+            }),
+            None => NormalCone::everywhere(),
+        };
+
+        let mut tree = Self {
+            nodes: vec![],
+            leaf_objects: vec![],
+            objects,
+            bounding_box,
+            normal_cone,
+        };
+        let max_depth = (8.0 + 1.3 * (boxes.len().max(1) as Float).log2()).round() as u32;
+        let all: Vec<u32> = (0..boxes.len() as u32).collect();
+        tree.build(&boxes, all, bounding_box, max_depth.min(MAX_DEPTH), 0);
+        stats::add_bvh_build_time(start.elapsed());
+        tree
+    }
+
+    fn build(
+        &mut self,
+        boxes: &[AABB],
+        objects: Vec<u32>,
+        bbox: AABB,
+        depth: u32,
+        bad_refines: u32,
+    ) {
+        let leaf_cost = INTERSECT_COST * objects.len() as Float;
+        let split = if objects.len() <= 1 || depth == 0 {
+            None
+        } else {
+            best_split(boxes, &objects, bbox)
+        };
+        let bad_refines = match split {
+            Some((_, _, cost)) if cost > leaf_cost => bad_refines + 1,
+            _ => bad_refines,
+        };
+        let split = split.filter(|&(_, _, cost)| {
+            (cost <= leaf_cost || bad_refines <= BAD_REFINES) && cost < 4.0 * leaf_cost
+        });
+        let Some((axis, split, _)) = split else {
+            self.nodes.push(KdNode::Leaf {
+                first: self.leaf_objects.len() as u32,
+                count: objects.len() as u32,
+            });
+            self.leaf_objects.extend(objects);
+            return;
+        };
+
+        let interval = |o: u32| boxes[o as usize].axis_interval(axis as i32);
+        let below: Vec<u32> = objects
+            .iter()
+            .copied()
+            .filter(|&o| interval(o).min <= split)
+            .collect();
+        let above: Vec<u32> = objects
+            .into_iter()
+            .filter(|&o| interval(o).max >= split)
+            .collect();
+        let (mut below_box, mut above_box) = (bbox, bbox);
+        match axis {
+            0 => (below_box.x.max, above_box.x.min) = (split, split),
+            1 => (below_box.y.max, above_box.y.min) = (split, split),
+            _ => (below_box.z.max, above_box.z.min) = (split, split),
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(KdNode::Leaf { first: 0, count: 0 });
+        self.build(boxes, below, below_box, depth - 1, bad_refines);
+        let above_index = self.nodes.len() as u32;
+        self.build(boxes, above, above_box, depth - 1, bad_refines);
+        self.nodes[index] = KdNode::Interior {
+            axis,
+            split,
+            above: above_index,
+        };
+    }
+}
+
+// The plane through `bbox` that splits `objects` for the lowest cost by the surface area
+// heuristic: its axis, position and cost. None if all objects span the whole box.
+fn best_split(boxes: &[AABB], objects: &[u32], bbox: AABB) -> Option<(u8, Float, Float)> {
+    let size = [bbox.x.size(), bbox.y.size(), bbox.z.size()];
+    let area = 2.0 * (size[0] * size[1] + size[1] * size[2] + size[2] * size[0]);
+    if !(area.is_finite() && area > 0.0) {
+        return None;
+    }
+    let mut best: Option<(u8, Float, Float)> = None;
+    let mut edges = Vec::with_capacity(2 * objects.len());
+    for axis in 0..3 {
+        let interval = bbox.axis_interval(axis as i32);
+        edges.clear();
+        for &o in objects {
+            let extent = boxes[o as usize].axis_interval(axis as i32);
+            edges.push(Edge {
+                t: extent.min,
+                object: o,
+                start: true,
+            });
+            edges.push(Edge {
+                t: extent.max,
+                object: o,
+                start: false,
+            });
+        }
+        // at the same position starts come first: a plane there has the object on both sides
+        edges.sort_by(|a, b| {
+            a.t.total_cmp(&b.t)
+                .then(b.start.cmp(&a.start))
+                .then(a.object.cmp(&b.object))
+        });
+
+        let (other1, other2) = (size[(axis + 1) % 3], size[(axis + 2) % 3]);
+        let (mut below, mut above) = (0, objects.len());
+        for edge in &edges {
+            if !edge.start {
+                above -= 1;
+            }
+            if edge.t > interval.min && edge.t < interval.max {
+                let below_length = edge.t - interval.min;
+                let above_length = interval.max - edge.t;
+                let below_area = 2.0 * (other1 * other2 + below_length * (other1 + other2));
+                let above_area = 2.0 * (other1 * other2 + above_length * (other1 + other2));
+                let bonus = if below == 0 || above == 0 {
+                    EMPTY_BONUS
+                } else {
+                    0.0
+                };
+                let cost = TRAVERSAL_COST
+                    + INTERSECT_COST
+                        * (1.0 - bonus)
+                        * (below_area * below as Float + above_area * above as Float)
+                        / area;
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis as u8, edge.t, cost));
+                }
+            }
+            if edge.start {
+                below += 1;
+            }
+        }
+    }
+    best
+}
+
+// deeper than MAX_DEPTH
+const STACK_SIZE: usize = 64;
+
+impl KdTree {
+    // The search for a ray with a cone (see Ray::footprint): where the footprint reaches across
+    // a node's plane, both children are visited, each over the part of the ray whose cone
+    // reaches into it. Those parts overlap, so a hit only ends the search once no node left
+    // starts closer.
+    fn cone_hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        stats::count(stats::Counter::AabbTest, 1);
+        let Some(t) = self.bounding_box.clip(r, ray_t) else {
+            return false;
+        };
+        // nodes still to visit, with their boxes and their part of the ray
+        let mut stack = [(0, AABB::EMPTY, Interval::EMPTY); STACK_SIZE];
+        stack[0] = (0, self.bounding_box, t);
+        let mut len = 1;
+        let mut closest = ray_t.max;
+        let mut hit_anything = false;
+        while len > 0 {
+            len -= 1;
+            let (index, bbox, t) = stack[len];
+            if t.min > closest {
+                continue;
+            }
+            match self.nodes[index] {
+                KdNode::Interior { axis, split, above } => {
+                    let (below_box, above_box) = split_box(bbox, axis, split);
+                    // the far child goes on the stack first, so the near one is searched first
+                    let children = if r.a_origin.lp(axis) < split {
+                        [(above as usize, above_box), (index + 1, below_box)]
+                    } else {
+                        [(index + 1, below_box), (above as usize, above_box)]
+                    };
+                    let reach = Interval::with_bounds(t.min, t.max.min(closest));
+                    for (child, child_box) in children {
+                        if let Some(child_t) = child_box.clip(r, reach) {
+                            stack[len] = (child, child_box, child_t);
+                            len += 1;
+                        }
+                    }
+                }
+                KdNode::Leaf { first, count } => {
+                    // a little beyond the leaf, as in hit
+                    let slack = 1e-6 * (1.0 + t.max.abs());
+                    let mut leaf_t = Interval::with_bounds(
+                        (t.min - slack).max(ray_t.min),
+                        (t.max + slack).min(closest),
+                    );
+                    for &o in &self.leaf_objects[first as usize..(first + count) as usize] {
+                        if self.objects[o as usize].hit(r, leaf_t, rec) {
+                            hit_anything = true;
+                            closest = rec.t;
+                            leaf_t.max = rec.t;
+                        }
+                    }
+                }
+            }
+        }
+        hit_anything
+    }
+}
+
+// The parts of `bbox` below and above `split` on `axis`.
+fn split_box(bbox: AABB, axis: u8, split: Float) -> (AABB, AABB) {
+    let (mut below, mut above) = (bbox, bbox);
+    match axis {
+        0 => (below.x.max, above.x.min) = (split, split),
+        1 => (below.y.max, above.y.min) = (split, split),
+        _ => (below.z.max, above.z.min) = (split, split),
+    }
+    (below, above)
+}
+
+impl Hittable for KdTree {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        if r.has_cone() {
+            return self.cone_hit(r, ray_t, rec);
+        }
+        stats::count(stats::Counter::AabbTest, 1);
+        let Some(mut t) = self.bounding_box.clip(r, ray_t) else {
+            return false;
+        };
+        // nodes still to visit behind the current one, with their part of the ray
+        let mut stack = [(0, Interval::EMPTY); STACK_SIZE];
+        let mut len = 0;
+        let mut index = 0;
+        loop {
+            match self.nodes[index] {
+                KdNode::Interior { axis, split, above } => {
+                    let origin = r.a_origin.lp(axis);
+                    let direction = r.b_direction.lp(axis);
+                    let below_first = origin < split || (origin == split && direction <= 0.0);
+                    let (first, second) = if below_first {
+                        (index + 1, above as usize)
+                    } else {
+                        (above as usize, index + 1)
+                    };
+                    let t_split = (split - origin) * r.inv_direction.lp(axis);
+                    if direction == 0.0 || t_split > t.max || t_split <= 0.0 {
+                        index = first;
+                    } else if t_split < t.min {
+                        index = second;
+                    } else {
+                        stack[len] = (second, Interval::with_bounds(t_split, t.max));
+                        len += 1;
+                        index = first;
+                        t.max = t_split;
+                    }
+                }
+                KdNode::Leaf { first, count } => {
+                    // a little beyond the leaf, for hits on its sides that rounding puts out
+                    let slack = 1e-6 * (1.0 + t.max.abs());
+                    let mut leaf_t = Interval::with_bounds(
+                        (t.min - slack).max(ray_t.min),
+                        (t.max + slack).min(ray_t.max),
+                    );
+                    let mut hit_anything = false;
+                    for &o in &self.leaf_objects[first as usize..(first + count) as usize] {
+                        if self.objects[o as usize].hit(r, leaf_t, rec) {
+                            hit_anything = true;
+                            leaf_t.max = rec.t;
+                        }
+                    }
+                    if hit_anything {
+                        return true;
+                    }
+                    if len == 0 {
+                        return false;
+                    }
+                    len -= 1;
+                    (index, t) = stack[len];
+                }
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+
+    fn normal_bounds(&self) -> NormalCone {
+        self.normal_cone
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        for object in &self.objects {
+            object.export(out)?;
+        }
+        Ok(())
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        for object in &self.objects {
+            object.collect_lights(lights);
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        for object in &self.objects {
+            object.collect_surfaces(surfaces);
+        }
+    }
+}
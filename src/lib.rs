@@ -0,0 +1,57 @@
+pub mod aabb;
+pub mod accelerator;
+pub mod animation;
+pub mod aperture;
+pub mod bracket;
+pub mod bvh;
+pub mod camera;
+pub mod camera_path;
+pub mod color;
+pub mod compare;
+pub mod config;
+pub mod contact_sheet;
+pub mod cornell;
+pub mod denoise;
+pub mod disk;
+pub mod exposure;
+pub mod film;
+pub mod font;
+pub mod grid;
+pub mod histogram;
+pub mod hittable;
+pub mod interval;
+pub mod irradiance_cache;
+pub mod kdtree;
+pub mod light_rig;
+pub mod lightmap;
+pub mod material;
+pub mod material_registry;
+pub mod material_preview;
+pub mod motion;
+pub mod normal_cone;
+pub mod obj;
+pub mod pbrt;
+pub mod perlin;
+pub mod preset;
+pub mod progress;
+pub mod quad;
+pub mod ray;
+pub mod ray_paths;
+pub mod scene;
+pub mod scene_builder;
+pub mod scene_catalog;
+pub mod scene_edit;
+pub mod scene_export;
+pub mod scene_file;
+pub mod scene_registry;
+pub mod simd;
+pub mod sphere;
+pub mod stats;
+pub mod text;
+pub mod texture;
+pub mod texture_registry;
+pub mod tiled_texture;
+pub mod triangle;
+pub mod util;
+pub mod vec3;
+pub mod wedge;
@@ -0,0 +1,147 @@
+use crate::vec3::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::disk::Disk;
+use crate::hittable::{Hittable, HittableList};
+use crate::material::{DiffuseLight, Material};
+use crate::quad::Quad;
+use crate::texture::SolidColor;
+use crate::vec3::{Float, Vec3};
+
+// Ready-made lighting for product shots, placed and sized from the bounds of the subject.
+// `front` points from the subject towards the camera. With `brightness` 1 the key light (or
+// the sky, or the ring) alone lights a white diffuse surface facing it to about full white.
+// All emitters are one-sided and face the subject.
+
+// Key softbox 45 degrees to the camera's left and above, a fill at a quarter of the key on
+// the right, and a smaller rim light from behind.
+pub fn three_point(subject: AABB, front: Vec3, brightness: Float) -> HittableList {
+    let rig = Rig::new(subject, front);
+    let r = rig.radius;
+    let mut lights = HittableList::new();
+    lights.add(rig.softbox(-45.0, 30.0, 3.0 * r, 2.0 * r, brightness));
+    lights.add(rig.softbox(45.0, 10.0, 3.0 * r, 2.0 * r, 0.25 * brightness));
+    lights.add(rig.softbox(150.0, 45.0, 2.5 * r, r, 0.75 * brightness));
+    lights
+}
+
+// `n` x `n` panels overhead, covering twice the width of the subject, for even top light.
+pub fn skylight_grid(subject: AABB, n: u32, brightness: Float) -> HittableList {
+    let rig = Rig::new(subject, Vec3::new(0.0, 0.0, 1.0));
+    let n = n.max(1);
+    let height = 2.0 * rig.radius;
+    let span = 4.0 * rig.radius;
+    let cell = span / n as Float;
+    // 10% gaps between the panels
+    let size = 0.9 * cell;
+    let mat = emitter(brightness, height, (n * n) as Float * size * size);
+
+    let top = subject.y.max + height;
+    let mut lights = HittableList::new();
+    for i in 0..n {
+        for j in 0..n {
+            let x = rig.center.x - 0.5 * span + (i as Float + 0.05) * cell;
+            let z = rig.center.z - 0.5 * span + (j as Float + 0.05) * cell;
+            // u x v points down
+            lights.add(Arc::new(Quad::new(
+                Vec3::new(x, top, z),
+                Vec3::new(size, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, size),
+                mat.clone(),
+            )));
+        }
+    }
+    lights
+}
+
+// A ring of small disks around the camera axis, wide enough to frame the subject, for the
+// flat, shadowless look with a round catch light.
+pub fn ring_light(subject: AABB, front: Vec3, brightness: Float) -> HittableList {
+    const DISKS: u32 = 16;
+    let rig = Rig::new(subject, front);
+    let distance = 3.0 * rig.radius;
+    let ring_radius = 1.5 * rig.radius;
+    let disk_radius = 0.2 * rig.radius;
+    let mat = emitter(
+        brightness,
+        distance,
+        DISKS as Float * PI * disk_radius * disk_radius,
+    );
+
+    let mut lights = HittableList::new();
+    for i in 0..DISKS {
+        let angle = 2.0 * PI * i as Float / DISKS as Float;
+        let offset = (rig.right * angle.cos() + rig.up * angle.sin()) * ring_radius;
+        let center = rig.center + rig.front * distance + offset;
+        lights.add(Arc::new(Disk::new(
+            center,
+            rig.center - center,
+            disk_radius,
+            mat.clone(),
+        )));
+    }
+    lights
+}
+
+// Radiance that gives `brightness` at `distance` from emitters of the given total area,
+// for the reflected light of a white Lambertian surface: L = pi * E / A * d^2.
+fn emitter(brightness: Float, distance: Float, area: Float) -> Arc<dyn Material> {
+    let intensity = PI * brightness * distance * distance / area;
+    Arc::new(DiffuseLight::new(
+        Arc::new(SolidColor::from_vec(Vec3::ones())),
+        intensity,
+        false,
+    ))
+}
+
+struct Rig {
+    center: Vec3,
+    // half the diagonal of the subject
+    radius: Float,
+    // horizontal frame: towards the camera, the camera's right, and up
+    front: Vec3,
+    right: Vec3,
+    up: Vec3,
+}
+
+impl Rig {
+    fn new(subject: AABB, front: Vec3) -> Self {
+        let min = Vec3::new(subject.x.min, subject.y.min, subject.z.min);
+        let max = Vec3::new(subject.x.max, subject.y.max, subject.z.max);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let mut front = Vec3::new(front.x, 0.0, front.z);
+        if front.near_zero() {
+            front = Vec3::new(0.0, 0.0, 1.0);
+        }
+        let front = front.unit();
+        Self {
+            center: (min + max) * 0.5,
+            radius: ((max - min).length() * 0.5).max(1e-3),
+            front,
+            right: up.cross(front),
+            up,
+        }
+    }
+
+    // Square softbox facing the subject from `azimuth` degrees right of the camera and
+    // `elevation` degrees up.
+    fn softbox(
+        &self,
+        azimuth: Float,
+        elevation: Float,
+        distance: Float,
+        size: Float,
+        brightness: Float,
+    ) -> Arc<dyn Hittable> {
+        let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+        let dir = (self.front * azimuth.cos() + self.right * azimuth.sin()) * elevation.cos()
+            + self.up * elevation.sin();
+        // u x v = -dir, towards the subject
+        let v = (self.up - dir * (self.up * dir)).unit();
+        let u = v.cross(-dir);
+        let q = self.center + dir * distance - (u + v) * (0.5 * size);
+        let mat = emitter(brightness, distance, size * size);
+        Arc::new(Quad::new(q, u * size, v * size, mat))
+    }
+}
@@ -0,0 +1,115 @@
+use image::{ImageBuffer, RgbImage};
+
+use crate::color::write_color;
+use crate::vec3::{Float, Vec3};
+
+// Light baked over surfaces (Camera::bake), as an atlas: a square cell of `size` by `size`
+// texels per surface over its texture coordinates, u to the right and v up as image textures
+// read them, the cells side by side in rows of `columns`. Texels with no point of their
+// surface in them (the corners around a disk) stay empty until `dilate` fills them in.
+pub struct Lightmap {
+    pub size: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub surfaces: usize,
+    // row by row over the whole atlas
+    texels: Vec<Option<Vec3>>,
+}
+
+impl Lightmap {
+    // Cells for `surfaces` surfaces in as square an atlas as they fill.
+    pub fn new(surfaces: usize, size: u32) -> Self {
+        let columns = (surfaces as Float).sqrt().ceil().max(1.0) as u32;
+        let rows = (surfaces as u32).div_ceil(columns).max(1);
+        Self {
+            size,
+            columns,
+            rows,
+            surfaces,
+            texels: vec![None; (columns * size * rows * size) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.columns * self.size
+    }
+
+    pub fn height(&self) -> u32 {
+        self.rows * self.size
+    }
+
+    // The top left texel of the cell of surface `k`.
+    pub fn cell(&self, k: usize) -> (u32, u32) {
+        let k = k as u32;
+        (
+            (k % self.columns) * self.size,
+            (k / self.columns) * self.size,
+        )
+    }
+
+    // The surface texel (x, y) of the atlas belongs to, if any, and the texture coordinates
+    // of its corner at the top left of the image (v is 1 at the top); a texel spans 1 / size.
+    pub fn texel(&self, x: u32, y: u32) -> Option<(usize, Float, Float)> {
+        let k = ((y / self.size) * self.columns + x / self.size) as usize;
+        if k >= self.surfaces {
+            return None;
+        }
+        let size = self.size as Float;
+        let (i, j) = (x % self.size, y % self.size);
+        Some((k, i as Float / size, 1.0 - j as Float / size))
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: Option<Vec3>) {
+        let index = (y * self.width() + x) as usize;
+        self.texels[index] = value;
+    }
+
+    // Fills the empty texels next to filled ones of the same cell with the mean of those,
+    // `passes` times, so that texture filtering and mipmaps near the edges of a surface don't
+    // bleed in black.
+    pub fn dilate(&mut self, passes: u32) {
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        let size = self.size as i64;
+        for _ in 0..passes {
+            let mut next = self.texels.clone();
+            for y in 0..height {
+                for x in 0..width {
+                    let index = (y * width + x) as usize;
+                    if self.texels[index].is_some() {
+                        continue;
+                    }
+                    let mut sum = Vec3::zero();
+                    let mut count = 0;
+                    for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                        let (nx, ny) = (x + dx, y + dy);
+                        // neighbours in other cells are other surfaces
+                        if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                            continue;
+                        }
+                        if nx / size != x / size || ny / size != y / size {
+                            continue;
+                        }
+                        if let Some(value) = self.texels[(ny * width + nx) as usize] {
+                            sum += value;
+                            count += 1;
+                        }
+                    }
+                    if count > 0 {
+                        next[index] = Some(sum / count as Float);
+                    }
+                }
+            }
+            self.texels = next;
+        }
+    }
+
+    // The atlas as the renders are written, through the output transform; black where empty.
+    pub fn image(&self) -> RgbImage {
+        let mut img: RgbImage = ImageBuffer::new(self.width(), self.height());
+        for (index, texel) in self.texels.iter().enumerate() {
+            let (x, y) = (index % self.width() as usize, index / self.width() as usize);
+            write_color(texel.unwrap_or(Vec3::zero()), &mut img, x, y);
+        }
+        img
+    }
+}
@@ -1,44 +1,652 @@
-mod aabb;
-mod bvh;
-mod camera;
-mod color;
-mod hittable;
-mod interval;
-mod material;
-mod perlin;
-mod quad;
-mod ray;
-mod scene;
-mod sphere;
-mod texture;
-mod util;
-mod vec3;
 
-use std::fs::File;
+use ray_tracer::{
+    animation, bracket, bvh, camera, camera_path, color, compare, config, denoise, film, hittable,
+    irradiance_cache, material_preview, pbrt, preset, ray_paths, scene, scene_catalog,
+    scene_export, scene_file, scene_registry, texture, util, vec3, wedge,
+};
+
+use animation::Animation;
+use config::Config;
+use hittable::Hittable;
+use image::RgbImage;
+use irradiance_cache::IrradianceCache;
 use scene::final_scene;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use vec3::Float;
 
 const AUTHOR: &str = "PhotonCollider";
 
 fn main() {
     let now = std::time::Instant::now();
-    let path = "output/final_scene.png";
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = Config::load(&args);
+    texture::set_texture_dirs(config.texture_dirs.clone());
+    texture::set_placeholder(config.missing_texture);
+    bvh::set_cache_dir(config.bvh_cache.clone());
+    color::set_dither(config.dither);
+    color::set_working_space(config.working_space);
+    color::set_output_transform(config.output_transform);
+    if let Err(e) = config.prepare_output_dir() {
+        println!("Cannot write output: {}", e);
+        std::process::exit(1);
+    }
+
+    if args.first().map(String::as_str) == Some("wedge") {
+        match wedge::run(&args[1..], config.threads) {
+            Ok(sheet) => save_png(sheet, &config.output_path("wedge.png"), &[]),
+            Err(e) => println!("wedge: {}", e),
+        }
+        println!("Total time cost: {}", now.elapsed().as_secs_f64());
+        return;
+    }
+
+    // `--list-scenes` prints the scenes `--builtin` takes, with their default size and spp
+    if args.iter().any(|a| a == "--list-scenes") {
+        print!("{}", scene_registry::standard().listing());
+        return;
+    }
+
+    if args.first().map(String::as_str) == Some("catalog") {
+        match scene_catalog::run(&args[1..], config.threads) {
+            Ok(sheet) => save_png(sheet, &config.output_path("catalog.png"), &[]),
+            Err(e) => println!("catalog: {}", e),
+        }
+        println!("Total time cost: {}", now.elapsed().as_secs_f64());
+        return;
+    }
+
+    if args.first().map(String::as_str) == Some("materials") {
+        match material_preview::run(&args[1..], config.threads) {
+            Ok((file_name, sheet)) => save_png(sheet, &config.output_path(&file_name), &[]),
+            Err(e) => println!("materials: {}", e),
+        }
+        println!("Total time cost: {}", now.elapsed().as_secs_f64());
+        return;
+    }
 
-    // 10k spp
-    // 800 10k 40
-    let (mut cam, world) = final_scene(800, 10000, 40);
-    cam.enable_ssaa = true;
-    cam.part_num_x = 40;
-    cam.part_num_y = 40;
-    let img = cam.render(&world);
+    let scene_file = arg_value(&args, "--scene");
+    // `--matte <name>` renders the named object alone over the key color, for compositing
+    let matte = arg_value(&args, "--matte").map(String::as_str);
+    if matte.is_some() && scene_file.is_none() {
+        println!("--matte needs a --scene with named objects");
+        std::process::exit(1);
+    }
+    // `.pbrt` files go through the pbrt importer, everything else is the native format
+    let load_scene_file = |scene: &str, frame: u32| {
+        let loaded = if scene.ends_with(".pbrt") {
+            pbrt::load_pbrt(scene)
+        } else {
+            scene_file::load_scene(scene, frame, matte)
+        };
+        loaded.unwrap_or_else(|e| {
+            println!("Cannot load scene: {}", e);
+            std::process::exit(1);
+        })
+    };
+    // `--builtin <name>` renders a scene of the registry instead of final_scene (`--list-scenes`)
+    let builtin = arg_value(&args, "--builtin").map(String::as_str);
+    // `--seed <n>` makes the render repeatable; it also picks the layout of the scenes that
+    // are laid out at random, which is that of seed 0 without it
+    let seed: Option<u64> = arg_value(&args, "--seed").map(|s| {
+        s.parse().unwrap_or_else(|_| {
+            println!("--seed expects an unsigned integer");
+            std::process::exit(1);
+        })
+    });
+    let name = match scene_file {
+        Some(scene) => {
+            let mut stem = Path::new(scene).file_stem().unwrap().to_string_lossy().into_owned();
+            if let Some(matte) = matte {
+                stem = format!("{}_{}", stem, matte);
+            }
+            stem
+        }
+        None => builtin.unwrap_or("final_scene").to_owned(),
+    };
+    // scene files are loaded per frame so that mesh sequences and tracks follow the frame
+    let load_scene = |frame: u32| match (scene_file, builtin) {
+        (Some(scene), _) => load_scene_file(scene, frame),
+        (None, Some(builtin)) => scene_registry::standard()
+            .build(builtin, seed.unwrap_or(0))
+            .unwrap_or_else(|e| {
+                println!("{}", e);
+                std::process::exit(1);
+            }),
+        (None, None) => {
+            // 10k spp
+            // 800 10k 40
+            let (mut cam, world) = final_scene(800, 10000, 40, seed.unwrap_or(0));
+            cam.enable_ssaa = true;
+            cam.part_num_x = 40;
+            cam.part_num_y = 40;
+            (cam, world)
+        }
+    };
+    let load_frame = |frame: u32| {
+        if let Some(seed) = seed {
+            util::seed_rng(seed);
+        }
+        load_scene(frame)
+    };
+    let prepare = |cam: &mut camera::Camera, world: &hittable::HittableList| {
+        cam.thread_limit = config.threads;
+        apply_preset(cam, &args);
+        apply_size(cam, &args);
+        cam.seed = seed;
+        apply_shutter(cam, &args);
+        apply_projection(cam, &args);
+        apply_render_mode(cam, config.preview, &args);
+        apply_irradiance_cache(cam, &args);
+        apply_light_sampling(cam, &args);
+        apply_ray_cones(cam, &args);
+        apply_filter(cam, &args);
+        apply_focus_assist(cam, &args);
+        apply_autofocus(cam, &args);
+        apply_denoise(cam, config.denoiser);
+        probe(cam, world, &args);
+    };
 
-    println!("Output image as \"{}\"\nAuthor: {}", path, AUTHOR);
+    // frame sequences come from `--frames <first>:<last>`, the frames of a camera path or a
+    // turntable; the camera is interpolated between the keys of the path
+    let keys = match arg_value(&args, "--camera-path") {
+        Some(csv) => camera_path::load_camera_path(csv).unwrap_or_else(|e| {
+            println!("Cannot load camera path: {}", e);
+            std::process::exit(1);
+        }),
+        None => vec![],
+    };
+    let animation = match arg_value(&args, "--frames") {
+        Some(range) => {
+            let (first, last) = range
+                .split_once(':')
+                .and_then(|(a, b)| Some((a.parse().ok()?, b.parse().ok()?)))
+                .unwrap_or_else(|| {
+                    println!("--frames expects <first>:<last>");
+                    std::process::exit(1);
+                });
+            Some(Animation::new(first, last, keys))
+        }
+        None if !keys.is_empty() => Some(Animation::from_keys(keys)),
+        // `--turntable <frames>` orbits the scene's camera around its lookat
+        None if arg_value(&args, "--turntable").is_some() => {
+            let frames = arg_value(&args, "--turntable").unwrap().parse().unwrap_or_else(|_| {
+                println!("--turntable expects a frame count");
+                std::process::exit(1);
+            });
+            Some(Animation::turntable_from(&load_frame(1).0, frames))
+        }
+        None => None,
+    };
 
-    let output_image: image::DynamicImage = image::DynamicImage::ImageRgb8(img);
-    let mut output_file: File = File::create(path).unwrap();
-    match output_image.write_to(&mut output_file, image::ImageOutputFormat::Png) {
-        Ok(_) => {}
-        Err(_) => println!("Outputting image fails."),
+    match animation {
+        Some(animation) => animation.render(load_frame, prepare, |frame, cam, img| {
+            let path = config.output_path(&animation::frame_file_name(&name, frame));
+            report_exposure(cam, &img, &args, &path);
+            save_png(img, &path, &render_metadata(&name, cam, &args));
+            report_stats(cam, &args, &path);
+            compare_with_baseline(&args, &path);
+        }),
+        None => {
+            let (mut cam, world) = load_frame(1);
+            prepare(&mut cam, &world);
+            // `--export-scene <file>` writes the scene as a scene file instead of rendering it
+            if let Some(file) = arg_value(&args, "--export-scene") {
+                match scene_export::save_scene(file, &cam, &world) {
+                    Ok(_) => println!("Scene written to \"{}\"", file),
+                    Err(e) => {
+                        println!("Cannot export scene: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            if arg_value(&args, "--trace-pixel").is_some() {
+                let out = config.output_path(&format!("{}_paths.obj", name));
+                trace_pixel(&mut cam, &world, &args, &out);
+                return;
+            }
+            if let Some(object) = arg_value(&args, "--bake") {
+                let Some(scene) = scene_file else {
+                    println!("--bake needs a --scene with named objects");
+                    std::process::exit(1);
+                };
+                let out = config.output_path(&format!("{}_{}_lightmap.png", name, object));
+                bake(&mut cam, &world, scene, object, &args, &out);
+                println!("Total time cost: {}", now.elapsed().as_secs_f64());
+                return;
+            }
+            // `--bracket <setting>=<from>:<to>:<steps>` renders the scene once per value of a
+            // camera setting (focus, defocus, exposure) into a contact sheet instead
+            if let Some(spec) = arg_value(&args, "--bracket") {
+                let sheet = wedge::WedgeAxis::parse(spec)
+                    .and_then(|axis| bracket::render_bracket(&mut cam, &world, &axis));
+                match sheet {
+                    Ok(sheet) => save_png(
+                        sheet,
+                        &config.output_path(&format!("{}_bracket.png", name)),
+                        &render_metadata(&name, &cam, &args),
+                    ),
+                    Err(e) => {
+                        println!("--bracket: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                println!("Total time cost: {}", now.elapsed().as_secs_f64());
+                return;
+            }
+            let img = cam.render(&world);
+            let path = config.output_path(&format!("{}.png", name));
+            report_exposure(&cam, &img, &args, &path);
+            save_png(img, &path, &render_metadata(&name, &cam, &args));
+            report_stats(&cam, &args, &path);
+            compare_with_baseline(&args, &path);
+        }
     }
 
     println!("Total time cost: {}", now.elapsed().as_secs_f64());
 }
+
+// `--preset draft|medium|final` overrides the scene's quality settings
+fn apply_preset(cam: &mut camera::Camera, args: &[String]) {
+    if let Some(name) = arg_value(args, "--preset") {
+        match preset::Preset::named(name) {
+            Ok(preset) => preset.apply(cam),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+// `--width <pixels>` and `--spp <samples>` override the scene's image width (keeping the
+// aspect ratio) and samples per pixel, after the preset.
+fn apply_size(cam: &mut camera::Camera, args: &[String]) {
+    if let Some(width) = arg_value(args, "--width") {
+        let width = width.parse().unwrap_or_else(|_| {
+            println!("--width expects a pixel count");
+            std::process::exit(1);
+        });
+        preset::set_image_width(cam, width);
+    }
+    if let Some(spp) = arg_value(args, "--spp") {
+        cam.sample_per_pixel = spp.parse().unwrap_or_else(|_| {
+            println!("--spp expects a sample count");
+            std::process::exit(1);
+        });
+    }
+}
+
+// `--shutter <open>:<close>` overrides the scene's shutter interval within the [0, 1] of the
+// motion; `--shutter 0.5:0.5` renders the moving scene frozen at its midpoint.
+fn apply_shutter(cam: &mut camera::Camera, args: &[String]) {
+    if let Some(shutter) = arg_value(args, "--shutter") {
+        let (open, close) = shutter
+            .split_once(':')
+            .and_then(|(a, b)| Some((a.parse().ok()?, b.parse().ok()?)))
+            .unwrap_or_else(|| {
+                println!("--shutter expects <open>:<close>");
+                std::process::exit(1);
+            });
+        cam.shutter_open = open;
+        cam.shutter_close = close;
+    }
+}
+
+// `--projection fisheye|equisolid|equirect|perspective` overrides the scene's projection;
+// equirect panoramas want an aspect ratio of 2, set in the scene.
+fn apply_projection(cam: &mut camera::Camera, args: &[String]) {
+    if let Some(name) = arg_value(args, "--projection") {
+        cam.projection = camera::Projection::named(name).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        });
+    }
+}
+
+// `--render-mode normals|depth|uv|albedo|direct|preview|path` renders a debug view of the
+// first hits or a preview instead of the image, see camera::RenderMode; `--preview-block
+// <pixels>` sets the size of the blocks that share their light in the preview (4). `--preview
+// on` (or `preview = true` in the config file) makes the preview the default.
+fn apply_render_mode(cam: &mut camera::Camera, preview: bool, args: &[String]) {
+    if preview {
+        cam.render_mode = camera::RenderMode::Preview;
+    }
+    if let Some(name) = arg_value(args, "--render-mode") {
+        cam.render_mode = camera::RenderMode::named(name).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        });
+    }
+    if let Some(block) = arg_value(args, "--preview-block") {
+        cam.preview_block = block.parse().unwrap_or_else(|_| {
+            println!("--preview-block expects a pixel count");
+            std::process::exit(1);
+        });
+    }
+}
+
+// `--irradiance-cache <accuracy>[:<samples>]` renders with an irradiance cache (e.g. 0.4,
+// 1024 gather rays per record by default), see irradiance_cache::IrradianceCache.
+fn apply_irradiance_cache(cam: &mut camera::Camera, args: &[String]) {
+    if let Some(value) = arg_value(args, "--irradiance-cache") {
+        let (accuracy, samples) = match value.split_once(':') {
+            Some((accuracy, samples)) => (accuracy.parse().ok(), samples.parse().ok()),
+            None => (value.parse().ok(), Some(1024)),
+        };
+        let (Some(accuracy), Some(samples)) = (accuracy, samples) else {
+            println!("--irradiance-cache expects <accuracy>[:<samples>]");
+            std::process::exit(1);
+        };
+        cam.irradiance_cache = Some(IrradianceCache::new(accuracy, samples));
+    }
+}
+
+// `--light-sampling bsdf|mixture[:<weight>]|mis` picks how paths find the lights, see
+// camera::LightSampling.
+fn apply_light_sampling(cam: &mut camera::Camera, args: &[String]) {
+    if let Some(name) = arg_value(args, "--light-sampling") {
+        cam.light_sampling = camera::LightSampling::named(name).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        });
+    }
+}
+
+// `--ray-cones` widens geometry thinner than a pixel to its footprint, see Camera::ray_cones.
+fn apply_ray_cones(cam: &mut camera::Camera, args: &[String]) {
+    if args.iter().any(|a| a == "--ray-cones") {
+        cam.ray_cones = true;
+    }
+}
+
+// `--filter box|tent|gaussian|mitchell|blackman-harris[:<radius>]` picks how samples are
+// shared among neighbouring pixels, see film::Filter.
+fn apply_filter(cam: &mut camera::Camera, args: &[String]) {
+    if let Some(name) = arg_value(args, "--filter") {
+        cam.filter = film::Filter::named(name).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        });
+    }
+}
+
+// `--focus-assist` tints what is in focus and `--halton-lens` samples the lens with the
+// Halton points, see Camera::focus_assist and Camera::halton_lens.
+fn apply_focus_assist(cam: &mut camera::Camera, args: &[String]) {
+    if args.iter().any(|a| a == "--focus-assist") {
+        cam.focus_assist = true;
+    }
+    if args.iter().any(|a| a == "--halton-lens") {
+        cam.halton_lens = true;
+    }
+}
+
+// `--autofocus lookat|center|<x>,<y>` sets the focus distance to lookat or to what is seen
+// at the center of the image or a pixel, see camera::Autofocus.
+fn apply_autofocus(cam: &mut camera::Camera, args: &[String]) {
+    if let Some(name) = arg_value(args, "--autofocus") {
+        cam.autofocus = Some(camera::Autofocus::named(name).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        }));
+    }
+}
+
+// `--denoise` or `--denoiser atrous` (or the config file) filters the noise out of the
+// finished image, see denoise::Denoiser; "none" leaves it to the scene.
+fn apply_denoise(cam: &mut camera::Camera, denoiser: Option<denoise::Denoiser>) {
+    if denoiser.is_some() {
+        cam.denoise = denoiser;
+    }
+}
+
+// `--probe <error>` reports the spp needed to get the noise down to the given fraction of
+// the pixel values (e.g. 0.02) and exits; `--auto-spp <error>` renders with that spp.
+fn probe(cam: &mut camera::Camera, world: &hittable::HittableList, args: &[String]) {
+    let (target, report_only) = match (arg_value(args, "--probe"), arg_value(args, "--auto-spp")) {
+        (Some(target), _) => (target, true),
+        (None, Some(target)) => (target, false),
+        (None, None) => return,
+    };
+    let target: Float = target.parse().unwrap_or_else(|_| {
+        println!("--probe and --auto-spp expect a relative error such as 0.02");
+        std::process::exit(1);
+    });
+
+    let report = cam.probe(world, 4, 8, target);
+    let min = report.tile_spp.iter().min().unwrap_or(&0);
+    let max = report.tile_spp.iter().max().unwrap_or(&0);
+    println!(
+        "probe: {} spp suggested for {}% noise (tiles need {} to {})",
+        report.suggested_spp,
+        target * 100.0,
+        min,
+        max
+    );
+    if report_only {
+        for row in report.tile_spp.chunks(cam.part_num_x as usize) {
+            let row: Vec<String> = row.iter().map(|spp| format!("{:>6}", spp)).collect();
+            println!("{}", row.join(""));
+        }
+        std::process::exit(0);
+    }
+    cam.sample_per_pixel = report.suggested_spp;
+}
+
+// `--trace-pixel <x>,<y>` traces `--trace-samples` paths (16 unless told) through a pixel
+// instead of rendering, prints every bounce of them and writes them as lines to
+// `--trace-out <file>`, "<output>_paths.obj" unless told; a ".ply" file colours them by
+// their radiance. See Camera::trace_pixel.
+fn trace_pixel(
+    cam: &mut camera::Camera,
+    world: &hittable::HittableList,
+    args: &[String],
+    out: &Path,
+) {
+    let pixel = arg_value(args, "--trace-pixel").unwrap();
+    let Some((i, j)) = pixel
+        .split_once(',')
+        .and_then(|(x, y)| Some((x.parse::<u32>().ok()?, y.parse::<u32>().ok()?)))
+    else {
+        println!("--trace-pixel expects <x>,<y>");
+        std::process::exit(1);
+    };
+    let samples = match arg_value(args, "--trace-samples") {
+        Some(samples) => samples.parse().unwrap_or_else(|_| {
+            println!("--trace-samples expects a number of paths");
+            std::process::exit(1);
+        }),
+        None => 16,
+    };
+    let paths = cam.trace_pixel(world, i, j, samples);
+    for (k, path) in paths.iter().enumerate() {
+        println!("{}", path.describe(k));
+    }
+    println!("pixel ({}, {}): {}", i, j, ray_paths::summary(&paths));
+    let file = match arg_value(args, "--trace-out") {
+        Some(file) => file.clone(),
+        None => out.to_string_lossy().into_owned(),
+    };
+    match ray_paths::save_paths(&file, &paths) {
+        Ok(_) => println!("Paths written to \"{}\"", file),
+        Err(e) => {
+            println!("Cannot write paths: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// `--bake <name>` bakes the light arriving at the surfaces of the scene's object `name`
+// into a lightmap atlas instead of rendering, `--bake-size` texels square per surface (256
+// unless told), for using as a texture over the object's own texture coordinates elsewhere.
+// See Camera::bake.
+fn bake(
+    cam: &mut camera::Camera,
+    world: &hittable::HittableList,
+    scene: &str,
+    object: &str,
+    args: &[String],
+    out: &Path,
+) {
+    let size = match arg_value(args, "--bake-size") {
+        Some(size) => size.parse().unwrap_or_else(|_| {
+            println!("--bake-size expects a number of texels");
+            std::process::exit(1);
+        }),
+        None => 256,
+    };
+    let objects = scene_file::load_named(scene, 1, object).unwrap_or_else(|e| {
+        println!("Cannot bake: {}", e);
+        std::process::exit(1);
+    });
+    let mut surfaces = hittable::HittableList::new();
+    objects.collect_surfaces(&mut surfaces);
+    if surfaces.objects.is_empty() {
+        println!(
+            "Cannot bake: \"{}\" has no surfaces with texture coordinates",
+            object
+        );
+        std::process::exit(1);
+    }
+    let mut lightmap = cam.bake(world, &surfaces, size);
+    lightmap.dilate(2);
+    for k in 0..lightmap.surfaces {
+        let (x, y) = lightmap.cell(k);
+        println!(
+            "surface {}: texels ({}, {}) to ({}, {})",
+            k,
+            x,
+            y,
+            x + size,
+            y + size
+        );
+    }
+    save_png(lightmap.image(), out, &[]);
+}
+
+// Prints the render statistics; `--path-depths` adds the histogram of path lengths and
+// `--stats-json` also writes them to "<output>_stats.json".
+fn report_stats(cam: &camera::Camera, args: &[String], output: &Path) {
+    println!("{}", cam.stats.summary());
+    if args.iter().any(|a| a == "--path-depths") {
+        println!("{}", cam.stats.depth_chart());
+    }
+    if args.iter().any(|a| a == "--stats-json") {
+        let json = output.with_file_name(format!(
+            "{}_stats.json",
+            output.file_stem().unwrap().to_string_lossy()
+        ));
+        match std::fs::write(&json, cam.stats.to_json()) {
+            Ok(_) => println!("Statistics written to \"{}\"", json.display()),
+            Err(e) => println!("Cannot write statistics: {}", e),
+        }
+    }
+}
+
+// Prints how much of the image clips or stays black, see histogram::ExposureReport;
+// `--histogram` adds the luminance histogram and `--zebra` writes "<output>_zebra.png", the
+// image with stripes over those pixels.
+fn report_exposure(cam: &camera::Camera, img: &RgbImage, args: &[String], output: &Path) {
+    let Some(exposure) = &cam.exposure else {
+        return;
+    };
+    println!("{}", exposure.summary());
+    if args.iter().any(|a| a == "--histogram") {
+        println!("{}", exposure.chart());
+    }
+    if args.iter().any(|a| a == "--zebra") {
+        let zebra = output.with_file_name(format!(
+            "{}_zebra.png",
+            output.file_stem().unwrap().to_string_lossy()
+        ));
+        save_png(exposure.zebra(img), &zebra, &[]);
+    }
+}
+
+// `--compare <baseline.png>` writes "<output>_compare.html", an A/B slider between the
+// baseline and the new render.
+fn compare_with_baseline(args: &[String], output: &Path) {
+    if let Some(baseline) = arg_value(args, "--compare") {
+        let html = output.with_file_name(format!(
+            "{}_compare.html",
+            output.file_stem().unwrap().to_string_lossy()
+        ));
+        match compare::write_ab_slider(Path::new(baseline), output, &html) {
+            Ok(_) => println!("Comparison written to \"{}\"", html.display()),
+            Err(e) => println!("Cannot write comparison: {}", e),
+        }
+    }
+}
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1))
+}
+
+// What goes into the PNG of a render of the scene `name` besides what save_png always
+// writes: how it was rendered, so that a file in the output directory says where it came
+// from.
+fn render_metadata(
+    name: &str,
+    cam: &camera::Camera,
+    args: &[String],
+) -> Vec<(&'static str, String)> {
+    vec![
+        ("Title", name.to_owned()),
+        ("Samples per pixel", cam.sample_per_pixel.to_string()),
+        (
+            "Seed",
+            cam.seed.map_or("none".to_owned(), |seed| seed.to_string()),
+        ),
+        ("Render time", format!("{:.2} s", cam.stats.render_seconds)),
+        ("Command", args.join(" ")),
+    ]
+}
+
+// Writes `img` with text chunks for the author, the renderer and its version, the resolution
+// and `metadata` (keyword and text), which image viewers show as the file's properties.
+fn save_png(img: RgbImage, path: &Path, metadata: &[(&str, String)]) {
+    println!("Output image as \"{}\"\nAuthor: {}", path.display(), AUTHOR);
+
+    // file names may reach into subdirectories of the output directory
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let output_file: File = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("Cannot create \"{}\": {}", path.display(), e);
+            return;
+        }
+    };
+    let mut encoder = png::Encoder::new(BufWriter::new(output_file), img.width(), img.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let text = [
+        ("Author", AUTHOR.to_owned()),
+        (
+            "Software",
+            format!("ray_tracer {}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("Resolution", format!("{}x{}", img.width(), img.height())),
+    ];
+    for (keyword, value) in text.iter().chain(metadata) {
+        // tEXt is Latin-1, iTXt takes anything in UTF-8
+        let added = if value.is_ascii() {
+            encoder.add_text_chunk(keyword.to_string(), value.clone())
+        } else {
+            encoder.add_itxt_chunk(keyword.to_string(), value.clone())
+        };
+        if let Err(e) = added {
+            println!("Cannot add \"{}\" to the image: {}", keyword, e);
+        }
+    }
+    let written = encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(img.as_raw()));
+    if written.is_err() {
+        println!("Outputting image fails.");
+    }
+}
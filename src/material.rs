@@ -1,9 +1,16 @@
+use crate::vec3::consts::PI;
+use crate::vec3::Float;
 use std::sync::Arc;
 
 use crate::{
+    color::wavelength_to_rgb,
     hittable::HitRecord,
+    scene_export::{vec3, SceneWriter},
     texture::{SolidColor, Texture},
-    util::{random_in_unit_sphere, reflect, refract, Ray, Vec3},
+    util::{
+        random_f64_0_1, random_f64_ranged, random_in_unit_sphere, reflect, reflectance, refract,
+        Ray, Vec3,
+    },
 };
 
 pub trait Material {
@@ -17,9 +24,57 @@ pub trait Material {
         false
     }
 
-    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
         Vec3::zero()
     }
+
+    // coverage in [0, 1] at a surface point, below 1 the surface is partly cut away
+    fn alpha(&self, u: Float, v: Float, p: Vec3) -> Float {
+        1.0
+    }
+
+    // The colour of the surface at a point, without lighting, for RenderMode::Albedo.
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::zero()
+    }
+
+    // true if the material scatters like Lambertian: albedo times the light arriving over
+    // the cosine-weighted hemisphere, which the irradiance cache can stand in for
+    fn is_diffuse(&self) -> bool {
+        false
+    }
+
+    // true if the material can emit light, so surfaces made of it are sampled as lights
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // true if the material only emits on the side the surface's normal points to
+    fn is_one_sided(&self) -> bool {
+        false
+    }
+
+    // Which ways of finding the lights the camera's light sampling uses on the material, see
+    // Estimator
+    fn estimator(&self) -> Estimator {
+        Estimator::Both
+    }
+
+    // What follows `material <name>` in a scene file (scene_export); phase functions of
+    // media give the albedo and emission options of a `medium` statement instead.
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Err(format!(
+            "material {} can't be exported",
+            std::any::type_name::<Self>()
+        ))
+    }
+}
+
+// Stochastic alpha test for primitives: true if the ray should pass through the surface
+// at this point as if it wasn't there.
+pub fn cut_out(mat: &dyn Material, u: Float, v: Float, p: Vec3) -> bool {
+    let alpha = mat.alpha(u, v, p);
+    alpha < 1.0 && random_f64_0_1() >= alpha
 }
 
 #[derive(Clone)]
@@ -46,20 +101,40 @@ impl Material for Lambertian {
         attenuation: &mut Vec3,
         scattered: &mut Ray,
     ) -> bool {
-        let scatter_direction = rec.normal + random_in_unit_sphere().unit();
-        *scattered = Ray::new(rec.p, scatter_direction, r_in.time);
-        *attenuation = self.tex.value(rec.u, rec.v, rec.p);
+        let mut scatter_direction = rec.normal + random_in_unit_sphere().unit();
+        if !rec.is_above(scatter_direction) {
+            // mirror back out of the surface instead of leaking through it
+            scatter_direction = reflect(scatter_direction, rec.geometric_normal);
+        }
+        *scattered = rec.spawn_ray(scatter_direction, r_in.time);
+        *attenuation = self.tex.value_at(rec);
+        true
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.tex.value_at(rec)
+    }
+
+    fn is_diffuse(&self) -> bool {
         true
     }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let definition = out.texture(&self.tex)?;
+        if definition.starts_with("blackbody") {
+            return Err("Lambertian of a BlackbodyTexture can't be exported".to_owned());
+        }
+        Ok(definition)
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct Metal {
     albedo: Vec3,
-    fuzz: f64,
+    fuzz: Float,
 }
 impl Metal {
-    pub fn new(albedo: Vec3, fuzz: f64) -> Self {
+    pub fn new(albedo: Vec3, fuzz: Float) -> Self {
         Self {
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
@@ -77,20 +152,217 @@ impl Material for Metal {
     ) -> bool {
         let mut reflected = reflect(r_in.b_direction, rec.normal);
         reflected = reflected.unit() + random_in_unit_sphere().unit() * self.fuzz;
-        *scattered = Ray::new(rec.p, reflected, r_in.time);
+        *scattered = rec.spawn_ray(reflected, r_in.time);
         *attenuation = self.albedo;
-        true
+        rec.is_above(reflected)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        self.albedo
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!("metal {} {}", vec3(self.albedo), self.fuzz))
+    }
+}
+
+// Metal with a measured complex index of refraction n + ik per RGB channel; the colour comes
+// from the Fresnel reflectance instead of a flat albedo, so it shifts towards white at
+// grazing angles like real metal.
+#[derive(Clone, Copy)]
+pub struct Conductor {
+    n: Vec3,
+    k: Vec3,
+    fuzz: Float,
+}
+
+impl Conductor {
+    pub fn new(n: Vec3, k: Vec3, fuzz: Float) -> Self {
+        Self {
+            n,
+            k,
+            fuzz: fuzz.clamp(0.0, 1.0),
+        }
+    }
+
+    // n and k sampled at 650, 550 and 450 nm
+    pub fn gold(fuzz: Float) -> Self {
+        Self::new(
+            Vec3::new(0.143, 0.374, 1.442),
+            Vec3::new(3.983, 2.386, 1.603),
+            fuzz,
+        )
+    }
+
+    pub fn copper(fuzz: Float) -> Self {
+        Self::new(
+            Vec3::new(0.200, 0.924, 1.102),
+            Vec3::new(3.912, 2.452, 2.142),
+            fuzz,
+        )
+    }
+
+    pub fn aluminum(fuzz: Float) -> Self {
+        Self::new(
+            Vec3::new(1.657, 0.880, 0.521),
+            Vec3::new(9.224, 6.270, 4.837),
+            fuzz,
+        )
+    }
+
+    pub fn silver(fuzz: Float) -> Self {
+        Self::new(
+            Vec3::new(0.155, 0.117, 0.138),
+            Vec3::new(4.828, 3.122, 2.147),
+            fuzz,
+        )
+    }
+
+    // unpolarized Fresnel reflectance of a conductor for one channel
+    fn fresnel(cos_theta: Float, n: Float, k: Float) -> Float {
+        let cos2 = cos_theta * cos_theta;
+        let sin2 = 1.0 - cos2;
+        let t0 = n * n - k * k - sin2;
+        let a2b2 = (t0 * t0 + 4.0 * n * n * k * k).sqrt();
+        let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+        let t1 = a2b2 + cos2;
+        let t2 = 2.0 * a * cos_theta;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let direction = r_in.b_direction.unit();
+        let cos_theta = (-direction * rec.normal).clamp(0.0, 1.0);
+        let reflected = reflect(direction, rec.normal) + random_in_unit_sphere().unit() * self.fuzz;
+        *scattered = rec.spawn_ray(reflected, r_in.time);
+        *attenuation = Vec3::new(
+            Self::fresnel(cos_theta, self.n.x, self.k.x),
+            Self::fresnel(cos_theta, self.n.y, self.k.y),
+            Self::fresnel(cos_theta, self.n.z, self.k.z),
+        );
+        rec.is_above(reflected)
+    }
+
+    // the reflectance head-on
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::new(
+            Self::fresnel(1.0, self.n.x, self.k.x),
+            Self::fresnel(1.0, self.n.y, self.k.y),
+            Self::fresnel(1.0, self.n.z, self.k.z),
+        )
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!(
+            "conductor n {} k {} {}",
+            vec3(self.n),
+            vec3(self.k),
+            self.fuzz
+        ))
+    }
+}
+
+// Wavelength dependent index of refraction, wavelengths in micrometres.
+#[derive(Clone, Copy)]
+pub enum Dispersion {
+    // n = a + b / lambda^2
+    Cauchy { a: Float, b: Float },
+    // n^2 = 1 + sum(b_i * lambda^2 / (lambda^2 - c_i))
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+impl Dispersion {
+    // Schott BK7, with the catalogue's digits even where f32 drops some
+    #[allow(clippy::excessive_precision)]
+    pub fn crown_glass() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    pub fn diamond() -> Self {
+        Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.1750 * 0.1750, 0.1060 * 0.1060, 0.0],
+        }
+    }
+
+    pub fn refraction_index(&self, wavelength_nm: Float) -> Float {
+        let l2 = (wavelength_nm / 1000.0) * (wavelength_nm / 1000.0);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let mut n2 = 1.0;
+                for i in 0..3 {
+                    n2 += b[i] * l2 / (l2 - c[i]);
+                }
+                n2.sqrt()
+            }
+        }
     }
 }
 
+// visible range sampled by dispersive paths, in nm
+const WAVELENGTH_MIN: Float = 380.0;
+const WAVELENGTH_MAX: Float = 780.0;
+
 #[derive(Clone, Copy)]
 pub struct Dielectric {
-    refraction_index: f64,
+    refraction_index: Float,
+    // Beer-Lambert absorption coefficient per unit distance, zero for clear glass
+    absorption: Vec3,
+    dispersion: Option<Dispersion>,
+    // scales wavelength_to_rgb() so that a uniformly sampled wavelength averages to white
+    white_balance: Vec3,
 }
 
 impl Dielectric {
-    pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+    pub fn new(refraction_index: Float) -> Self {
+        Self {
+            refraction_index,
+            absorption: Vec3::zero(),
+            dispersion: None,
+            white_balance: Vec3::ones(),
+        }
+    }
+
+    // Light travelling 1 / density units through the glass is tinted by `color`.
+    pub fn with_absorption(refraction_index: Float, color: Vec3, density: Float) -> Self {
+        let absorb = |c: Float| -c.max(1e-6).ln() * density;
+        Self {
+            absorption: Vec3::new(absorb(color.x), absorb(color.y), absorb(color.z)),
+            ..Self::new(refraction_index)
+        }
+    }
+
+    // Paths hitting this glass pick a single wavelength and refract with its own index,
+    // which splits white light into a rainbow (prisms, diamonds).
+    pub fn with_dispersion(dispersion: Dispersion) -> Self {
+        let steps = 400;
+        let mut average = Vec3::zero();
+        for i in 0..steps {
+            let wavelength = WAVELENGTH_MIN
+                + (WAVELENGTH_MAX - WAVELENGTH_MIN) * (i as Float + 0.5) / steps as Float;
+            average += wavelength_to_rgb(wavelength) / steps as Float;
+        }
+        Self {
+            dispersion: Some(dispersion),
+            white_balance: Vec3::new(1.0 / average.x, 1.0 / average.y, 1.0 / average.z),
+            ..Self::new(dispersion.refraction_index(550.0))
+        }
     }
 }
 
@@ -102,37 +374,546 @@ impl Material for Dielectric {
         attenuation: &mut Vec3,
         scattered: &mut Ray,
     ) -> bool {
-        *attenuation = Vec3::ones();
+        *attenuation = if rec.front_face {
+            Vec3::ones()
+        } else {
+            // the ray has been travelling inside the glass since it last crossed the surface
+            let distance = rec.t * r_in.b_direction.length();
+            Vec3::new(
+                (-self.absorption.x * distance).exp(),
+                (-self.absorption.y * distance).exp(),
+                (-self.absorption.z * distance).exp(),
+            )
+        };
+
+        let mut wavelength = r_in.wavelength;
+        let refraction_index = match self.dispersion {
+            Some(dispersion) => {
+                if wavelength == 0.0 {
+                    // first dispersive event of the path: from here on it carries one wavelength
+                    wavelength = random_f64_ranged(WAVELENGTH_MIN, WAVELENGTH_MAX);
+                    *attenuation = attenuation
+                        .component_mul(wavelength_to_rgb(wavelength))
+                        .component_mul(self.white_balance);
+                }
+                dispersion.refraction_index(wavelength)
+            }
+            None => self.refraction_index,
+        };
+
         let ri = if rec.front_face {
+            1.0 / refraction_index
+        } else {
+            refraction_index
+        };
+        let refracted: Vec3 = refract(r_in.b_direction.unit(), rec.normal, ri);
+        *scattered = rec.spawn_ray(refracted, r_in.time);
+        scattered.wavelength = wavelength;
+        true
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::ones()
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(match self.dispersion {
+            Some(Dispersion::Cauchy { a, b }) => format!("dispersive cauchy {} {}", a, b),
+            Some(Dispersion::Sellmeier { b, c }) => format!(
+                "dispersive sellmeier {} {} {}  {} {} {}",
+                b[0], b[1], b[2], c[0], c[1], c[2]
+            ),
+            None if self.absorption.near_zero() => format!("dielectric {}", self.refraction_index),
+            None => {
+                // the tint after one unit of distance
+                let a = self.absorption;
+                let color = Vec3::new((-a.x).exp(), (-a.y).exp(), (-a.z).exp());
+                format!("dielectric {} absorb {} 1", self.refraction_index, vec3(color))
+            }
+        })
+    }
+}
+
+// Frosted glass: refraction and reflection through a GGX microfacet distribution
+// (Walter et al. 2007, "Microfacet Models for Refraction through Rough Surfaces").
+#[derive(Clone, Copy)]
+pub struct RoughDielectric {
+    refraction_index: Float,
+    alpha: Float,
+}
+
+impl RoughDielectric {
+    // `roughness` in [0, 1]; 0 is (nearly) polished glass
+    pub fn new(refraction_index: Float, roughness: Float) -> Self {
+        let roughness = roughness.clamp(0.01, 1.0);
+        Self {
+            refraction_index,
+            alpha: roughness * roughness,
+        }
+    }
+
+    // Smith masking term for one direction
+    fn g1(&self, v: Vec3, m: Vec3, n: Vec3) -> Float {
+        let cos_v = v * n;
+        if (v * m) / cos_v <= 0.0 {
+            return 0.0;
+        }
+        let tan2 = (1.0 - cos_v * cos_v).max(0.0) / (cos_v * cos_v);
+        2.0 / (1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt())
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let n = rec.normal;
+        let wi = -r_in.b_direction.unit();
+        let eta = if rec.front_face {
             1.0 / self.refraction_index
         } else {
             self.refraction_index
         };
-        let refracted: Vec3 = refract(r_in.b_direction.unit(), rec.normal, ri);
-        *scattered = Ray::new(rec.p, refracted, r_in.time);
+
+        // sample a microfacet normal proportionally to D(m) * cos(theta_m)
+        let xi1 = random_f64_0_1();
+        let xi2 = random_f64_0_1();
+        let tan_theta = self.alpha * (xi1 / (1.0 - xi1)).sqrt();
+        let cos_theta = 1.0 / (1.0 + tan_theta * tan_theta).sqrt();
+        let sin_theta = tan_theta * cos_theta;
+        let phi = 2.0 * PI * xi2;
+        let helper = if n.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t = n.cross(helper).unit();
+        let b = n.cross(t);
+        let m = (t * phi.cos() + b * phi.sin()) * sin_theta + n * cos_theta;
+
+        let c = wi * m;
+        if c <= 0.0 {
+            return false;
+        }
+        let sin2_t = eta * eta * (1.0 - c * c);
+        let wo = if sin2_t >= 1.0 || reflectance(c, eta) > random_f64_0_1() {
+            m * (2.0 * c) - wi
+        } else {
+            -wi * eta + m * (eta * c - (1.0 - sin2_t).sqrt())
+        };
+        // reflections must stay above and refractions below the macro surface
+        let reflected = wo * m > 0.0;
+        if (wo * n > 0.0) != reflected || wi * n <= 0.0 {
+            return false;
+        }
+
+        let weight = c * self.g1(wi, m, n) * self.g1(wo, m, n) / ((wi * n) * (m * n));
+        *attenuation = Vec3::ones() * weight;
+        *scattered = rec.spawn_ray(wo, r_in.time);
         true
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::ones()
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!(
+            "rough_dielectric {} {}",
+            self.refraction_index,
+            self.alpha.sqrt()
+        ))
+    }
+}
+
+// Picks one of two materials per hit, `b` with probability `factor` (a constant or the red
+// channel of a texture, e.g. a black and white mask image).
+pub struct Mix {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    factor: Arc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, factor: Float) -> Self {
+        Self::from_texture(a, b, Arc::new(SolidColor::from_vec(Vec3::ones() * factor)))
+    }
+
+    pub fn from_texture(
+        a: Arc<dyn Material>,
+        b: Arc<dyn Material>,
+        factor: Arc<dyn Texture>,
+    ) -> Self {
+        Self { a, b, factor }
+    }
+}
+
+impl Material for Mix {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        if random_f64_0_1() < self.factor.value(rec.u, rec.v, rec.p).x {
+            self.b.scatter(r_in, rec, attenuation, scattered)
+        } else {
+            self.a.scatter(r_in, rec, attenuation, scattered)
+        }
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        let factor = self.factor.value(rec.u, rec.v, rec.p).x.clamp(0.0, 1.0);
+        self.a.emitted(rec) * (1.0 - factor) + self.b.emitted(rec) * factor
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        let factor = self.factor.value(rec.u, rec.v, rec.p).x.clamp(0.0, 1.0);
+        self.a.albedo(rec) * (1.0 - factor) + self.b.albedo(rec) * factor
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.a.is_emissive() || self.b.is_emissive()
+    }
+
+    fn is_one_sided(&self) -> bool {
+        let one_sided = |mat: &Arc<dyn Material>| !mat.is_emissive() || mat.is_one_sided();
+        self.is_emissive() && one_sided(&self.a) && one_sided(&self.b)
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let a = out.material(&self.a)?;
+        let b = out.material(&self.b)?;
+        let factor = out.texture(&self.factor)?;
+        let factor = match factor.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["lambertian", x, _, _] => x.to_string(),
+            ["image", file, "linear"] => format!("image {}", file),
+            _ => return Err(format!("mix factor \"{}\" can't be exported", factor)),
+        };
+        Ok(format!("mix {} {} {}", a, b, factor))
+    }
+}
+
+// A clear coat over a base material, e.g. varnish over wood or car paint: the coat takes the
+// Fresnel reflected share of the light (dielectric of index `ior`), the base the rest.
+pub struct Layer {
+    coat: Arc<dyn Material>,
+    base: Arc<dyn Material>,
+    ior: Float,
+}
+
+impl Layer {
+    pub fn new(coat: Arc<dyn Material>, base: Arc<dyn Material>, ior: Float) -> Self {
+        Self { coat, base, ior }
+    }
+}
+
+impl Material for Layer {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let cos_theta = (-r_in.b_direction.unit() * rec.normal).clamp(0.0, 1.0);
+        if random_f64_0_1() < reflectance(cos_theta, 1.0 / self.ior) {
+            self.coat.scatter(r_in, rec, attenuation, scattered)
+        } else {
+            self.base.scatter(r_in, rec, attenuation, scattered)
+        }
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        self.base.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.base.albedo(rec)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+
+    fn is_one_sided(&self) -> bool {
+        self.base.is_one_sided()
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let coat = out.material(&self.coat)?;
+        let base = out.material(&self.base)?;
+        Ok(format!("layer {} {} {}", coat, base, self.ior))
+    }
+}
+
+// Any material with holes cut by a mask texture (its red channel, white is solid), for
+// leaves, fences and decals.
+pub struct Cutout {
+    mat: Arc<dyn Material>,
+    mask: Arc<dyn Texture>,
+}
+
+impl Cutout {
+    pub fn new(mat: Arc<dyn Material>, mask: Arc<dyn Texture>) -> Self {
+        Self { mat, mask }
+    }
+}
+
+impl Material for Cutout {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.mat.scatter(r_in, rec, attenuation, scattered)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        self.mat.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.mat.albedo(rec)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_emissive()
+    }
+
+    fn is_one_sided(&self) -> bool {
+        self.mat.is_one_sided()
+    }
+
+    fn alpha(&self, u: Float, v: Float, p: Vec3) -> Float {
+        self.mask.value(u, v, p).x * self.mat.alpha(u, v, p)
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let base = out.material(&self.mat)?;
+        let mask = out.texture(&self.mask)?;
+        match mask.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["image", file, "linear"] => Ok(format!("cutout {} {}", base, file)),
+            _ => Err(format!("cutout mask \"{}\" can't be exported", mask)),
+        }
+    }
+}
+
+// The ways light sampling (camera::LightSampling) finds the lights from a diffuse surface:
+// shadow rays aimed at them and bounces that hit them by chance. Under mis every surface
+// uses both; where one of them is known to only cost rays, a material can turn it off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Estimator {
+    Both,
+    // no shadow rays, e.g. for surfaces far from small lights or lit through glass
+    Bsdf,
+    // lights found by bounces count nothing, e.g. for surfaces next to small lights; the
+    // bounces still bring the light from everything else (mis only, the others ignore it)
+    Lights,
+}
+
+impl Estimator {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "both" => Ok(Estimator::Both),
+            "bsdf" => Ok(Estimator::Bsdf),
+            "lights" => Ok(Estimator::Lights),
+            _ => Err(format!(
+                "unknown estimator \"{}\" (both, bsdf, lights)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Estimator::Both => "both",
+            Estimator::Bsdf => "bsdf",
+            Estimator::Lights => "lights",
+        }
+    }
+}
+
+// Any material with its own Estimator.
+pub struct WithEstimator {
+    mat: Arc<dyn Material>,
+    estimator: Estimator,
+}
+
+impl WithEstimator {
+    pub fn new(mat: Arc<dyn Material>, estimator: Estimator) -> Self {
+        Self { mat, estimator }
+    }
+}
+
+impl Material for WithEstimator {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.mat.scatter(r_in, rec, attenuation, scattered)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        self.mat.emitted(rec)
+    }
+
+    fn alpha(&self, u: Float, v: Float, p: Vec3) -> Float {
+        self.mat.alpha(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.mat.albedo(rec)
+    }
+
+    fn is_diffuse(&self) -> bool {
+        self.mat.is_diffuse()
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_emissive()
+    }
+
+    fn is_one_sided(&self) -> bool {
+        self.mat.is_one_sided()
+    }
+
+    fn estimator(&self) -> Estimator {
+        self.estimator
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let base = out.material(&self.mat)?;
+        Ok(format!("estimator {} {}", base, self.estimator.name()))
+    }
 }
 
 pub struct DiffuseLight {
     tex: Arc<dyn Texture>,
+    intensity: Float,
+    // one-sided lights only emit on the side their outward normal points to
+    two_sided: bool,
 }
 
 impl DiffuseLight {
+    // `tex` can be an image, e.g. a TV screen; its colour is scaled by `intensity`
+    pub fn new(tex: Arc<dyn Texture>, intensity: Float, two_sided: bool) -> Self {
+        DiffuseLight {
+            tex,
+            intensity,
+            two_sided,
+        }
+    }
+
     pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
-        DiffuseLight { tex }
+        Self::new(tex, 1.0, true)
     }
 
     pub fn from_color(emit: Vec3) -> Self {
-        DiffuseLight {
-            tex: Arc::new(SolidColor::from_vec(emit)),
-        }
+        Self::from_texture(Arc::new(SolidColor::from_vec(emit)))
     }
 }
 
 impl Material for DiffuseLight {
-    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
-        self.tex.value(u, v, p)
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        if !self.two_sided && !rec.front_face {
+            return Vec3::zero();
+        }
+        self.tex.value_at(rec) * self.intensity
+    }
+
+    // the colour of the light, not its brightness
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.tex.value_at(rec)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn is_one_sided(&self) -> bool {
+        !self.two_sided
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let tex = out.texture(&self.tex)?;
+        let mut definition = if let Some(color) = tex.strip_prefix("lambertian ") {
+            format!("light {}", color)
+        } else if let Some(image) = tex.strip_prefix("image ") {
+            format!("light_image {}", image)
+        } else {
+            return Err(format!("light texture \"{}\" can't be exported", tex));
+        };
+        definition += &format!(" intensity {}", self.intensity);
+        if !self.two_sided {
+            definition += " one_sided";
+        }
+        Ok(definition)
+    }
+}
+
+// Phase function of a glowing medium (fire, nebulae): scatters like Isotropic and emits
+// `emission` per unit distance travelled through the medium, which ConstantMedium turns into
+// emission per collision by dividing by the density.
+pub struct EmissiveIsotropic {
+    albedo: Vec3,
+    emission: Arc<dyn Texture>,
+    density: Float,
+}
+
+impl EmissiveIsotropic {
+    pub fn new(albedo: Vec3, emission: Arc<dyn Texture>, density: Float) -> Self {
+        Self {
+            albedo,
+            emission,
+            density,
+        }
+    }
+}
+
+impl Material for EmissiveIsotropic {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        // a black medium only absorbs, no point in following the path further
+        if self.albedo.near_zero() {
+            return false;
+        }
+        *scattered = Ray::new(rec.p, random_in_unit_sphere().unit(), r_in.time);
+        *attenuation = self.albedo;
+        true
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        self.emission.value(rec.u, rec.v, rec.p) / self.density
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        self.albedo
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let emission = out.texture(&self.emission)?;
+        let emission = match emission.strip_prefix("lambertian ") {
+            Some(color) => format!("emit {}", color),
+            None if emission.starts_with("blackbody") => emission,
+            None => return Err(format!("medium emission \"{}\" can't be exported", emission)),
+        };
+        Ok(format!("albedo {} {}", vec3(self.albedo), emission))
     }
 }
 
@@ -163,4 +944,16 @@ impl Material for Isotropic {
         *attenuation = self.tex.value(rec.u, rec.v, rec.p);
         return true;
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.tex.value(rec.u, rec.v, rec.p)
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let tex = out.texture(&self.tex)?;
+        match tex.strip_prefix("lambertian ") {
+            Some(color) => Ok(format!("albedo {}", color)),
+            None => Err(format!("medium albedo \"{}\" can't be exported", tex)),
+        }
+    }
 }
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use image::RgbImage;
+
+use crate::contact_sheet::{contact_sheet, positive_arg};
+use crate::scene::shader_ball;
+use crate::scene_file::load_materials;
+
+// `materials <library.scene> [--size W] [--spp N]` renders every material the library
+// defines on a shader ball and lays the thumbnails out in a labelled sheet, returned with
+// its file name "<library>_materials.png".
+pub fn run(args: &[String], thread_limit: u32) -> Result<(String, RgbImage), String> {
+    let mut library = None;
+    let mut size = 96;
+    let mut spp = 32;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--size" | "--spp" => {
+                let value = positive_arg(args, i)?;
+                if args[i] == "--size" {
+                    size = value;
+                } else {
+                    spp = value;
+                }
+                i += 1;
+            }
+            arg if !arg.starts_with("--") && library.is_none() => library = Some(arg),
+            _ => {}
+        }
+        i += 1;
+    }
+    let library = library.ok_or("materials takes a library file")?;
+
+    let materials = load_materials(library)?;
+    if materials.is_empty() {
+        return Err(format!("{}: no materials", library));
+    }
+    let mut cells = vec![];
+    for (name, mat) in materials {
+        println!("material: {}", name);
+        let (mut cam, world) = shader_ball(mat, 4.0, size, spp);
+        cam.thread_limit = thread_limit;
+        cells.push((cam.render(&world), name));
+    }
+    let columns = (cells.len() as f64).sqrt().ceil() as usize;
+    let stem = Path::new(library)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    Ok((
+        format!("{}_materials.png", stem),
+        contact_sheet(&cells, columns),
+    ))
+}
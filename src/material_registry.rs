@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use crate::material::{Conductor, Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::texture::{CheckerTexture, Texture};
+use crate::texture_registry::TextureRegistry;
+use crate::vec3::Vec3;
+
+// Materials and textures by name, defined once and shared by every object that uses them.
+// standard() holds the common ones; SceneBuilder starts from a copy of it, and scene files
+// fall back to it for material names they don't define themselves.
+#[derive(Clone)]
+pub struct MaterialRegistry {
+    materials: HashMap<String, Arc<dyn Material>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+}
+
+// Only read once built, like HittableList.
+unsafe impl Send for MaterialRegistry {}
+unsafe impl Sync for MaterialRegistry {}
+
+impl MaterialRegistry {
+    pub fn new() -> Self {
+        Self {
+            materials: HashMap::new(),
+            textures: HashMap::new(),
+        }
+    }
+
+    // Replaces an earlier material of the same name; returns the material for further use.
+    pub fn add_material(&mut self, name: &str, mat: Arc<dyn Material>) -> Arc<dyn Material> {
+        self.materials.insert(name.to_owned(), mat.clone());
+        mat
+    }
+
+    pub fn add_texture(&mut self, name: &str, tex: Arc<dyn Texture>) -> Arc<dyn Texture> {
+        self.textures.insert(name.to_owned(), tex.clone());
+        tex
+    }
+
+    pub fn material(&self, name: &str) -> Result<Arc<dyn Material>, String> {
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or(format!("unknown material \"{}\"", name))
+    }
+
+    pub fn texture(&self, name: &str) -> Result<Arc<dyn Texture>, String> {
+        self.textures
+            .get(name)
+            .cloned()
+            .ok_or(format!("unknown texture \"{}\"", name))
+    }
+
+    // The name `mat` is registered under, if it is this very material (not an equal one);
+    // the first in alphabetical order if it has several.
+    pub fn material_name(&self, mat: &Arc<dyn Material>) -> Option<&str> {
+        self.materials
+            .iter()
+            .filter(|(_, m)| Arc::ptr_eq(m, mat))
+            .map(|(name, _)| name.as_str())
+            .min()
+    }
+
+    pub fn texture_name(&self, tex: &Arc<dyn Texture>) -> Option<&str> {
+        self.textures
+            .iter()
+            .filter(|(_, t)| Arc::ptr_eq(t, tex))
+            .map(|(name, _)| name.as_str())
+            .min()
+    }
+
+    pub fn material_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.materials.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The colours of the Cornell box and the other materials the built-in scenes keep
+// redefining. Images are only read when a ray first hits them.
+pub fn standard() -> &'static MaterialRegistry {
+    static STANDARD: OnceLock<MaterialRegistry> = OnceLock::new();
+    STANDARD.get_or_init(|| {
+        let mut registry = MaterialRegistry::new();
+
+        let earth =
+            registry.add_texture("earth", TextureRegistry::new().image("earthmap.jpg", false));
+        let checker = registry.add_texture(
+            "checker",
+            Arc::new(CheckerTexture::from_color(
+                0.32,
+                Vec3::new(0.2, 0.3, 0.1),
+                Vec3::new(0.9, 0.9, 0.9),
+            )),
+        );
+
+        let lambertian = |albedo| Arc::new(Lambertian::from_color(albedo));
+        registry.add_material("white", lambertian(Vec3::new(0.73, 0.73, 0.73)));
+        registry.add_material("red", lambertian(Vec3::new(0.65, 0.05, 0.05)));
+        registry.add_material("green", lambertian(Vec3::new(0.12, 0.45, 0.15)));
+        registry.add_material("glass", Arc::new(Dielectric::new(1.5)));
+        registry.add_material("mirror", Arc::new(Metal::new(Vec3::ones(), 0.0)));
+        registry.add_material("gold", Arc::new(Conductor::gold(0.0)));
+        registry.add_material(
+            "light",
+            Arc::new(DiffuseLight::from_color(Vec3::new(15.0, 15.0, 15.0))),
+        );
+        registry.add_material("earth", Arc::new(Lambertian::from_texture(earth)));
+        registry.add_material("checker", Arc::new(Lambertian::from_texture(checker)));
+        registry
+    })
+}
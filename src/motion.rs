@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::normal_cone::NormalCone;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::{Float, Vec3};
+
+// Rigid movement over the shutter interval, as an offset from the rest position at time in [0, 1].
+#[derive(Clone)]
+pub enum Motion {
+    Static,
+    // moves by the given offset between time 0 and time 1
+    Linear(Vec3),
+    // (time, offset) keys sorted by time, linearly interpolated and held beyond the ends
+    Keyframed(Vec<(Float, Vec3)>),
+}
+
+impl Motion {
+    pub fn keyframed(mut keys: Vec<(Float, Vec3)>) -> Self {
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        match keys.len() {
+            0 => Motion::Static,
+            _ => Motion::Keyframed(keys),
+        }
+    }
+
+    pub fn is_static(&self) -> bool {
+        matches!(self, Motion::Static)
+    }
+
+    pub fn offset(&self, time: Float) -> Vec3 {
+        match self {
+            Motion::Static => Vec3::zero(),
+            Motion::Linear(offset) => *offset * time,
+            Motion::Keyframed(keys) => {
+                let next = keys.partition_point(|(t, _)| *t <= time);
+                if next == 0 {
+                    return keys[0].1;
+                }
+                if next == keys.len() {
+                    return keys[next - 1].1;
+                }
+                let (t0, p0) = keys[next - 1];
+                let (t1, p1) = keys[next];
+                p0 + (p1 - p0) * ((time - t0) / (t1 - t0))
+            }
+        }
+    }
+
+    // Box swept by `bbox` over the shutter; motion is piecewise linear, so the boxes at the
+    // ends of every segment are enough.
+    pub fn bounding_box(&self, bbox: AABB) -> AABB {
+        match self {
+            Motion::Static => bbox,
+            Motion::Linear(offset) => AABB::new_two_boxes(bbox, bbox + *offset),
+            Motion::Keyframed(keys) => keys.iter().fold(bbox + keys[0].1, |acc, (_, offset)| {
+                AABB::new_two_boxes(acc, bbox + *offset)
+            }),
+        }
+    }
+}
+
+// Attaches a Motion to any hittable: instances, quads, boxes, meshes.
+pub struct Moving {
+    object: Arc<dyn Hittable>,
+    motion: Motion,
+    bounding_box: AABB,
+}
+
+impl Moving {
+    pub fn new(object: Arc<dyn Hittable>, motion: Motion) -> Self {
+        let bounding_box = motion.bounding_box(object.bounding_box());
+        Self {
+            object,
+            motion,
+            bounding_box,
+        }
+    }
+}
+
+impl Hittable for Moving {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        // same as Translate, with the offset taken at the ray's time
+        let offset = self.motion.offset(r.time);
+        let offset_r = r.moved(r.a_origin - offset, r.b_direction);
+        if !self.object.hit(&offset_r, ray_t, rec) {
+            return false;
+        }
+        rec.p += offset;
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+
+    fn normal_bounds(&self) -> NormalCone {
+        self.object.normal_bounds()
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        out.moving(&self.motion, &*self.object)
+    }
+}
+
+// Moves any hittable through a sequence of placements over the shutter: rotated about the Y
+// axis by an angle in degrees, then translated by an offset (RotateY and Translate, animated).
+// The rotation and the translation are keyed together, so an object can turn while it
+// follows a path; both are interpolated linearly between the keys.
+pub struct Animate {
+    object: Arc<dyn Hittable>,
+    // (time, offset, angle) sorted by time, held beyond the ends like Motion::Keyframed
+    keys: Vec<(Float, Vec3, Float)>,
+    bounding_box: AABB,
+}
+
+impl Animate {
+    // `start` and `end` are (offset, angle in degrees) at time 0 and time 1.
+    pub fn new(object: Arc<dyn Hittable>, start: (Vec3, Float), end: (Vec3, Float)) -> Self {
+        Self::keyframed(object, vec![(0.0, start.0, start.1), (1.0, end.0, end.1)])
+    }
+
+    // (time, offset, angle in degrees) keys; no keys leave the object where it is.
+    pub fn keyframed(object: Arc<dyn Hittable>, mut keys: Vec<(Float, Vec3, Float)>) -> Self {
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        keys.dedup_by(|a, b| a.0 == b.0);
+        if keys.is_empty() {
+            keys.push((0.0, Vec3::zero(), 0.0));
+        }
+        let bbox = object.bounding_box();
+        // any rotation about Y stays inside the cylinder through the farthest corner
+        let mut radius: Float = 0.0;
+        for x in [bbox.x.min, bbox.x.max] {
+            for z in [bbox.z.min, bbox.z.max] {
+                radius = radius.max((x * x + z * z).sqrt());
+            }
+        }
+        let spun = AABB::new(
+            Interval::with_bounds(-radius, radius),
+            bbox.y,
+            Interval::with_bounds(-radius, radius),
+        );
+        // the offsets are piecewise linear, so the boxes at the keys are enough
+        let bounding_box = keys.iter().fold(spun + keys[0].1, |acc, (_, offset, _)| {
+            AABB::new_two_boxes(acc, spun + *offset)
+        });
+        Self {
+            object,
+            keys,
+            bounding_box,
+        }
+    }
+
+    // (offset, angle) at `time`
+    fn placement(&self, time: Float) -> (Vec3, Float) {
+        let next = self.keys.partition_point(|(t, _, _)| *t <= time);
+        if next == 0 {
+            return (self.keys[0].1, self.keys[0].2);
+        }
+        if next == self.keys.len() {
+            return (self.keys[next - 1].1, self.keys[next - 1].2);
+        }
+        let (t0, offset0, angle0) = self.keys[next - 1];
+        let (t1, offset1, angle1) = self.keys[next];
+        let f = (time - t0) / (t1 - t0);
+        (
+            offset0 + (offset1 - offset0) * f,
+            angle0 + (angle1 - angle0) * f,
+        )
+    }
+}
+
+impl Hittable for Animate {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (offset, angle) = self.placement(r.time);
+        let (sin, cos) = angle.to_radians().sin_cos();
+        // world to object space: move back, then rotate by -angle
+        let to_object = |v: Vec3| Vec3::new(cos * v.x - sin * v.z, v.y, sin * v.x + cos * v.z);
+        let to_world = |v: Vec3| Vec3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z);
+
+        let object_r = r.moved(to_object(r.a_origin - offset), to_object(r.b_direction));
+        if !self.object.hit(&object_r, ray_t, rec) {
+            return false;
+        }
+        rec.p = to_world(rec.p) + offset;
+        rec.normal = to_world(rec.normal);
+        rec.geometric_normal = to_world(rec.geometric_normal);
+        rec.lift = to_world(rec.lift);
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+}
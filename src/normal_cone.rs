@@ -0,0 +1,81 @@
+use crate::vec3::consts::PI;
+
+use crate::aabb::AABB;
+use crate::vec3::{Float, Vec3};
+
+// Bounds the outward surface normals of a group of primitives: every normal lies within
+// `theta` radians of `axis` (Conty & Kulla 2018, "Importance Sampling of Many Lights").
+#[derive(Clone, Copy)]
+pub struct NormalCone {
+    pub axis: Vec3,
+    pub theta: Float,
+}
+
+impl NormalCone {
+    pub fn new(axis: Vec3, theta: Float) -> Self {
+        Self {
+            axis: axis.unit(),
+            theta: theta.min(PI),
+        }
+    }
+
+    // no bound at all, e.g. spheres or volumes
+    pub fn everywhere() -> Self {
+        Self {
+            axis: Vec3::new(0.0, 1.0, 0.0),
+            theta: PI,
+        }
+    }
+
+    pub fn union(&self, other: NormalCone) -> Self {
+        let (a, b) = if self.theta >= other.theta {
+            (*self, other)
+        } else {
+            (other, *self)
+        };
+        if a.theta >= PI {
+            return a;
+        }
+
+        let theta_d = (a.axis * b.axis).clamp(-1.0, 1.0).acos();
+        if (theta_d + b.theta).min(PI) <= a.theta {
+            return a;
+        }
+        let theta = (a.theta + theta_d + b.theta) / 2.0;
+        if theta >= PI {
+            return Self::everywhere();
+        }
+
+        // rotate a's axis towards b's until the new cone just covers both
+        let perpendicular = b.axis - a.axis * (a.axis * b.axis);
+        if perpendicular.near_zero() {
+            return Self::everywhere();
+        }
+        let rotation = theta - a.theta;
+        Self {
+            axis: a.axis * rotation.cos() + perpendicular.unit() * rotation.sin(),
+            theta,
+        }
+    }
+
+    // Whether any surface inside `bbox` with a normal in this cone can face the point `p`.
+    // Conservative: false means every emitter in the box has its back to `p`.
+    pub fn may_face(&self, bbox: AABB, p: Vec3) -> bool {
+        if self.theta >= PI / 2.0 {
+            return true;
+        }
+        let min = Vec3::new(bbox.x.min, bbox.y.min, bbox.z.min);
+        let max = Vec3::new(bbox.x.max, bbox.y.max, bbox.z.max);
+        let center = (min + max) / 2.0;
+        let radius = (max - center).length();
+        let to_p = p - center;
+        let distance = to_p.length();
+        if distance <= radius {
+            return true;
+        }
+
+        let theta_w = (self.axis * (to_p / distance)).clamp(-1.0, 1.0).acos();
+        let theta_b = (radius / distance).asin();
+        theta_w - self.theta - theta_b < PI / 2.0
+    }
+}
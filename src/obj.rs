@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use crate::bvh::BVHNode;
+use crate::hittable::HittableList;
+use crate::material::Material;
+use crate::triangle::Triangle;
+use crate::vec3::{Float, Vec3};
+
+// Vertex positions and fan-triangulated faces (as vertex indices) of an OBJ file.
+pub struct ObjMesh {
+    pub vertices: Vec<Vec3>,
+    pub triangles: Vec<[usize; 3]>,
+    pub normals: Vec<Vec3>,
+    // normal indices per triangle, None where the face gave no normals
+    pub triangle_normals: Vec<Option<[usize; 3]>>,
+}
+
+// How the triangles of a mesh are shaded.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Shading {
+    // with the normals of the file where its faces give them, flat elsewhere
+    File,
+    // with vertex normals everywhere; where the file gives none, a vertex takes the average
+    // of the normals of the faces around it, weighted by their areas
+    Smooth,
+    // with the normals of the faces, ignoring those of the file
+    Flat,
+}
+
+impl Shading {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shading::File => "file",
+            Shading::Smooth => "smooth",
+            Shading::Flat => "flat",
+        }
+    }
+}
+
+impl ObjMesh {
+    // Replaces the normals of the triangles as `shading` asks.
+    pub fn set_shading(&mut self, shading: Shading) {
+        match shading {
+            Shading::File => {}
+            Shading::Flat => self.triangle_normals.iter_mut().for_each(|n| *n = None),
+            Shading::Smooth => {
+                // the cross product of two edges is as long as twice the face's area
+                let mut sums = vec![Vec3::zero(); self.vertices.len()];
+                for [a, b, c] in &self.triangles {
+                    let (pa, pb, pc) = (self.vertices[*a], self.vertices[*b], self.vertices[*c]);
+                    let normal = (pb - pa).cross(pc - pa);
+                    for i in [a, b, c] {
+                        sums[*i] += normal;
+                    }
+                }
+                let first = self.normals.len();
+                self.normals.extend(sums.iter().copied());
+                for (i, [a, b, c]) in self.triangles.iter().enumerate() {
+                    // vertices no face gives a direction keep the triangle flat
+                    if self.triangle_normals[i].is_none()
+                        && [a, b, c].iter().all(|v| sums[**v].length() > 0.0)
+                    {
+                        self.triangle_normals[i] = Some([first + a, first + b, first + c]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn triangle(&self, i: usize, mat: Arc<dyn Material>) -> Triangle {
+        let [a, b, c] = self.triangles[i];
+        let mut triangle = Triangle::new(self.vertices[a], self.vertices[b], self.vertices[c], mat);
+        if let Some([na, nb, nc]) = self.triangle_normals[i] {
+            triangle.set_vertex_normals([self.normals[na], self.normals[nb], self.normals[nc]]);
+        }
+        triangle
+    }
+}
+
+// Parses the geometry of a Wavefront OBJ file: `v`, `vn` and `f` statements, polygons are
+// fan-triangulated, everything else is ignored.
+pub fn parse_obj(path: &str) -> Result<ObjMesh, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut mesh = ObjMesh {
+        vertices: vec![],
+        triangles: vec![],
+        normals: vec![],
+        triangle_normals: vec![],
+    };
+    for (line_no, line) in text.lines().enumerate() {
+        let error = |msg: &str| format!("{}:{}: {}", path, line_no + 1, msg);
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let coords: Vec<Float> = tokens.take(3).filter_map(|t| t.parse().ok()).collect();
+                if coords.len() != 3 {
+                    return Err(error("vertex needs three coordinates"));
+                }
+                mesh.vertices
+                    .push(Vec3::new(coords[0], coords[1], coords[2]));
+            }
+            Some("vn") => {
+                let coords: Vec<Float> = tokens.take(3).filter_map(|t| t.parse().ok()).collect();
+                if coords.len() != 3 {
+                    return Err(error("normal needs three coordinates"));
+                }
+                mesh.normals
+                    .push(Vec3::new(coords[0], coords[1], coords[2]));
+            }
+            Some("f") => {
+                // "7", "7/1", "7/1/3" and "7//3" all refer to vertex 7, the third field is
+                // the normal; negative indices count back from the last one read
+                let resolve = |token: &str, field: Option<&str>, count: usize| {
+                    let index: i64 = field
+                        .and_then(|i| i.parse().ok())
+                        .ok_or(error(&format!("bad face index \"{}\"", token)))?;
+                    let index = if index < 0 {
+                        count as i64 + index
+                    } else {
+                        index - 1
+                    };
+                    if index < 0 || index as usize >= count {
+                        return Err(error(&format!("face index \"{}\" out of range", token)));
+                    }
+                    Ok(index as usize)
+                };
+                let mut corners = vec![];
+                let mut normals = vec![];
+                for token in tokens {
+                    let mut fields = token.split('/');
+                    corners.push(resolve(token, fields.next(), mesh.vertices.len())?);
+                    match fields.nth(1) {
+                        Some(n) if !n.is_empty() => {
+                            normals.push(resolve(token, Some(n), mesh.normals.len())?)
+                        }
+                        _ => {}
+                    }
+                }
+                if corners.len() < 3 {
+                    return Err(error("face needs at least three vertices"));
+                }
+                let smooth = normals.len() == corners.len();
+                for i in 1..corners.len() - 1 {
+                    mesh.triangles
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                    mesh.triangle_normals.push(if smooth {
+                        Some([normals[0], normals[i], normals[i + 1]])
+                    } else {
+                        None
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if mesh.triangles.is_empty() {
+        return Err(format!("{}: no faces", path));
+    }
+    Ok(mesh)
+}
+
+// Loads an OBJ file into a BVH of triangles.
+pub fn load_obj(path: &str, mat: Arc<dyn Material>, shading: Shading) -> Result<BVHNode, String> {
+    let mut mesh = parse_obj(path)?;
+    mesh.set_shading(shading);
+    let mut triangles = HittableList::new();
+    for i in 0..mesh.triangles.len() {
+        triangles.add(Arc::new(mesh.triangle(i, mat.clone())));
+    }
+    Ok(BVHNode::new(triangles))
+}
+
+// Loads `path` with per-vertex velocities taken from `next_path`, the following frame of the
+// same deforming mesh, so the shutter interval spans the motion between the two frames.
+pub fn load_obj_moving(
+    path: &str,
+    next_path: &str,
+    mat: Arc<dyn Material>,
+    shading: Shading,
+) -> Result<BVHNode, String> {
+    let mut mesh = parse_obj(path)?;
+    mesh.set_shading(shading);
+    let next = parse_obj(next_path)?;
+    if next.vertices.len() != mesh.vertices.len() {
+        return Err(format!(
+            "{}: vertex count differs from {}, cannot derive velocities",
+            next_path, path
+        ));
+    }
+
+    let velocity = |i: usize| next.vertices[i] - mesh.vertices[i];
+    let mut triangles = HittableList::new();
+    for (i, [a, b, c]) in mesh.triangles.iter().enumerate() {
+        let mut triangle = Triangle::new_moving(
+            mesh.vertices[*a],
+            mesh.vertices[*b],
+            mesh.vertices[*c],
+            [velocity(*a), velocity(*b), velocity(*c)],
+            mat.clone(),
+        );
+        // normals are taken from the first frame only
+        if let Some([na, nb, nc]) = mesh.triangle_normals[i] {
+            triangle.set_vertex_normals([mesh.normals[na], mesh.normals[nb], mesh.normals[nc]]);
+        }
+        triangles.add(Arc::new(triangle));
+    }
+    Ok(BVHNode::new(triangles))
+}
+
+// "cloth_####.obj" with frame 7 -> "cloth_0007.obj"; the run of '#' sets the zero padding.
+pub fn sequence_path(pattern: &str, frame: u32) -> String {
+    match pattern.find('#') {
+        Some(start) => {
+            let width = pattern[start..].chars().take_while(|c| *c == '#').count();
+            format!(
+                "{}{:0width$}{}",
+                &pattern[..start],
+                frame,
+                &pattern[start + width..],
+                width = width
+            )
+        }
+        None => pattern.to_owned(),
+    }
+}
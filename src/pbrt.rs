@@ -0,0 +1,938 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::bvh::BVHNode;
+use crate::camera::{Camera, Projection};
+use crate::color::blackbody_color;
+use crate::disk::Disk;
+use crate::hittable::HittableList;
+use crate::material::{
+    Conductor, Dielectric, DiffuseLight, Lambertian, Layer, Material, Metal, Mix, RoughDielectric,
+};
+use crate::preset;
+use crate::sphere::Sphere;
+use crate::texture::{SolidColor, Texture, UvChecker, UvTransform, Wrap};
+use crate::texture_registry::TextureRegistry;
+use crate::triangle::Triangle;
+use crate::vec3::{Float, Vec3};
+
+// Importer for the pbrt-v3 and pbrt-v4 scene formats, as far as this renderer can follow:
+//
+//   camera      LookAt, Camera "perspective" (fov, lensradius, focaldistance) or "spherical",
+//               Film resolution, Sampler pixelsamples, Integrator maxdepth
+//   transforms  Translate, Scale, Rotate, Transform, ConcatTransform, Identity,
+//               CoordinateSystem, CoordSysTransform, AttributeBegin/End, TransformBegin/End
+//   shapes      sphere, disk, trianglemesh (and loopsubdiv, as its control mesh)
+//   materials   diffuse/matte, coateddiffuse/plastic/substrate, conductor/metal, mirror,
+//               dielectric/glass/thindielectric, mix; MakeNamedMaterial, NamedMaterial
+//   textures    imagemap, checkerboard, constant
+//   lights      AreaLightSource "diffuse"; LightSource "infinite" as a uniform background
+//   files       Include, Import
+//
+// Everything else (plymesh, point and spot lights, media, object instances...) is reported
+// once and skipped. pbrt is left-handed: the scene is mirrored along x so that the image
+// comes out the same way round.
+pub fn load_pbrt(path: &str) -> Result<(Camera, HittableList), String> {
+    let dir = Path::new(path)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let mut importer = Importer {
+        dir,
+        state: GraphicsState {
+            ctm: Transform::identity(),
+            material: Some(Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)))),
+            area_light: None,
+        },
+        stack: vec![],
+        named_materials: HashMap::new(),
+        textures: HashMap::new(),
+        coordinate_systems: HashMap::new(),
+        images: TextureRegistry::new(),
+        camera: Camera::default(),
+        camera_from_world: None,
+        camera_params: Params::default(),
+        camera_type: "perspective".to_owned(),
+        film: Params::default(),
+        sampler: Params::default(),
+        integrator: Params::default(),
+        world: HittableList::new(),
+        warned: HashSet::new(),
+    };
+    importer.load_file(Path::new(path))?;
+    importer.setup_camera()?;
+
+    let mut world = importer.world;
+    if !world.objects.is_empty() {
+        world = HittableList::new_and_add(Arc::new(BVHNode::new(world)));
+    }
+    Ok((importer.camera, world))
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Transform,
+    // None for "interface" materials, whose shapes only bound media
+    material: Option<Arc<dyn Material>>,
+    area_light: Option<Arc<dyn Material>>,
+}
+
+struct Importer {
+    dir: PathBuf,
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    named_materials: HashMap<String, Option<Arc<dyn Material>>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    coordinate_systems: HashMap<String, Transform>,
+    images: TextureRegistry,
+    camera: Camera,
+    camera_from_world: Option<Transform>,
+    camera_params: Params,
+    camera_type: String,
+    film: Params,
+    sampler: Params,
+    integrator: Params,
+    world: HittableList,
+    // unsupported features already reported
+    warned: HashSet<String>,
+}
+
+impl Importer {
+    fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let tokens = tokenize(&text).map_err(|e| format!("{}:{}", path.display(), e))?;
+        let mut pos = 0;
+        while pos < tokens.len() {
+            let directive = &tokens[pos];
+            if directive.quoted || !directive.text.starts_with(|c: char| c.is_ascii_uppercase()) {
+                return Err(format!(
+                    "{}:{}: expected a directive, got \"{}\"",
+                    path.display(),
+                    directive.line,
+                    directive.text
+                ));
+            }
+            pos += 1;
+            let start = pos;
+            while pos < tokens.len() && !tokens[pos].is_directive() {
+                pos += 1;
+            }
+            let mut args = Args {
+                items: group(&tokens[start..pos]),
+                pos: 0,
+                location: format!("{}:{}", path.display(), directive.line),
+            };
+            self.directive(&directive.text, &mut args)?;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, name: &str, args: &mut Args) -> Result<(), String> {
+        match name {
+            "LookAt" => {
+                let v = args.numbers(9)?;
+                let look_at = Transform::look_at(
+                    Vec3::new(v[0], v[1], v[2]),
+                    Vec3::new(v[3], v[4], v[5]),
+                    Vec3::new(v[6], v[7], v[8]),
+                )
+                .map_err(|e| args.error(&e))?;
+                self.state.ctm = self.state.ctm.mul(&look_at);
+            }
+            "Translate" => {
+                let v = args.numbers(3)?;
+                self.state.ctm = self.state.ctm.mul(&Transform::translate(v[0], v[1], v[2]));
+            }
+            "Scale" => {
+                let v = args.numbers(3)?;
+                self.state.ctm = self.state.ctm.mul(&Transform::scale(v[0], v[1], v[2]));
+            }
+            "Rotate" => {
+                let v = args.numbers(4)?;
+                let axis = Vec3::new(v[1], v[2], v[3]);
+                self.state.ctm = self.state.ctm.mul(&Transform::rotate(v[0], axis));
+            }
+            "Transform" | "ConcatTransform" => {
+                let m = Transform::from_pbrt(&args.numbers(16)?);
+                self.state.ctm = if name == "Transform" {
+                    m
+                } else {
+                    self.state.ctm.mul(&m)
+                };
+            }
+            "Identity" => self.state.ctm = Transform::identity(),
+            "CoordinateSystem" => {
+                let name = args.string()?;
+                self.coordinate_systems.insert(name, self.state.ctm);
+            }
+            "CoordSysTransform" => {
+                let name = args.string()?;
+                match self.coordinate_systems.get(&name) {
+                    Some(ctm) => self.state.ctm = *ctm,
+                    None => return Err(args.error(&format!("no coordinate system \"{}\"", name))),
+                }
+            }
+            "Camera" => {
+                self.camera_type = args.string()?;
+                self.camera_params = args.params()?;
+                self.camera_from_world = Some(self.state.ctm);
+                // named like this in pbrt too
+                self.coordinate_systems
+                    .insert("camera".to_owned(), self.state.ctm.inverse());
+            }
+            "Film" => {
+                args.string()?;
+                self.film = args.params()?;
+            }
+            "Sampler" => {
+                args.string()?;
+                self.sampler = args.params()?;
+            }
+            "Integrator" => {
+                args.string()?;
+                self.integrator = args.params()?;
+            }
+            "WorldBegin" => {
+                self.state.ctm = Transform::identity();
+                self.coordinate_systems
+                    .insert("world".to_owned(), Transform::identity());
+            }
+            "WorldEnd" => {}
+            "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+            "AttributeEnd" | "TransformEnd" => {
+                let saved = self
+                    .stack
+                    .pop()
+                    .ok_or(args.error(&format!("{} without a begin", name)))?;
+                if name == "AttributeEnd" {
+                    self.state = saved;
+                } else {
+                    self.state.ctm = saved.ctm;
+                }
+            }
+            "Material" => {
+                let ty = args.string()?;
+                let params = args.params()?;
+                self.state.material = self.material(&ty, &params);
+            }
+            "MakeNamedMaterial" => {
+                let name = args.string()?;
+                let params = args.params()?;
+                let ty = params.string("type").unwrap_or("diffuse").to_owned();
+                let mat = self.material(&ty, &params);
+                self.named_materials.insert(name, mat);
+            }
+            "NamedMaterial" => {
+                let name = args.string()?;
+                self.state.material = self
+                    .named_materials
+                    .get(&name)
+                    .ok_or(args.error(&format!("no material named \"{}\"", name)))?
+                    .clone();
+            }
+            "Texture" => {
+                let name = args.string()?;
+                args.string()?;
+                let class = args.string()?;
+                let params = args.params()?;
+                let tex = self.texture(&class, &params);
+                self.textures.insert(name, tex);
+            }
+            "AreaLightSource" => {
+                let ty = args.string()?;
+                let params = args.params()?;
+                if ty != "diffuse" {
+                    self.warn(&format!("area light \"{}\"", ty));
+                }
+                let color = self.spectrum(&params, "L").unwrap_or(Vec3::ones());
+                self.state.area_light = Some(Arc::new(DiffuseLight::new(
+                    Arc::new(SolidColor::from_vec(color)),
+                    params.float("scale", 1.0),
+                    params.bool("twosided", false),
+                )));
+            }
+            "LightSource" => {
+                let ty = args.string()?;
+                let params = args.params()?;
+                if ty == "infinite" {
+                    if params.string("filename").is_some() {
+                        self.warn("environment maps (the infinite light is a uniform colour)");
+                    }
+                    let color = self.spectrum(&params, "L").unwrap_or(Vec3::ones());
+                    self.camera.background = color * params.float("scale", 1.0);
+                } else {
+                    self.warn(&format!("\"{}\" lights", ty));
+                }
+            }
+            "Shape" => {
+                let ty = args.string()?;
+                let params = args.params()?;
+                self.shape(&ty, &params).map_err(|e| args.error(&e))?;
+            }
+            "Include" | "Import" => {
+                let file = self.dir.join(args.string()?);
+                self.load_file(&file)?;
+            }
+            "ReverseOrientation" | "Option" | "Attribute" | "ColorSpace" | "PixelFilter"
+            | "Accelerator" | "ActiveTransform" | "TransformTimes" | "WorldEndOfOptions" => {
+                args.skip()
+            }
+            _ => {
+                self.warn(name);
+                args.skip();
+            }
+        }
+        Ok(())
+    }
+
+    fn setup_camera(&mut self) -> Result<(), String> {
+        let cam = &mut self.camera;
+        let width = self.film.int("xresolution", 1280).max(1) as u32;
+        let height = self.film.int("yresolution", 720).max(1) as u32;
+        cam.aspect_ratio = width as Float / height as Float;
+        preset::set_image_width(cam, width);
+        cam.sample_per_pixel = self.sampler.int("pixelsamples", 16).max(1) as u32;
+        cam.max_depth = self.integrator.int("maxdepth", 5).max(1) as u32;
+
+        let world_from_camera = self
+            .camera_from_world
+            .ok_or("pbrt scene without a Camera")?
+            .inverse();
+        let eye = world_from_camera.point(Vec3::zero());
+        cam.lookfrom = mirror(eye);
+        cam.lookat = mirror(eye + world_from_camera.vector(Vec3::new(0.0, 0.0, 1.0)));
+        cam.vup = mirror(world_from_camera.vector(Vec3::new(0.0, 1.0, 0.0)));
+
+        let params = &self.camera_params;
+        match self.camera_type.as_str() {
+            "perspective" => {
+                // pbrt's fov spans the shorter side of the image
+                let fov = params.float("fov", 90.0);
+                cam.vfov = if width >= height {
+                    fov
+                } else {
+                    let tan = (0.5 * fov).to_radians().tan() * height as Float / width as Float;
+                    2.0 * tan.atan().to_degrees()
+                };
+                let lens_radius = params.float("lensradius", 0.0);
+                // not pbrt's default of 1e6: camera rays span the focus distance, and rays
+                // that long make every hit look like a self-intersection
+                cam.focus_dist = params.float("focaldistance", cam.focus_dist);
+                cam.defocus_angle = 2.0 * (lens_radius / cam.focus_dist).atan().to_degrees();
+            }
+            "spherical" => cam.projection = Projection::Equirectangular,
+            other => {
+                return Err(format!(
+                    "pbrt camera \"{}\" is not supported (perspective or spherical)",
+                    other
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn shape(&mut self, ty: &str, params: &Params) -> Result<(), String> {
+        let mat = match (&self.state.area_light, &self.state.material) {
+            (Some(light), _) => light.clone(),
+            (None, Some(mat)) => mat.clone(),
+            (None, None) => return Ok(()),
+        };
+        let ctm = self.state.ctm;
+        match ty {
+            "sphere" => {
+                let center = mirror(ctm.point(Vec3::zero()));
+                let radius = params.float("radius", 1.0) * ctm.scale_factor();
+                self.world.add(Arc::new(Sphere::new(center, radius, mat)));
+            }
+            "disk" => {
+                let center = mirror(ctm.point(Vec3::new(0.0, 0.0, params.float("height", 0.0))));
+                let normal = mirror(ctm.normal(Vec3::new(0.0, 0.0, 1.0)));
+                let radius = params.float("radius", 1.0) * ctm.scale_factor();
+                self.world
+                    .add(Arc::new(Disk::new(center, normal, radius, mat)));
+            }
+            "trianglemesh" | "loopsubdiv" => {
+                let p = params.numbers("P");
+                if p.len() < 9 || !p.len().is_multiple_of(3) {
+                    return Err("trianglemesh needs at least three points P".to_owned());
+                }
+                let points: Vec<Vec3> = p
+                    .chunks(3)
+                    .map(|c| mirror(ctm.point(Vec3::new(c[0], c[1], c[2]))))
+                    .collect();
+                let normals: Vec<Vec3> = params
+                    .numbers("N")
+                    .chunks(3)
+                    .filter(|c| c.len() == 3)
+                    .map(|c| mirror(ctm.normal(Vec3::new(c[0], c[1], c[2]))))
+                    .collect();
+                let indices: Vec<usize> = match params.get("indices") {
+                    Some(param) => param.numbers.iter().map(|i| *i as usize).collect(),
+                    None if points.len() == 3 => vec![0, 1, 2],
+                    None => return Err("trianglemesh needs indices".to_owned()),
+                };
+                // the mirror turns the winding around, a transform with a negative
+                // determinant turns it back
+                let swap = ctm.determinant() > 0.0;
+                let mut mesh = HittableList::new();
+                for tri in indices.chunks(3).filter(|t| t.len() == 3) {
+                    let (a, mut b, mut c) = (tri[0], tri[1], tri[2]);
+                    if swap {
+                        (b, c) = (c, b);
+                    }
+                    if [a, b, c].iter().any(|&i| i >= points.len()) {
+                        return Err("trianglemesh index out of range".to_owned());
+                    }
+                    let mut triangle = Triangle::new(points[a], points[b], points[c], mat.clone());
+                    if normals.len() == points.len() {
+                        triangle.set_vertex_normals([normals[a], normals[b], normals[c]]);
+                    }
+                    mesh.add(Arc::new(triangle));
+                }
+                if !mesh.objects.is_empty() {
+                    self.world.add(Arc::new(BVHNode::new(mesh)));
+                }
+            }
+            other => self.warn(&format!("\"{}\" shapes", other)),
+        }
+        Ok(())
+    }
+
+    fn material(&mut self, ty: &str, params: &Params) -> Option<Arc<dyn Material>> {
+        let roughness = params.float("roughness", params.float("uroughness", 0.0));
+        Some(match ty {
+            "diffuse" | "matte" => self.diffuse(params, &["reflectance", "Kd"]),
+            "coateddiffuse" | "plastic" | "substrate" => {
+                let base = self.diffuse(params, &["reflectance", "Kd"]);
+                let coat = Arc::new(Metal::new(Vec3::ones(), roughness));
+                Arc::new(Layer::new(coat, base, params.float("eta", 1.5)))
+            }
+            "conductor" | "metal" => {
+                if let Some(color) = self.spectrum(params, "reflectance") {
+                    Arc::new(Metal::new(color, roughness))
+                } else {
+                    // the named spectra of pbrt's metals, copper by default
+                    Arc::new(match params.string("eta").unwrap_or("metal-Cu-eta") {
+                        "metal-Au-eta" => Conductor::gold(roughness),
+                        "metal-Ag-eta" => Conductor::silver(roughness),
+                        "metal-Al-eta" => Conductor::aluminum(roughness),
+                        "metal-Cu-eta" => Conductor::copper(roughness),
+                        other => {
+                            self.warn(&format!("conductor \"{}\" (copper instead)", other));
+                            Conductor::copper(roughness)
+                        }
+                    })
+                }
+            }
+            "mirror" => {
+                let color = self
+                    .spectrum(params, "Kr")
+                    .unwrap_or(Vec3::new(0.9, 0.9, 0.9));
+                Arc::new(Metal::new(color, 0.0))
+            }
+            "dielectric" | "glass" | "thindielectric" => {
+                let eta = params.float("eta", params.float("index", 1.5));
+                if roughness > 0.0 {
+                    Arc::new(RoughDielectric::new(eta, roughness))
+                } else {
+                    Arc::new(Dielectric::new(eta))
+                }
+            }
+            "mix" => {
+                let names = params.strings("materials");
+                let amount = params.float("amount", 0.5);
+                match names {
+                    [a, b] => match (self.named_materials.get(a), self.named_materials.get(b)) {
+                        (Some(Some(a)), Some(Some(b))) => {
+                            Arc::new(Mix::new(a.clone(), b.clone(), amount))
+                        }
+                        _ => {
+                            self.warn("mix of unknown or interface materials");
+                            self.diffuse(params, &[])
+                        }
+                    },
+                    _ => {
+                        self.warn("mix without two materials");
+                        self.diffuse(params, &[])
+                    }
+                }
+            }
+            "interface" | "" | "none" => return None,
+            other => {
+                self.warn(&format!("\"{}\" materials (diffuse instead)", other));
+                self.diffuse(params, &["reflectance", "Kd"])
+            }
+        })
+    }
+
+    // Lambertian of the first of `names` given as a texture or a colour, else 0.5 gray.
+    fn diffuse(&mut self, params: &Params, names: &[&str]) -> Arc<dyn Material> {
+        for name in names {
+            if let Some(tex) = params.texture(name) {
+                match self.textures.get(tex) {
+                    Some(tex) => return Arc::new(Lambertian::from_texture(tex.clone())),
+                    None => self.warn(&format!("undefined texture \"{}\"", tex)),
+                }
+            }
+            if let Some(color) = self.spectrum(params, name) {
+                return Arc::new(Lambertian::from_color(color));
+            }
+        }
+        Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn texture(&mut self, class: &str, params: &Params) -> Arc<dyn Texture> {
+        let scale = (params.float("uscale", 1.0), params.float("vscale", 1.0));
+        match class {
+            "imagemap" => {
+                let file = self.dir.join(params.string("filename").unwrap_or(""));
+                let tex = self.images.image(&file.to_string_lossy(), false);
+                if scale == (1.0, 1.0) {
+                    tex
+                } else {
+                    Arc::new(UvTransform::new(tex, scale, 0.0, (0.0, 0.0), Wrap::Repeat))
+                }
+            }
+            "checkerboard" => {
+                let even = self.spectrum(params, "tex1").unwrap_or(Vec3::ones());
+                let odd = self.spectrum(params, "tex2").unwrap_or(Vec3::zero());
+                Arc::new(UvChecker::new(scale.0, even, odd))
+            }
+            "constant" => {
+                let color = self.spectrum(params, "value").unwrap_or(Vec3::ones());
+                Arc::new(SolidColor::from_vec(color))
+            }
+            other => {
+                self.warn(&format!("\"{}\" textures (gray instead)", other));
+                Arc::new(SolidColor::from_vec(Vec3::new(0.5, 0.5, 0.5)))
+            }
+        }
+    }
+
+    // An RGB value of the parameter, whichever way pbrt spells it.
+    fn spectrum(&mut self, params: &Params, name: &str) -> Option<Vec3> {
+        let param = params.get(name)?;
+        let n = &param.numbers;
+        match param.ty.as_str() {
+            "rgb" | "color" if n.len() >= 3 => Some(Vec3::new(n[0], n[1], n[2])),
+            // pbrt-v3 adds a scale after the temperature
+            "blackbody" if !n.is_empty() => {
+                Some(blackbody_color(n[0]) * n.get(1).copied().unwrap_or(1.0))
+            }
+            "spectrum" if n.len() >= 2 => {
+                // (wavelength, value) pairs, taken as gray
+                let values: Vec<Float> = n.iter().skip(1).step_by(2).copied().collect();
+                Some(Vec3::ones() * (values.iter().sum::<Float>() / values.len() as Float))
+            }
+            "float" if !n.is_empty() => Some(Vec3::ones() * n[0]),
+            _ => {
+                self.warn(&format!("\"{} {}\" values", param.ty, name));
+                None
+            }
+        }
+    }
+
+    fn warn(&mut self, what: &str) {
+        if self.warned.insert(what.to_owned()) {
+            println!("pbrt: {} not supported, ignored", what);
+        }
+    }
+}
+
+// pbrt's left-handed world in this renderer's right-handed one
+fn mirror(v: Vec3) -> Vec3 {
+    Vec3::new(-v.x, v.y, v.z)
+}
+
+struct Token {
+    text: String,
+    quoted: bool,
+    line: usize,
+}
+
+impl Token {
+    fn is_directive(&self) -> bool {
+        !self.quoted && self.text.starts_with(|c: char| c.is_ascii_uppercase())
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\n') | None => return Err(format!("{}: unterminated string", line)),
+                        Some(c) => s.push(c),
+                    }
+                }
+                tokens.push(Token {
+                    text: s,
+                    quoted: true,
+                    line,
+                });
+            }
+            '[' | ']' => {
+                chars.next();
+                tokens.push(Token {
+                    text: c.to_string(),
+                    quoted: false,
+                    line,
+                });
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]\"#".contains(c) {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(Token {
+                    text: s,
+                    quoted: false,
+                    line,
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// A single value or a bracketed list of them.
+enum Item<'a> {
+    Single(&'a Token),
+    List(Vec<&'a Token>),
+}
+
+fn group(tokens: &[Token]) -> Vec<Item<'_>> {
+    let mut items = vec![];
+    let mut list: Option<Vec<&Token>> = None;
+    for token in tokens {
+        match (token.quoted, token.text.as_str()) {
+            (false, "[") => list = Some(vec![]),
+            (false, "]") => items.push(Item::List(list.take().unwrap_or_default())),
+            _ => match &mut list {
+                Some(list) => list.push(token),
+                None => items.push(Item::Single(token)),
+            },
+        }
+    }
+    items
+}
+
+struct Args<'a> {
+    items: Vec<Item<'a>>,
+    pos: usize,
+    location: String,
+}
+
+impl<'a> Args<'a> {
+    fn error(&self, msg: &str) -> String {
+        format!("{}: {}", self.location, msg)
+    }
+
+    fn next_tokens(&mut self) -> Result<Vec<&'a Token>, String> {
+        let item = self
+            .items
+            .get(self.pos)
+            .ok_or(self.error("missing argument"))?;
+        self.pos += 1;
+        Ok(match item {
+            Item::Single(token) => vec![*token],
+            Item::List(tokens) => tokens.clone(),
+        })
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        match self.next_tokens()?.as_slice() {
+            [token] if token.quoted => Ok(token.text.clone()),
+            _ => Err(self.error("expected a quoted string")),
+        }
+    }
+
+    // `n` numbers, one by one or in brackets
+    fn numbers(&mut self, n: usize) -> Result<Vec<Float>, String> {
+        let mut numbers = vec![];
+        while numbers.len() < n {
+            for token in self.next_tokens()? {
+                numbers.push(
+                    token.text.parse().map_err(|_| {
+                        self.error(&format!("expected a number, got {}", token.text))
+                    })?,
+                );
+            }
+        }
+        if numbers.len() != n {
+            return Err(self.error(&format!("expected {} numbers", n)));
+        }
+        Ok(numbers)
+    }
+
+    // The rest as `"type name" value` pairs.
+    fn params(&mut self) -> Result<Params, String> {
+        let mut params = Params::default();
+        while self.pos < self.items.len() {
+            let declaration = self.string()?;
+            let mut words = declaration.split_whitespace();
+            let (Some(ty), Some(name), None) = (words.next(), words.next(), words.next()) else {
+                return Err(self.error(&format!("bad parameter \"{}\"", declaration)));
+            };
+            let mut param = Param {
+                ty: ty.to_owned(),
+                name: name.to_owned(),
+                numbers: vec![],
+                strings: vec![],
+            };
+            for token in self.next_tokens()? {
+                match token.text.parse::<Float>() {
+                    Ok(number) if !token.quoted => param.numbers.push(number),
+                    _ => param.strings.push(token.text.clone()),
+                }
+            }
+            params.0.push(param);
+        }
+        Ok(params)
+    }
+
+    fn skip(&mut self) {
+        self.pos = self.items.len();
+    }
+}
+
+struct Param {
+    ty: String,
+    name: String,
+    numbers: Vec<Float>,
+    // quoted values and booleans
+    strings: Vec<String>,
+}
+
+#[derive(Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    fn float(&self, name: &str, default: Float) -> Float {
+        self.get(name)
+            .and_then(|p| p.numbers.first().copied())
+            .unwrap_or(default)
+    }
+
+    fn int(&self, name: &str, default: i64) -> i64 {
+        self.float(name, default as Float) as i64
+    }
+
+    fn numbers(&self, name: &str) -> &[Float] {
+        self.get(name).map_or(&[], |p| p.numbers.as_slice())
+    }
+
+    fn strings(&self, name: &str) -> &[String] {
+        self.get(name).map_or(&[], |p| p.strings.as_slice())
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.strings(name).first().map(String::as_str)
+    }
+
+    fn bool(&self, name: &str, default: bool) -> bool {
+        self.string(name).map_or(default, |s| s == "true")
+    }
+
+    // the name of the texture given for `name`, if it is given as one
+    fn texture(&self, name: &str) -> Option<&str> {
+        self.get(name)
+            .filter(|p| p.ty == "texture")
+            .and_then(|p| p.strings.first())
+            .map(String::as_str)
+    }
+}
+
+// Row-major 4x4 matrix acting on column vectors.
+#[derive(Clone, Copy)]
+struct Transform {
+    m: [[Float; 4]; 4],
+}
+
+impl Transform {
+    fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Self { m }
+    }
+
+    fn translate(x: Float, y: Float, z: Float) -> Self {
+        let mut t = Self::identity();
+        t.m[0][3] = x;
+        t.m[1][3] = y;
+        t.m[2][3] = z;
+        t
+    }
+
+    fn scale(x: Float, y: Float, z: Float) -> Self {
+        let mut t = Self::identity();
+        t.m[0][0] = x;
+        t.m[1][1] = y;
+        t.m[2][2] = z;
+        t
+    }
+
+    // `degrees` about `axis`
+    fn rotate(degrees: Float, axis: Vec3) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut t = Self::identity();
+        t.m[0] = [
+            a.x * a.x + (1.0 - a.x * a.x) * cos,
+            a.x * a.y * (1.0 - cos) - a.z * sin,
+            a.x * a.z * (1.0 - cos) + a.y * sin,
+            0.0,
+        ];
+        t.m[1] = [
+            a.x * a.y * (1.0 - cos) + a.z * sin,
+            a.y * a.y + (1.0 - a.y * a.y) * cos,
+            a.y * a.z * (1.0 - cos) - a.x * sin,
+            0.0,
+        ];
+        t.m[2] = [
+            a.x * a.z * (1.0 - cos) - a.y * sin,
+            a.y * a.z * (1.0 - cos) + a.x * sin,
+            a.z * a.z + (1.0 - a.z * a.z) * cos,
+            0.0,
+        ];
+        t
+    }
+
+    // camera from world, as pbrt's LookAt
+    fn look_at(eye: Vec3, look: Vec3, up: Vec3) -> Result<Self, String> {
+        let dir = (look - eye).unit();
+        let right = up.unit().cross(dir);
+        if right.near_zero() {
+            return Err("LookAt: the up vector is parallel to the view direction".to_owned());
+        }
+        let right = right.unit();
+        let new_up = dir.cross(right);
+        let mut world_from_camera = Self::identity();
+        for (i, column) in [right, new_up, dir, eye].iter().enumerate() {
+            world_from_camera.m[0][i] = column.x;
+            world_from_camera.m[1][i] = column.y;
+            world_from_camera.m[2][i] = column.z;
+        }
+        Ok(world_from_camera.inverse())
+    }
+
+    // pbrt lists matrices column by column
+    fn from_pbrt(v: &[Float]) -> Self {
+        let mut t = Self::identity();
+        for (i, value) in v.iter().enumerate() {
+            t.m[i % 4][i / 4] = *value;
+        }
+        t
+    }
+
+    fn mul(&self, o: &Transform) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * o.m[k][j]).sum();
+            }
+        }
+        Self { m }
+    }
+
+    fn point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        (self.vector(p) + Vec3::new(m[0][3], m[1][3], m[2][3])) * (1.0 / w)
+    }
+
+    fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    // by the inverse transpose, so normals stay perpendicular under non-uniform scaling
+    fn normal(&self, n: Vec3) -> Vec3 {
+        let inv = self.inverse().m;
+        Vec3::new(
+            inv[0][0] * n.x + inv[1][0] * n.y + inv[2][0] * n.z,
+            inv[0][1] * n.x + inv[1][1] * n.y + inv[2][1] * n.z,
+            inv[0][2] * n.x + inv[1][2] * n.y + inv[2][2] * n.z,
+        )
+        .unit()
+    }
+
+    // of the upper 3x3 part
+    fn determinant(&self) -> Float {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // how much the transform scales lengths, averaged over the axes (spheres stay round)
+    fn scale_factor(&self) -> Float {
+        self.determinant().abs().cbrt()
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; singular matrices give the identity.
+    fn inverse(&self) -> Self {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return Self::identity();
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let d = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= d;
+                inv[col][j] /= d;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Self { m: inv }
+    }
+}
@@ -1,4 +1,5 @@
-use crate::util::{random_i32_ranged, random_in_unit_sphere, Vec3};
+use crate::util::{random_i32_ranged, random_in_unit_sphere, random_positive_vec3, Vec3};
+use crate::vec3::Float;
 
 const POINT_COUNT: usize = 256;
 
@@ -27,7 +28,7 @@ impl Perlin {
         ret
     }
 
-    pub fn noise(&self, p: Vec3) -> f64 {
+    pub fn noise(&self, p: Vec3) -> Float {
         let mut u = p.x() - p.x().floor();
         let mut v = p.y() - p.y().floor();
         let mut w = p.z() - p.z().floor();
@@ -59,7 +60,7 @@ impl Perlin {
         */
     }
 
-    pub fn turb(&self, p: Vec3, depth: i32) -> f64 {
+    pub fn turb(&self, p: Vec3, depth: i32) -> Float {
         let mut accum = 0.0;
         let mut temp_p = p;
         let mut weight = 1.0;
@@ -73,7 +74,7 @@ impl Perlin {
         accum.abs()
     }
 
-    fn trilinear_interpolate(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+    fn trilinear_interpolate(c: &[[[Vec3; 2]; 2]; 2], u: Float, v: Float, w: Float) -> Float {
         let uu = u * u * (3.0 - 2.0 * u);
         let vv = v * v * (3.0 - 2.0 * v);
         let ww = w * w * (3.0 - 2.0 * w);
@@ -81,10 +82,10 @@ impl Perlin {
         for i in 0..2 {
             for j in 0..2 {
                 for k in 0..2 {
-                    let weight_v = Vec3::new(u - i as f64, v - j as f64, w - k as f64);
-                    accum += (i as f64 * uu + (1.0 - i as f64) * (1.0 - uu))
-                        * (j as f64 * vv + (1.0 - j as f64) * (1.0 - vv))
-                        * (k as f64 * ww + (1.0 - k as f64) * (1.0 - ww))
+                    let weight_v = Vec3::new(u - i as Float, v - j as Float, w - k as Float);
+                    accum += (i as Float * uu + (1.0 - i as Float) * (1.0 - uu))
+                        * (j as Float * vv + (1.0 - j as Float) * (1.0 - vv))
+                        * (k as Float * ww + (1.0 - k as Float) * (1.0 - ww))
                         * c[i][j][k]
                         * weight_v;
                 }
@@ -109,3 +110,151 @@ impl Perlin {
         }
     }
 }
+
+// Worley (cellular) noise: one random feature point per unit cell, distances to the nearest
+// and second nearest point. F1 gives cells and pebbles, F2 - F1 thin cracks along the cell
+// borders.
+pub struct Worley {
+    points: [Vec3; POINT_COUNT],
+    perm: [i32; POINT_COUNT],
+}
+
+impl Worley {
+    pub fn new() -> Self {
+        let mut ret = Self {
+            points: [Vec3::zero(); POINT_COUNT],
+            perm: [0; POINT_COUNT],
+        };
+        for i in 0..POINT_COUNT {
+            ret.points[i] = random_positive_vec3();
+        }
+        Perlin::perlin_generate_perm(&mut ret.perm);
+        ret
+    }
+
+    // (F1, F2)
+    pub fn distances(&self, p: Vec3) -> (Float, Float) {
+        let i = p.x().floor() as i32;
+        let j = p.y().floor() as i32;
+        let k = p.z().floor() as i32;
+        let mut f1 = Float::INFINITY;
+        let mut f2 = Float::INFINITY;
+
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let (ci, cj, ck) = (i + di, j + dj, k + dk);
+                    let point = Vec3::new(ci as Float, cj as Float, ck as Float)
+                        + self.points[hash(&self.perm, ci, cj, ck)];
+                    let d = (point - p).length();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+impl Default for Worley {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 3D simplex noise (Gustavson's formulation), in about [-1, 1]. Smoother than Perlin noise
+// and without its axis-aligned artifacts, which shows in soft patterns such as clouds.
+pub struct Simplex {
+    perm: [i32; POINT_COUNT],
+}
+
+const GRAD3: [[Float; 3]; 12] = [
+    [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0],
+];
+
+impl Simplex {
+    pub fn new() -> Self {
+        let mut perm = [0; POINT_COUNT];
+        Perlin::perlin_generate_perm(&mut perm);
+        Self { perm }
+    }
+
+    pub fn noise(&self, p: Vec3) -> Float {
+        const F3: Float = 1.0 / 3.0;
+        const G3: Float = 1.0 / 6.0;
+
+        // skew into the simplex grid and find the cell
+        let s = (p.x() + p.y() + p.z()) * F3;
+        let i = (p.x() + s).floor();
+        let j = (p.y() + s).floor();
+        let k = (p.z() + s).floor();
+        let t = (i + j + k) * G3;
+        let x0 = p.x() - (i - t);
+        let y0 = p.y() - (j - t);
+        let z0 = p.z() - (k - t);
+
+        // which of the six tetrahedra of the cube we are in
+        let (i1, j1, k1, i2, j2, k2) = if x0 >= y0 {
+            if y0 >= z0 {
+                (1, 0, 0, 1, 1, 0)
+            } else if x0 >= z0 {
+                (1, 0, 0, 1, 0, 1)
+            } else {
+                (0, 0, 1, 1, 0, 1)
+            }
+        } else if y0 < z0 {
+            (0, 0, 1, 0, 1, 1)
+        } else if x0 < z0 {
+            (0, 1, 0, 0, 1, 1)
+        } else {
+            (0, 1, 0, 1, 1, 0)
+        };
+
+        // the four corners of the tetrahedron: lattice offset and n-th corner unskew
+        let corners = [(0, 0, 0, 0.0), (i1, j1, k1, 1.0), (i2, j2, k2, 2.0), (1, 1, 1, 3.0)];
+        let (i, j, k) = (i as i32, j as i32, k as i32);
+        let mut accum = 0.0;
+        for (ci, cj, ck, n) in corners {
+            let x = x0 - ci as Float + n * G3;
+            let y = y0 - cj as Float + n * G3;
+            let z = z0 - ck as Float + n * G3;
+            let falloff = 0.6 - x * x - y * y - z * z;
+            if falloff <= 0.0 {
+                continue;
+            }
+            let g = GRAD3[hash(&self.perm, i + ci, j + cj, k + ck) % 12];
+            accum += falloff.powi(4) * (g[0] * x + g[1] * y + g[2] * z);
+        }
+        32.0 * accum
+    }
+
+    // fractal sum of octaves, like Perlin::turb but signed
+    pub fn fbm(&self, p: Vec3, depth: i32) -> Float {
+        let mut accum = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(temp_p);
+            weight *= 0.5;
+            temp_p = temp_p * 2.0;
+        }
+        accum
+    }
+}
+
+impl Default for Simplex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// lattice point to table index
+fn hash(perm: &[i32; POINT_COUNT], i: i32, j: i32, k: i32) -> usize {
+    perm[((perm[((perm[(i & 255) as usize] + j) & 255) as usize] + k) & 255) as usize] as usize
+}
@@ -0,0 +1,60 @@
+use crate::camera::Camera;
+use crate::denoise::Denoiser;
+use crate::vec3::Float;
+
+// Quality settings that go together, so switching between iterating and the final render is
+// one switch: `--preset draft` on the command line or `camera preset draft` in a scene file.
+pub struct Preset {
+    pub sample_per_pixel: u32,
+    pub max_depth: u32,
+    // multiplies the image width (the aspect ratio is kept)
+    pub resolution_scale: Float,
+    // filter the finished image; the final preset has samples enough not to need the blur
+    pub denoise: bool,
+}
+
+impl Preset {
+    pub fn named(name: &str) -> Result<Self, String> {
+        let (sample_per_pixel, max_depth, resolution_scale, denoise) = match name {
+            "draft" => (16, 8, 0.5, true),
+            "medium" => (256, 20, 1.0, true),
+            "final" => (4096, 50, 1.0, false),
+            _ => {
+                return Err(format!(
+                    "unknown preset \"{}\" (draft, medium or final)",
+                    name
+                ))
+            }
+        };
+        Ok(Self {
+            sample_per_pixel,
+            max_depth,
+            resolution_scale,
+            denoise,
+        })
+    }
+
+    pub fn apply(&self, cam: &mut Camera) {
+        cam.sample_per_pixel = self.sample_per_pixel;
+        cam.max_depth = self.max_depth;
+        cam.denoise = self.denoise.then(Denoiser::default);
+        if self.resolution_scale != 1.0 {
+            let width = (cam.image_width as Float * self.resolution_scale).round() as u32;
+            set_image_width(cam, width);
+        }
+    }
+}
+
+// Changes the image width, keeping the aspect ratio, and the tile counts with it.
+pub fn set_image_width(cam: &mut Camera, width: u32) {
+    let width = width.max(1);
+    let height = ((width as Float / cam.aspect_ratio) as u32).max(1);
+    // the tiles have to divide the new size too
+    cam.part_num_x = divisor_at_most(width, cam.part_num_x);
+    cam.part_num_y = divisor_at_most(height, cam.part_num_y);
+    cam.image_width = width;
+}
+
+fn divisor_at_most(n: u32, max: u32) -> u32 {
+    (1..=max.max(1)).rev().find(|d| n.is_multiple_of(*d)).unwrap()
+}
@@ -0,0 +1,101 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use indicatif::ProgressBar;
+
+// Where Camera::render reports how far it got. The render threads call `update` after every
+// wave of samples, so implementations should be quick and must be thread safe; a GUI would
+// typically store the latest Progress and repaint from its own thread.
+pub trait ProgressSink: Send + Sync {
+    fn start(&self, _total_pixels: u64, _total_tiles: u32) {}
+    fn update(&self, progress: &Progress);
+    fn finish(&self) {}
+}
+
+pub struct Progress {
+    // in [0, 1]
+    pub fraction: f64,
+    // pixels count as done in proportion to their samples
+    pub pixels_done: u64,
+    pub total_pixels: u64,
+    pub tiles_done: u32,
+    pub total_tiles: u32,
+    pub elapsed: Duration,
+    // extrapolated from the rate so far; None until something is done
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    pub fn new(
+        pixels_done: u64,
+        total_pixels: u64,
+        tiles_done: u32,
+        total_tiles: u32,
+        elapsed: Duration,
+    ) -> Self {
+        let fraction = if total_pixels > 0 {
+            pixels_done as f64 / total_pixels as f64
+        } else {
+            1.0
+        };
+        let eta = (pixels_done > 0).then(|| {
+            elapsed
+                .mul_f64((total_pixels - pixels_done.min(total_pixels)) as f64)
+                .div_f64(pixels_done as f64)
+        });
+        Self {
+            fraction,
+            pixels_done,
+            total_pixels,
+            tiles_done,
+            total_tiles,
+            elapsed,
+            eta,
+        }
+    }
+}
+
+// The progress bar on the terminal, the default of Camera.
+pub struct TerminalProgress {
+    bar: Mutex<ProgressBar>,
+}
+
+impl TerminalProgress {
+    pub fn new() -> Self {
+        Self {
+            bar: Mutex::new(ProgressBar::hidden()),
+        }
+    }
+}
+
+impl Default for TerminalProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressSink for TerminalProgress {
+    fn start(&self, total_pixels: u64, total_tiles: u32) {
+        let bar = ProgressBar::new(total_pixels);
+        bar.set_style(
+            indicatif::ProgressStyle::default_bar()
+                .template("{msg}  {bar:40.cyan/blue} {pos:>7}/{len:7}  {per_sec}   {eta_precise}"),
+        );
+        bar.set_message(format!("|0/{} tiles|", total_tiles));
+        *self.bar.lock().unwrap() = bar;
+    }
+
+    fn update(&self, progress: &Progress) {
+        let bar = self.bar.lock().unwrap();
+        bar.set_position(progress.pixels_done);
+        bar.set_message(format!(
+            "|{}/{} tiles|",
+            progress.tiles_done, progress.total_tiles
+        ));
+    }
+
+    fn finish(&self) {
+        std::process::Command::new("clear").status().unwrap();
+        self.bar.lock().unwrap().finish();
+    }
+}
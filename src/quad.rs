@@ -2,12 +2,57 @@ use std::sync::Arc;
 
 use crate::{
     aabb::AABB,
-    hittable::{HitRecord, Hittable, HittableList},
+    hittable::{area_pdf, HitRecord, Hittable, HittableList},
     interval::Interval,
-    material::Material,
-    util::{Ray, Vec3},
+    material::{cut_out, Material},
+    normal_cone::NormalCone,
+    scene_export::SceneWriter,
+    stats::{self, Counter},
+    util::{random_f64_0_1, Ray, Vec3},
+    vec3::Float,
 };
 
+// How an image lies on a quad. Its texture coordinates start out as the plane coordinates,
+// u from 0 at q to 1 at q + u and v likewise along v: the image's bottom left corner is at q
+// and its width along u. The image is then turned counterclockwise by quarter turns (as seen
+// from the side u x v points to) and mirrored.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct UvOrientation {
+    pub quarter_turns: u8,
+    pub flip_u: bool,
+    pub flip_v: bool,
+}
+
+impl UvOrientation {
+    fn apply(&self, alpha: Float, beta: Float) -> (Float, Float) {
+        let (mut u, mut v) = (alpha, beta);
+        for _ in 0..self.quarter_turns % 4 {
+            (u, v) = (v, 1.0 - u);
+        }
+        if self.flip_u {
+            u = 1.0 - u;
+        }
+        if self.flip_v {
+            v = 1.0 - v;
+        }
+        (u, v)
+    }
+
+    // The plane coordinates that apply turns into (u, v).
+    fn invert(&self, mut u: Float, mut v: Float) -> (Float, Float) {
+        if self.flip_u {
+            u = 1.0 - u;
+        }
+        if self.flip_v {
+            v = 1.0 - v;
+        }
+        for _ in 0..self.quarter_turns % 4 {
+            (u, v) = (1.0 - v, u);
+        }
+        (u, v)
+    }
+}
+
 // quadrilateral
 #[derive(Clone)]
 pub struct Quad {
@@ -15,10 +60,11 @@ pub struct Quad {
     u: Vec3,
     v: Vec3,
     w: Vec3,
+    uv: UvOrientation,
     mat: Arc<dyn Material>,
     bounding_box: AABB,
     normal: Vec3,
-    d: f64,
+    d: Float,
 }
 
 impl Quad {
@@ -28,6 +74,7 @@ impl Quad {
             u,
             v,
             w: Vec3::zero(),
+            uv: UvOrientation::default(),
             mat,
             bounding_box: AABB::default(),
             normal: Vec3::zero(),
@@ -41,6 +88,10 @@ impl Quad {
         quad
     }
 
+    pub fn set_uv_orientation(&mut self, uv: UvOrientation) {
+        self.uv = uv;
+    }
+
     fn set_bounding_box(&mut self) {
         // Compute the bounding box of all four vertices.
         let bbox_diagonal1 = AABB::new_two_points(self.q, self.q + self.u + self.v);
@@ -48,7 +99,7 @@ impl Quad {
         self.bounding_box = AABB::new_two_boxes(bbox_diagonal1, bbox_diagonal2);
     }
 
-    fn is_interior(&self, a: f64, b: f64, rec: &mut HitRecord) -> bool {
+    fn is_interior(&self, a: Float, b: Float, rec: &mut HitRecord) -> bool {
         let unit_interval = Interval::with_bounds(0.0, 1.0);
     
         // Given the hit Vec in plane coordinates, return false if it is outside the
@@ -57,14 +108,52 @@ impl Quad {
             return false;
         }
     
-        rec.u = a;
-        rec.v = b;
+        (rec.u, rec.v) = self.uv.apply(a, b);
         true
     }
+
+    // Where the quad is narrower along an edge than the footprint of a ray cone hitting its
+    // plane at `t`, it is widened to the footprint there and covers the fraction of it it
+    // fills (as a hair thinner than a pixel is drawn a pixel wide and see-through): a light
+    // seen edge-on then shows as a faint line in every sample instead of a bright one in a
+    // few. Returns the plane coordinates, moved onto the quad if they were off it, and the
+    // coverage; None if the ray misses the widened quad. Rays without a cone pass through
+    // unchanged.
+    fn widen(
+        &self,
+        r: &Ray,
+        t: Float,
+        alpha: Float,
+        beta: Float,
+    ) -> Option<(Float, Float, Float)> {
+        let footprint = r.footprint(t);
+        if footprint <= 0.0 {
+            return Some((alpha, beta, 1.0));
+        }
+        let direction = r.b_direction.unit();
+        let cos = (direction * self.normal).abs().max(1e-4);
+        let mut coords = [alpha, beta];
+        let mut coverage = 1.0;
+        for (c, edge) in coords.iter_mut().zip([self.u, self.v]) {
+            let length = edge.length();
+            // half the extent along the edge of the footprint's ellipse in the plane
+            let along = direction * edge / length;
+            let reach = 0.5 * footprint * (1.0 + along * along / (cos * cos)).sqrt();
+            if 2.0 * reach > length {
+                if (*c - 0.5).abs() * length > reach {
+                    return None;
+                }
+                coverage *= length / (2.0 * reach);
+                *c = c.clamp(0.0, 1.0);
+            }
+        }
+        Some((coords[0], coords[1], coverage))
+    }
 }
 
 impl Hittable for Quad {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        stats::count(Counter::QuadTest, 1);
         let denom = r.b_direction * self.normal;
 
         // No hit if the ray is parallel to the plane.
@@ -83,7 +172,15 @@ impl Hittable for Quad {
         let planar_hitpt_Vector = intersection - self.q;
         let alpha = self.w * planar_hitpt_Vector.cross(self.v);
         let beta = self.w * self.u.cross(planar_hitpt_Vector);
+        let Some((alpha, beta, coverage)) = self.widen(r, t, alpha, beta) else {
+            return false;
+        };
 
+        // a cut-out material lets the ray through (checked first, is_interior fills in rec)
+        let (u, v) = self.uv.apply(alpha, beta);
+        if cut_out(self.mat.as_ref(), u, v, intersection) {
+            return false;
+        }
         if !self.is_interior(alpha, beta, rec) {
             return false;
         }
@@ -92,8 +189,9 @@ impl Hittable for Quad {
 
         rec.t = t;
         rec.p = intersection;
-        rec.mat = self.mat.clone();
+        rec.mat = &*self.mat;
         rec.set_face_normal(r, &self.normal);
+        rec.coverage = coverage;
 
         true
     }
@@ -101,6 +199,41 @@ impl Hittable for Quad {
     fn bounding_box(&self) -> AABB {
         self.bounding_box
     }
+
+    fn normal_bounds(&self) -> NormalCone {
+        if self.mat.is_one_sided() {
+            NormalCone::new(self.normal, 0.0)
+        } else {
+            NormalCone::everywhere()
+        }
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        out.quad(self.q, self.u, self.v, self.uv, &self.mat)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        if self.mat.is_emissive() {
+            lights.add(Arc::new(self.clone()));
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        surfaces.add(Arc::new(self.clone()));
+    }
+
+    fn surface_at(&self, u: Float, v: Float) -> Option<(Vec3, Vec3)> {
+        let (alpha, beta) = self.uv.invert(u, v);
+        Some((self.q + self.u * alpha + self.v * beta, self.normal))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        area_pdf(self, origin, direction, self.u.cross(self.v).length())
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        self.q + self.u * random_f64_0_1() + self.v * random_f64_0_1() - origin
+    }
 }
 
 pub fn box_from_vec(a: Vec3, b: Vec3, mat: Arc<dyn Material>) -> Arc<HittableList> {
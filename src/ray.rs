@@ -1,29 +1,66 @@
-use crate::vec3::Vec3;
+use crate::vec3::{Float, Vec3};
 #[derive(Clone, Debug, PartialEq)]
 pub struct Ray {
     pub a_origin: Vec3,
     pub b_direction: Vec3,
-    pub time: f64,
+    pub time: Float,
+    // wavelength in nm carried by the path after a dispersive event, 0.0 for plain RGB paths
+    pub wavelength: Float,
+    // ray cone: the width of the footprint at the origin and the angle it widens by, in
+    // radians; both 0.0 for a thin ray (see footprint)
+    pub cone_width: Float,
+    pub cone_spread: Float,
+    // 1 / b_direction per axis and whether b_direction is negative there (-0.0 included), for
+    // the slab tests of boxes; the constructors set them, so a ray's direction is only
+    // changed through them
+    pub inv_direction: Vec3,
+    pub negative: [bool; 3],
 }
 
 impl Ray {
     pub fn default() -> Self {
-        Self {
-            a_origin: Vec3::zero(),
-            b_direction: Vec3::zero(),
-            time: 0.0,
-        }
+        Self::new(Vec3::zero(), Vec3::zero(), 0.0)
     }
-    pub fn new(a_origin: Vec3, b_direction: Vec3, time: f64) -> Self {
+    pub fn new(a_origin: Vec3, b_direction: Vec3, time: Float) -> Self {
         Self {
             a_origin,
             b_direction,
             time,
+            wavelength: 0.0,
+            cone_width: 0.0,
+            cone_spread: 0.0,
+            inv_direction: Vec3::new(
+                1.0 / b_direction.x,
+                1.0 / b_direction.y,
+                1.0 / b_direction.z,
+            ),
+            negative: [
+                b_direction.x.is_sign_negative(),
+                b_direction.y.is_sign_negative(),
+                b_direction.z.is_sign_negative(),
+            ],
         }
     }
-    pub fn at(&self, t: f64) -> Vec3 {
+    // The same ray in another frame (moved or turned, not scaled): keeps its time,
+    // wavelength and cone.
+    pub fn moved(&self, a_origin: Vec3, b_direction: Vec3) -> Self {
+        Self {
+            wavelength: self.wavelength,
+            cone_width: self.cone_width,
+            cone_spread: self.cone_spread,
+            ..Self::new(a_origin, b_direction, self.time)
+        }
+    }
+    pub fn at(&self, t: Float) -> Vec3 {
         self.a_origin + self.b_direction * t
     }
+    pub fn has_cone(&self) -> bool {
+        self.cone_width > 0.0 || self.cone_spread > 0.0
+    }
+    // Width of the ray's cone at `t`, the size of the pixel it stands for there.
+    pub fn footprint(&self, t: Float) -> Float {
+        self.cone_width + self.cone_spread * t * self.b_direction.length()
+    }
     pub fn info(&self) {
         println!("ori");
         self.a_origin.info();
@@ -0,0 +1,208 @@
+use crate::color::{output_transform, to_rec709};
+use crate::vec3::{Float, Vec3};
+
+// Where a bounce of a path traced by Camera::trace_pixel went: the point its ray hit, or as
+// far as the escape length along it where it hit nothing, the light the path gathered
+// there and what it carries on with (its throughput after the bounce).
+pub struct PathVertex {
+    pub point: Vec3,
+    // the shading normal and object id (see hittable::Named) of the hit, None for a miss
+    pub hit: Option<(Vec3, u32)>,
+    pub light: Vec3,
+    pub throughput: Vec3,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PathEnd {
+    // the last ray hit nothing and took the background
+    Escaped,
+    // at a surface that doesn't scatter, such as a light, or lost to Russian roulette
+    Stopped,
+    // still going after max_depth bounces
+    Cut,
+}
+
+impl PathEnd {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PathEnd::Escaped => "escaped",
+            PathEnd::Stopped => "stopped",
+            PathEnd::Cut => "cut at max_depth",
+        }
+    }
+}
+
+// A camera ray and every ray after it, one vertex per bounce, with the radiance the path
+// brings to its pixel (before the exposure).
+pub struct TracedPath {
+    pub origin: Vec3,
+    pub vertices: Vec<PathVertex>,
+    pub radiance: Vec3,
+    pub end: PathEnd,
+}
+
+impl TracedPath {
+    pub fn new(origin: Vec3) -> Self {
+        Self {
+            origin,
+            vertices: vec![],
+            radiance: Vec3::zero(),
+            end: PathEnd::Cut,
+        }
+    }
+
+    // the bounces it made, max_depth when it was cut there
+    pub fn bounces(&self) -> usize {
+        match self.end {
+            PathEnd::Cut => self.vertices.len(),
+            _ => self.vertices.len().saturating_sub(1),
+        }
+    }
+
+    // A line on the path and one per bounce, for reading what it went through.
+    pub fn describe(&self, index: usize) -> String {
+        let mut lines = vec![format!(
+            "path {}: radiance {}, {} bounces, {}",
+            index,
+            triple(self.radiance),
+            self.bounces(),
+            self.end.name()
+        )];
+        for (bounce, vertex) in self.vertices.iter().enumerate() {
+            let at = match vertex.hit {
+                Some((normal, id)) => format!(
+                    "hit {} normal {} object {}",
+                    triple(vertex.point),
+                    triple(normal),
+                    id
+                ),
+                None => "missed".to_owned(),
+            };
+            lines.push(format!(
+                "  {:>3} {}, light {}, throughput {}",
+                bounce,
+                at,
+                triple(vertex.light),
+                triple(vertex.throughput)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// A line on the paths of a pixel: how they ended and which brought the most light.
+pub fn summary(paths: &[TracedPath]) -> String {
+    let count = |end: PathEnd| paths.iter().filter(|p| p.end == end).count();
+    let mean =
+        paths.iter().fold(Vec3::zero(), |sum, p| sum + p.radiance) / paths.len().max(1) as Float;
+    let brightest = (0..paths.len())
+        .filter(|&k| luminance(paths[k].radiance) > 0.0)
+        .max_by(|&a, &b| luminance(paths[a].radiance).total_cmp(&luminance(paths[b].radiance)));
+    let mut text = format!(
+        "{} paths, mean radiance {}: {} escaped, {} stopped, {} cut at max_depth",
+        paths.len(),
+        triple(mean),
+        count(PathEnd::Escaped),
+        count(PathEnd::Stopped),
+        count(PathEnd::Cut)
+    );
+    if let Some(k) = brightest {
+        text += &format!(
+            "; the brightest is path {} with {}",
+            k,
+            triple(paths[k].radiance)
+        );
+    }
+    text
+}
+
+// Writes the paths as line sets, as Wavefront OBJ or, for a ".ply" file, as PLY with the
+// vertices coloured by the radiance of their path as the image shows it.
+pub fn save_paths(path: &str, paths: &[TracedPath]) -> Result<(), String> {
+    let text = if path.ends_with(".ply") {
+        paths_ply(paths)
+    } else {
+        paths_obj(paths)
+    };
+    std::fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+}
+
+// An object per path: its camera ray origin, a vertex per bounce and a polyline through them.
+pub fn paths_obj(paths: &[TracedPath]) -> String {
+    let mut lines = vec!["# ray paths".to_owned()];
+    let mut first = 1;
+    for (k, path) in paths.iter().enumerate() {
+        lines.push(format!("o path_{}", k));
+        lines.push(format!(
+            "# radiance {}, {}",
+            triple(path.radiance),
+            path.end.name()
+        ));
+        let points: Vec<Vec3> = points(path).collect();
+        for p in &points {
+            lines.push(format!("v {}", triple(*p)));
+        }
+        let indices: Vec<String> = (first..first + points.len())
+            .map(|i| i.to_string())
+            .collect();
+        lines.push(format!("l {}", indices.join(" ")));
+        first += points.len();
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+// The vertices of all paths, coloured, and an edge per ray.
+pub fn paths_ply(paths: &[TracedPath]) -> String {
+    let mut vertices = vec![];
+    let mut edges = vec![];
+    for path in paths {
+        let encoded = output_transform().encode(to_rec709(path.radiance));
+        let channel = |c: Float| (c * 255.0).round().clamp(0.0, 255.0) as u8;
+        let color = format!(
+            "{} {} {}",
+            channel(encoded.x),
+            channel(encoded.y),
+            channel(encoded.z)
+        );
+        let first = vertices.len();
+        for p in points(path) {
+            vertices.push(format!("{} {}", triple(p), color));
+        }
+        for i in first + 1..vertices.len() {
+            edges.push(format!("{} {}", i - 1, i));
+        }
+    }
+    let mut lines = vec![
+        "ply".to_owned(),
+        "format ascii 1.0".to_owned(),
+        "comment ray paths".to_owned(),
+        format!("element vertex {}", vertices.len()),
+        "property float x".to_owned(),
+        "property float y".to_owned(),
+        "property float z".to_owned(),
+        "property uchar red".to_owned(),
+        "property uchar green".to_owned(),
+        "property uchar blue".to_owned(),
+        format!("element edge {}", edges.len()),
+        "property int vertex1".to_owned(),
+        "property int vertex2".to_owned(),
+        "end_header".to_owned(),
+    ];
+    lines.append(&mut vertices);
+    lines.append(&mut edges);
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn points(path: &TracedPath) -> impl Iterator<Item = Vec3> + '_ {
+    std::iter::once(path.origin).chain(path.vertices.iter().map(|v| v.point))
+}
+
+fn luminance(c: Vec3) -> Float {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+fn triple(v: Vec3) -> String {
+    format!("{:.4} {:.4} {:.4}", v.x, v.y, v.z)
+}
@@ -1,477 +1,409 @@
 use std::sync::Arc;
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
 use crate::bvh::BVHNode;
 use crate::camera::Camera;
-use crate::hittable::{ConstantMedium, HittableList, RotateY, Translate};
-use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::cornell::{CornellBox, Fill};
+use crate::hittable::{ConstantMedium, Hittable, HittableList, RotateY, Translate};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal, RoughDielectric};
+use crate::material_registry;
+use crate::motion::{Motion, Moving};
 use crate::quad::{box_from_vec, Quad};
-use crate::sphere::Sphere;
-use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture};
-use crate::util::{
-    random_f64_0_1, random_f64_ranged, random_positive_vec3, random_positive_vec3_ranged,
-};
-use crate::vec3::Vec3;
-
-pub fn bouncing_spheres() -> (Camera, HittableList) {
-    // World
-    let mut world = HittableList::new();
+use crate::scene_builder::{diffuse_light, lambertian, textured, SceneBuilder};
+use crate::scene_registry::SceneRegistry;
+use crate::sphere::{Sphere, SphereBatch};
+use crate::texture::{CheckerTexture, NoiseTexture};
+use crate::vec3::{Float, Vec3};
+
+// The random layouts of bouncing_spheres and final_scene come from their own generator,
+// seeded with `seed`: the same seed gives the same scene on every machine, whatever the
+// renderer's random numbers are doing.
+fn random_vec3(rng: &mut StdRng, min: Float, max: Float) -> Vec3 {
+    Vec3::new(
+        rng.gen_range(min..max),
+        rng.gen_range(min..max),
+        rng.gen_range(min..max),
+    )
+}
 
-    let checker = Arc::from(CheckerTexture::from_color(
-        0.32,
-        Vec3::new(0.2, 0.3, 0.1),
-        Vec3::new(0.9, 0.9, 0.9),
-    ));
-    world.add(Arc::from(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Arc::from(Lambertian::from_texture(checker)),
-    )));
+pub fn bouncing_spheres(seed: u64) -> (Camera, HittableList) {
+    SphereField::default().build(seed)
+}
+
+// The field of small spheres of bouncing_spheres: one sphere at a random spot of every cell
+// of a grid `extent` units out from the origin on both axes, with `density` cells per square
+// unit (smaller spheres for denser fields), around the three large spheres. 80% are diffuse
+// and bounce, 15% metal, 5% glass, with their materials drawn from small palettes shared by
+// all the spheres. Instanced, each material has a single sphere at the origin which every
+// sphere of that material places with a Translate (and its bounce with a Moving), which
+// renders the same. Raise both to stress the accelerators: the default is 22 by 22 cells.
+#[derive(Clone, Copy)]
+pub struct SphereField {
+    pub extent: Float,
+    pub density: Float,
+    pub instanced: bool,
+}
+
+impl Default for SphereField {
+    fn default() -> Self {
+        Self {
+            extent: 11.0,
+            density: 1.0,
+            instanced: false,
+        }
+    }
+}
 
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_f64_0_1();
-            let center = Vec3::new(
-                a as f64 + 0.9 * random_f64_0_1(),
-                0.2,
-                b as f64 + 0.9 * random_f64_0_1(),
-            );
-
-            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let sphere_material: Arc<dyn Material>;
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = random_positive_vec3().component_mul(random_positive_vec3());
-                    sphere_material = Arc::from(Lambertian::from_color(albedo));
-                    let center2 = center + Vec3::new(0.0, random_f64_ranged(0.0, 0.5), 0.0);
+const DIFFUSE_PALETTE: usize = 16;
+const METAL_PALETTE: usize = 8;
+
+impl SphereField {
+    pub fn build(&self, seed: u64) -> (Camera, HittableList) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // World
+        let mut world = HittableList::new();
+
+        let checker = Arc::from(CheckerTexture::from_color(
+            0.32,
+            Vec3::new(0.2, 0.3, 0.1),
+            Vec3::new(0.9, 0.9, 0.9),
+        ));
+        world.add(Arc::from(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::from(Lambertian::from_texture(checker)),
+        )));
+
+        let diffuse: Vec<Arc<dyn Material>> = (0..DIFFUSE_PALETTE)
+            .map(|_| {
+                let albedo =
+                    random_vec3(&mut rng, 0.0, 1.0).component_mul(random_vec3(&mut rng, 0.0, 1.0));
+                Arc::from(Lambertian::from_color(albedo)) as Arc<dyn Material>
+            })
+            .collect();
+        let metal: Vec<Arc<dyn Material>> = (0..METAL_PALETTE)
+            .map(|_| {
+                let albedo = random_vec3(&mut rng, 0.5, 1.0);
+                let fuzz = rng.gen_range(0.0..0.5);
+                Arc::from(Metal::new(albedo, fuzz)) as Arc<dyn Material>
+            })
+            .collect();
+        let glass: Arc<dyn Material> = Arc::from(Dielectric::new(1.5));
+
+        let spacing = 1.0 / self.density.sqrt();
+        let radius = 0.2 * spacing.min(1.0);
+        let materials: Vec<Arc<dyn Material>> = diffuse
+            .iter()
+            .chain(&metal)
+            .chain([&glass])
+            .cloned()
+            .collect();
+        // the shared spheres of the instanced field, one per material
+        let prototypes: Vec<Arc<dyn Hittable>> = materials
+            .iter()
+            .map(|mat| Arc::new(Sphere::new(Vec3::zero(), radius, mat.clone())) as _)
+            .collect();
+
+        let cells = (self.extent / spacing).round() as i32;
+        for a in -cells..cells {
+            for b in -cells..cells {
+                let choose_mat: Float = rng.gen();
+                let center = Vec3::new(
+                    (a as Float + 0.9 * rng.gen::<Float>()) * spacing,
+                    radius,
+                    (b as Float + 0.9 * rng.gen::<Float>()) * spacing,
+                );
+                if (center - Vec3::new(4.0, 0.2, 0.0)).length() <= 0.9 {
+                    continue;
+                }
+
+                let (k, bounce) = if choose_mat < 0.8 {
+                    let k = rng.gen_range(0..DIFFUSE_PALETTE);
+                    (k, rng.gen_range(0.0..0.5) * spacing.min(1.0))
+                } else if choose_mat < 0.95 {
+                    (DIFFUSE_PALETTE + rng.gen_range(0..METAL_PALETTE), 0.0)
+                } else {
+                    (materials.len() - 1, 0.0)
+                };
+                let up = Vec3::new(0.0, bounce, 0.0);
+                if self.instanced {
+                    let mut sphere = prototypes[k].clone();
+                    if bounce != 0.0 {
+                        sphere = Arc::new(Moving::new(sphere, Motion::Linear(up)));
+                    }
+                    world.add(Arc::new(Translate::new(sphere, center)));
+                } else if bounce != 0.0 {
+                    let mat = materials[k].clone();
                     world.add(Arc::from(Sphere::new_moving(
                         center,
-                        center2,
-                        0.2,
-                        sphere_material,
+                        center + up,
+                        radius,
+                        mat,
                     )));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = random_positive_vec3_ranged(0.5, 1.0);
-                    let fuzz = random_f64_ranged(0.0, 0.5);
-                    sphere_material = Arc::from(Metal::new(albedo, fuzz));
-                    world.add(Arc::from(Sphere::new(center, 0.2, sphere_material)));
                 } else {
-                    // glass
-                    sphere_material = Arc::from(Dielectric::new(1.5));
-                    world.add(Arc::from(Sphere::new(center, 0.2, sphere_material)));
+                    world.add(Arc::from(Sphere::new(center, radius, materials[k].clone())));
                 }
             }
         }
-    }
 
-    let material1 = Arc::from(Dielectric::new(1.5));
-    world.add(Arc::from(Sphere::new(
-        Vec3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
+        world.add(Arc::from(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, glass)));
 
-    let material2 = Arc::from(Lambertian::from_color(Vec3::new(0.4, 0.2, 0.1)));
-    world.add(Arc::from(Sphere::new(
-        Vec3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
+        let material2 = Arc::from(Lambertian::from_color(Vec3::new(0.4, 0.2, 0.1)));
+        world.add(Arc::from(Sphere::new(
+            Vec3::new(-4.0, 1.0, 0.0),
+            1.0,
+            material2,
+        )));
 
-    let material3 = Arc::from(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Arc::from(Sphere::new(
-        Vec3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
+        let material3 = Arc::from(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0));
+        world.add(Arc::from(Sphere::new(
+            Vec3::new(4.0, 1.0, 0.0),
+            1.0,
+            material3,
+        )));
 
-    world = HittableList::new_and_add(Arc::from(BVHNode::new(world)));
+        world = HittableList::new_and_add(Arc::from(BVHNode::new(world)));
 
-    let mut cam = Camera::default();
+        let mut cam = Camera::default();
 
-    cam.image_width = 400;
-    cam.sample_per_pixel = 100;
-    cam.max_depth = 50;
-    cam.background = Vec3::new(0.70, 0.80, 1.00);
+        cam.image_width = 400;
+        cam.sample_per_pixel = 100;
+        cam.max_depth = 50;
+        cam.background = Vec3::new(0.70, 0.80, 1.00);
 
-    cam.vfov = 20.0;
-    cam.lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    cam.lookat = Vec3::zero();
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+        cam.vfov = 20.0;
+        cam.lookfrom = Vec3::new(13.0, 2.0, 3.0);
+        cam.lookat = Vec3::zero();
+        cam.vup = Vec3::new(0.0, 1.0, 0.0);
 
-    cam.defocus_angle = 0.6;
-    cam.focus_dist = 10.0;
-    (cam, world)
+        cam.defocus_angle = 0.6;
+        cam.focus_dist = 10.0;
+        (cam, world)
+    }
 }
 
 pub fn checkered_spheres() -> (Camera, HittableList) {
-    let mut world = HittableList::new();
-    let checker = Arc::from(CheckerTexture::from_color(
-        0.32,
-        Vec3::new(0.2, 0.3, 0.1),
-        Vec3::new(0.9, 0.9, 0.9),
-    ));
-    world.add(Arc::from(Sphere::new(
-        Vec3::new(0.0, -10.0, 0.0),
-        10.0,
-        Arc::from(Lambertian::from_texture(checker.clone())),
-    )));
-    world.add(Arc::from(Sphere::new(
-        Vec3::new(0.0, 10.0, 0.0),
-        10.0,
-        Arc::from(Lambertian::from_texture(checker)),
-    )));
-
-    let mut cam = Camera::default();
-
-    cam.image_width = 400;
-    cam.sample_per_pixel = 100;
-    cam.max_depth = 50;
-    cam.background = Vec3::new(0.70, 0.80, 1.00);
-
-    cam.vfov = 20.0;
-    cam.lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    cam.lookat = Vec3::zero();
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-    (cam, world)
+    SceneBuilder::new()
+        .sphere(Vec3::new(0.0, -10.0, 0.0), 10.0)
+        .named("checker")
+        .sphere(Vec3::new(0.0, 10.0, 0.0), 10.0)
+        .named("checker")
+        .camera(|cam| {
+            cam.image_width = 400;
+            cam.sample_per_pixel = 100;
+            cam.max_depth = 50;
+            cam.background = Vec3::new(0.70, 0.80, 1.00);
+
+            cam.vfov = 20.0;
+            cam.lookfrom = Vec3::new(13.0, 2.0, 3.0);
+            cam.lookat = Vec3::zero();
+            cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+            cam.defocus_angle = 0.0;
+        })
+        .build()
 }
 
 pub fn earth() -> (Camera, HittableList) {
-    let mut world = HittableList::new();
-    let earth_texture = Arc::from(ImageTexture::new("earthmap.jpg"));
-    let earth_surface = Arc::from(Lambertian::from_texture(earth_texture));
-
-    world.add(Arc::from(Sphere::new(Vec3::zero(), 2.0, earth_surface)));
-
-    let mut cam = Camera::default();
-
-    cam.image_width = 400;
-    cam.sample_per_pixel = 100;
-    cam.max_depth = 50;
-    cam.background = Vec3::new(0.70, 0.80, 1.00);
-
-    cam.vfov = 20.0;
-    cam.lookfrom = Vec3::new(0.0, 0.0, 12.0);
-    cam.lookat = Vec3::zero();
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-    (cam, world)
+    SceneBuilder::new()
+        .sphere(Vec3::zero(), 2.0)
+        .named("earth")
+        .camera(|cam| {
+            cam.image_width = 400;
+            cam.sample_per_pixel = 100;
+            cam.max_depth = 50;
+            cam.background = Vec3::new(0.70, 0.80, 1.00);
+
+            cam.vfov = 20.0;
+            cam.lookfrom = Vec3::new(0.0, 0.0, 12.0);
+            cam.lookat = Vec3::zero();
+            cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+            cam.defocus_angle = 0.0;
+        })
+        .build()
 }
 
 pub fn perlin_spheres() -> (Camera, HittableList) {
-    let mut world = HittableList::new();
-
     let pertext = Arc::new(NoiseTexture::new(4.0));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Arc::new(Lambertian::from_texture(pertext.clone())),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, 2.0, 0.0),
-        2.0,
-        Arc::new(Lambertian::from_texture(pertext)),
-    )));
-
-    let mut cam = Camera::default();
-
-    cam.image_width = 400;
-    cam.sample_per_pixel = 100;
-    cam.max_depth = 50;
-    cam.background = Vec3::new(0.70, 0.80, 1.00);
-
-    cam.vfov = 20.0;
-    cam.lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    cam.lookat = Vec3::zero();
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-    (cam, world)
+    SceneBuilder::new()
+        .sphere(Vec3::new(0.0, -1000.0, 0.0), 1000.0)
+        .material(textured(pertext.clone()))
+        .sphere(Vec3::new(0.0, 2.0, 0.0), 2.0)
+        .material(textured(pertext))
+        .camera(|cam| {
+            cam.image_width = 400;
+            cam.sample_per_pixel = 100;
+            cam.max_depth = 50;
+            cam.background = Vec3::new(0.70, 0.80, 1.00);
+
+            cam.vfov = 20.0;
+            cam.lookfrom = Vec3::new(13.0, 2.0, 3.0);
+            cam.lookat = Vec3::zero();
+            cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+            cam.defocus_angle = 0.0;
+        })
+        .build()
 }
 
 pub fn quads() -> (Camera, HittableList) {
-    // Materials
-    let left_red = Arc::new(Lambertian::from_color(Vec3::new(1.0, 0.2, 0.2)));
-    let back_green = Arc::new(Lambertian::from_color(Vec3::new(0.2, 1.0, 0.2)));
-    let right_blue = Arc::new(Lambertian::from_color(Vec3::new(0.2, 0.2, 1.0)));
-    let upper_orange = Arc::new(Lambertian::from_color(Vec3::new(1.0, 0.5, 0.0)));
-    let lower_teal = Arc::new(Lambertian::from_color(Vec3::new(0.2, 0.8, 0.8)));
-
-    // Quads
-    let mut world = HittableList::new();
-    world.add(Arc::new(Quad::new(
-        Vec3::new(-3.0, -2.0, 5.0),
-        Vec3::new(0.0, 0.0, -4.0),
-        Vec3::new(0.0, 4.0, 0.0),
-        left_red.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(-2.0, -2.0, 0.0),
-        Vec3::new(4.0, 0.0, 0.0),
-        Vec3::new(0.0, 4.0, 0.0),
-        back_green.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(3.0, -2.0, 1.0),
-        Vec3::new(0.0, 0.0, 4.0),
-        Vec3::new(0.0, 4.0, 0.0),
-        right_blue.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(-2.0, 3.0, 1.0),
-        Vec3::new(4.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 4.0),
-        upper_orange.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(-2.0, -3.0, 5.0),
-        Vec3::new(4.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -4.0),
-        lower_teal.clone(),
-    )));
-
-    let mut cam = Camera::default();
-
-    cam.aspect_ratio = 1.0;
-    cam.image_width = 400;
-    cam.sample_per_pixel = 100;
-    cam.max_depth = 50;
-    cam.background = Vec3::new(0.70, 0.80, 1.00);
-
-    cam.vfov = 80.0;
-    cam.lookfrom = Vec3::new(0.0, 0.0, 9.0);
-    cam.lookat = Vec3::zero();
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-    (cam, world)
+    SceneBuilder::new()
+        // left red
+        .quad(
+            Vec3::new(-3.0, -2.0, 5.0),
+            Vec3::new(0.0, 0.0, -4.0),
+            Vec3::new(0.0, 4.0, 0.0),
+        )
+        .material(lambertian(Vec3::new(1.0, 0.2, 0.2)))
+        // back green
+        .quad(
+            Vec3::new(-2.0, -2.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+        )
+        .material(lambertian(Vec3::new(0.2, 1.0, 0.2)))
+        // right blue
+        .quad(
+            Vec3::new(3.0, -2.0, 1.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::new(0.0, 4.0, 0.0),
+        )
+        .material(lambertian(Vec3::new(0.2, 0.2, 1.0)))
+        // upper orange
+        .quad(
+            Vec3::new(-2.0, 3.0, 1.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+        )
+        .material(lambertian(Vec3::new(1.0, 0.5, 0.0)))
+        // lower teal
+        .quad(
+            Vec3::new(-2.0, -3.0, 5.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -4.0),
+        )
+        .material(lambertian(Vec3::new(0.2, 0.8, 0.8)))
+        .camera(|cam| {
+            cam.aspect_ratio = 1.0;
+            cam.image_width = 400;
+            cam.sample_per_pixel = 100;
+            cam.max_depth = 50;
+            cam.background = Vec3::new(0.70, 0.80, 1.00);
+
+            cam.vfov = 80.0;
+            cam.lookfrom = Vec3::new(0.0, 0.0, 9.0);
+            cam.lookat = Vec3::zero();
+            cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+            cam.defocus_angle = 0.0;
+        })
+        .build()
 }
 
 pub fn simple_light() -> (Camera, HittableList) {
-    let mut world = HittableList::new();
-
     let pertext = Arc::new(NoiseTexture::new(4.0));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Arc::new(Lambertian::from_texture(pertext.clone())),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, 2.0, 0.0),
-        2.0,
-        Arc::new(Lambertian::from_texture(pertext)),
-    )));
-
-    let difflight = Arc::new(DiffuseLight::from_color(Vec3::new(4.0, 4.0, 4.0)));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(3.0, 1.0, -2.0),
-        Vec3::new(2.0, 0.0, 0.0),
-        Vec3::new(0.0, 2.0, 0.0),
-        difflight.clone(),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, 7.0, 0.0),
-        2.0,
-        difflight.clone(),
-    )));
-
-    let mut cam = Camera::default();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = 400;
-    cam.sample_per_pixel = 100;
-    cam.max_depth = 50;
-    cam.background = Vec3::zero();
-
-    cam.vfov = 20.0;
-    cam.lookfrom = Vec3::new(26.0, 3.0, 6.0);
-    cam.lookat = Vec3::new(0.0, 2.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-    (cam, world)
+    let difflight = diffuse_light(Vec3::new(4.0, 4.0, 4.0));
+    SceneBuilder::new()
+        .sphere(Vec3::new(0.0, -1000.0, 0.0), 1000.0)
+        .material(textured(pertext.clone()))
+        .sphere(Vec3::new(0.0, 2.0, 0.0), 2.0)
+        .material(textured(pertext))
+        .quad(
+            Vec3::new(3.0, 1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+        )
+        .material(difflight.clone())
+        .sphere(Vec3::new(0.0, 7.0, 0.0), 2.0)
+        .material(difflight)
+        .camera(|cam| {
+            cam.aspect_ratio = 16.0 / 9.0;
+            cam.image_width = 400;
+            cam.sample_per_pixel = 100;
+            cam.max_depth = 50;
+            cam.background = Vec3::zero();
+
+            cam.vfov = 20.0;
+            cam.lookfrom = Vec3::new(26.0, 3.0, 6.0);
+            cam.lookat = Vec3::new(0.0, 2.0, 0.0);
+            cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+            cam.defocus_angle = 0.0;
+        })
+        .build()
 }
 
 pub fn cornell_box() -> (Camera, HittableList) {
-    let mut world = HittableList::new();
-
-    let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
-    let white = Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::from_color(Vec3::new(0.12, 0.45, 0.15)));
-    let light = Arc::new(DiffuseLight::from_color(Vec3::new(15.0, 15.0, 15.0)));
-
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        green.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::zero(),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        red.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(343.0, 554.0, 332.0),
-        Vec3::new(-130.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -105.0),
-        light.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::zero(),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        white.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 555.0, 555.0),
-        Vec3::new(-555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -555.0),
-        white.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 555.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        white.clone(),
-    )));
-
-    let box1 = box_from_vec(Vec3::zero(), Vec3::new(165.0, 330.0, 165.0), white.clone());
-    let box1 = Arc::from(RotateY::new(box1, 15.0));
-    let box1 = Arc::from(Translate::new(box1, Vec3::new(265.0, 0.0, 295.0)));
-    world.add(box1);
-
-    let box2 = box_from_vec(Vec3::zero(), Vec3::new(165.0, 165.0, 165.0), white.clone());
-    let box2 = Arc::from(RotateY::new(box2, -18.0));
-    let box2 = Arc::from(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
-    world.add(box2);
-
-    let mut cam = Camera::default();
-    cam.aspect_ratio = 1.0;
-    cam.image_width = 600;
-    cam.sample_per_pixel = 200;
-    cam.max_depth = 50;
-    cam.background = Vec3::zero();
-
-    cam.vfov = 40.0;
-    cam.lookfrom = Vec3::new(278.0, 278.0, -800.0);
-    cam.lookat = Vec3::new(278.0, 278.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-    (cam, world)
+    CornellBox::new().build()
 }
 
 pub fn cornell_smoke() -> (Camera, HittableList) {
-    let mut world = HittableList::new();
-
-    let red = Arc::from(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
-    let white = Arc::from(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
-    let green = Arc::from(Lambertian::from_color(Vec3::new(0.12, 0.45, 0.15)));
-    let light = Arc::from(DiffuseLight::from_color(Vec3::new(7.0, 7.0, 7.0)));
-
-    world.add(Arc::from(Quad::new(
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        green,
-    )));
-    world.add(Arc::from(Quad::new(
-        Vec3::zero(),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        red,
-    )));
-    world.add(Arc::from(Quad::new(
-        Vec3::new(113.0, 554.0, 127.0),
-        Vec3::new(330.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 305.0),
-        light,
-    )));
-    world.add(Arc::from(Quad::new(
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        white.clone(),
-    )));
-    world.add(Arc::from(Quad::new(
-        Vec3::zero(),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        white.clone(),
-    )));
-    world.add(Arc::from(Quad::new(
-        Vec3::new(0.0, 0.0, 555.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        white.clone(),
-    )));
-
-    let box1 = box_from_vec(Vec3::zero(), Vec3::new(165.0, 330.0, 165.0), white.clone());
-    let box1 = Arc::from(RotateY::new(box1, 15.0));
-    let box1 = Arc::from(Translate::new(box1, Vec3::new(265.0, 0.0, 295.0)));
-
-    let box2 = box_from_vec(Vec3::zero(), Vec3::new(165.0, 165.0, 165.0), white.clone());
-    let box2 = Arc::from(RotateY::new(box2, -18.0));
-    let box2 = Arc::from(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
-
-    world.add(Arc::from(ConstantMedium::from_color(
-        box1,
-        0.01,
-        Vec3::zero(),
-    )));
-    world.add(Arc::from(ConstantMedium::from_color(
-        box2,
-        0.01,
-        Vec3::new(1.0, 1.0, 1.0),
-    )));
-
-    let mut cam = Camera::default();
-
-    cam.aspect_ratio = 1.0;
-    cam.image_width = 600;
-    cam.sample_per_pixel = 200;
-    cam.max_depth = 50;
-    cam.background = Vec3::zero();
+    CornellBox::new()
+        .light(330.0, 305.0, Vec3::new(7.0, 7.0, 7.0))
+        .tall_block(Fill::Smoke(0.01, Vec3::zero()))
+        .short_block(Fill::Smoke(0.01, Vec3::ones()))
+        .build()
+}
 
-    cam.vfov = 40.0;
-    cam.lookfrom = Vec3::new(278.0, 278.0, -800.0);
-    cam.lookat = Vec3::new(278.0, 278.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+// The tall block a mirror, the short one glass.
+pub fn cornell_mirror() -> (Camera, HittableList) {
+    let standard = material_registry::standard();
+    CornellBox::new()
+        .tall_block(Fill::Material(standard.material("mirror").unwrap()))
+        .short_block(Fill::Material(standard.material("glass").unwrap()))
+        .build()
+}
 
-    cam.defocus_angle = 0.0;
-    (cam, world)
+// A glass sphere and a gold one under a wide, dimmer light.
+pub fn cornell_spheres() -> (Camera, HittableList) {
+    let standard = material_registry::standard();
+    CornellBox::new()
+        .light(330.0, 305.0, Vec3::new(7.0, 7.0, 7.0))
+        .empty()
+        .sphere(
+            Vec3::new(370.0, 120.0, 370.0),
+            120.0,
+            Fill::Material(standard.material("gold").unwrap()),
+        )
+        .sphere(
+            Vec3::new(170.0, 90.0, 170.0),
+            90.0,
+            Fill::Material(standard.material("glass").unwrap()),
+        )
+        .build()
 }
 
 pub fn final_scene(
     image_width: u32,
     sample_per_pixel: u32,
     max_depth: u32,
+    seed: u64,
 ) -> (Camera, HittableList) {
-    let mut boxes1 = HittableList::new();
-    let ground = Arc::new(Lambertian::from_color(Vec3::new(0.48, 0.83, 0.53)));
+    let mut rng = StdRng::seed_from_u64(seed);
 
+    // heights are drawn up front, row by row, so that the layout of a seed doesn't depend on
+    // which thread makes which box
     let boxes_per_side = 20;
-    for i in 0..boxes_per_side {
-        for j in 0..boxes_per_side {
-            let w = 100.0;
-            let x0 = -1000.0 + i as f64 * w;
-            let z0 = -1000.0 + j as f64 * w;
-            let y0 = 0.0;
-            let x1 = x0 + w;
-            let y1 = random_f64_ranged(1.0, 101.0);
-            let z1 = z0 + w;
-
-            boxes1.add(box_from_vec(
-                Vec3::new(x0, y0, z0),
-                Vec3::new(x1, y1, z1),
-                ground.clone(),
-            ));
-        }
-    }
+    let heights: Vec<Float> = (0..boxes_per_side * boxes_per_side)
+        .map(|_| rng.gen_range(1.0..101.0))
+        .collect();
+    let boxes1 = HittableList::par_from_fn(heights.len(), |k| {
+        let (i, j) = (k / boxes_per_side, k % boxes_per_side);
+        let w = 100.0;
+        let x0 = -1000.0 + i as Float * w;
+        let z0 = -1000.0 + j as Float * w;
+        let y0 = 0.0;
+        let x1 = x0 + w;
+        let y1 = heights[k];
+        let z1 = z0 + w;
+
+        // materials can't be shared between the threads making the boxes, each box gets one
+        let ground = Arc::new(Lambertian::from_color(Vec3::new(0.48, 0.83, 0.53)));
+        box_from_vec(Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1), ground)
+    });
 
     let mut world = HittableList::new();
     world.add(Arc::new(BVHNode::new(boxes1)));
@@ -527,9 +459,8 @@ pub fn final_scene(
         Vec3::new(1.0, 1.0, 1.0),
     )));
 
-    let emat = Arc::new(Lambertian::from_texture(Arc::new(ImageTexture::new(
-        "earthmap.jpg",
-    ))));
+    let materials = material_registry::standard();
+    let emat = materials.material("earth").unwrap();
     world.add(Arc::new(Sphere::new(
         Vec3::new(400.0, 200.0, 400.0),
         100.0,
@@ -542,19 +473,14 @@ pub fn final_scene(
         Arc::new(Lambertian::from_texture(pertext)),
     )));
 
-    let mut boxes2 = HittableList::new();
-    let white = Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
+    let white = materials.material("white").unwrap();
     let ns = 1000;
-    for _ in 0..ns {
-        boxes2.add(Arc::new(Sphere::new(
-            Vec3::random_ranged(0.0, 165.0),
-            10.0,
-            white.clone(),
-        )));
-    }
+    let boxes2 = (0..ns)
+        .map(|_| Sphere::new(random_vec3(&mut rng, 0.0, 165.0), 10.0, white.clone()))
+        .collect();
 
     world.add(Arc::new(Translate::new(
-        Arc::new(RotateY::new(Arc::new(BVHNode::new(boxes2)), 15.0)),
+        Arc::new(RotateY::new(Arc::new(SphereBatch::bvh(boxes2)), 15.0)),
         Vec3::new(-100.0, 270.0, 395.0),
     )));
 
@@ -571,3 +497,170 @@ pub fn final_scene(
     cam.defocus_angle = 0.0;
     (cam, world)
 }
+
+// the knobs material_wedge reads, wedge::run rejects others
+pub const MATERIAL_WEDGE_PARAMS: [&str; 3] = ["fuzz", "ior", "light"];
+
+// Shader ball for wedge renders. Knobs (all optional): `fuzz` of a gold metal ball,
+// `ior` switches the ball to glass (frosted by `fuzz`), `light` is the overhead light intensity.
+pub fn material_wedge(
+    params: &[(&str, Float)],
+    image_width: u32,
+    sample_per_pixel: u32,
+) -> (Camera, HittableList) {
+    let param = |name: &str| params.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+
+    let ball: Arc<dyn Material> = match (param("ior"), param("fuzz")) {
+        (Some(ior), Some(roughness)) => Arc::new(RoughDielectric::new(ior, roughness)),
+        (Some(ior), None) => Arc::new(Dielectric::new(ior)),
+        (None, _) => Arc::new(Metal::new(
+            Vec3::new(0.9, 0.7, 0.3),
+            param("fuzz").unwrap_or(0.0),
+        )),
+    };
+    shader_ball(
+        ball,
+        param("light").unwrap_or(4.0),
+        image_width,
+        sample_per_pixel,
+    )
+}
+
+// A unit ball of `ball` on a checker floor under a square overhead light.
+pub fn shader_ball(
+    ball: Arc<dyn Material>,
+    light_intensity: Float,
+    image_width: u32,
+    sample_per_pixel: u32,
+) -> (Camera, HittableList) {
+    let mut world = HittableList::new();
+    let checker = Arc::new(CheckerTexture::from_color(
+        0.5,
+        Vec3::new(0.2, 0.2, 0.2),
+        Vec3::new(0.8, 0.8, 0.8),
+    ));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_texture(checker)),
+    )));
+
+    world.add(Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, ball)));
+
+    world.add(Arc::new(Quad::new(
+        Vec3::new(-2.0, 5.0, -2.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        Arc::new(DiffuseLight::from_color(Vec3::ones() * light_intensity)),
+    )));
+
+    let mut cam = Camera::default();
+    cam.aspect_ratio = 1.0;
+    cam.image_width = image_width;
+    cam.sample_per_pixel = sample_per_pixel;
+    cam.max_depth = 20;
+    cam.background = Vec3::new(0.35, 0.40, 0.50);
+    // largest tile count up to 8 that divides the image
+    let parts = (1..=8).rev().find(|n| image_width.is_multiple_of(*n)).unwrap();
+    cam.part_num_x = parts;
+    cam.part_num_y = parts;
+
+    cam.vfov = 30.0;
+    cam.lookfrom = Vec3::new(0.0, 2.0, 6.0);
+    cam.lookat = Vec3::new(0.0, 0.8, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+    (cam, world)
+}
+
+// The scenes above by function name, final_scene at its usual settings.
+pub fn register_scenes(registry: &mut SceneRegistry) {
+    registry.register(
+        "bouncing_spheres",
+        "spheres of every material in a field, the small ones bouncing",
+        400,
+        100,
+        bouncing_spheres,
+    );
+    registry.register(
+        "sphere_field",
+        "bouncing_spheres with four times the spheres over twice the area, instanced",
+        400,
+        100,
+        |seed| {
+            let field = SphereField {
+                extent: 22.0,
+                density: 4.0,
+                instanced: true,
+            };
+            field.build(seed)
+        },
+    );
+    registry.register(
+        "checkered_spheres",
+        "two large checkered spheres",
+        400,
+        100,
+        |_| checkered_spheres(),
+    );
+    registry.register("earth", "a globe with the earth texture", 400, 100, |_| {
+        earth()
+    });
+    registry.register(
+        "perlin_spheres",
+        "Perlin noise on a sphere and the ground",
+        400,
+        100,
+        |_| perlin_spheres(),
+    );
+    registry.register(
+        "quads",
+        "five coloured quads facing the camera",
+        400,
+        100,
+        |_| quads(),
+    );
+    registry.register(
+        "simple_light",
+        "a noise textured sphere lit by a quad and a sphere light",
+        400,
+        100,
+        |_| simple_light(),
+    );
+    registry.register(
+        "cornell_box",
+        "the Cornell box with two blocks",
+        600,
+        200,
+        |_| cornell_box(),
+    );
+    registry.register(
+        "cornell_smoke",
+        "the Cornell box with two blocks of smoke",
+        600,
+        200,
+        |_| cornell_smoke(),
+    );
+    registry.register(
+        "cornell_mirror",
+        "the Cornell box with a mirror block and a glass block",
+        600,
+        200,
+        |_| cornell_mirror(),
+    );
+    registry.register(
+        "cornell_spheres",
+        "the Cornell box with a gold and a glass sphere under a wide light",
+        600,
+        200,
+        |_| cornell_spheres(),
+    );
+    registry.register(
+        "final_scene",
+        "everything of The Next Week: boxes, media, noise, glass and motion blur",
+        800,
+        10000,
+        |seed| final_scene(800, 10000, 40, seed),
+    );
+}
@@ -0,0 +1,231 @@
+use std::sync::Arc;
+
+use crate::accelerator::Accelerator;
+use crate::camera::Camera;
+use crate::hittable::{ConstantMedium, Hittable, HittableList, RotateY, Translate};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::material_registry::{self, MaterialRegistry};
+use crate::quad::{box_from_vec, Quad};
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::vec3::{Float, Vec3};
+
+// Assembles a scene one object at a time:
+//
+//     let white = lambertian(Vec3::new(0.73, 0.73, 0.73));
+//     SceneBuilder::new()
+//         .quad(Vec3::zero(), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 555.0))
+//         .material(white.clone())
+//         .box_(Vec3::zero(), Vec3::new(165.0, 330.0, 165.0))
+//         .named("red")
+//         .rotate_y(15.0)
+//         .translate(Vec3::new(265.0, 0.0, 295.0))
+//         .camera(|c| c.vfov = 40.0)
+//         .build()
+//
+// `material`, `named`, `texture`, `light`, `rotate_y`, `translate` and `smoke` apply to the
+// object added last; an object without a material is a mid gray Lambertian. Names refer to
+// material_registry::standard() and whatever `define` adds. The camera starts as
+// Camera::default().
+pub struct SceneBuilder {
+    world: HittableList,
+    last: Option<Pending>,
+    camera: Camera,
+    materials: MaterialRegistry,
+}
+
+enum Shape {
+    Sphere(Vec3, Float),
+    MovingSphere(Vec3, Vec3, Float),
+    Quad(Vec3, Vec3, Vec3),
+    Box(Vec3, Vec3),
+}
+
+struct Pending {
+    shape: Shape,
+    mat: Option<Arc<dyn Material>>,
+    rotate_y: Float,
+    offset: Vec3,
+    // density and albedo of a constant medium filling the shape
+    smoke: Option<(Float, Vec3)>,
+}
+
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Self {
+            world: HittableList::new(),
+            last: None,
+            camera: Camera::default(),
+            materials: material_registry::standard().clone(),
+        }
+    }
+
+    // Names a material for `named`, replacing a standard one of the same name.
+    pub fn define(mut self, name: &str, mat: Arc<dyn Material>) -> Self {
+        self.materials.add_material(name, mat);
+        self
+    }
+
+    pub fn define_texture(mut self, name: &str, tex: Arc<dyn Texture>) -> Self {
+        self.materials.add_texture(name, tex);
+        self
+    }
+
+    pub fn sphere(self, center: Vec3, radius: Float) -> Self {
+        self.shape(Shape::Sphere(center, radius))
+    }
+
+    // moves from `center1` at time 0 to `center2` at time 1
+    pub fn moving_sphere(self, center1: Vec3, center2: Vec3, radius: Float) -> Self {
+        self.shape(Shape::MovingSphere(center1, center2, radius))
+    }
+
+    // the parallelogram from corner `q` along `u` and `v`
+    pub fn quad(self, q: Vec3, u: Vec3, v: Vec3) -> Self {
+        self.shape(Shape::Quad(q, u, v))
+    }
+
+    // the axis-aligned box with opposite corners `a` and `b`
+    pub fn box_(self, a: Vec3, b: Vec3) -> Self {
+        self.shape(Shape::Box(a, b))
+    }
+
+    // anything else, added as it is
+    pub fn object(mut self, object: Arc<dyn Hittable>) -> Self {
+        self.flush();
+        self.world.add(object);
+        self
+    }
+
+    pub fn material(mut self, mat: Arc<dyn Material>) -> Self {
+        self.last_mut().mat = Some(mat);
+        self
+    }
+
+    // the material of that name; panics if there is none
+    pub fn named(self, name: &str) -> Self {
+        let mat = self.materials.material(name).unwrap();
+        self.material(mat)
+    }
+
+    // a Lambertian of the texture of that name; panics if there is none
+    pub fn texture(self, name: &str) -> Self {
+        let tex = self.materials.texture(name).unwrap();
+        self.material(textured(tex))
+    }
+
+    // makes the last object an emitter of `color`
+    pub fn light(self, color: Vec3) -> Self {
+        self.material(diffuse_light(color))
+    }
+
+    // degrees about the Y axis, applied before `translate`
+    pub fn rotate_y(mut self, angle: Float) -> Self {
+        self.last_mut().rotate_y = angle;
+        self
+    }
+
+    pub fn translate(mut self, offset: Vec3) -> Self {
+        self.last_mut().offset = offset;
+        self
+    }
+
+    // fills the last object with a constant medium instead of giving it a surface
+    pub fn smoke(mut self, density: Float, albedo: Vec3) -> Self {
+        self.last_mut().smoke = Some((density, albedo));
+        self
+    }
+
+    pub fn camera(mut self, setup: impl FnOnce(&mut Camera)) -> Self {
+        setup(&mut self.camera);
+        self
+    }
+
+    pub fn build(mut self) -> (Camera, HittableList) {
+        self.flush();
+        (self.camera, self.world)
+    }
+
+    // Like build, with the objects under a BVH; for scenes of more than a handful of them.
+    pub fn build_bvh(self) -> (Camera, HittableList) {
+        self.build_with(Accelerator::Bvh)
+    }
+
+    // Like build, with the objects under `accelerator`.
+    pub fn build_with(mut self, accelerator: Accelerator) -> (Camera, HittableList) {
+        self.flush();
+        let world = HittableList::new_and_add(accelerator.build(self.world));
+        (self.camera, world)
+    }
+
+    fn shape(mut self, shape: Shape) -> Self {
+        self.flush();
+        self.last = Some(Pending {
+            shape,
+            mat: None,
+            rotate_y: 0.0,
+            offset: Vec3::zero(),
+            smoke: None,
+        });
+        self
+    }
+
+    fn last_mut(&mut self) -> &mut Pending {
+        self.last
+            .as_mut()
+            .expect("SceneBuilder: add a shape before setting its material or transform")
+    }
+
+    fn flush(&mut self) {
+        let Some(last) = self.last.take() else {
+            return;
+        };
+        let mat = last
+            .mat
+            .unwrap_or_else(|| lambertian(Vec3::new(0.5, 0.5, 0.5)));
+        let mut object: Arc<dyn Hittable> = match last.shape {
+            Shape::Sphere(center, radius) => Arc::new(Sphere::new(center, radius, mat)),
+            Shape::MovingSphere(center1, center2, radius) => {
+                Arc::new(Sphere::new_moving(center1, center2, radius, mat))
+            }
+            Shape::Quad(q, u, v) => Arc::new(Quad::new(q, u, v, mat)),
+            Shape::Box(a, b) => box_from_vec(a, b, mat),
+        };
+        if last.rotate_y != 0.0 {
+            object = Arc::new(RotateY::new(object, last.rotate_y));
+        }
+        if !last.offset.near_zero() {
+            object = Arc::new(Translate::new(object, last.offset));
+        }
+        if let Some((density, albedo)) = last.smoke {
+            object = Arc::new(ConstantMedium::from_color(object, density, albedo));
+        }
+        self.world.add(object);
+    }
+}
+
+impl Default for SceneBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn lambertian(albedo: Vec3) -> Arc<dyn Material> {
+    Arc::new(Lambertian::from_color(albedo))
+}
+
+pub fn textured(tex: Arc<dyn Texture>) -> Arc<dyn Material> {
+    Arc::new(Lambertian::from_texture(tex))
+}
+
+pub fn metal(albedo: Vec3, fuzz: Float) -> Arc<dyn Material> {
+    Arc::new(Metal::new(albedo, fuzz))
+}
+
+pub fn dielectric(refraction_index: Float) -> Arc<dyn Material> {
+    Arc::new(Dielectric::new(refraction_index))
+}
+
+pub fn diffuse_light(color: Vec3) -> Arc<dyn Material> {
+    Arc::new(DiffuseLight::from_color(color))
+}
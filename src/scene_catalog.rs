@@ -0,0 +1,53 @@
+use image::RgbImage;
+
+use crate::contact_sheet::{contact_sheet, positive_arg};
+use crate::preset::set_image_width;
+use crate::scene_registry;
+use crate::util;
+
+// `catalog [--size W] [--spp N] [--seed N]` renders every scene of the registry W pixels wide
+// (160 by default) with N samples per pixel (16) and lays the thumbnails out in a labelled
+// sheet: a quick look at the whole scene library after a change to the renderer. The seed
+// (0 by default) picks the layouts of the random scenes and makes the sheet repeatable.
+pub fn run(args: &[String], thread_limit: u32) -> Result<RgbImage, String> {
+    let mut size = 160;
+    let mut spp = 16;
+    let mut seed = 0;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--size" | "--spp" => {
+                let value = positive_arg(args, i)?;
+                if args[i] == "--size" {
+                    size = value;
+                } else {
+                    spp = value;
+                }
+                i += 1;
+            }
+            "--seed" => {
+                seed = args
+                    .get(i + 1)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or("--seed expects a whole number")?;
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let mut cells = vec![];
+    for entry in scene_registry::standard().entries() {
+        println!("scene: {}", entry.name);
+        util::seed_rng(seed);
+        let (mut cam, world) = entry.build(seed);
+        set_image_width(&mut cam, size);
+        cam.sample_per_pixel = spp;
+        cam.seed = Some(seed);
+        cam.thread_limit = thread_limit;
+        cells.push((cam.render(&world), entry.name.to_owned()));
+    }
+    let columns = (cells.len() as f64).sqrt().ceil() as usize;
+    Ok(contact_sheet(&cells, columns))
+}
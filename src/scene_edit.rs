@@ -0,0 +1,156 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::aabb::AABB;
+use crate::bvh::BVHNode;
+use crate::hittable::{HitRecord, Hittable, HittableList, RotateY, Translate};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Float, Vec3};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InstanceId(usize);
+
+#[derive(Clone)]
+struct Instance {
+    object: Arc<dyn Hittable + Send + Sync>,
+    offset: Vec3,
+    rotate_y: Float,
+    mat: Option<Arc<dyn Material + Send + Sync>>,
+}
+
+// Double-buffered world for interactive hosts such as an editor. Edits from any thread go to
+// a staging copy; the renderer keeps tracing the published snapshot and calls `publish()`
+// between progressive passes to swap the edits in. Instances keep their own geometry (and
+// its BVH), so publishing only rebuilds the small BVH over the instances, however much
+// was edited.
+pub struct EditableScene {
+    staging: Mutex<Vec<Option<Instance>>>,
+    live: RwLock<Arc<HittableList>>,
+}
+
+impl EditableScene {
+    pub fn new() -> Self {
+        Self {
+            staging: Mutex::new(vec![]),
+            live: RwLock::new(Arc::new(HittableList::new())),
+        }
+    }
+
+    pub fn add(&self, object: Arc<dyn Hittable + Send + Sync>) -> InstanceId {
+        let mut staging = self.staging.lock().unwrap();
+        staging.push(Some(Instance {
+            object,
+            offset: Vec3::zero(),
+            rotate_y: 0.0,
+            mat: None,
+        }));
+        InstanceId(staging.len() - 1)
+    }
+
+    pub fn remove(&self, id: InstanceId) -> Result<(), String> {
+        let mut staging = self.staging.lock().unwrap();
+        match staging.get_mut(id.0) {
+            Some(slot @ Some(_)) => {
+                *slot = None;
+                Ok(())
+            }
+            _ => Err(format!("no instance {:?}", id)),
+        }
+    }
+
+    // Rotates the instance about the Y axis by `rotate_y` degrees, then moves it by `offset`.
+    pub fn set_transform(
+        &self,
+        id: InstanceId,
+        offset: Vec3,
+        rotate_y: Float,
+    ) -> Result<(), String> {
+        self.edit(id, |instance| {
+            instance.offset = offset;
+            instance.rotate_y = rotate_y;
+        })
+    }
+
+    // Replaces the material of everything in the instance.
+    pub fn set_material(
+        &self,
+        id: InstanceId,
+        mat: Arc<dyn Material + Send + Sync>,
+    ) -> Result<(), String> {
+        self.edit(id, |instance| instance.mat = Some(mat))
+    }
+
+    fn edit<F: FnOnce(&mut Instance)>(&self, id: InstanceId, f: F) -> Result<(), String> {
+        let mut staging = self.staging.lock().unwrap();
+        match staging.get_mut(id.0) {
+            Some(Some(instance)) => {
+                f(instance);
+                Ok(())
+            }
+            _ => Err(format!("no instance {:?}", id)),
+        }
+    }
+
+    // Makes the staged edits visible to the next `snapshot()`. Passes holding the previous
+    // snapshot finish on it undisturbed.
+    pub fn publish(&self) {
+        let instances: Vec<Instance> = self
+            .staging
+            .lock()
+            .unwrap()
+            .iter()
+            .flatten()
+            .cloned()
+            .collect();
+
+        let mut world = HittableList::new();
+        for instance in instances {
+            let mut object: Arc<dyn Hittable> = instance.object;
+            if let Some(mat) = instance.mat {
+                object = Arc::new(MaterialOverride { object, mat });
+            }
+            if instance.rotate_y != 0.0 {
+                object = Arc::new(RotateY::new(object, instance.rotate_y));
+            }
+            if instance.offset != Vec3::zero() {
+                object = Arc::new(Translate::new(object, instance.offset));
+            }
+            world.add(object);
+        }
+        if !world.objects.is_empty() {
+            world = HittableList::new_and_add(Arc::new(BVHNode::new(world)));
+        }
+        *self.live.write().unwrap() = Arc::new(world);
+    }
+
+    // The world to render this pass with.
+    pub fn snapshot(&self) -> Arc<HittableList> {
+        self.live.read().unwrap().clone()
+    }
+}
+
+impl Default for EditableScene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct MaterialOverride {
+    object: Arc<dyn Hittable>,
+    mat: Arc<dyn Material>,
+}
+
+impl Hittable for MaterialOverride {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        if !self.object.hit(r, ray_t, rec) {
+            return false;
+        }
+        rec.mat = &*self.mat;
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.object.bounding_box()
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::aperture::Aperture;
+use crate::camera::{Camera, LightSampling};
+use crate::film::Filter;
+use crate::hittable::{Hittable, HittableList};
+use crate::material::Material;
+use crate::material_registry;
+use crate::motion::Motion;
+use crate::quad::UvOrientation;
+use crate::texture::Texture;
+use crate::vec3::{Float, Vec3};
+
+// Writes a camera and world, however they were built, as a scene file that load_scene reads
+// back into the same scene:
+//
+//     let (cam, world) = scene::bouncing_spheres(1);
+//     scene_export::save_scene("bouncing.scene", &cam, &world)?;
+//
+// Objects are written in world space, with RotateY and Translate applied to their
+// coordinates; BVHs and sphere batches come out as the primitives they hold. Materials are
+// written once each, as m1, m2, ..., except those of material_registry::standard(), which
+// are referred to by name. Objects, materials and textures the format has no statement for
+// (Animate, procedural textures with nested textures, mask apertures...) make the export
+// fail with an error naming them. The random patterns of noise textures are not part of
+// the file: they come out alike when both renders use the same `--seed`.
+pub fn export_scene(cam: &Camera, world: &HittableList) -> Result<String, String> {
+    let mut out = SceneWriter::new();
+    world.export(&mut out)?;
+
+    let mut lines = vec!["# exported scene".to_owned()];
+    lines.extend(camera_lines(cam)?);
+    lines.push(String::new());
+    lines.append(&mut out.materials);
+    lines.push(String::new());
+    lines.append(&mut out.objects);
+    lines.push(String::new());
+    Ok(lines.join("\n"))
+}
+
+pub fn save_scene(path: &str, cam: &Camera, world: &HittableList) -> Result<(), String> {
+    let text = export_scene(cam, world)?;
+    std::fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn camera_lines(cam: &Camera) -> Result<Vec<String>, String> {
+    let mut lines = vec![
+        format!("camera lookfrom {}", vec3(cam.lookfrom)),
+        format!("camera lookat {}", vec3(cam.lookat)),
+        format!("camera vup {}", vec3(cam.vup)),
+        format!("camera vfov {}", cam.vfov),
+        format!("camera aspect_ratio {}", cam.aspect_ratio),
+        format!("camera image_width {}", cam.image_width),
+        format!("camera spp {}", cam.sample_per_pixel),
+        format!("camera max_depth {}", cam.max_depth),
+        format!("camera roulette_depth {}", cam.roulette_depth),
+        format!("camera background {}", vec3(cam.background)),
+        format!("camera defocus_angle {}", cam.defocus_angle),
+        format!("camera focus_dist {}", cam.focus_dist),
+        format!("camera tiles {} {}", cam.part_num_x, cam.part_num_y),
+        format!("camera projection {}", cam.projection.name()),
+        format!("camera shutter {} {}", cam.shutter_open, cam.shutter_close),
+        format!("camera matte_key {}", vec3(cam.matte_key)),
+    ];
+    if let Some(autofocus) = cam.autofocus {
+        lines.push(format!("camera autofocus {}", autofocus.name()));
+    }
+    if cam.shift_x != 0.0 || cam.shift_y != 0.0 {
+        lines.push(format!("camera shift {} {}", cam.shift_x, cam.shift_y));
+    }
+    if let Some(sky) = cam.sky {
+        lines.push(format!(
+            "camera sky {}  {}",
+            vec3(sky.bottom),
+            vec3(sky.top)
+        ));
+    }
+    if let Some(physical) = cam.physical {
+        lines.push(format!(
+            "camera exposure {} {} {} {}",
+            physical.iso, physical.shutter_speed, physical.f_number, physical.units_per_meter
+        ));
+    }
+    match cam.light_sampling {
+        LightSampling::Bsdf => {}
+        LightSampling::Mixture(weight) => {
+            lines.push(format!("camera light_sampling mixture:{}", weight))
+        }
+        LightSampling::Mis => lines.push("camera light_sampling mis".to_owned()),
+    }
+    if cam.ray_cones {
+        lines.push("camera ray_cones".to_owned());
+    }
+    if cam.halton_lens {
+        lines.push("camera halton_lens".to_owned());
+    }
+    if cam.denoise.is_some() {
+        lines.push("camera denoise".to_owned());
+    }
+    if cam.filter != Filter::default() {
+        lines.push(format!(
+            "camera filter {}:{}",
+            cam.filter.name(),
+            cam.filter.radius()
+        ));
+    }
+    match cam.aperture {
+        Aperture::Circle => {}
+        Aperture::Polygon { blades, rotation } => {
+            lines.push(format!("camera aperture blades {} {}", blades, rotation))
+        }
+        Aperture::Mask(_) => return Err("mask apertures can't be exported".to_owned()),
+    }
+    Ok(lines)
+}
+
+// The shape of a medium's boundary, collected instead of written: spheres in world space,
+// quads as given along with the angle and offset they are placed with.
+enum Boundary {
+    Sphere(Vec3, Float),
+    Quad(Vec3, Vec3, Vec3, Float, Vec3),
+}
+
+// Collects the statements of a scene; objects call it from Hittable::export, materials and
+// textures return their definitions from Material::export and Texture::export.
+pub struct SceneWriter {
+    materials: Vec<String>,
+    objects: Vec<String>,
+    // names of the materials written so far, by address and by definition
+    names: HashMap<usize, String>,
+    definitions: HashMap<String, String>,
+    // where the objects written go: rotated about y by `angle` degrees, then moved by
+    // `offset`, then moved by `motion` (in world space) over the shutter
+    angle: Float,
+    offset: Vec3,
+    motion: Motion,
+    // `name` statement of the objects written, see hittable::Named
+    name: Option<String>,
+    // set while the boundary of a medium is being collected
+    boundary: Option<Vec<Boundary>>,
+}
+
+impl SceneWriter {
+    fn new() -> Self {
+        Self {
+            materials: vec![],
+            objects: vec![],
+            names: HashMap::new(),
+            definitions: HashMap::new(),
+            angle: 0.0,
+            offset: Vec3::zero(),
+            motion: Motion::Static,
+            name: None,
+            boundary: None,
+        }
+    }
+
+    // The name of `mat` in the file, defining it on first use.
+    pub fn material(&mut self, mat: &Arc<dyn Material>) -> Result<String, String> {
+        if let Some(name) = material_registry::standard().material_name(mat) {
+            return Ok(name.to_owned());
+        }
+        let key = Arc::as_ptr(mat) as *const () as usize;
+        if let Some(name) = self.names.get(&key) {
+            return Ok(name.clone());
+        }
+        // equal materials share a name, so that a texture shared between them (the noise of
+        // scene::perlin_spheres, say) is loaded as one texture again
+        let definition = mat.export(self)?;
+        let name = match self.definitions.get(&definition) {
+            Some(name) => name.clone(),
+            None => {
+                let name = format!("m{}", self.definitions.len() + 1);
+                self.materials
+                    .push(format!("material {} {}", name, definition));
+                self.definitions.insert(definition, name.clone());
+                name
+            }
+        };
+        self.names.insert(key, name.clone());
+        Ok(name)
+    }
+
+    // What follows `material <name>` for a Lambertian of `tex`, see Texture::export.
+    pub fn texture(&mut self, tex: &Arc<dyn Texture>) -> Result<String, String> {
+        match material_registry::standard().texture_name(tex) {
+            Some(name) => Ok(format!("texture {}", name)),
+            None => tex.export(self),
+        }
+    }
+
+    pub fn sphere(
+        &mut self,
+        center: Vec3,
+        radius: Float,
+        motion: &Motion,
+        mat: &Arc<dyn Material>,
+    ) -> Result<(), String> {
+        let placed = self.place(center);
+        if let Some(boundary) = &mut self.boundary {
+            boundary.push(Boundary::Sphere(placed, radius));
+            return Ok(());
+        }
+        let motion = match (motion, &self.motion) {
+            (Motion::Static, outer) => outer.clone(),
+            (own, Motion::Static) => self.rotate_motion(own),
+            _ => return Err("a moving sphere inside a moving object can't be exported".to_owned()),
+        };
+        let mat = self.material(mat)?;
+        self.object(format!(
+            "sphere {}  {}  {}{}",
+            vec3(self.place(center)),
+            radius,
+            mat,
+            motion_suffix(&motion)
+        ));
+        Ok(())
+    }
+
+    pub fn quad(
+        &mut self,
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        uv: UvOrientation,
+        mat: &Arc<dyn Material>,
+    ) -> Result<(), String> {
+        if let Some(boundary) = &mut self.boundary {
+            boundary.push(Boundary::Quad(q, u, v, self.angle, self.offset));
+            return Ok(());
+        }
+        let mat = self.material(mat)?;
+        let mut orientation = String::new();
+        if !uv.quarter_turns.is_multiple_of(4) {
+            orientation += &format!(" uv_rotate {}", 90 * (uv.quarter_turns % 4) as u32);
+        }
+        if uv.flip_u {
+            orientation += " flip_u";
+        }
+        if uv.flip_v {
+            orientation += " flip_v";
+        }
+        self.object(format!(
+            "quad {}  {}  {}  {}{}{}",
+            vec3(self.place(q)),
+            vec3(self.rotate(u)),
+            vec3(self.rotate(v)),
+            mat,
+            orientation,
+            motion_suffix(&self.motion)
+        ));
+        Ok(())
+    }
+
+    pub fn disk(
+        &mut self,
+        center: Vec3,
+        normal: Vec3,
+        radius: Float,
+        mat: &Arc<dyn Material>,
+    ) -> Result<(), String> {
+        self.no_boundary("disk")?;
+        let mat = self.material(mat)?;
+        self.object(format!(
+            "disk {}  {}  {}  {}{}",
+            vec3(self.place(center)),
+            vec3(self.rotate(normal)),
+            radius,
+            mat,
+            motion_suffix(&self.motion)
+        ));
+        Ok(())
+    }
+
+    // `velocity` moves the corners over the shutter (Triangle::new_moving)
+    pub fn triangle(
+        &mut self,
+        corners: [Vec3; 3],
+        normals: Option<[Vec3; 3]>,
+        velocity: Option<[Vec3; 3]>,
+        mat: &Arc<dyn Material>,
+    ) -> Result<(), String> {
+        self.no_boundary("triangle")?;
+        if velocity.is_some() && !self.motion.is_static() {
+            return Err("a deforming triangle inside a moving object can't be exported".to_owned());
+        }
+        let mat = self.material(mat)?;
+        let [a, b, c] = corners.map(|p| vec3(self.place(p)));
+        let mut line = format!("triangle {}  {}  {}  {}", a, b, c, mat);
+        if let Some(normals) = normals {
+            let [a, b, c] = normals.map(|n| vec3(self.rotate(n)));
+            line += &format!(" normals {}  {}  {}", a, b, c);
+        }
+        if let Some(velocity) = velocity {
+            let [a, b, c] = velocity.map(|v| vec3(self.rotate(v)));
+            line += &format!(" velocity {}  {}  {}", a, b, c);
+        }
+        line += &motion_suffix(&self.motion);
+        self.object(line);
+        Ok(())
+    }
+
+    // Writes `object` rotated about y by `angle` degrees, then moved by `offset`, within the
+    // current placement (RotateY, Translate).
+    pub fn transformed(
+        &mut self,
+        angle: Float,
+        offset: Vec3,
+        object: &dyn Hittable,
+    ) -> Result<(), String> {
+        let saved = (self.angle, self.offset);
+        self.offset = self.place(offset);
+        self.angle += angle;
+        let result = object.export(self);
+        (self.angle, self.offset) = saved;
+        result
+    }
+
+    // Writes `object` moving by `motion` over the shutter (motion::Moving).
+    pub fn moving(&mut self, motion: &Motion, object: &dyn Hittable) -> Result<(), String> {
+        self.no_boundary("moving object")?;
+        if !self.motion.is_static() {
+            return Err("nested motion can't be exported".to_owned());
+        }
+        self.motion = self.rotate_motion(motion);
+        let result = object.export(self);
+        self.motion = Motion::Static;
+        result
+    }
+
+    // Writes `object` under `name` (hittable::Named); an outer name wins, as in rendering.
+    pub fn named(&mut self, name: String, object: &dyn Hittable) -> Result<(), String> {
+        if self.name.is_some() {
+            return object.export(self);
+        }
+        self.name = Some(name);
+        let result = object.export(self);
+        self.name = None;
+        result
+    }
+
+    // A `medium` statement: `boundary` must be a sphere or a box, `options` are the density,
+    // albedo and emission options of the statement.
+    pub fn medium(&mut self, boundary: &dyn Hittable, options: &str) -> Result<(), String> {
+        self.no_boundary("nested medium")?;
+        if !self.motion.is_static() {
+            return Err("moving media can't be exported".to_owned());
+        }
+        self.boundary = Some(vec![]);
+        let result = boundary.export(self);
+        let shapes = self.boundary.take().unwrap_or_default();
+        result?;
+
+        let line = match shapes.as_slice() {
+            [Boundary::Sphere(center, radius)] => {
+                format!("medium sphere {}  {}  {}", vec3(*center), radius, options)
+            }
+            quads if quads.len() == 6 => {
+                // box_from_vec: six axis-aligned quads, spanning the box between them, all
+                // placed alike
+                let mut corners = vec![];
+                let mut placement = None;
+                for quad in quads {
+                    match quad {
+                        Boundary::Quad(q, u, v, angle, offset)
+                            if axis_aligned(*u)
+                                && axis_aligned(*v)
+                                && *placement.get_or_insert((*angle, *offset))
+                                    == (*angle, *offset) =>
+                        {
+                            corners.extend([*q, *q + *u + *v])
+                        }
+                        _ => return Err("medium boundaries must be spheres or boxes".to_owned()),
+                    }
+                }
+                let (angle, offset) = placement.unwrap();
+                let pick = |f: fn(Float, Float) -> Float| {
+                    corners.iter().fold(corners[0], |a, b| {
+                        Vec3::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z))
+                    })
+                };
+                let (min, max) = (pick(Float::min), pick(Float::max));
+                if angle == 0.0 {
+                    format!(
+                        "medium box {}  {}  {}",
+                        vec3(min + offset),
+                        vec3(max + offset),
+                        options
+                    )
+                } else {
+                    format!(
+                        "medium box {}  {}  {} rotate_y {} translate {}",
+                        vec3(min),
+                        vec3(max),
+                        options,
+                        angle,
+                        vec3(offset)
+                    )
+                }
+            }
+            _ => return Err("medium boundaries must be spheres or boxes".to_owned()),
+        };
+        self.object(line);
+        Ok(())
+    }
+
+    fn object(&mut self, line: String) {
+        match &self.name {
+            Some(name) => self.objects.push(format!("name {} {}", name, line)),
+            None => self.objects.push(line),
+        }
+    }
+
+    fn no_boundary(&self, what: &str) -> Result<(), String> {
+        match self.boundary {
+            Some(_) => Err(format!("a {} as a medium boundary can't be exported", what)),
+            None => Ok(()),
+        }
+    }
+
+    fn rotate(&self, v: Vec3) -> Vec3 {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        Vec3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z)
+    }
+
+    fn place(&self, p: Vec3) -> Vec3 {
+        self.rotate(p) + self.offset
+    }
+
+    fn rotate_motion(&self, motion: &Motion) -> Motion {
+        match motion {
+            Motion::Static => Motion::Static,
+            Motion::Linear(offset) => Motion::Linear(self.rotate(*offset)),
+            Motion::Keyframed(keys) => {
+                Motion::Keyframed(keys.iter().map(|(t, v)| (*t, self.rotate(*v))).collect())
+            }
+        }
+    }
+}
+
+fn axis_aligned(v: Vec3) -> bool {
+    [v.x, v.y, v.z].iter().filter(|c| **c != 0.0).count() == 1
+}
+
+// "x y z", each number as exactly as Display gives it, so the file reads back the same values
+pub fn vec3(v: Vec3) -> String {
+    format!("{} {} {}", v.x, v.y, v.z)
+}
+
+fn motion_suffix(motion: &Motion) -> String {
+    match motion {
+        Motion::Static => String::new(),
+        Motion::Linear(offset) => format!(" move {}", vec3(*offset)),
+        Motion::Keyframed(keys) => {
+            let keys: Vec<String> = keys
+                .iter()
+                .map(|(t, offset)| format!("{} {}", t, vec3(*offset)))
+                .collect();
+            format!(" keyframes {}", keys.join("  "))
+        }
+    }
+}
@@ -0,0 +1,1171 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::accelerator::Accelerator;
+use crate::aperture::Aperture;
+use crate::camera::{Autofocus, Camera, LightSampling, Projection};
+use crate::denoise::Denoiser;
+use crate::disk::Disk;
+use crate::exposure::PhysicalCamera;
+use crate::film::Filter;
+use crate::hittable::{ConstantMedium, Hittable, HittableList, Named, RotateY, Translate};
+use crate::light_rig::{ring_light, skylight_grid, three_point};
+use crate::material::{
+    Conductor, Cutout, Dielectric, DiffuseLight, Dispersion, Estimator, Lambertian, Layer,
+    Material, Metal, Mix, RoughDielectric, WithEstimator,
+};
+use crate::material_registry;
+use crate::motion::{Animate, Motion, Moving};
+use crate::obj::{load_obj, load_obj_moving, sequence_path, Shading};
+use crate::preset::{set_image_width, Preset};
+use crate::quad::{box_from_vec, Quad, UvOrientation};
+use crate::scene_registry;
+use crate::sphere::Sphere;
+use crate::text::text_mesh;
+use crate::texture::{
+    find_texture, BlackbodyTexture, Brick, CheckerTexture, Gradient, Marble, NoisePattern,
+    NoiseTexture, Ramp, SolidColor, Texture, Triplanar, UvChecker, UvGrid, UvTransform, Wood, Wrap,
+};
+use crate::texture_registry::TextureRegistry;
+use crate::tiled_texture::{self, TiledImageTexture};
+use crate::triangle::Triangle;
+use crate::vec3::{Float, Vec3};
+
+// Line based scene description, one statement per line, a '#' token starts a comment:
+//
+//     material white lambertian 0.73 0.73 0.73
+//     material earth big_image earth_16k.png cache 256   # paged in 64x64 tiles, LRU of 256
+//     material tiles image tiles.png scale 8 8 rotate 45 offset 0.5 0 wrap mirror
+//     material debug uv_checker 16              # or uv_grid 10 [line width, 0.05]
+//     material tiles uv_checker 16 colors 1 1 1  0 0 0    # even, odd colours
+//     material ground checker 0.32  0.2 0.3 0.1  0.9 0.9 0.9   # square size, even, odd
+//     material mud noise cracks 3               # or marble, cells, clouds; then the scale
+//     material moss noise cells 5 colors 0.1 0.2 0 0.3 0.5 0.1          # low, high colours
+//     material land noise clouds 1 ramp 0.4 0 0 0.5  0.45 0.8 0.7 0.4  0.6 0.2 0.5 0.1
+//     material slab marble 4  0.2 0.2 0.25  0.9 0.9 0.9     # scale, vein, base
+//     material floor wood 8  0.8 0.6 0.4  0.5 0.3 0.15       # rings per unit, light, dark
+//     material wall brick 0.2 0.06 0.01  0.6 0.25 0.2  0.8 0.8 0.75   # w, h, mortar, colours
+//     material rock image rock.png triplanar 2 # projected along x, y, z; tiles every 2 units
+//     material plot image false_color.png linear   # data, not sRGB colours; masks always are
+//     material globe texture earth scale 2 1    # a texture of material_registry::standard()
+//     material lamp light 15 15 15
+//     material panel light 1 1 1 intensity 15 one_sided   # emits on the normal's side only
+//     material tv light_image screen.png intensity 3
+//     material bottle dielectric 1.5 absorb 0.2 0.8 0.3 0.05
+//     material frosted rough_dielectric 1.5 0.3
+//     material prism dispersive crown_glass     # or diamond, cauchy A B, sellmeier B1..B3 C1..C3
+//     material ring conductor gold 0.05         # or copper, aluminum, silver, n r g b k r g b fuzz
+//     material worn mix ring white image rust_mask.png   # or a constant factor: mix a b 0.3
+//     material gloss metal 1 1 1 0
+//     material varnished layer gloss white 1.5           # coat, base, ior of the coat
+//     material leaf cutout green leaf_mask.png           # white in the mask is solid
+//     material wall estimator white bsdf        # no shadow rays from it; or lights, see below
+//     sphere 0 1 0  1  white
+//     quad 343 554 332  -130 0 0  0 0 -105  lamp
+//     quad 0 1 0  1.6 0 0  0 0.9 0  tv  uv_rotate 90 flip_u   # turns and mirrors the image
+//     box 0 0 0  165 330 165  white
+//     disk 0 5 0  0 -1 0  1.5  lamp             # center, facing direction, radius
+//     triangle 0 0 0  1 0 0  0 1 0  white normals 0 0 1  0 0 1  0 0 1   # smooth shading
+//     triangle 0 0 0  1 0 0  0 1 0  white velocity 0 0 0  0 1 0  0 0 0  # corners move
+//     light_rig three_point front 0 0 1 brightness 1.5   # or skylight [grid n], ring
+//     light_rig ring bounds -1 0 -1  1 2 1      # default: bounds of the objects so far
+//     obj bunny.obj white
+//     obj teapot.obj white smooth               # or flat; default: the normals of the file
+//     text -2 0 0  0.5 0.1  white  DejaVuSans.ttf  Hello   # position, size, depth (0: flat)
+//     medium sphere 0 1 0 1  density 2 albedo 0 0 0 blackbody 1900 20 noise 4   # fire
+//     medium box 0 0 0  1 1 1  density 0.5 albedo 0.9 0.9 0.9 emit 0.2 0.4 1
+//     medium box 0 0 0  165 330 165  density 0.01 rotate_y 15 translate 265 0 295
+//     obj_sequence cloth/cloth_####.obj white smooth blur
+//     camera lookfrom 278 278 -800
+//     camera image_width 600                    # required; tile counts shrink to divide it
+//     camera tiles 20 20
+//     camera sky                                # white to blue; or sky <bottom rgb> <top rgb>
+//     camera shutter 0.25 0.75                  # half the motion blur; equal times freeze it
+//     camera shift 0 0.25                       # lens shift right and up, in frame sizes
+//     camera projection equirect                # perspective, fisheye, equisolid, equirect
+//     camera exposure 100 0.008 16              # ISO, shutter seconds, f-number [units/meter]
+//     camera aperture blades 6 15               # hexagonal bokeh; or circle, or mask heart.png
+//     camera halton_lens                        # stratified lens samples, smoother defocus
+//     camera autofocus center                   # focus_dist from lookat, center or <x>,<y>
+//     camera preset draft                       # draft, medium or final
+//     camera light_sampling mis                 # bsdf, mixture[:<weight>] or mis
+//     camera ray_cones                          # widens thin geometry to the pixel footprint
+//     camera filter gaussian:2                  # box, tent, gaussian, mitchell, blackman-harris
+//     camera denoise                            # edge-aware filter over the finished image
+//     camera frame name hero fill 0.6 direction 1 0.5 2   # aims at what is loaded so far
+//     include light_rig.scene as rig rotate_y 15 translate 265 0 295
+//     sphere 400 400 200  50  white  move 30 0 0
+//     box 0 0 0  1 1 1  white  keyframes 0 0 0 0  0.5 0 1 0  1 0 0 0
+//     box 0 0 0  1 1 1  white  move 2 0 0 spin 90    # turns 90 degrees about its center
+//     box 0 0 0  1 1 1  white  spin 90 keyframes 0 0 0 0  1 2 1 0   # turns along the way
+//     sphere 0 1 0  1  white  track 1 0 0 0  48 4 0 0   # moves 4 units over frames 1 to 48
+//     name hero obj hero.obj white              # names what the rest of the line adds
+//     camera matte_key 0 0 1                    # background of `--matte` renders
+//     accelerator kdtree                        # bvh (the default), kdtree or grid
+//     builtin cornell_box seed 2                # a scene of the registry, see below
+//
+// Material names a file doesn't define refer to material_registry::standard() ("white", "red",
+// "green", "glass", "mirror", "gold", "light", "earth", "checker"), so
+//     box 0 0 0  165 330 165  white
+// works without a `material white` line. A file's own definition of a name comes first.
+//
+// `estimator` turns off one of the two ways `camera light_sampling` finds the lights from a
+// diffuse material (material::Estimator): `bsdf` sends no shadow rays from it, `lights`
+// leaves the lights to shadow rays alone under mis (its bounces still carry the rest of
+// the light), `both` is the default.
+//
+// Included files are self-contained: they only see their own materials. After loading, their
+// materials are exported to the includer, as "<prefix>.<name>" when `as <prefix>` is given.
+// Exporting a name that already exists is an error unless both definitions are identical.
+// `rotate_y` and `translate` at the include site are applied (in that order) to every
+// object of the included file. Camera statements apply in file order, includes included.
+//
+// `obj_sequence` loads the mesh of the given frame (the '#' run is replaced by the zero padded
+// frame number), so animated scenes are loaded once per frame. With a trailing `blur` the next
+// frame is loaded as well and the vertices move towards it over the shutter interval.
+//
+// Objects (sphere, quad, box, triangle, obj, obj_sequence) can end with a rigid motion over
+// the shutter: `move` is an offset reached at time 1, `keyframes` lists (time, offset) keys.
+// `track` lists (frame, offset) keys for animations (`--frames`): the object sits at the
+// offset of the frame and blurs towards that of the next one.
+// All but spheres can also `spin` by some degrees about their vertical center line, over
+// the shutter (per frame with a track), before or after the motion.
+//
+// `matte` selects a named object for a matte pass (Camera::matte). Several statements can
+// share a name; an object inside a named include takes the include's name. `--bake` bakes
+// a lightmap over the surfaces of a named object (load_named, Camera::bake).
+//
+// `builtin` adds the objects of a scene of scene_registry::standard() (`--list-scenes`), laid
+// out by the seed (0 without one), and replaces the camera with the scene's: camera
+// statements after it change that camera, those before it are lost.
+//
+// `camera frame` (Camera::frame) aims at everything loaded so far, or at a named object,
+// from the given direction or the current lookfrom side; set vfov and aspect_ratio first.
+// Objects named inside an included file are framed where that file places them, before
+// the include's own rotate_y and translate.
+pub fn load_scene(
+    path: &str,
+    frame: u32,
+    matte: Option<&str>,
+) -> Result<(Camera, HittableList), String> {
+    let mut loader = Loader::new(frame);
+    let mut world = HittableList::new();
+    loader.load_file(Path::new(path), &mut world)?;
+    if let Some(matte) = matte {
+        let id = loader
+            .names
+            .iter()
+            .position(|(name, _)| name == matte)
+            .ok_or(format!("{}: no object named \"{}\"", path, matte))?;
+        loader.camera.matte = Some(id as u32 + 1);
+    }
+    if loader.camera.image_width == 0 {
+        return Err(format!("{}: no `camera image_width`", path));
+    }
+    // the tiles have to divide the image
+    let width = loader.camera.image_width;
+    set_image_width(&mut loader.camera, width);
+    if !world.objects.is_empty() {
+        world = HittableList::new_and_add(loader.accelerator.build(world));
+    }
+    Ok((loader.camera, world))
+}
+
+pub type NamedMaterials = Vec<(String, Arc<dyn Material>)>;
+
+// The materials a library file defines (includes and all), sorted by name. Objects and
+// camera settings in the file are ignored.
+pub fn load_materials(path: &str) -> Result<NamedMaterials, String> {
+    let mut loader = Loader::new(1);
+    let materials = loader.load_file(Path::new(path), &mut HittableList::new())?;
+    let mut materials: NamedMaterials = materials
+        .into_iter()
+        .map(|(name, def)| (name, def.mat))
+        .collect();
+    materials.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(materials)
+}
+
+// The objects of a scene file named `name`, in world space but for those inside named
+// includes, which are where the included file places them (as with `camera frame`).
+pub fn load_named(path: &str, frame: u32, name: &str) -> Result<HittableList, String> {
+    let mut loader = Loader::new(frame);
+    loader.load_file(Path::new(path), &mut HittableList::new())?;
+    loader
+        .names
+        .into_iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, objects)| objects)
+        .ok_or(format!("{}: no object named \"{}\"", path, name))
+}
+
+struct MaterialDef {
+    mat: Arc<dyn Material>,
+    // the normalized definition, used to tell harmless re-definitions from real collisions
+    definition: String,
+}
+
+struct Loader {
+    camera: Camera,
+    include_stack: Vec<PathBuf>,
+    frame: u32,
+    // images shared by all materials, includes and all
+    textures: TextureRegistry,
+    // named objects in order of appearance; the id of a name is its index + 1
+    names: Vec<(String, HittableList)>,
+    // what the world is put in once loaded
+    accelerator: Accelerator,
+}
+
+impl Loader {
+    fn new(frame: u32) -> Self {
+        Self {
+            camera: Camera::default(),
+            include_stack: vec![],
+            frame,
+            textures: TextureRegistry::new(),
+            names: vec![],
+            accelerator: Accelerator::Bvh,
+        }
+    }
+
+    fn load_file(
+        &mut self,
+        path: &Path,
+        world: &mut HittableList,
+    ) -> Result<HashMap<String, MaterialDef>, String> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        if self.include_stack.contains(&canonical) {
+            return Err(format!("{}: recursive include", path.display()));
+        }
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        self.include_stack.push(canonical);
+        let mut materials = HashMap::new();
+        for (line_no, line) in text.lines().enumerate() {
+            // a comment starts with a '#' token, so "cloth_####.obj" stays intact
+            let tokens: Vec<&str> = line
+                .split_whitespace()
+                .take_while(|t| !t.starts_with('#'))
+                .collect();
+            if tokens.is_empty() {
+                continue;
+            }
+            let mut stmt = Statement {
+                tokens,
+                pos: 0,
+                location: format!("{}:{}", path.display(), line_no + 1),
+            };
+            self.statement(&mut stmt, path, world, &mut materials)?;
+            if stmt.pos < stmt.tokens.len() {
+                return Err(stmt.error(&format!("unexpected \"{}\"", stmt.tokens[stmt.pos])));
+            }
+        }
+        self.include_stack.pop();
+        Ok(materials)
+    }
+
+    fn statement(
+        &mut self,
+        stmt: &mut Statement,
+        path: &Path,
+        world: &mut HittableList,
+        materials: &mut HashMap<String, MaterialDef>,
+    ) -> Result<(), String> {
+        match stmt.word()? {
+            "material" => {
+                let name = stmt.word()?.to_owned();
+                let def = material(stmt, materials, &self.textures)?;
+                define_material(materials, name, def).map_err(|e| stmt.error(&e))?;
+            }
+            "sphere" => {
+                let center = stmt.vec3()?;
+                let radius = stmt.f64()?;
+                let mat = stmt.material_ref(materials)?;
+                let motion = stmt.motion(self.frame)?;
+                world.add(Arc::new(Sphere::with_motion(center, radius, mat, motion)));
+            }
+            "quad" => {
+                let q = stmt.vec3()?;
+                let u = stmt.vec3()?;
+                let v = stmt.vec3()?;
+                let mat = stmt.material_ref(materials)?;
+                let mut quad = Quad::new(q, u, v, mat);
+                quad.set_uv_orientation(stmt.uv_orientation()?);
+                add_moving(world, Arc::new(quad), stmt, self.frame)?;
+            }
+            "disk" => {
+                let center = stmt.vec3()?;
+                let normal = stmt.vec3()?;
+                let radius = stmt.f64()?;
+                let mat = stmt.material_ref(materials)?;
+                add_moving(
+                    world,
+                    Arc::new(Disk::new(center, normal, radius, mat)),
+                    stmt,
+                    self.frame,
+                )?;
+            }
+            "triangle" => {
+                let (a, b, c) = (stmt.vec3()?, stmt.vec3()?, stmt.vec3()?);
+                let mat = stmt.material_ref(materials)?;
+                let normals = if stmt.flag("normals") {
+                    Some([stmt.vec3()?, stmt.vec3()?, stmt.vec3()?])
+                } else {
+                    None
+                };
+                let mut triangle = if stmt.flag("velocity") {
+                    let velocity = [stmt.vec3()?, stmt.vec3()?, stmt.vec3()?];
+                    Triangle::new_moving(a, b, c, velocity, mat)
+                } else {
+                    Triangle::new(a, b, c, mat)
+                };
+                if let Some(normals) = normals {
+                    triangle.set_vertex_normals(normals);
+                }
+                add_moving(world, Arc::new(triangle), stmt, self.frame)?;
+            }
+            "light_rig" => {
+                let kind = stmt.word()?;
+                let mut subject = world.bounding_box();
+                let mut front = Vec3::new(0.0, 0.0, 1.0);
+                let mut brightness = 1.0;
+                let mut grid = 3;
+                while stmt.pos < stmt.tokens.len() {
+                    match stmt.word()? {
+                        "bounds" => {
+                            let a = stmt.vec3()?;
+                            subject = AABB::new_two_points(a, stmt.vec3()?);
+                        }
+                        "front" => front = stmt.vec3()?,
+                        "brightness" => brightness = stmt.f64()?,
+                        "grid" => grid = stmt.positive()?,
+                        other => {
+                            return Err(
+                                stmt.error(&format!("unknown light_rig option \"{}\"", other))
+                            )
+                        }
+                    }
+                }
+                if subject.x.size() < 0.0 {
+                    return Err(stmt.error("light_rig needs objects before it, or bounds"));
+                }
+                let lights = match kind {
+                    "three_point" => three_point(subject, front, brightness),
+                    "skylight" => skylight_grid(subject, grid, brightness),
+                    "ring" => ring_light(subject, front, brightness),
+                    other => return Err(stmt.error(&format!("unknown light rig \"{}\"", other))),
+                };
+                world.add(Arc::new(lights));
+            }
+            "box" => {
+                let a = stmt.vec3()?;
+                let b = stmt.vec3()?;
+                let mat = stmt.material_ref(materials)?;
+                add_moving(world, box_from_vec(a, b, mat), stmt, self.frame)?;
+            }
+            "obj" => {
+                let file = path.parent().unwrap_or(Path::new(".")).join(stmt.word()?);
+                let mat = stmt.material_ref(materials)?;
+                let shading = stmt.shading();
+                let mesh =
+                    load_obj(&file.to_string_lossy(), mat, shading).map_err(|e| stmt.error(&e))?;
+                add_moving(world, Arc::new(mesh), stmt, self.frame)?;
+            }
+            "obj_sequence" => {
+                let dir = path.parent().unwrap_or(Path::new("."));
+                let pattern = stmt.word()?;
+                let mat = stmt.material_ref(materials)?;
+                let file = dir.join(sequence_path(pattern, self.frame));
+                let file = file.to_string_lossy();
+                let shading = stmt.shading();
+                let mesh = if stmt.tokens.get(stmt.pos) == Some(&"blur") {
+                    stmt.pos += 1;
+                    let next = dir.join(sequence_path(pattern, self.frame + 1));
+                    load_obj_moving(&file, &next.to_string_lossy(), mat, shading)
+                } else {
+                    load_obj(&file, mat, shading)
+                };
+                let mesh = mesh.map_err(|e| stmt.error(&e))?;
+                add_moving(world, Arc::new(mesh), stmt, self.frame)?;
+            }
+            "text" => {
+                let origin = stmt.vec3()?;
+                let height = stmt.f64()?;
+                let depth = stmt.f64()?;
+                let mat = stmt.material_ref(materials)?;
+                let font = path.parent().unwrap_or(Path::new(".")).join(stmt.word()?);
+                // the rest of the line is the text
+                let text = stmt.tokens[stmt.pos..].join(" ");
+                stmt.pos = stmt.tokens.len();
+                let mesh = text_mesh(&font.to_string_lossy(), &text, origin, height, depth, mat)
+                    .map_err(|e| stmt.error(&e))?;
+                world.add(Arc::new(mesh));
+            }
+            "medium" => {
+                // only the shape of the boundary matters
+                let shape_mat: Arc<dyn Material> = Arc::new(Lambertian::from_color(Vec3::ones()));
+                let boundary: Arc<dyn Hittable> = match stmt.word()? {
+                    "sphere" => {
+                        let center = stmt.vec3()?;
+                        Arc::new(Sphere::new(center, stmt.f64()?, shape_mat))
+                    }
+                    "box" => {
+                        let a = stmt.vec3()?;
+                        box_from_vec(a, stmt.vec3()?, shape_mat)
+                    }
+                    other => return Err(stmt.error(&format!("unknown medium shape \"{}\"", other))),
+                };
+                let mut density = 1.0;
+                let mut albedo = Vec3::ones();
+                let mut emission: Option<Arc<dyn Texture>> = None;
+                let (mut angle, mut offset) = (0.0, Vec3::zero());
+                while stmt.pos < stmt.tokens.len() {
+                    match stmt.word()? {
+                        "density" => density = stmt.f64()?,
+                        "rotate_y" => angle = stmt.f64()?,
+                        "translate" => offset = stmt.vec3()?,
+                        "albedo" => albedo = stmt.vec3()?,
+                        "emit" => emission = Some(Arc::new(SolidColor::from_vec(stmt.vec3()?))),
+                        "blackbody" => {
+                            let kelvin = stmt.f64()?;
+                            let intensity = stmt.f64()?;
+                            let temperature: Arc<dyn Texture> =
+                                if stmt.tokens.get(stmt.pos) == Some(&"noise") {
+                                    stmt.pos += 1;
+                                    Arc::new(NoiseTexture::new(stmt.f64()?))
+                                } else {
+                                    Arc::new(SolidColor::from_vec(Vec3::ones()))
+                                };
+                            emission = Some(Arc::new(BlackbodyTexture::new(
+                                temperature,
+                                kelvin,
+                                intensity,
+                            )));
+                        }
+                        other => {
+                            return Err(stmt.error(&format!("unknown medium option \"{}\"", other)))
+                        }
+                    }
+                }
+                // like at an include site: rotated first, then moved
+                let mut boundary = boundary;
+                if angle != 0.0 {
+                    boundary = Arc::new(RotateY::new(boundary, angle));
+                }
+                if offset != Vec3::zero() {
+                    boundary = Arc::new(Translate::new(boundary, offset));
+                }
+                world.add(Arc::new(match emission {
+                    Some(emission) => ConstantMedium::emissive(boundary, density, albedo, emission),
+                    None => ConstantMedium::from_color(boundary, density, albedo),
+                }));
+            }
+            "name" => {
+                let name = stmt.word()?.to_owned();
+                let id = match self.names.iter().position(|(n, _)| *n == name) {
+                    Some(i) => i + 1,
+                    None => {
+                        self.names.push((name, HittableList::new()));
+                        self.names.len()
+                    }
+                };
+                let first = world.objects.len();
+                self.statement(stmt, path, world, materials)?;
+                let mut named = HittableList::new();
+                for object in world.objects.split_off(first) {
+                    self.names[id - 1].1.add(object.clone());
+                    named.add(object);
+                }
+                if !named.objects.is_empty() {
+                    world.add(Arc::new(Named::new(Arc::new(named), id as u32)));
+                }
+            }
+            "camera" => self.camera_setting(stmt, world)?,
+            "builtin" => {
+                let name = stmt.word()?;
+                let seed = match stmt.tokens.get(stmt.pos) {
+                    Some(&"seed") => {
+                        stmt.pos += 1;
+                        stmt.f64()? as u64
+                    }
+                    _ => 0,
+                };
+                let (camera, objects) = scene_registry::standard()
+                    .build(name, seed)
+                    .map_err(|e| stmt.error(&e))?;
+                self.camera = camera;
+                world.extend(objects.objects);
+            }
+            "accelerator" => {
+                self.accelerator = Accelerator::named(stmt.word()?).map_err(|e| stmt.error(&e))?
+            }
+            "include" => {
+                let file = path.parent().unwrap_or(Path::new(".")).join(stmt.word()?);
+                let mut prefix = None;
+                let mut angle = 0.0;
+                let mut offset = Vec3::zero();
+                while stmt.pos < stmt.tokens.len() {
+                    match stmt.word()? {
+                        "as" => prefix = Some(stmt.word()?.to_owned()),
+                        "rotate_y" => angle = stmt.f64()?,
+                        "translate" => offset = stmt.vec3()?,
+                        other => {
+                            return Err(stmt.error(&format!("unknown include option \"{}\"", other)))
+                        }
+                    }
+                }
+
+                let mut objects = HittableList::new();
+                let exported = self
+                    .load_file(&file, &mut objects)
+                    .map_err(|e| format!("{}\n  included from {}", e, stmt.location))?;
+                for (name, def) in exported {
+                    let name = match &prefix {
+                        Some(prefix) => format!("{}.{}", prefix, name),
+                        None => name,
+                    };
+                    define_material(materials, name, def).map_err(|e| {
+                        stmt.error(&format!("{} (use `include ... as <prefix>`)", e))
+                    })?;
+                }
+
+                if !objects.objects.is_empty() {
+                    let mut group: Arc<dyn Hittable> = Arc::new(objects);
+                    if angle != 0.0 {
+                        group = Arc::new(RotateY::new(group, angle));
+                    }
+                    if offset != Vec3::zero() {
+                        group = Arc::new(Translate::new(group, offset));
+                    }
+                    world.add(group);
+                }
+            }
+            other => return Err(stmt.error(&format!("unknown statement \"{}\"", other))),
+        }
+        Ok(())
+    }
+
+    fn camera_setting(&mut self, stmt: &mut Statement, world: &HittableList) -> Result<(), String> {
+        let cam = &mut self.camera;
+        match stmt.word()? {
+            "lookfrom" => cam.lookfrom = stmt.vec3()?,
+            "lookat" => cam.lookat = stmt.vec3()?,
+            "vup" => cam.vup = stmt.vec3()?,
+            "vfov" => cam.vfov = stmt.f64()?,
+            "aspect_ratio" => cam.aspect_ratio = stmt.f64()?,
+            "image_width" => cam.image_width = stmt.positive()?,
+            "spp" => cam.sample_per_pixel = stmt.positive()?,
+            "max_depth" => cam.max_depth = stmt.count()?,
+            "roulette_depth" => cam.roulette_depth = stmt.count()?,
+            "background" => cam.background = stmt.vec3()?,
+            "shift" => {
+                cam.shift_x = stmt.f64()?;
+                cam.shift_y = stmt.f64()?;
+            }
+            "projection" => {
+                cam.projection = Projection::named(stmt.word()?).map_err(|e| stmt.error(&e))?
+            }
+            "light_sampling" => {
+                cam.light_sampling =
+                    LightSampling::named(stmt.word()?).map_err(|e| stmt.error(&e))?
+            }
+            "ray_cones" => cam.ray_cones = true,
+            "halton_lens" => cam.halton_lens = true,
+            "denoise" => cam.denoise = Some(Denoiser::default()),
+            "filter" => cam.filter = Filter::named(stmt.word()?).map_err(|e| stmt.error(&e))?,
+            "shutter" => {
+                cam.shutter_open = stmt.f64()?;
+                cam.shutter_close = stmt.f64()?;
+            }
+            "sky" => {
+                cam.sky = Some(if stmt.pos < stmt.tokens.len() {
+                    let bottom = stmt.vec3()?;
+                    Gradient::new(bottom, stmt.vec3()?)
+                } else {
+                    Gradient::sky()
+                })
+            }
+            "matte_key" => cam.matte_key = stmt.vec3()?,
+            "defocus_angle" => cam.defocus_angle = stmt.f64()?,
+            "focus_dist" => cam.focus_dist = stmt.f64()?,
+            "autofocus" => {
+                cam.autofocus = Some(Autofocus::named(stmt.word()?).map_err(|e| stmt.error(&e))?)
+            }
+            "exposure" => {
+                let (iso, shutter_speed) = (stmt.f64()?, stmt.f64()?);
+                let mut physical = PhysicalCamera::new(iso, shutter_speed, stmt.f64()?);
+                if stmt.pos < stmt.tokens.len() {
+                    physical.units_per_meter = stmt.f64()?;
+                }
+                cam.physical = Some(physical);
+            }
+            "aperture" => {
+                cam.aperture = match stmt.word()? {
+                    "circle" => Aperture::Circle,
+                    "blades" => {
+                        let blades = stmt.count()?;
+                        let rotation = if stmt.pos < stmt.tokens.len() {
+                            stmt.f64()?
+                        } else {
+                            0.0
+                        };
+                        Aperture::polygon(blades, rotation)
+                    }
+                    "mask" => {
+                        Aperture::mask(&find_texture(stmt.word()?)).map_err(|e| stmt.error(&e))?
+                    }
+                    other => return Err(stmt.error(&format!("unknown aperture \"{}\"", other))),
+                }
+            }
+            "tiles" => {
+                cam.part_num_x = stmt.positive()?;
+                cam.part_num_y = stmt.positive()?;
+            }
+            "frame" => {
+                let mut target = world;
+                let mut fill = 0.8;
+                let mut direction = cam.lookfrom - cam.lookat;
+                while stmt.pos < stmt.tokens.len() {
+                    match stmt.word()? {
+                        "name" => {
+                            let name = stmt.word()?;
+                            target = self
+                                .names
+                                .iter()
+                                .find(|(n, _)| n == name)
+                                .map(|(_, objects)| objects)
+                                .ok_or(stmt.error(&format!("no object named \"{}\"", name)))?;
+                        }
+                        "fill" => fill = stmt.f64()?,
+                        "direction" => direction = stmt.vec3()?,
+                        other => {
+                            return Err(stmt.error(&format!("unknown frame option \"{}\"", other)))
+                        }
+                    }
+                }
+                if target.objects.is_empty() {
+                    return Err(stmt.error("nothing to frame yet"));
+                }
+                cam.frame(target, direction, fill);
+            }
+            "preset" => Preset::named(stmt.word()?)
+                .map_err(|e| stmt.error(&e))?
+                .apply(cam),
+            other => return Err(stmt.error(&format!("unknown camera setting \"{}\"", other))),
+        }
+        Ok(())
+    }
+}
+
+// Adds `object` with the motion that ends the statement, if any.
+fn add_moving(
+    world: &mut HittableList,
+    object: Arc<dyn Hittable>,
+    stmt: &mut Statement,
+    frame: u32,
+) -> Result<(), String> {
+    // `keyframes` and `track` take the rest of the line, so `spin` can come before them
+    let mut spin = if stmt.flag("spin") { stmt.f64()? } else { 0.0 };
+    let tracked = stmt.tokens.get(stmt.pos) == Some(&"track");
+    let motion = stmt.motion(frame)?;
+    if stmt.flag("spin") {
+        spin = stmt.f64()?;
+    }
+    if spin != 0.0 {
+        // spin about the object's own vertical axis, keyed together with the motion: by
+        // `spin` degrees over the shutter, or per frame along a track
+        let bbox = object.bounding_box();
+        let center = Vec3::new(
+            0.5 * (bbox.x.min + bbox.x.max),
+            0.0,
+            0.5 * (bbox.z.min + bbox.z.max),
+        );
+        let turned = if tracked { frame as Float } else { 0.0 };
+        let mut times = vec![0.0, 1.0];
+        if let Motion::Keyframed(keys) = &motion {
+            times.extend(keys.iter().map(|(t, _)| *t));
+        }
+        let keys = times
+            .into_iter()
+            .map(|t| {
+                let angle = spin * (turned + t.clamp(0.0, 1.0));
+                (t, center + motion.offset(t), angle)
+            })
+            .collect();
+        let centered = Arc::new(Translate::new(object, -center));
+        world.add(Arc::new(Animate::keyframed(centered, keys)));
+    } else if motion.is_static() {
+        world.add(object);
+    } else {
+        world.add(Arc::new(Moving::new(object, motion)));
+    }
+    Ok(())
+}
+
+fn define_material(
+    materials: &mut HashMap<String, MaterialDef>,
+    name: String,
+    def: MaterialDef,
+) -> Result<(), String> {
+    if let Some(existing) = materials.get(&name) {
+        if existing.definition != def.definition {
+            return Err(format!(
+                "material \"{}\" is already defined as \"{}\"",
+                name, existing.definition
+            ));
+        }
+        return Ok(());
+    }
+    materials.insert(name, def);
+    Ok(())
+}
+
+fn material(
+    stmt: &mut Statement,
+    materials: &HashMap<String, MaterialDef>,
+    textures: &TextureRegistry,
+) -> Result<MaterialDef, String> {
+    let mut definition = stmt.tokens[stmt.pos..].join(" ");
+    let kind = stmt.word()?;
+    let mat: Arc<dyn Material> = match kind {
+        "lambertian" => Arc::new(Lambertian::from_color(stmt.vec3()?)),
+        "image" => {
+            let file = stmt.word()?;
+            let tex = textures.image(file, stmt.flag("linear"));
+            Arc::new(Lambertian::from_texture(stmt.uv_transform(tex)?))
+        }
+        "texture" => {
+            let name = stmt.word()?;
+            let tex = material_registry::standard()
+                .texture(name)
+                .map_err(|e| stmt.error(&e))?;
+            Arc::new(Lambertian::from_texture(stmt.uv_transform(tex)?))
+        }
+        // huge textures, decoded lazily and paged in tile by tile
+        "big_image" => {
+            let file = stmt.word()?;
+            tiled_texture::check_format(file).map_err(|e| stmt.error(&e))?;
+            let cache_tiles = if stmt.tokens.get(stmt.pos) == Some(&"cache") {
+                stmt.pos += 1;
+                stmt.positive()? as usize
+            } else {
+                1024
+            };
+            let tex = Arc::new(if stmt.flag("linear") {
+                TiledImageTexture::new_linear(file, cache_tiles)
+            } else {
+                TiledImageTexture::new(file, cache_tiles)
+            });
+            Arc::new(Lambertian::from_texture(stmt.uv_transform(tex)?))
+        }
+        "uv_checker" => {
+            let frequency = stmt.f64()?;
+            let (mut even, mut odd) = (Vec3::new(0.9, 0.9, 0.9), Vec3::new(0.1, 0.1, 0.1));
+            if stmt.flag("colors") {
+                even = stmt.vec3()?;
+                odd = stmt.vec3()?;
+            }
+            Arc::new(Lambertian::from_texture(Arc::new(UvChecker::new(
+                frequency, even, odd,
+            ))))
+        }
+        // solid checker in space, squares of the given size
+        "checker" => {
+            let scale = stmt.f64()?;
+            let even = stmt.vec3()?;
+            Arc::new(Lambertian::from_texture(Arc::new(
+                CheckerTexture::from_color(scale, even, stmt.vec3()?),
+            )))
+        }
+        "uv_grid" => {
+            let frequency = stmt.f64()?;
+            let line_width = if stmt.pos < stmt.tokens.len() {
+                stmt.f64()?
+            } else {
+                0.05
+            };
+            Arc::new(Lambertian::from_texture(Arc::new(UvGrid::new(
+                frequency, line_width,
+            ))))
+        }
+        "noise" => {
+            let pattern = NoisePattern::named(stmt.word()?).map_err(|e| stmt.error(&e))?;
+            let scale = stmt.f64()?;
+            let (mut low, mut high) = (Vec3::zero(), Vec3::ones());
+            if stmt.flag("colors") {
+                low = stmt.vec3()?;
+                high = stmt.vec3()?;
+            }
+            let mut tex: Arc<dyn Texture> =
+                Arc::new(NoiseTexture::with_colors(scale, pattern, low, high));
+            if stmt.flag("ramp") {
+                let mut stops = vec![];
+                while stmt.pos < stmt.tokens.len() {
+                    let t = stmt.f64()?;
+                    stops.push((t, stmt.vec3()?));
+                }
+                if stops.is_empty() {
+                    return Err(stmt.error("ramp needs at least one stop"));
+                }
+                tex = Arc::new(Ramp::new(tex, stops));
+            }
+            Arc::new(Lambertian::from_texture(tex))
+        }
+        "marble" => {
+            let scale = stmt.f64()?;
+            let vein = stmt.vec3()?;
+            Arc::new(Lambertian::from_texture(Arc::new(Marble::new(
+                scale,
+                vein,
+                stmt.vec3()?,
+            ))))
+        }
+        "wood" => {
+            let scale = stmt.f64()?;
+            let light = stmt.vec3()?;
+            Arc::new(Lambertian::from_texture(Arc::new(Wood::new(
+                scale,
+                light,
+                stmt.vec3()?,
+            ))))
+        }
+        "brick" => {
+            let width = stmt.f64()?;
+            let height = stmt.f64()?;
+            let mortar = stmt.f64()?;
+            let brick = stmt.vec3()?;
+            Arc::new(Lambertian::from_texture(Arc::new(Brick::new(
+                width,
+                height,
+                mortar,
+                brick,
+                stmt.vec3()?,
+            ))))
+        }
+        "metal" => {
+            let albedo = stmt.vec3()?;
+            Arc::new(Metal::new(albedo, stmt.f64()?))
+        }
+        "conductor" => {
+            let preset = stmt.word()?;
+            if preset == "n" {
+                let n = stmt.vec3()?;
+                stmt.expect("k")?;
+                let k = stmt.vec3()?;
+                Arc::new(Conductor::new(n, k, stmt.f64()?))
+            } else {
+                let fuzz = stmt.f64()?;
+                Arc::new(match preset {
+                    "gold" => Conductor::gold(fuzz),
+                    "copper" => Conductor::copper(fuzz),
+                    "aluminum" => Conductor::aluminum(fuzz),
+                    "silver" => Conductor::silver(fuzz),
+                    other => return Err(stmt.error(&format!("unknown conductor \"{}\"", other))),
+                })
+            }
+        }
+        "dielectric" => {
+            let ior = stmt.f64()?;
+            if stmt.tokens.get(stmt.pos) == Some(&"absorb") {
+                stmt.pos += 1;
+                let color = stmt.vec3()?;
+                Arc::new(Dielectric::with_absorption(ior, color, stmt.f64()?))
+            } else {
+                Arc::new(Dielectric::new(ior))
+            }
+        }
+        "rough_dielectric" => {
+            let ior = stmt.f64()?;
+            Arc::new(RoughDielectric::new(ior, stmt.f64()?))
+        }
+        "dispersive" => {
+            let dispersion = match stmt.word()? {
+                "crown_glass" => Dispersion::crown_glass(),
+                "diamond" => Dispersion::diamond(),
+                "cauchy" => {
+                    let a = stmt.f64()?;
+                    Dispersion::Cauchy { a, b: stmt.f64()? }
+                }
+                "sellmeier" => {
+                    let b = stmt.vec3()?;
+                    let c = stmt.vec3()?;
+                    Dispersion::Sellmeier {
+                        b: [b.x, b.y, b.z],
+                        c: [c.x, c.y, c.z],
+                    }
+                }
+                other => return Err(stmt.error(&format!("unknown dispersion \"{}\"", other))),
+            };
+            Arc::new(Dielectric::with_dispersion(dispersion))
+        }
+        "mix" => {
+            let a = stmt.material_def(materials)?;
+            let b = stmt.material_def(materials)?;
+            // combinators are defined by what they combine, not by this file's names for it
+            let factor_start = stmt.pos;
+            let mat = if stmt.tokens.get(stmt.pos) == Some(&"image") {
+                stmt.pos += 1;
+                let mask = textures.image(stmt.word()?, true);
+                Mix::from_texture(a.mat.clone(), b.mat.clone(), mask)
+            } else {
+                Mix::new(a.mat.clone(), b.mat.clone(), stmt.f64()?)
+            };
+            definition = format!(
+                "mix ({}) ({}) {}",
+                a.definition,
+                b.definition,
+                stmt.tokens[factor_start..stmt.pos].join(" ")
+            );
+            Arc::new(mat)
+        }
+        "layer" => {
+            let coat = stmt.material_def(materials)?;
+            let base = stmt.material_def(materials)?;
+            let ior = stmt.f64()?;
+            definition = format!("layer ({}) ({}) {}", coat.definition, base.definition, ior);
+            Arc::new(Layer::new(coat.mat.clone(), base.mat.clone(), ior))
+        }
+        "cutout" => {
+            let base = stmt.material_def(materials)?;
+            let mask = stmt.word()?;
+            definition = format!("cutout ({}) {}", base.definition, mask);
+            Arc::new(Cutout::new(base.mat.clone(), textures.image(mask, true)))
+        }
+        "estimator" => {
+            let base = stmt.material_def(materials)?;
+            let estimator = Estimator::named(stmt.word()?).map_err(|e| stmt.error(&e))?;
+            definition = format!("estimator ({}) {}", base.definition, estimator.name());
+            Arc::new(WithEstimator::new(base.mat.clone(), estimator))
+        }
+        "light" | "light_image" => {
+            let tex: Arc<dyn Texture> = if kind == "light" {
+                Arc::new(SolidColor::from_vec(stmt.vec3()?))
+            } else {
+                let file = stmt.word()?;
+                let tex = textures.image(file, stmt.flag("linear"));
+                stmt.uv_transform(tex)?
+            };
+            let mut intensity = 1.0;
+            let mut two_sided = true;
+            while stmt.pos < stmt.tokens.len() {
+                match stmt.word()? {
+                    "intensity" => intensity = stmt.f64()?,
+                    "one_sided" => two_sided = false,
+                    other => return Err(stmt.error(&format!("unknown light option \"{}\"", other))),
+                }
+            }
+            Arc::new(DiffuseLight::new(tex, intensity, two_sided))
+        }
+        other => return Err(stmt.error(&format!("unknown material type \"{}\"", other))),
+    };
+    Ok(MaterialDef { mat, definition })
+}
+
+struct Statement<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+    location: String,
+}
+
+impl<'a> Statement<'a> {
+    fn error(&self, msg: &str) -> String {
+        format!("{}: {}", self.location, msg)
+    }
+
+    // Optional `scale su sv`, `rotate degrees`, `offset du dv` and `wrap repeat|clamp|mirror`
+    // after an image; any of them wraps the texture in a UvTransform (repeating by default).
+    // A final `triplanar size` projects the result along the axes instead of using the UVs.
+    fn uv_transform(&mut self, tex: Arc<dyn Texture>) -> Result<Arc<dyn Texture>, String> {
+        let mut scale = (1.0, 1.0);
+        let mut rotation = 0.0;
+        let mut offset = (0.0, 0.0);
+        let mut wrap = None;
+        loop {
+            match self.tokens.get(self.pos) {
+                Some(&"scale") => {
+                    self.pos += 1;
+                    scale = (self.f64()?, self.f64()?);
+                }
+                Some(&"rotate") => {
+                    self.pos += 1;
+                    rotation = self.f64()?;
+                }
+                Some(&"offset") => {
+                    self.pos += 1;
+                    offset = (self.f64()?, self.f64()?);
+                }
+                Some(&"wrap") => {
+                    self.pos += 1;
+                    let mode = self.word()?;
+                    wrap = Some(Wrap::named(mode).map_err(|e| self.error(&e))?);
+                }
+                _ => break,
+            }
+        }
+        let mut tex = tex;
+        if scale != (1.0, 1.0) || rotation != 0.0 || offset != (0.0, 0.0) || wrap.is_some() {
+            let wrap = wrap.unwrap_or(Wrap::Repeat);
+            tex = Arc::new(UvTransform::new(tex, scale, rotation, offset, wrap));
+        }
+        if self.flag("triplanar") {
+            tex = Arc::new(Triplanar::new(tex, self.f64()?));
+        }
+        Ok(tex)
+    }
+
+    fn word(&mut self) -> Result<&'a str, String> {
+        let word = self
+            .tokens
+            .get(self.pos)
+            .ok_or(self.error("unexpected end of line"))?;
+        self.pos += 1;
+        Ok(word)
+    }
+
+    // Consumes `keyword` if it comes next.
+    fn flag(&mut self, keyword: &str) -> bool {
+        if self.tokens.get(self.pos) == Some(&keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    // optional `uv_rotate <degrees>` (a multiple of 90), `flip_u` and `flip_v` after the
+    // material of a quad, see quad::UvOrientation
+    fn uv_orientation(&mut self) -> Result<UvOrientation, String> {
+        let mut uv = UvOrientation::default();
+        loop {
+            if self.flag("uv_rotate") {
+                let degrees = self.f64()?;
+                if degrees % 90.0 != 0.0 {
+                    return Err(self.error("uv_rotate takes a multiple of 90 degrees"));
+                }
+                uv.quarter_turns = (degrees / 90.0).rem_euclid(4.0) as u8;
+            } else if self.flag("flip_u") {
+                uv.flip_u = true;
+            } else if self.flag("flip_v") {
+                uv.flip_v = true;
+            } else {
+                return Ok(uv);
+            }
+        }
+    }
+
+    // optional `smooth` or `flat` after the material of a mesh
+    fn shading(&mut self) -> Shading {
+        for shading in [Shading::Smooth, Shading::Flat] {
+            if self.flag(shading.name()) {
+                return shading;
+            }
+        }
+        Shading::File
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), String> {
+        let word = self.word()?;
+        if word != keyword {
+            return Err(self.error(&format!("expected \"{}\", got \"{}\"", keyword, word)));
+        }
+        Ok(())
+    }
+
+    fn f64(&mut self) -> Result<Float, String> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| self.error(&format!("expected a number, got \"{}\"", word)))
+    }
+
+    fn count(&mut self) -> Result<u32, String> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| self.error(&format!("expected a whole number, got \"{}\"", word)))
+    }
+
+    fn positive(&mut self) -> Result<u32, String> {
+        match self.count()? {
+            0 => Err(self.error("expected a positive number, got 0")),
+            n => Ok(n),
+        }
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, String> {
+        Ok(Vec3::new(self.f64()?, self.f64()?, self.f64()?))
+    }
+
+    // optional trailing `move dx dy dz`, `keyframes t dx dy dz [t dx dy dz ...]` or
+    // `track f dx dy dz [f dx dy dz ...]`; a track keys offsets by frame, and the shutter of
+    // `frame` covers the way to the next frame
+    fn motion(&mut self, frame: u32) -> Result<Motion, String> {
+        match self.tokens.get(self.pos) {
+            Some(&"move") => {
+                self.pos += 1;
+                Ok(Motion::Linear(self.vec3()?))
+            }
+            Some(&"keyframes") => {
+                self.pos += 1;
+                let mut keys = vec![];
+                while self.pos < self.tokens.len() {
+                    let time = self.f64()?;
+                    keys.push((time, self.vec3()?));
+                }
+                if keys.is_empty() {
+                    return Err(self.error("keyframes needs at least one key"));
+                }
+                Ok(Motion::keyframed(keys))
+            }
+            Some(&"track") => {
+                self.pos += 1;
+                let mut keys = vec![];
+                while self.pos < self.tokens.len() {
+                    let frame = self.f64()?;
+                    keys.push((frame, self.vec3()?));
+                }
+                if keys.is_empty() {
+                    return Err(self.error("track needs at least one key"));
+                }
+                let track = Motion::keyframed(keys);
+                let frame = frame as Float;
+                Ok(Motion::keyframed(vec![
+                    (0.0, track.offset(frame)),
+                    (1.0, track.offset(frame + 1.0)),
+                ]))
+            }
+            _ => Ok(Motion::Static),
+        }
+    }
+
+    fn material_ref(
+        &mut self,
+        materials: &HashMap<String, MaterialDef>,
+    ) -> Result<Arc<dyn Material>, String> {
+        Ok(self.material_def(materials)?.mat)
+    }
+
+    // a material of the file, or else a standard one (material_registry::standard)
+    fn material_def(
+        &mut self,
+        materials: &HashMap<String, MaterialDef>,
+    ) -> Result<MaterialDef, String> {
+        let name = self.word()?;
+        if let Some(def) = materials.get(name) {
+            return Ok(MaterialDef {
+                mat: def.mat.clone(),
+                definition: def.definition.clone(),
+            });
+        }
+        match material_registry::standard().material(name) {
+            Ok(mat) => Ok(MaterialDef {
+                mat,
+                definition: format!("standard {}", name),
+            }),
+            Err(e) => Err(self.error(&e)),
+        }
+    }
+}
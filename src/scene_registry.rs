@@ -0,0 +1,116 @@
+use std::sync::OnceLock;
+
+use crate::camera::Camera;
+use crate::hittable::HittableList;
+use crate::preset::set_image_width;
+use crate::scene;
+
+// Makes a scene and its camera; the seed picks the layout of scenes laid out at random.
+pub type SceneFn = fn(u64) -> (Camera, HittableList);
+
+// A scene of the registry, with the image width and samples per pixel it renders at unless
+// told otherwise.
+pub struct SceneEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub width: u32,
+    pub spp: u32,
+    make: SceneFn,
+}
+
+impl SceneEntry {
+    pub fn build(&self, seed: u64) -> (Camera, HittableList) {
+        let (mut cam, world) = (self.make)(seed);
+        // the scene's tiles stay as they are at its own width
+        if cam.image_width != self.width {
+            set_image_width(&mut cam, self.width);
+        }
+        cam.sample_per_pixel = self.spp;
+        (cam, world)
+    }
+}
+
+// Scenes made in code, by name, in the order they were registered. standard() holds the
+// built-in ones, which scene.rs registers where it defines them (scene::register_scenes);
+// `--builtin`, `--list-scenes`, the catalog subcommand and the `builtin` statement of scene
+// files all find them there.
+pub struct SceneRegistry {
+    entries: Vec<SceneEntry>,
+}
+
+impl SceneRegistry {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    // Replaces an earlier scene of the same name.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        description: &'static str,
+        width: u32,
+        spp: u32,
+        make: SceneFn,
+    ) {
+        let entry = SceneEntry {
+            name,
+            description,
+            width,
+            spp,
+            make,
+        };
+        match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&SceneEntry, String> {
+        self.entries.iter().find(|e| e.name == name).ok_or(format!(
+            "unknown scene \"{}\" ({})",
+            name,
+            self.names().join(", ")
+        ))
+    }
+
+    pub fn build(&self, name: &str, seed: u64) -> Result<(Camera, HittableList), String> {
+        Ok(self.get(name)?.build(seed))
+    }
+
+    pub fn entries(&self) -> &[SceneEntry] {
+        &self.entries
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.entries.iter().map(|e| e.name).collect()
+    }
+
+    // One line per scene: name, width, samples per pixel and description, in columns.
+    pub fn listing(&self) -> String {
+        let name_width = self.entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+        self.entries
+            .iter()
+            .map(|e| {
+                format!(
+                    "{:<name_width$}  {:>5} px  {:>6} spp  {}\n",
+                    e.name, e.width, e.spp, e.description
+                )
+            })
+            .collect()
+    }
+}
+
+impl Default for SceneRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn standard() -> &'static SceneRegistry {
+    static STANDARD: OnceLock<SceneRegistry> = OnceLock::new();
+    STANDARD.get_or_init(|| {
+        let mut registry = SceneRegistry::new();
+        scene::register_scenes(&mut registry);
+        registry
+    })
+}
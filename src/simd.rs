@@ -0,0 +1,207 @@
+// Four-lane Float vectors for the hot intersection tests: the slab test of AABB::hit (x, y and
+// z in three lanes) and SphereBatch (four spheres at once). With the `simd` feature on x86_64
+// the lanes are SSE registers, one for f32 and two for f64; otherwise they are plain arrays.
+// SSE and SSE2 are part of every x86_64 CPU, which makes the intrinsics below safe to call.
+
+use crate::vec3::Float;
+use std::ops::{Add, Mul, Sub};
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+use std::arch::x86_64::*;
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", feature = "f32"))]
+#[derive(Clone, Copy)]
+pub struct F4(__m128);
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", not(feature = "f32")))]
+#[derive(Clone, Copy)]
+pub struct F4(__m128d, __m128d);
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+#[derive(Clone, Copy)]
+pub struct F4([Float; 4]);
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", feature = "f32"))]
+impl F4 {
+    pub fn new(a: [Float; 4]) -> Self {
+        unsafe { F4(_mm_set_ps(a[3], a[2], a[1], a[0])) }
+    }
+    pub fn splat(x: Float) -> Self {
+        unsafe { F4(_mm_set1_ps(x)) }
+    }
+    pub fn min(self, o: Self) -> Self {
+        unsafe { F4(_mm_min_ps(self.0, o.0)) }
+    }
+    pub fn max(self, o: Self) -> Self {
+        unsafe { F4(_mm_max_ps(self.0, o.0)) }
+    }
+    // bit i set where lane i of self <= o
+    pub fn le_mask(self, o: Self) -> u32 {
+        unsafe { _mm_movemask_ps(_mm_cmple_ps(self.0, o.0)) as u32 }
+    }
+    pub fn to_array(self) -> [Float; 4] {
+        let mut a = [0.0; 4];
+        unsafe { _mm_storeu_ps(a.as_mut_ptr(), self.0) };
+        a
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", feature = "f32"))]
+impl Add for F4 {
+    type Output = Self;
+    fn add(self, o: Self) -> Self {
+        unsafe { F4(_mm_add_ps(self.0, o.0)) }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", feature = "f32"))]
+impl Sub for F4 {
+    type Output = Self;
+    fn sub(self, o: Self) -> Self {
+        unsafe { F4(_mm_sub_ps(self.0, o.0)) }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", feature = "f32"))]
+impl Mul for F4 {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        unsafe { F4(_mm_mul_ps(self.0, o.0)) }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", not(feature = "f32")))]
+impl F4 {
+    pub fn new(a: [Float; 4]) -> Self {
+        unsafe { F4(_mm_set_pd(a[1], a[0]), _mm_set_pd(a[3], a[2])) }
+    }
+    pub fn splat(x: Float) -> Self {
+        unsafe { F4(_mm_set1_pd(x), _mm_set1_pd(x)) }
+    }
+    pub fn min(self, o: Self) -> Self {
+        unsafe { F4(_mm_min_pd(self.0, o.0), _mm_min_pd(self.1, o.1)) }
+    }
+    pub fn max(self, o: Self) -> Self {
+        unsafe { F4(_mm_max_pd(self.0, o.0), _mm_max_pd(self.1, o.1)) }
+    }
+    // bit i set where lane i of self <= o
+    pub fn le_mask(self, o: Self) -> u32 {
+        unsafe {
+            let lo = _mm_movemask_pd(_mm_cmple_pd(self.0, o.0)) as u32;
+            let hi = _mm_movemask_pd(_mm_cmple_pd(self.1, o.1)) as u32;
+            lo | hi << 2
+        }
+    }
+    pub fn to_array(self) -> [Float; 4] {
+        let mut a = [0.0; 4];
+        unsafe {
+            _mm_storeu_pd(a.as_mut_ptr(), self.0);
+            _mm_storeu_pd(a.as_mut_ptr().add(2), self.1);
+        }
+        a
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", not(feature = "f32")))]
+impl Add for F4 {
+    type Output = Self;
+    fn add(self, o: Self) -> Self {
+        unsafe { F4(_mm_add_pd(self.0, o.0), _mm_add_pd(self.1, o.1)) }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", not(feature = "f32")))]
+impl Sub for F4 {
+    type Output = Self;
+    fn sub(self, o: Self) -> Self {
+        unsafe { F4(_mm_sub_pd(self.0, o.0), _mm_sub_pd(self.1, o.1)) }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", not(feature = "f32")))]
+impl Mul for F4 {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        unsafe { F4(_mm_mul_pd(self.0, o.0), _mm_mul_pd(self.1, o.1)) }
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+impl F4 {
+    pub fn new(a: [Float; 4]) -> Self {
+        F4(a)
+    }
+    pub fn splat(x: Float) -> Self {
+        F4([x; 4])
+    }
+    fn zip(self, o: Self, f: impl Fn(Float, Float) -> Float) -> Self {
+        F4([
+            f(self.0[0], o.0[0]),
+            f(self.0[1], o.0[1]),
+            f(self.0[2], o.0[2]),
+            f(self.0[3], o.0[3]),
+        ])
+    }
+    #[cfg(feature = "simd")]
+    pub fn min(self, o: Self) -> Self {
+        self.zip(o, |a, b| if a < b { a } else { b })
+    }
+    #[cfg(feature = "simd")]
+    pub fn max(self, o: Self) -> Self {
+        self.zip(o, |a, b| if a > b { a } else { b })
+    }
+    // bit i set where lane i of self <= o
+    pub fn le_mask(self, o: Self) -> u32 {
+        (0..4)
+            .filter(|&i| self.0[i] <= o.0[i])
+            .fold(0, |m, i| m | 1 << i)
+    }
+    #[cfg(feature = "simd")]
+    pub fn to_array(self) -> [Float; 4] {
+        self.0
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+impl Add for F4 {
+    type Output = Self;
+    fn add(self, o: Self) -> Self {
+        self.zip(o, |a, b| a + b)
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+impl Sub for F4 {
+    type Output = Self;
+    fn sub(self, o: Self) -> Self {
+        self.zip(o, |a, b| a - b)
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+impl Mul for F4 {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        self.zip(o, |a, b| a * b)
+    }
+}
+
+// Slab test of one ray against a box, the axes side by side in the first three lanes (the
+// fourth is ignored).
+#[cfg(feature = "simd")]
+pub fn slab_hit(
+    min: F4,
+    max: F4,
+    origin: F4,
+    inv_direction: F4,
+    t_min: Float,
+    t_max: Float,
+) -> bool {
+    let t0 = (min - origin) * inv_direction;
+    let t1 = (max - origin) * inv_direction;
+    let near = t0.min(t1).to_array();
+    let far = t0.max(t1).to_array();
+    let enter = near[0].max(near[1]).max(near[2]).max(t_min);
+    let exit = far[0].min(far[1]).min(far[2]).min(t_max);
+    enter < exit
+}
@@ -1,60 +1,86 @@
-use std::f64::consts::PI;
+use crate::vec3::consts::PI;
 use std::sync::Arc;
 
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::bvh::BVHNode;
+use crate::hittable::{HitRecord, Hittable, HittableList};
 use crate::interval::Interval;
-use crate::material::Material;
+use crate::material::{cut_out, Material};
+use crate::motion::Motion;
 use crate::ray::Ray;
-use crate::vec3::Vec3;
+use crate::scene_export::SceneWriter;
+use crate::simd::F4;
+use crate::stats::{self, Counter};
+use crate::util::{random_f64_0_1, random_in_unit_sphere};
+use crate::vec3::{Float, Vec3};
 #[derive(Clone)]
 pub struct Sphere {
     center: Vec3,
-    radius: f64,
+    radius: Float,
     mat: Arc<dyn Material>,
-    velocity: Vec3,
-    is_moving: bool,
+    motion: Motion,
     bounding_box: AABB,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
-        Self {
-            center,
-            radius,
-            mat,
-            velocity: Vec3::zero(),
-            is_moving: false,
-            bounding_box: AABB::new_two_points(
-                center - Vec3::new(radius, radius, radius),
-                center + Vec3::new(radius, radius, radius),
-            ),
-        }
+    pub fn new(center: Vec3, radius: Float, mat: Arc<dyn Material>) -> Self {
+        Self::with_motion(center, radius, mat, Motion::Static)
     }
 
-    pub fn new_moving(center1: Vec3, center2: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+    pub fn new_moving(center1: Vec3, center2: Vec3, radius: Float, mat: Arc<dyn Material>) -> Self {
+        Self::with_motion(center1, radius, mat, Motion::Linear(center2 - center1))
+    }
+
+    pub fn with_motion(
+        center: Vec3,
+        radius: Float,
+        mat: Arc<dyn Material>,
+        motion: Motion,
+    ) -> Self {
         let rvec = Vec3::new(radius, radius, radius);
-        let box1 = AABB::new_two_points(center1 - rvec, center1 + rvec);
-        let box2 = AABB::new_two_points(center2 - rvec, center2 + rvec);
+        let bounding_box = motion.bounding_box(AABB::new_two_points(center - rvec, center + rvec));
         Self {
-            center: center1,
+            center,
             radius,
             mat,
-            velocity: center2 - center1,
-            is_moving: true,
-            bounding_box: AABB::new_two_boxes(box1, box2),
+            motion,
+            bounding_box,
         }
     }
 
-    pub fn get_center(&self, time: f64) -> Vec3 {
-        self.center + self.velocity * time
+    pub fn get_center(&self, time: Float) -> Vec3 {
+        self.center + self.motion.offset(time)
+    }
+
+    // pdf_value for points picked uniformly over the surface: a direction meets the sphere
+    // on both sides, so the densities at both points add up.
+    fn area_pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        let oc = self.center - origin;
+        let a = direction.squared_length();
+        let h = direction * oc;
+        let discriminant = h * h - a * (oc.squared_length() - self.radius * self.radius);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let area = 4.0 * PI * self.radius * self.radius;
+        let sqrtd = discriminant.sqrt();
+        [(h - sqrtd) / a, (h + sqrtd) / a]
+            .into_iter()
+            .filter(|t| *t > 0.001)
+            .map(|t| {
+                let to_point = direction * t;
+                let normal = (origin + to_point - self.center) / self.radius;
+                let cosine = (to_point.unit() * normal).abs();
+                to_point.squared_length() / (cosine * area)
+            })
+            .sum()
     }
 
     pub fn bounding_box(&self) -> AABB {
         self.bounding_box
     }
 
-    pub fn get_sphere_uv(p: Vec3) -> (f64, f64) {
+    pub fn get_sphere_uv(p: Vec3) -> (Float, Float) {
         // p: a given point on the sphere of radius one, centered at the origin.
         // u: returned value [0,1] of angle around the Y axis from X=-1.
         // v: returned value [0,1] of angle from Y=-1 to Y=+1.
@@ -72,38 +98,243 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        stats::count(Counter::SphereTest, 1);
         // returns t in rec
         let center: Vec3 = self.get_center(r.time);
         let oc = center - r.a_origin;
         let a = r.b_direction.squared_length();
         let h = r.b_direction * oc;
-        let c = oc.squared_length() - self.radius * self.radius;
+        // a sphere smaller than the footprint of a ray cone at its center is widened to it
+        // and covers the fraction of it its outline fills (see Quad::widen)
+        let radius = self.radius.max(0.5 * r.footprint(h / a));
+        let c = oc.squared_length() - radius * radius;
         let discriminant = h * h - a * c;
         if discriminant < 0.0 {
             return false;
         }
 
         let sqrtd = discriminant.sqrt();
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (h - sqrtd) / a;
-        if !ray_t.surrounds(root) {
-            root = (h + sqrtd) / a;
+        // Find the nearest root that lies in the acceptable range and isn't cut out.
+        for root in [(h - sqrtd) / a, (h + sqrtd) / a] {
             if !ray_t.surrounds(root) {
-                return false;
+                continue;
+            }
+            let p = r.at(root);
+            let outward_normal = (p - center) / radius;
+            let (u, v) = Sphere::get_sphere_uv(outward_normal);
+            if cut_out(self.mat.as_ref(), u, v, p) {
+                continue;
             }
+
+            rec.mat = &*self.mat;
+            rec.t = root;
+            rec.p = p;
+            rec.set_face_normal(&r, &outward_normal);
+            rec.coverage = (self.radius / radius).powi(2);
+            (rec.u, rec.v) = (u, v);
+            return true;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        out.sphere(self.center, self.radius, &self.motion, &self.mat)
+    }
+
+    // moving spheres are left out, where they are depends on the time
+    fn collect_lights(&self, lights: &mut HittableList) {
+        if self.mat.is_emissive() && self.motion.is_static() {
+            lights.add(Arc::new(self.clone()));
         }
+    }
 
-        rec.mat = self.mat.clone();
-        rec.t = root;
-        rec.p = r.at(rec.t);
-        let outward_normal = (rec.p - self.center) / self.radius;
-        rec.set_face_normal(&r, &outward_normal);
-        (rec.u, rec.v) = Sphere::get_sphere_uv(outward_normal);
-        true
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        if self.motion.is_static() {
+            surfaces.add(Arc::new(self.clone()));
+        }
+    }
+
+    // the inverse of get_sphere_uv
+    fn surface_at(&self, u: Float, v: Float) -> Option<(Vec3, Vec3)> {
+        let (theta, phi) = (v * PI, u * 2.0 * PI);
+        let normal = Vec3::new(
+            -theta.sin() * phi.cos(),
+            -theta.cos(),
+            theta.sin() * phi.sin(),
+        );
+        Some((self.center + normal * self.radius, normal))
+    }
+
+    // From outside, directions are picked uniformly within the cone the sphere subtends,
+    // which wastes none on its far side or on the parts seen at a grazing angle. From inside
+    // every direction meets it and the points are picked over its area instead.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        let to_center = self.center - origin;
+        let distance_squared = to_center.squared_length();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return self.area_pdf_value(origin, direction);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        if direction.unit() * to_center.unit() < cos_theta_max {
+            return 0.0;
+        }
+        // 1 - cos_theta_max without the cancellation for small, distant spheres
+        let one_minus_cos = radius_squared / distance_squared / (1.0 + cos_theta_max);
+        1.0 / (2.0 * PI * one_minus_cos)
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        let to_center = self.center - origin;
+        let distance_squared = to_center.squared_length();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return self.center + random_in_unit_sphere().unit() * self.radius - origin;
+        }
+        let one_minus_cos = radius_squared
+            / distance_squared
+            / (1.0 + (1.0 - radius_squared / distance_squared).sqrt());
+        let z = 1.0 - random_f64_0_1() * one_minus_cos;
+        let phi = 2.0 * PI * random_f64_0_1();
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        // about the axis towards the center
+        let w = to_center.unit();
+        let helper = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let u = w.cross(helper).unit();
+        let v = w.cross(u);
+        u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * z
+    }
+}
+
+// Up to four static spheres tested against a ray together (see simd::F4): the discriminants
+// are computed side by side, and only the spheres that a line through the ray touches are
+// intersected in full.
+pub struct SphereBatch {
+    spheres: Vec<Sphere>,
+    center_x: F4,
+    center_y: F4,
+    center_z: F4,
+    radius_squared: F4,
+    bounding_box: AABB,
+}
+
+impl SphereBatch {
+    pub fn new(spheres: Vec<Sphere>) -> Self {
+        assert!(!spheres.is_empty() && spheres.len() <= 4);
+        debug_assert!(spheres.iter().all(|s| s.motion.is_static()));
+        // unused lanes get a negative infinite radius squared, so they never have real roots
+        let lane = |f: &dyn Fn(&Sphere) -> Float, unused: Float| {
+            let mut a = [unused; 4];
+            for (i, s) in spheres.iter().enumerate() {
+                a[i] = f(s);
+            }
+            F4::new(a)
+        };
+        let bounding_box = spheres
+            .iter()
+            .fold(AABB::EMPTY, |acc, s| acc.union(s.bounding_box));
+        Self {
+            center_x: lane(&|s| s.center.x, 0.0),
+            center_y: lane(&|s| s.center.y, 0.0),
+            center_z: lane(&|s| s.center.z, 0.0),
+            radius_squared: lane(&|s| s.radius * s.radius, -Float::INFINITY),
+            spheres,
+            bounding_box,
+        }
+    }
+
+    // A BVH over batches of nearby spheres, for large sphere counts. Moving spheres are
+    // left out of the batches.
+    pub fn bvh(spheres: Vec<Sphere>) -> BVHNode {
+        let (still, moving): (Vec<Sphere>, Vec<Sphere>) =
+            spheres.into_iter().partition(|s| s.motion.is_static());
+        let mut list = HittableList::new();
+        for sphere in moving {
+            list.add(Arc::new(sphere));
+        }
+        if !still.is_empty() {
+            Self::split(still, &mut list);
+        }
+        BVHNode::new(list)
+    }
+
+    // halves along the longest axis of the centers until four or fewer are left
+    fn split(mut spheres: Vec<Sphere>, list: &mut HittableList) {
+        if spheres.len() <= 4 {
+            list.add(Arc::new(SphereBatch::new(spheres)));
+            return;
+        }
+        let centers = spheres.iter().fold(AABB::EMPTY, |acc, s| {
+            acc.union(AABB::new_two_points(s.center, s.center))
+        });
+        let axis = centers.longest_axis() as u8;
+        spheres.sort_by(|a, b| a.center.lp(axis).total_cmp(&b.center.lp(axis)));
+        let rest = spheres.split_off(spheres.len() / 2);
+        Self::split(spheres, list);
+        Self::split(rest, list);
+    }
+}
+
+impl Hittable for SphereBatch {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (o, d) = (r.a_origin, r.b_direction);
+        let oc_x = self.center_x - F4::splat(o.x);
+        let oc_y = self.center_y - F4::splat(o.y);
+        let oc_z = self.center_z - F4::splat(o.z);
+        let a = F4::splat(d.squared_length());
+        let h = oc_x * F4::splat(d.x) + oc_y * F4::splat(d.y) + oc_z * F4::splat(d.z);
+        let c = oc_x * oc_x + oc_y * oc_y + oc_z * oc_z - self.radius_squared;
+        let discriminant = h * h - a * c;
+        // widened spheres (see Sphere::hit) are larger than their radius says
+        let touched = if r.has_cone() {
+            0b1111
+        } else {
+            F4::splat(0.0).le_mask(discriminant)
+        };
+
+        let mut closest = ray_t.max;
+        let mut hit = false;
+        for (i, sphere) in self.spheres.iter().enumerate() {
+            if touched & 1 << i != 0
+                && sphere.hit(r, Interval::with_bounds(ray_t.min, closest), rec)
+            {
+                closest = rec.t;
+                hit = true;
+            }
+        }
+        hit
     }
 
     fn bounding_box(&self) -> AABB {
         self.bounding_box
     }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        for sphere in &self.spheres {
+            sphere.export(out)?;
+        }
+        Ok(())
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        for sphere in &self.spheres {
+            sphere.collect_lights(lights);
+        }
+    }
+
+    fn collect_surfaces(&self, surfaces: &mut HittableList) {
+        for sphere in &self.spheres {
+            sphere.collect_surfaces(surfaces);
+        }
+    }
 }
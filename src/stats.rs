@@ -0,0 +1,270 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Time spent building BVHs since the start of the program, summed over all of them.
+static BVH_BUILD_NANOS: AtomicU64 = AtomicU64::new(0);
+
+pub fn add_bvh_build_time(time: Duration) {
+    BVH_BUILD_NANOS.fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+}
+
+pub fn bvh_build_time() -> Duration {
+    Duration::from_nanos(BVH_BUILD_NANOS.load(Ordering::Relaxed))
+}
+
+// Intersection tests by kind and shadow rays, counted with the `counters` feature only: an
+// atomic add in every test slows the render down, and without the feature count() does
+// nothing.
+#[derive(Clone, Copy)]
+pub enum Counter {
+    AabbTest,
+    SphereTest,
+    QuadTest,
+    DiskTest,
+    TriangleTest,
+    // rays that only look whether a light or the sky is in sight
+    ShadowRay,
+}
+
+impl Counter {
+    pub const ALL: [Counter; 6] = [
+        Counter::AabbTest,
+        Counter::SphereTest,
+        Counter::QuadTest,
+        Counter::DiskTest,
+        Counter::TriangleTest,
+        Counter::ShadowRay,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Counter::AabbTest => "aabb_tests",
+            Counter::SphereTest => "sphere_tests",
+            Counter::QuadTest => "quad_tests",
+            Counter::DiskTest => "disk_tests",
+            Counter::TriangleTest => "triangle_tests",
+            Counter::ShadowRay => "shadow_rays",
+        }
+    }
+}
+
+static COUNTERS: [AtomicU64; 6] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+#[inline(always)]
+pub fn count(counter: Counter, n: u64) {
+    #[cfg(feature = "counters")]
+    COUNTERS[counter as usize].fetch_add(n, Ordering::Relaxed);
+    #[cfg(not(feature = "counters"))]
+    let _ = (counter, n);
+}
+
+pub fn reset_counters() {
+    for counter in &COUNTERS {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+// The counts since the last reset, None when built without the `counters` feature.
+pub fn counters() -> Option<Vec<(Counter, u64)>> {
+    cfg!(feature = "counters").then(|| {
+        Counter::ALL
+            .iter()
+            .map(|&c| (c, COUNTERS[c as usize].load(Ordering::Relaxed)))
+            .collect()
+    })
+}
+
+pub struct TileTime {
+    // tile column and row
+    pub x: u32,
+    pub y: u32,
+    pub seconds: f64,
+}
+
+// What Camera::render did, kept in Camera::stats after each render.
+#[derive(Default)]
+pub struct RenderStats {
+    // camera rays and every bounce after them
+    pub rays: u64,
+    pub render_seconds: f64,
+    pub bvh_build_seconds: f64,
+    // in the order the tiles finished
+    pub tiles: Vec<TileTime>,
+    // peak resident memory of the process in bytes, where the OS tells (Linux)
+    pub peak_memory: Option<u64>,
+    // see Counter; None without the `counters` feature
+    pub counters: Option<Vec<(Counter, u64)>>,
+    // paths by the bounces they made before they ended, up to max_depth: the last entry
+    // holds those still going when max_depth cut them. Paths gathered for the irradiance
+    // cache and the preview count from their first bounce off the surface they light.
+    pub path_depths: Vec<u64>,
+}
+
+impl RenderStats {
+    pub fn rays_per_second(&self) -> f64 {
+        if self.render_seconds > 0.0 {
+            self.rays as f64 / self.render_seconds
+        } else {
+            0.0
+        }
+    }
+
+    // The bounces that `p` (0 to 1) of the paths end within; those cut count as max_depth.
+    pub fn depth_percentile(&self, p: f64) -> usize {
+        let paths: u64 = self.path_depths.iter().sum();
+        let needed = (paths as f64 * p.clamp(0.0, 1.0)).ceil() as u64;
+        let mut seen = 0;
+        for (bounces, &count) in self.path_depths.iter().enumerate() {
+            seen += count;
+            if seen >= needed.max(1) {
+                return bounces;
+            }
+        }
+        self.path_depths.len().saturating_sub(1)
+    }
+
+    // A line on the path lengths, for telling whether max_depth is higher than the paths
+    // need or cuts them short.
+    fn depth_summary(&self) -> Option<String> {
+        let paths: u64 = self.path_depths.iter().sum();
+        if paths == 0 {
+            return None;
+        }
+        let max_depth = self.path_depths.len() - 1;
+        let bounces: u64 = (0..max_depth).map(|b| b as u64 * self.path_depths[b]).sum();
+        let cut = self.path_depths[max_depth];
+        let mean = (bounces + cut * max_depth as u64) as f64 / paths as f64;
+        let within = self.depth_percentile(0.999);
+        let mut line = format!(
+            "path depth: mean {:.2} bounces, 99% within {}, 99.9% within {}, {:.3}% cut at \
+             max_depth {}",
+            mean,
+            self.depth_percentile(0.99),
+            within,
+            100.0 * cut as f64 / paths as f64,
+            max_depth
+        );
+        if within + 1 < max_depth {
+            line += &format!(" (max_depth {} would do)", within + 1);
+        }
+        Some(line)
+    }
+
+    // The path depths as text, a bar per number of bounces up to the longest path, then
+    // the paths cut at max_depth.
+    pub fn depth_chart(&self) -> String {
+        let Some(max_depth) = self.path_depths.len().checked_sub(1) else {
+            return String::new();
+        };
+        let most = self.path_depths.iter().copied().max().unwrap_or(0).max(1);
+        let longest = (0..max_depth)
+            .rev()
+            .find(|&b| self.path_depths[b] > 0)
+            .unwrap_or(0);
+        let mut lines = vec!["paths by bounces:".to_owned()];
+        for (bounces, &count) in self.path_depths[..=longest].iter().enumerate() {
+            let bar = "#".repeat((count * 50).div_ceil(most) as usize);
+            lines.push(format!("{:>5} {:>10} {}", bounces, count, bar));
+        }
+        let cut = self.path_depths[max_depth];
+        let bar = "#".repeat((cut * 50).div_ceil(most) as usize);
+        lines.push(format!("{:>5} {:>10} {}", "cut", cut, bar));
+        lines.join("\n")
+    }
+
+    pub fn summary(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "rays: {} in {:.2} s ({:.2} Mrays/s)",
+                self.rays,
+                self.render_seconds,
+                self.rays_per_second() / 1e6
+            ),
+            format!("BVH build: {:.3} s", self.bvh_build_seconds),
+        ];
+        let mut times: Vec<f64> = self.tiles.iter().map(|t| t.seconds).collect();
+        times.sort_by(|a, b| a.total_cmp(b));
+        if let Some(slowest) = self
+            .tiles
+            .iter()
+            .max_by(|a, b| a.seconds.total_cmp(&b.seconds))
+        {
+            lines.push(format!(
+                "tiles: {} (min {:.3} s, median {:.3} s, max {:.3} s at column {}, row {})",
+                times.len(),
+                times[0],
+                times[times.len() / 2],
+                slowest.seconds,
+                slowest.x,
+                slowest.y
+            ));
+        }
+        if let Some(bytes) = self.peak_memory {
+            lines.push(format!("peak memory: {:.1} MiB", bytes as f64 / 1048576.0));
+        }
+        lines.extend(self.depth_summary());
+        for (counter, n) in self.counters.iter().flatten() {
+            lines.push(format!(
+                "{}: {} ({:.1} per ray)",
+                counter.name(),
+                n,
+                *n as f64 / self.rays.max(1) as f64
+            ));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let tiles: Vec<String> = self
+            .tiles
+            .iter()
+            .map(|t| {
+                format!(
+                    "    {{\"x\": {}, \"y\": {}, \"seconds\": {}}}",
+                    t.x, t.y, t.seconds
+                )
+            })
+            .collect();
+        let peak_memory = self
+            .peak_memory
+            .map_or("null".to_owned(), |bytes| bytes.to_string());
+        let counters = self
+            .counters
+            .as_ref()
+            .map_or("null".to_owned(), |counters| {
+                let fields: Vec<String> = counters
+                    .iter()
+                    .map(|(counter, n)| format!("\"{}\": {}", counter.name(), n))
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            });
+        [
+            "{".to_owned(),
+            format!("  \"rays\": {},", self.rays),
+            format!("  \"render_seconds\": {},", self.render_seconds),
+            format!("  \"rays_per_second\": {},", self.rays_per_second()),
+            format!("  \"bvh_build_seconds\": {},", self.bvh_build_seconds),
+            format!("  \"peak_memory_bytes\": {},", peak_memory),
+            format!("  \"counters\": {},", counters),
+            format!("  \"path_depths\": {:?},", self.path_depths),
+            format!("  \"tiles\": [\n{}\n  ]", tiles.join(",\n")),
+            "}\n".to_owned(),
+        ]
+        .join("\n")
+    }
+}
+
+// VmHWM of /proc/self/status
+pub fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
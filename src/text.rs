@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use crate::bvh::BVHNode;
+use crate::hittable::HittableList;
+use crate::material::Material;
+use crate::triangle::Triangle;
+use crate::vec3::{Float, Vec3};
+
+// straight pieces a quadratic or cubic piece of an outline is flattened into
+const CURVE_SEGMENTS: usize = 8;
+
+// A point of a glyph outline in font units, x along the text and y up.
+type Point = (Float, Float);
+
+// Text as scene geometry for annotations, logos and title cards: the glyph outlines of the
+// TrueType or OpenType font at `font`, triangulated into a flat front face facing +Z, and
+// extruded `depth` back along -Z into a back face and side walls (just the front face when
+// `depth` is 0). The text starts at `origin` and runs along +X with its baseline on
+// origin.y; `height` is the size of the font (its em), and the front face is at origin.z.
+// Characters the font has no glyph for come out as its missing-glyph box.
+pub fn text_mesh(
+    font: &str,
+    text: &str,
+    origin: Vec3,
+    height: Float,
+    depth: Float,
+    mat: Arc<dyn Material>,
+) -> Result<HittableList, String> {
+    let data = std::fs::read(font).map_err(|e| format!("cannot read font {}: {}", font, e))?;
+    let face = Face::parse(&data, 0).map_err(|e| format!("cannot read font {}: {}", font, e))?;
+    let scale = height / face.units_per_em() as Float;
+
+    let mut pieces = HittableList::new();
+    let mut pen = 0.0;
+    for c in text.chars() {
+        let id = face.glyph_index(c).unwrap_or(GlyphId(0));
+        let mut outline = Outline::default();
+        face.outline_glyph(id, &mut outline);
+        let place = |(x, y): Point, z: Float| {
+            Vec3::new(origin.x + (pen + x) * scale, origin.y + y * scale, z)
+        };
+        let back = origin.z - depth;
+        let (polygons, contours) = polygons(outline.contours);
+        for polygon in polygons {
+            for [a, b, c] in triangulate(&polygon) {
+                let (a, b, c) = (polygon[a], polygon[b], polygon[c]);
+                pieces.add(Arc::new(Triangle::new(
+                    place(a, origin.z),
+                    place(b, origin.z),
+                    place(c, origin.z),
+                    mat.clone(),
+                )));
+                if depth > 0.0 {
+                    pieces.add(Arc::new(Triangle::new(
+                        place(a, back),
+                        place(c, back),
+                        place(b, back),
+                        mat.clone(),
+                    )));
+                }
+            }
+        }
+        // outer contours run counter-clockwise and holes clockwise, so the walls face out of
+        // the letter either way
+        for contour in contours.iter().filter(|_| depth > 0.0) {
+            for i in 0..contour.len() {
+                let (p, q) = (contour[i], contour[(i + 1) % contour.len()]);
+                let (front_p, front_q) = (place(p, origin.z), place(q, origin.z));
+                let (back_p, back_q) = (place(p, back), place(q, back));
+                pieces.add(Arc::new(Triangle::new(
+                    front_p,
+                    back_p,
+                    back_q,
+                    mat.clone(),
+                )));
+                pieces.add(Arc::new(Triangle::new(
+                    front_p,
+                    back_q,
+                    front_q,
+                    mat.clone(),
+                )));
+            }
+        }
+        pen += face.glyph_hor_advance(id).unwrap_or(0) as Float;
+    }
+
+    if pieces.objects.is_empty() {
+        return Ok(pieces);
+    }
+    Ok(HittableList::new_and_add(Arc::new(BVHNode::new(pieces))))
+}
+
+// The contours of a glyph, with their curves flattened into straight pieces.
+#[derive(Default)]
+struct Outline {
+    contours: Vec<Vec<Point>>,
+}
+
+impl Outline {
+    fn last(&self) -> Point {
+        *self.contours.last().and_then(|c| c.last()).unwrap_or(&(0.0, 0.0))
+    }
+
+    fn push(&mut self, p: Point) {
+        if let Some(contour) = self.contours.last_mut() {
+            if contour.last() != Some(&p) {
+                contour.push(p);
+            }
+        }
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.contours.push(vec![(x as Float, y as Float)]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push((x as Float, y as Float));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x0, y0) = self.last();
+        let (x1, y1, x, y) = (x1 as Float, y1 as Float, x as Float, y as Float);
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as Float / CURVE_SEGMENTS as Float;
+            let s = 1.0 - t;
+            self.push((
+                s * s * x0 + 2.0 * s * t * x1 + t * t * x,
+                s * s * y0 + 2.0 * s * t * y1 + t * t * y,
+            ));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (x0, y0) = self.last();
+        let (x1, y1, x2, y2) = (x1 as Float, y1 as Float, x2 as Float, y2 as Float);
+        let (x, y) = (x as Float, y as Float);
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as Float / CURVE_SEGMENTS as Float;
+            let s = 1.0 - t;
+            let (a, b, c, d) = (s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t);
+            self.push((
+                a * x0 + b * x1 + c * x2 + d * x,
+                a * y0 + b * y1 + c * y2 + d * y,
+            ));
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(contour) = self.contours.last_mut() {
+            // the closing point repeats the first one
+            if contour.len() > 1 && contour.first() == contour.last() {
+                contour.pop();
+            }
+        }
+    }
+}
+
+// Twice the signed area of `contour`, positive when it runs counter-clockwise.
+fn area(contour: &[Point]) -> Float {
+    (0..contour.len())
+        .map(|i| {
+            let (p, q) = (contour[i], contour[(i + 1) % contour.len()]);
+            p.0 * q.1 - q.0 * p.1
+        })
+        .sum()
+}
+
+// Whether `p` is inside `contour`, by the even-odd rule.
+fn inside(p: Point, contour: &[Point]) -> bool {
+    let mut inside = false;
+    for i in 0..contour.len() {
+        let (a, b) = (contour[i], contour[(i + 1) % contour.len()]);
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) / (b.1 - a.1) * (b.0 - a.0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// The contours of a glyph as simple polygons, counter-clockwise, each outer contour with
+// its holes joined into it, and the contours themselves turned counter-clockwise when outer
+// and clockwise when holes. Fonts disagree on which way outer contours run, so a contour is
+// a hole when it lies inside an odd number of others.
+fn polygons(contours: Vec<Vec<Point>>) -> (Vec<Vec<Point>>, Vec<Vec<Point>>) {
+    let contours: Vec<_> = contours
+        .into_iter()
+        .filter(|c| c.len() >= 3 && area(c) != 0.0)
+        .collect();
+    // the contours each one lies inside
+    let around: Vec<Vec<usize>> = (0..contours.len())
+        .map(|i| {
+            (0..contours.len())
+                .filter(|&j| j != i && inside(contours[i][0], &contours[j]))
+                .collect()
+        })
+        .collect();
+    let depth: Vec<usize> = around.iter().map(|around| around.len()).collect();
+    let contours: Vec<_> = contours
+        .into_iter()
+        .zip(&depth)
+        .map(|(mut contour, depth)| {
+            if (area(&contour) > 0.0) != (depth % 2 == 0) {
+                contour.reverse();
+            }
+            contour
+        })
+        .collect();
+
+    let mut polygons = vec![];
+    for (i, contour) in contours.iter().enumerate() {
+        if depth[i] % 2 == 1 {
+            continue;
+        }
+        let mut outer = contour.clone();
+        // the holes right inside this contour
+        let mut holes: Vec<&Vec<Point>> = (0..contours.len())
+            .filter(|&j| depth[j] == depth[i] + 1 && around[j].contains(&i))
+            .map(|j| &contours[j])
+            .collect();
+        // rightmost first, so that every bridge reaches the outline the ones before made
+        holes.sort_by(|a, b| rightmost(b).1 .0.partial_cmp(&rightmost(a).1 .0).unwrap());
+        for hole in holes {
+            outer = bridge(outer, hole);
+        }
+        polygons.push(outer);
+    }
+    (polygons, contours)
+}
+
+// The index and point of the rightmost point of `contour`.
+fn rightmost(contour: &[Point]) -> (usize, Point) {
+    let mut best = 0;
+    for (i, p) in contour.iter().enumerate() {
+        if p.0 > contour[best].0 {
+            best = i;
+        }
+    }
+    (best, contour[best])
+}
+
+// `outer` with `hole` inside it joined in through a cut from the hole's rightmost point to
+// a point of `outer` it can see, so that ear clipping triangulates around the hole
+// (Eberly, "Triangulation by Ear Clipping").
+fn bridge(outer: Vec<Point>, hole: &[Point]) -> Vec<Point> {
+    let (m_index, m) = rightmost(hole);
+    // the closest crossing of the ray from m along +x with an edge of `outer`
+    let mut closest: Option<(Float, usize)> = None;
+    for i in 0..outer.len() {
+        let (a, b) = (outer[i], outer[(i + 1) % outer.len()]);
+        if (a.1 > m.1) == (b.1 > m.1) {
+            continue;
+        }
+        let x = a.0 + (m.1 - a.1) / (b.1 - a.1) * (b.0 - a.0);
+        if x >= m.0 && closest.is_none_or(|(best, _)| x < best) {
+            closest = Some((x, i));
+        }
+    }
+    let Some((x, edge)) = closest else {
+        // not inside after all; leave the hole out
+        return outer;
+    };
+    let next = (edge + 1) % outer.len();
+    let mut target = if outer[edge].0 > outer[next].0 {
+        edge
+    } else {
+        next
+    };
+    // a reflex point of `outer` inside the triangle of m, the crossing and the target can
+    // block the view; the one at the smallest angle from the ray is visible
+    let crossing = (x, m.1);
+    let p = outer[target];
+    let mut best_angle = Float::INFINITY;
+    for i in 0..outer.len() {
+        let q = outer[i];
+        let (prev, next) = (
+            outer[(i + outer.len() - 1) % outer.len()],
+            outer[(i + 1) % outer.len()],
+        );
+        if i == target || cross(prev, q, next) >= 0.0 || !in_triangle(q, m, crossing, p) {
+            continue;
+        }
+        let angle = (q.1 - m.1).abs().atan2(q.0 - m.0);
+        if angle < best_angle {
+            (best_angle, target) = (angle, i);
+        }
+    }
+
+    let mut joined = Vec::with_capacity(outer.len() + hole.len() + 2);
+    joined.extend_from_slice(&outer[..=target]);
+    joined.extend_from_slice(&hole[m_index..]);
+    joined.extend_from_slice(&hole[..=m_index]);
+    joined.extend_from_slice(&outer[target..]);
+    joined
+}
+
+// Twice the signed area of the triangle a, b, c: positive when it turns left at b.
+fn cross(a: Point, b: Point, c: Point) -> Float {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+// Whether `p` is inside the triangle a, b, c or on its edges, either way round.
+fn in_triangle(p: Point, a: Point, b: Point, c: Point) -> bool {
+    let (d1, d2, d3) = (cross(a, b, p), cross(b, c, p), cross(c, a, p));
+    let negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(negative && positive)
+}
+
+// Triangles, as indices into `polygon`, covering the simple counter-clockwise polygon, by
+// ear clipping: cut off convex corners with no other point inside them until one is left.
+fn triangulate(polygon: &[Point]) -> Vec<[usize; 3]> {
+    let mut left: Vec<usize> = (0..polygon.len()).collect();
+    let mut triangles = Vec::with_capacity(polygon.len().saturating_sub(2));
+    let mut i = 0;
+    // corners looked at since the last cut; a full round without one means the rest is
+    // degenerate (collinear points), and is dropped
+    let mut tried = 0;
+    while left.len() > 3 && tried < left.len() {
+        let n = left.len();
+        let (a, b, c) = (left[(i + n - 1) % n], left[i % n], left[(i + 1) % n]);
+        let (pa, pb, pc) = (polygon[a], polygon[b], polygon[c]);
+        let ear = cross(pa, pb, pc) > 0.0
+            && !left.iter().any(|&k| {
+                let pk = polygon[k];
+                pk != pa && pk != pb && pk != pc && in_triangle(pk, pa, pb, pc)
+            });
+        if ear {
+            triangles.push([a, b, c]);
+            left.remove(i % n);
+            tried = 0;
+        } else {
+            i += 1;
+            tried += 1;
+        }
+        i %= left.len();
+    }
+    if left.len() == 3 && cross(polygon[left[0]], polygon[left[1]], polygon[left[2]]) > 0.0 {
+        triangles.push([left[0], left[1], left[2]]);
+    }
+    triangles
+}
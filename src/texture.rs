@@ -1,13 +1,112 @@
-use crate::{perlin::Perlin, util::Vec3};
+use crate::{
+    color::{blackbody_color, from_rec709, srgb_to_linear},
+    hittable::HitRecord,
+    perlin::{Perlin, Simplex, Worley},
+    scene_export::{vec3, SceneWriter},
+    util::Vec3,
+    vec3::Float,
+};
 use opencv::imgcodecs::imread;
 use opencv::{
     core::{MatTraitConst, VecN},
     imgcodecs::IMREAD_COLOR,
 };
-use std::sync::Arc;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+// Directories searched (in order) for image textures, see set_texture_dirs().
+static TEXTURE_DIRS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+pub fn set_texture_dirs(dirs: Vec<String>) {
+    *TEXTURE_DIRS.write().unwrap() = dirs;
+}
+
+// Returns the first existing "<dir>/<filename>", or "./texture/<filename>" if none matches.
+pub fn find_texture(filename: &str) -> String {
+    for dir in TEXTURE_DIRS.read().unwrap().iter() {
+        let path = Path::new(dir).join(filename);
+        if path.exists() {
+            return path.to_string_lossy().into_owned();
+        }
+    }
+    "./texture/".to_owned() + filename
+}
+
+// What image textures that can't be loaded show instead, see set_placeholder(). A missing or
+// misspelt file is reported when the texture is first looked up and the render carries on,
+// with the objects that use it standing out in bright magenta.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Placeholder {
+    Magenta,
+    // magenta and black squares, 8 by 8 over the texture
+    Checker,
+}
+
+impl Placeholder {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "magenta" => Ok(Placeholder::Magenta),
+            "checker" => Ok(Placeholder::Checker),
+            _ => Err(format!(
+                "unknown missing texture placeholder \"{}\" (magenta, checker)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placeholder::Magenta => "magenta",
+            Placeholder::Checker => "checker",
+        }
+    }
+}
+
+static PLACEHOLDER: RwLock<Placeholder> = RwLock::new(Placeholder::Checker);
+
+pub fn set_placeholder(placeholder: Placeholder) {
+    *PLACEHOLDER.write().unwrap() = placeholder;
+}
+
+// The placeholder at (u, v), as an sRGB colour like the texels it stands for.
+pub fn placeholder_color(u: Float, v: Float) -> Vec3 {
+    let magenta = Vec3::new(1.0, 0.0, 1.0);
+    match *PLACEHOLDER.read().unwrap() {
+        Placeholder::Magenta => magenta,
+        Placeholder::Checker => {
+            let square = |t: Float| (t.clamp(0.0, 0.999) * 8.0) as u32;
+            if (square(u) + square(v)) % 2 == 0 {
+                magenta
+            } else {
+                Vec3::zero()
+            }
+        }
+    }
+}
+
+// Reports a texture that will show the placeholder; `error` starts with the file's path.
+pub fn report_missing_texture(error: &str) {
+    println!(
+        "Cannot load texture: {} (using the {} placeholder)",
+        error,
+        PLACEHOLDER.read().unwrap().name()
+    );
+}
 
 pub trait Texture {
-    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3;
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3;
+
+    // Lookup at a surface hit, for textures that need more than (u, v, p) such as Triplanar.
+    // Materials that have the hit record call this.
+    fn value_at(&self, rec: &HitRecord) -> Vec3 {
+        self.value(rec.u, rec.v, rec.p)
+    }
+
+    // What follows `material <name>` in a scene file for a Lambertian of this texture
+    // (scene_export); the default is for textures the format has no statement for.
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Err(format!("texture {} can't be exported", std::any::type_name::<Self>()))
+    }
 }
 
 // SolidColor
@@ -19,7 +118,7 @@ impl SolidColor {
     pub fn from_vec(albedo: Vec3) -> Self {
         Self { albedo }
     }
-    pub fn from_rgb(r: f64, g: f64, b: f64) -> Self {
+    pub fn from_rgb(r: Float, g: Float, b: Float) -> Self {
         Self {
             albedo: Vec3::new(r, g, b),
         }
@@ -27,27 +126,31 @@ impl SolidColor {
 }
 
 impl Texture for SolidColor {
-    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
         self.albedo
     }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!("lambertian {}", vec3(self.albedo)))
+    }
 }
 
 // CheckerTexture
 pub struct CheckerTexture {
-    inv_scale: f64,
+    inv_scale: Float,
     even: Arc<dyn Texture>,
     odd: Arc<dyn Texture>,
 }
 
 impl CheckerTexture {
-    pub fn from_texture(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+    pub fn from_texture(scale: Float, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
         Self {
             inv_scale: 1.0 / scale,
             even,
             odd,
         }
     }
-    pub fn from_color(scale: f64, even: Vec3, odd: Vec3) -> Self {
+    pub fn from_color(scale: Float, even: Vec3, odd: Vec3) -> Self {
         Self {
             inv_scale: 1.0 / scale,
             even: Arc::from(SolidColor::from_vec(even)),
@@ -57,7 +160,7 @@ impl CheckerTexture {
 }
 
 impl Texture for CheckerTexture {
-    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
         let xint = (self.inv_scale * p.x).floor() as i32;
         let yint = (self.inv_scale * p.y).floor() as i32;
         let zint = (self.inv_scale * p.z).floor() as i32;
@@ -67,13 +170,133 @@ impl Texture for CheckerTexture {
             self.odd.value(u, v, p)
         }
     }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let even = out.texture(&self.even)?;
+        let odd = out.texture(&self.odd)?;
+        match (even.strip_prefix("lambertian "), odd.strip_prefix("lambertian ")) {
+            (Some(even), Some(odd)) => {
+                Ok(format!("checker {}  {}  {}", 1.0 / self.inv_scale, even, odd))
+            }
+            _ => Err("checker of textures can't be exported".to_owned()),
+        }
+    }
+}
+
+// Vertical blend from `bottom` to `top`: over v on objects, and over the height of the ray
+// direction as a sky (Camera::sky).
+#[derive(Clone, Copy)]
+pub struct Gradient {
+    pub bottom: Vec3,
+    pub top: Vec3,
+}
+
+impl Gradient {
+    pub fn new(bottom: Vec3, top: Vec3) -> Self {
+        Self { bottom, top }
+    }
+    // the white to blue sky of "Ray Tracing in One Weekend"
+    pub fn sky() -> Self {
+        Self::new(Vec3::ones(), Vec3::new(0.5, 0.7, 1.0))
+    }
+
+    pub fn at_direction(&self, direction: Vec3) -> Vec3 {
+        let a = 0.5 * (direction.unit().y + 1.0);
+        self.bottom * (1.0 - a) + self.top * a
+    }
+}
+
+impl Texture for Gradient {
+    fn value(&self, _u: Float, v: Float, _p: Vec3) -> Vec3 {
+        let a = v.clamp(0.0, 1.0);
+        self.bottom * (1.0 - a) + self.top * a
+    }
+}
+
+// Checkerboard in texture space, `frequency` squares along u and v; unlike CheckerTexture
+// it follows the surface parameterization, which makes stretching and seams visible.
+pub struct UvChecker {
+    frequency: Float,
+    even: Vec3,
+    odd: Vec3,
+}
+
+impl UvChecker {
+    pub fn new(frequency: Float, even: Vec3, odd: Vec3) -> Self {
+        Self {
+            frequency,
+            even,
+            odd,
+        }
+    }
+}
+
+impl Texture for UvChecker {
+    fn value(&self, u: Float, v: Float, _p: Vec3) -> Vec3 {
+        let ui = (u * self.frequency).floor() as i64;
+        let vi = (v * self.frequency).floor() as i64;
+        if (ui + vi) % 2 == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!(
+            "uv_checker {} colors {}  {}",
+            self.frequency,
+            vec3(self.even),
+            vec3(self.odd)
+        ))
+    }
+}
+
+// Debug grid in texture space: `frequency` cells along u and v, lines `line_width` of a cell
+// wide. The cell background shows u in red and v in green, so orientation can be read off.
+pub struct UvGrid {
+    frequency: Float,
+    line_width: Float,
+}
+
+impl UvGrid {
+    pub fn new(frequency: Float, line_width: Float) -> Self {
+        Self {
+            frequency,
+            line_width,
+        }
+    }
+}
+
+impl Texture for UvGrid {
+    fn value(&self, u: Float, v: Float, _p: Vec3) -> Vec3 {
+        let on_line = |x: Float| {
+            let cell = x * self.frequency;
+            (cell - cell.round()).abs() < 0.5 * self.line_width
+        };
+        if on_line(u) || on_line(v) {
+            return Vec3::zero();
+        }
+        Vec3::new(u.clamp(0.0, 1.0), v.clamp(0.0, 1.0), 0.2)
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!("uv_grid {} {}", self.frequency, self.line_width))
+    }
 }
 
 // ImageTexture
+// LDR images are read through OpenCV and decoded from sRGB into the working space (see
+// color::WorkingSpace), unless they hold data rather than colours (masks, roughness or normal
+// maps): those are loaded with `new_linear`. Radiance .hdr and OpenEXR files are kept as
+// linear floats, so they can hold emission and sky values above 1, and taken to have Rec.709
+// primaries.
 pub struct ImageTexture {
     pub img_data: opencv::core::Mat,
+    hdr_data: Option<image::Rgb32FImage>,
     width: u32,
     height: u32,
+    linear: bool,
 }
 
 // unsafe impl Send for Image {}
@@ -81,17 +304,54 @@ pub struct ImageTexture {
 
 impl ImageTexture {
     pub fn new(filename: &str) -> Self {
-        let img_data = imread(&("./texture/".to_owned() + filename), IMREAD_COLOR)
-            .expect("Image reading error!");
+        Self::load(filename, false)
+    }
+    pub fn new_linear(filename: &str) -> Self {
+        Self::load(filename, true)
+    }
+    fn load(filename: &str, linear: bool) -> Self {
+        let path = find_texture(filename);
+        let lower = path.to_lowercase();
+        if lower.ends_with(".hdr") || lower.ends_with(".exr") {
+            let hdr_data = match image::open(&path) {
+                Ok(img) => img.into_rgb32f(),
+                Err(e) => {
+                    report_missing_texture(&format!("{}: {}", path, e));
+                    image::Rgb32FImage::new(0, 0)
+                }
+            };
+            return Self {
+                img_data: opencv::core::Mat::default(),
+                width: hdr_data.width(),
+                height: hdr_data.height(),
+                hdr_data: Some(hdr_data),
+                linear: true,
+            };
+        }
+
+        // OpenCV reads a missing or undecodable file as an empty image
+        let img_data = match imread(&path, IMREAD_COLOR) {
+            Ok(img) if img.cols() > 0 && img.rows() > 0 => img,
+            Ok(_) => {
+                report_missing_texture(&format!("{}: no such file or not an image", path));
+                opencv::core::Mat::default()
+            }
+            Err(e) => {
+                report_missing_texture(&format!("{}: {}", path, e));
+                opencv::core::Mat::default()
+            }
+        };
         let width = img_data.cols() as u32;
         let height = img_data.rows() as u32;
         Self {
             img_data,
+            hdr_data: None,
             width,
             height,
+            linear,
         }
     }
-    pub fn get_color(&self, mut u: f64, mut v: f64) -> Vec3 {
+    pub fn get_color(&self, mut u: Float, mut v: Float) -> Vec3 {
         // println!("u: {}, v: {}", u, v);
         if u <= 0.0 {
             u = 0.001;
@@ -106,48 +366,501 @@ impl ImageTexture {
             v = 0.999;
         }
 
-        let u_img = u * self.width as f64;
-        let v_img = (1.0 - v) * self.height as f64;
+        let u_img = u * self.width as Float;
+        let v_img = (1.0 - v) * self.height as Float;
+        if let Some(hdr_data) = &self.hdr_data {
+            let color = hdr_data.get_pixel(u_img as u32, v_img as u32);
+            return Vec3::new(color[0] as Float, color[1] as Float, color[2] as Float);
+        }
         let color: &VecN<u8, 3> = self.img_data.at_2d(v_img as i32, u_img as i32).unwrap();
         // println!("color: {:?}", color);
 
-        Vec3::new(color[2] as f64, color[1] as f64, color[0] as f64) * (1.0 / 255.0)
+        Vec3::new(color[2] as Float, color[1] as Float, color[0] as Float) * (1.0 / 255.0)
     }
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
         if self.width == 0 || self.height == 0 {
-            return Vec3::new(0.0, 1.0, 1.0);
+            return from_rec709(placeholder_color(u, v));
         }
         let org_color = self.get_color(u, v);
+        if self.hdr_data.is_some() {
+            return from_rec709(org_color);
+        }
+        if self.linear {
+            return org_color;
+        }
+        from_rec709(srgb_to_linear(org_color))
+    }
+}
+
+// How texture coordinates outside [0, 1] are brought back in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl Wrap {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "repeat" => Ok(Wrap::Repeat),
+            "clamp" => Ok(Wrap::Clamp),
+            "mirror" => Ok(Wrap::Mirror),
+            _ => Err(format!("unknown wrap mode \"{}\" (repeat, clamp or mirror)", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Wrap::Repeat => "repeat",
+            Wrap::Clamp => "clamp",
+            Wrap::Mirror => "mirror",
+        }
+    }
 
-        //Adjust the color to right gamma
-        Vec3::new(
-            org_color.x * org_color.x,
-            org_color.y * org_color.y,
-            org_color.z * org_color.z,
-        )
+    pub fn apply(&self, x: Float) -> Float {
+        match self {
+            Wrap::Repeat => x - x.floor(),
+            Wrap::Clamp => x.clamp(0.0, 1.0),
+            Wrap::Mirror => {
+                let x = x.rem_euclid(2.0);
+                if x > 1.0 {
+                    2.0 - x
+                } else {
+                    x
+                }
+            }
+        }
+    }
+}
+
+// Scales, rotates (degrees, counter-clockwise) and offsets the texture coordinates, in that
+// order, then wraps them into [0, 1]: a texture with scale (8, 8) and Wrap::Repeat tiles 8
+// times across a floor quad instead of being stretched over it once. Textures that are
+// defined in space (NoiseTexture, CheckerTexture) are passed the hit point unchanged.
+pub struct UvTransform {
+    tex: Arc<dyn Texture>,
+    scale: (Float, Float),
+    rotation: Float,
+    offset: (Float, Float),
+    wrap: Wrap,
+}
+
+impl UvTransform {
+    pub fn new(
+        tex: Arc<dyn Texture>,
+        scale: (Float, Float),
+        rotation: Float,
+        offset: (Float, Float),
+        wrap: Wrap,
+    ) -> Self {
+        Self {
+            tex,
+            scale,
+            rotation,
+            offset,
+            wrap,
+        }
+    }
+}
+
+impl Texture for UvTransform {
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (u, v) = (u * cos - v * sin, u * sin + v * cos);
+        let u = self.wrap.apply(u + self.offset.0);
+        let v = self.wrap.apply(v + self.offset.1);
+        self.tex.value(u, v, p)
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let tex = out.texture(&self.tex)?;
+        if !takes_uv_options(&tex) {
+            return Err(format!("UvTransform of \"{}\" can't be exported", tex));
+        }
+        Ok(format!(
+            "{} scale {} {} rotate {} offset {} {} wrap {}",
+            tex,
+            self.scale.0,
+            self.scale.1,
+            self.rotation,
+            self.offset.0,
+            self.offset.1,
+            self.wrap.name()
+        ))
+    }
+}
+
+// Images take the texture coordinate options of the scene file (see Statement::uv_transform).
+fn takes_uv_options(definition: &str) -> bool {
+    let kind = definition.split_whitespace().next().unwrap_or("");
+    ["image", "texture", "big_image"].contains(&kind) && !definition.contains(" triplanar ")
+}
+
+// Projects a texture along the x, y and z axes and blends the three by the surface normal,
+// for meshes without texture coordinates and for boxes. The texture repeats every `size`
+// world units. Without a normal (plain `value`) the three projections are averaged.
+pub struct Triplanar {
+    tex: Arc<dyn Texture>,
+    size: Float,
+    // higher values narrow the blend zone where two projections overlap
+    sharpness: Float,
+}
+
+impl Triplanar {
+    pub fn new(tex: Arc<dyn Texture>, size: Float) -> Self {
+        Self {
+            tex,
+            size,
+            sharpness: 4.0,
+        }
+    }
+
+    fn blend(&self, p: Vec3, weights: Vec3) -> Vec3 {
+        let q = p / self.size;
+        let fract = |x: Float| x - x.floor();
+        let sum = weights.x + weights.y + weights.z;
+        (self.tex.value(fract(q.z), fract(q.y), p) * weights.x
+            + self.tex.value(fract(q.x), fract(q.z), p) * weights.y
+            + self.tex.value(fract(q.x), fract(q.y), p) * weights.z)
+            / sum
+    }
+}
+
+impl Texture for Triplanar {
+    fn value(&self, _u: Float, _v: Float, p: Vec3) -> Vec3 {
+        self.blend(p, Vec3::ones())
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Vec3 {
+        let n = rec.normal;
+        let weights = Vec3::new(
+            n.x.abs().powf(self.sharpness),
+            n.y.abs().powf(self.sharpness),
+            n.z.abs().powf(self.sharpness),
+        );
+        self.blend(rec.p, weights)
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let tex = out.texture(&self.tex)?;
+        if !takes_uv_options(&tex) {
+            return Err(format!("Triplanar of \"{}\" can't be exported", tex));
+        }
+        Ok(format!("{} triplanar {}", tex, self.size))
+    }
+}
+
+// Glow of a temperature field: `temperature` (red channel, 0..1) is scaled to 0..max_kelvin
+// and emits the black body colour, with brightness growing as T^4 up to `intensity`.
+pub struct BlackbodyTexture {
+    temperature: Arc<dyn Texture>,
+    max_kelvin: Float,
+    intensity: Float,
+}
+
+impl BlackbodyTexture {
+    pub fn new(temperature: Arc<dyn Texture>, max_kelvin: Float, intensity: Float) -> Self {
+        Self {
+            temperature,
+            max_kelvin,
+            intensity,
+        }
+    }
+}
+
+impl Texture for BlackbodyTexture {
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        let t = self.temperature.value(u, v, p).x.clamp(0.0, 1.0);
+        // too cold to glow visibly
+        if t * self.max_kelvin < 500.0 {
+            return Vec3::zero();
+        }
+        blackbody_color(t * self.max_kelvin) * (self.intensity * t.powi(4))
+    }
+
+    // the emission options of a `medium` statement
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let temperature = out.texture(&self.temperature)?;
+        let noise = match temperature.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["lambertian", "1", "1", "1"] => String::new(),
+            ["noise", "marble", scale] => format!(" noise {}", scale),
+            _ => return Err(format!("temperature \"{}\" can't be exported", temperature)),
+        };
+        Ok(format!("blackbody {} {}{}", self.max_kelvin, self.intensity, noise))
     }
 }
 
 // NoiseTexture
+// Gray patterns from the noise generators in perlin.rs.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NoisePattern {
+    // Perlin turbulence bent into veins (the original NoiseTexture)
+    Marble,
+    // Worley F1: round cells, dark at their centers
+    Cells,
+    // Worley F2 - F1: dark cracks along the cell borders, as in dried earth
+    Cracks,
+    // simplex fBm: soft clouds
+    Clouds,
+}
+
+impl NoisePattern {
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "marble" => Ok(NoisePattern::Marble),
+            "cells" => Ok(NoisePattern::Cells),
+            "cracks" => Ok(NoisePattern::Cracks),
+            "clouds" => Ok(NoisePattern::Clouds),
+            _ => Err(format!(
+                "unknown noise pattern \"{}\" (marble, cells, cracks or clouds)",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoisePattern::Marble => "marble",
+            NoisePattern::Cells => "cells",
+            NoisePattern::Cracks => "cracks",
+            NoisePattern::Clouds => "clouds",
+        }
+    }
+}
+
+// The pattern goes from `low` to `high` (black to white unless given with_colors).
 pub struct NoiseTexture {
     noise: Perlin,
-    scale: f64,
+    worley: Worley,
+    simplex: Simplex,
+    scale: Float,
+    pattern: NoisePattern,
+    low: Vec3,
+    high: Vec3,
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f64) -> Self {
+    pub fn new(scale: Float) -> Self {
+        Self::with_pattern(scale, NoisePattern::Marble)
+    }
+    pub fn with_pattern(scale: Float, pattern: NoisePattern) -> Self {
+        Self::with_colors(scale, pattern, Vec3::zero(), Vec3::ones())
+    }
+    pub fn with_colors(scale: Float, pattern: NoisePattern, low: Vec3, high: Vec3) -> Self {
         Self {
             noise: Perlin::new(),
-            scale
+            worley: Worley::new(),
+            simplex: Simplex::new(),
+            scale,
+            pattern,
+            low,
+            high,
         }
     }
 }
 impl Texture for NoiseTexture {
-    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
-        Vec3::new(0.5, 0.5, 0.5) * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turb(p, 7)).sin())
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        let gray = match self.pattern {
+            NoisePattern::Marble => {
+                0.5 * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turb(p, 7)).sin())
+            }
+            NoisePattern::Cells => self.worley.distances(p * self.scale).0.min(1.0),
+            NoisePattern::Cracks => {
+                let (f1, f2) = self.worley.distances(p * self.scale);
+                ((f2 - f1) * 8.0).min(1.0)
+            }
+            NoisePattern::Clouds => {
+                (0.5 * (1.0 + self.simplex.fbm(p * self.scale, 5))).clamp(0.0, 1.0)
+            }
+        };
+        self.low * (1.0 - gray) + self.high * gray
         // Vec3::new(1.0, 1.0, 1.0) * self.noise.turb(p, 7)
     }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        let mut definition = format!("noise {} {}", self.pattern.name(), self.scale);
+        if self.low != Vec3::zero() || self.high != Vec3::ones() {
+            definition += &format!(" colors {}  {}", vec3(self.low), vec3(self.high));
+        }
+        Ok(definition)
+    }
+}
+
+// Maps a scalar texture (its red channel, e.g. a NoiseTexture) through a gradient: `stops`
+// are (position, colour) pairs, interpolated linearly and held beyond the first and last.
+// Drives fire, terrain or sky palettes from a noise pattern.
+pub struct Ramp {
+    input: Arc<dyn Texture>,
+    stops: Vec<(Float, Vec3)>,
+}
+
+impl Ramp {
+    pub fn new(input: Arc<dyn Texture>, mut stops: Vec<(Float, Vec3)>) -> Self {
+        assert!(!stops.is_empty(), "a ramp needs at least one stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { input, stops }
+    }
+}
+
+impl Texture for Ramp {
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        let t = self.input.value(u, v, p).x;
+        let next = self.stops.partition_point(|stop| stop.0 <= t);
+        if next == 0 {
+            return self.stops[0].1;
+        }
+        if next == self.stops.len() {
+            return self.stops[next - 1].1;
+        }
+        let (t0, c0) = self.stops[next - 1];
+        let (t1, c1) = self.stops[next];
+        let f = (t - t0) / (t1 - t0);
+        c0 * (1.0 - f) + c1 * f
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<String, String> {
+        let input = out.texture(&self.input)?;
+        if !input.starts_with("noise ") || input.contains(" ramp ") {
+            return Err(format!("ramp over \"{}\" can't be exported", input));
+        }
+        let stops: Vec<String> = self
+            .stops
+            .iter()
+            .map(|(t, color)| format!("{} {}", t, vec3(*color)))
+            .collect();
+        Ok(format!("{} ramp {}", input, stops.join("  ")))
+    }
+}
+
+// Marble: veins of `vein` colour in `base`, bent by turbulence; `scale` sets the vein
+// frequency along z (NoiseTexture is this with fixed grays).
+pub struct Marble {
+    noise: Perlin,
+    scale: Float,
+    vein: Vec3,
+    base: Vec3,
+}
+
+impl Marble {
+    pub fn new(scale: Float, vein: Vec3, base: Vec3) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            vein,
+            base,
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: Float, _v: Float, p: Vec3) -> Vec3 {
+        let t = 0.5 * (1.0 + (self.scale * p.z + 10.0 * self.noise.turb(p, 7)).sin());
+        self.vein * (1.0 - t) + self.base * t
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!("marble {}  {}  {}", self.scale, vec3(self.vein), vec3(self.base)))
+    }
+}
+
+// Wood: growth rings around the y axis, `scale` rings per unit, wobbled by noise. Each ring
+// goes from `light` early wood to `dark` late wood.
+pub struct Wood {
+    noise: Perlin,
+    scale: Float,
+    light: Vec3,
+    dark: Vec3,
+}
+
+impl Wood {
+    pub fn new(scale: Float, light: Vec3, dark: Vec3) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _u: Float, _v: Float, p: Vec3) -> Vec3 {
+        let radius = (p.x * p.x + p.z * p.z).sqrt() * self.scale;
+        let ring = radius + 0.3 * self.noise.noise(p * (self.scale * 0.5)) + 0.1 * p.y;
+        // sharp edge at the end of each ring
+        let t = (ring - ring.floor()).powi(3);
+        self.light * (1.0 - t) + self.dark * t
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!("wood {}  {}  {}", self.scale, vec3(self.light), vec3(self.dark)))
+    }
+}
+
+// Brick bond for walls along x or z: rows `brick_height` high, bricks `brick_width` long,
+// every other row shifted by half a brick, joints `mortar` wide. Each brick's colour
+// varies a little around `brick`.
+pub struct Brick {
+    noise: Perlin,
+    brick_width: Float,
+    brick_height: Float,
+    mortar: Float,
+    brick: Vec3,
+    mortar_color: Vec3,
+}
+
+impl Brick {
+    pub fn new(
+        brick_width: Float,
+        brick_height: Float,
+        mortar: Float,
+        brick: Vec3,
+        mortar_color: Vec3,
+    ) -> Self {
+        Self {
+            noise: Perlin::new(),
+            brick_width,
+            brick_height,
+            mortar,
+            brick,
+            mortar_color,
+        }
+    }
+}
+
+impl Texture for Brick {
+    fn value(&self, _u: Float, _v: Float, p: Vec3) -> Vec3 {
+        let row = (p.y / self.brick_height).floor();
+        let shift = if row as i64 % 2 == 0 { 0.0 } else { 0.5 * self.brick_width };
+        let x = (p.x + shift) / self.brick_width;
+        let z = (p.z + shift) / self.brick_width;
+        // distance to the nearest joint, in world units
+        let joint = |x: Float, size: Float| (x - x.round()).abs() * size;
+        let in_mortar = joint(p.y / self.brick_height, self.brick_height) < 0.5 * self.mortar
+            || joint(x, self.brick_width) < 0.5 * self.mortar
+            || joint(z, self.brick_width) < 0.5 * self.mortar;
+        if in_mortar {
+            return self.mortar_color;
+        }
+
+        let brick_id = Vec3::new(x.floor(), row, z.floor());
+        let shade = 1.0 + 0.5 * self.noise.noise(brick_id * 0.37 + Vec3::new(0.5, 0.5, 0.5));
+        self.brick * shade
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        Ok(format!(
+            "brick {} {} {}  {}  {}",
+            self.brick_width,
+            self.brick_height,
+            self.mortar,
+            vec3(self.brick),
+            vec3(self.mortar_color)
+        ))
+    }
 }
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::scene_export::SceneWriter;
+use crate::texture::{ImageTexture, Texture};
+use crate::vec3::{Float, Vec3};
+
+// Image textures by file name, so a scene that uses the same image in many materials holds
+// it in memory once. Images are not read until a ray first looks them up, so textures of
+// objects that never show up in the frame cost nothing.
+pub struct TextureRegistry {
+    images: Mutex<HashMap<(String, bool), Arc<LazyImageTexture>>>,
+}
+
+impl TextureRegistry {
+    pub fn new() -> Self {
+        Self {
+            images: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The shared texture for `filename`; `linear` as for ImageTexture::new_linear. The same
+    // file loaded as colour and as data gives two textures.
+    pub fn image(&self, filename: &str, linear: bool) -> Arc<dyn Texture> {
+        self.images
+            .lock()
+            .unwrap()
+            .entry((filename.to_owned(), linear))
+            .or_insert_with(|| {
+                Arc::new(LazyImageTexture {
+                    filename: filename.to_owned(),
+                    linear,
+                    image: OnceLock::new(),
+                })
+            })
+            .clone()
+    }
+}
+
+impl Default for TextureRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct LazyImageTexture {
+    filename: String,
+    linear: bool,
+    image: OnceLock<ImageTexture>,
+}
+
+impl Texture for LazyImageTexture {
+    fn value(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        self.image
+            .get_or_init(|| {
+                if self.linear {
+                    ImageTexture::new_linear(&self.filename)
+                } else {
+                    ImageTexture::new(&self.filename)
+                }
+            })
+            .value(u, v, p)
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        if self.linear {
+            Ok(format!("image {} linear", self.filename))
+        } else {
+            Ok(format!("image {}", self.filename))
+        }
+    }
+}
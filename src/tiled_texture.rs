@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::color::{from_rec709, srgb_to_linear};
+use crate::scene_export::SceneWriter;
+use crate::texture::{find_texture, placeholder_color, report_missing_texture, Texture};
+use crate::vec3::{Float, Vec3};
+
+const TILE_SIZE: u32 = 64;
+const TILE_BYTES: usize = (TILE_SIZE * TILE_SIZE * 3) as usize;
+// tiles are spread over this many caches by index, so lookups from different threads rarely
+// wait for the same lock
+const SHARDS: usize = 16;
+// end of an Lru list
+const NIL: usize = usize::MAX;
+
+// numbers the tile files of this process
+static TILE_FILES: AtomicUsize = AtomicUsize::new(0);
+
+// Image texture for huge PNG maps (16k earth textures and the like) that only keeps the tiles
+// actually sampled in memory. Nothing is decoded until the first lookup; then the image is
+// streamed row by row into a raw tile file in the temp directory and tiles are paged in from
+// there through LRU caches holding `cache_tiles` 64x64 tiles between them. Like ImageTexture,
+// values are decoded from sRGB unless the texture is created with `new_linear`.
+pub struct TiledImageTexture {
+    filename: String,
+    cache_tiles: usize,
+    linear: bool,
+    tile_file: OnceLock<Result<TileFile, String>>,
+    shards: Vec<Mutex<Shard>>,
+}
+
+struct TileFile {
+    width: u32,
+    height: u32,
+    tiles_x: u32,
+    path: PathBuf,
+}
+
+// one of the tile caches, with its own handle on the tile file
+struct Shard {
+    file: Option<File>,
+    tiles: Lru,
+}
+
+// Tiles by index, least recently used first out: a doubly linked list threaded through
+// `entries`, most recent at `head`, and `slots` to find a tile's entry.
+struct Lru {
+    capacity: usize,
+    slots: HashMap<u32, usize>,
+    entries: Vec<LruEntry>,
+    head: usize,
+    tail: usize,
+}
+
+struct LruEntry {
+    index: u32,
+    tile: Arc<Vec<u8>>,
+    prev: usize,
+    next: usize,
+}
+
+// big_image pages PNGs in without ever holding the whole image; other formats only decode
+// whole, so they are left to `image`.
+pub fn check_format(filename: &str) -> Result<(), String> {
+    if filename.to_lowercase().ends_with(".png") {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: big_image streams PNG files only, convert it or load it with image",
+            filename
+        ))
+    }
+}
+
+impl TiledImageTexture {
+    pub fn new(filename: &str, cache_tiles: usize) -> Self {
+        let cache_tiles = cache_tiles.max(1);
+        let shards = SHARDS.min(cache_tiles);
+        Self {
+            filename: filename.to_owned(),
+            cache_tiles,
+            linear: false,
+            tile_file: OnceLock::new(),
+            shards: (0..shards)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        file: None,
+                        tiles: Lru::new(cache_tiles / shards),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn new_linear(filename: &str, cache_tiles: usize) -> Self {
+        Self {
+            linear: true,
+            ..Self::new(filename, cache_tiles)
+        }
+    }
+
+    pub fn get_color(&self, u: Float, v: Float) -> Vec3 {
+        let tile_file = self.tile_file.get_or_init(|| {
+            let tile_file = TileFile::build(&self.filename);
+            if let Err(e) = &tile_file {
+                report_missing_texture(e);
+            }
+            tile_file
+        });
+        let tile_file = match tile_file {
+            Ok(tile_file) => tile_file,
+            // like ImageTexture without data
+            Err(_) => return placeholder_color(u, v),
+        };
+
+        let u = u.clamp(0.001, 0.999);
+        let v = v.clamp(0.001, 0.999);
+        let x = (u * tile_file.width as Float) as u32;
+        let y = ((1.0 - v) * tile_file.height as Float) as u32;
+        let index = (y / TILE_SIZE) * tile_file.tiles_x + x / TILE_SIZE;
+        let shard = &self.shards[index as usize % self.shards.len()];
+        let tile = match shard.lock().unwrap().tile(tile_file, index) {
+            Ok(tile) => tile,
+            Err(_) => return placeholder_color(u, v),
+        };
+        let offset = (((y % TILE_SIZE) * TILE_SIZE + x % TILE_SIZE) * 3) as usize;
+        Vec3::new(
+            tile[offset] as Float,
+            tile[offset + 1] as Float,
+            tile[offset + 2] as Float,
+        ) * (1.0 / 255.0)
+    }
+}
+
+impl Texture for TiledImageTexture {
+    fn value(&self, u: Float, v: Float, _p: Vec3) -> Vec3 {
+        let color = self.get_color(u, v);
+        if self.linear {
+            return color;
+        }
+        from_rec709(srgb_to_linear(color))
+    }
+
+    fn export(&self, _out: &mut SceneWriter) -> Result<String, String> {
+        let mut definition = format!("big_image {} cache {}", self.filename, self.cache_tiles);
+        if self.linear {
+            definition += " linear";
+        }
+        Ok(definition)
+    }
+}
+
+impl TileFile {
+    // Decodes one band of TILE_SIZE rows at a time and writes it out tile by tile.
+    fn build(filename: &str) -> Result<Self, String> {
+        let source = find_texture(filename);
+        check_format(&source)?;
+        let path = std::env::temp_dir().join(format!(
+            "raytracer_tiles_{}_{}_{}.raw",
+            std::process::id(),
+            TILE_FILES.fetch_add(1, Ordering::SeqCst),
+            Path::new(&source)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+        ));
+        let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let mut out = BufWriter::new(File::create(&path).map_err(error)?);
+
+        let file = File::open(&source).map_err(|e| format!("{}: {}", source, e))?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| format!("{}: {}", source, e))?;
+        let (width, height) = (reader.info().width, reader.info().height);
+        let (color, _) = reader.output_color_type();
+        let channels = color.samples();
+        let mut band = vec![];
+        for _ in 0..height {
+            let row = reader
+                .next_row()
+                .map_err(|e| format!("{}: {}", source, e))?
+                .ok_or(format!("{}: truncated image", source))?;
+            for pixel in row.data().chunks(channels) {
+                // grey (+ alpha) or RGB(A), to RGB
+                let rgb = if channels < 3 {
+                    [pixel[0]; 3]
+                } else {
+                    [pixel[0], pixel[1], pixel[2]]
+                };
+                band.extend_from_slice(&rgb);
+            }
+            if band.len() == (width * TILE_SIZE * 3) as usize {
+                write_band(&mut out, &band, width).map_err(error)?;
+                band.clear();
+            }
+        }
+        write_band(&mut out, &band, width).map_err(error)?;
+        out.flush().map_err(error)?;
+
+        Ok(Self {
+            width,
+            height,
+            tiles_x: width.div_ceil(TILE_SIZE),
+            path,
+        })
+    }
+}
+
+impl Drop for TileFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Shard {
+    fn tile(&mut self, tile_file: &TileFile, index: u32) -> std::io::Result<Arc<Vec<u8>>> {
+        if let Some(tile) = self.tiles.get(index) {
+            return Ok(tile);
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            file => file.insert(File::open(&tile_file.path)?),
+        };
+        let mut tile = vec![0; TILE_BYTES];
+        file.seek(SeekFrom::Start(index as u64 * TILE_BYTES as u64))?;
+        file.read_exact(&mut tile)?;
+        let tile = Arc::new(tile);
+        self.tiles.insert(index, tile.clone());
+        Ok(tile)
+    }
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            slots: HashMap::new(),
+            entries: vec![],
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    fn get(&mut self, index: u32) -> Option<Arc<Vec<u8>>> {
+        let slot = *self.slots.get(&index)?;
+        self.unlink(slot);
+        self.push_front(slot);
+        Some(self.entries[slot].tile.clone())
+    }
+
+    // Adds a tile that isn't cached yet, in place of the least recently used one when full.
+    fn insert(&mut self, index: u32, tile: Arc<Vec<u8>>) {
+        let slot = if self.entries.len() < self.capacity {
+            self.entries.push(LruEntry {
+                index,
+                tile,
+                prev: NIL,
+                next: NIL,
+            });
+            self.entries.len() - 1
+        } else {
+            let slot = self.tail;
+            self.unlink(slot);
+            let entry = &mut self.entries[slot];
+            self.slots.remove(&entry.index);
+            entry.index = index;
+            entry.tile = tile;
+            slot
+        };
+        self.slots.insert(index, slot);
+        self.push_front(slot);
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let LruEntry { prev, next, .. } = self.entries[slot];
+        match prev {
+            NIL => self.head = next,
+            prev => self.entries[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.entries[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, slot: usize) {
+        self.entries[slot].prev = NIL;
+        self.entries[slot].next = self.head;
+        match self.head {
+            NIL => self.tail = slot,
+            head => self.entries[head].prev = slot,
+        }
+        self.head = slot;
+    }
+}
+
+// Writes up to TILE_SIZE rows of RGB pixels as full tiles; pixels beyond the image are zero.
+fn write_band(out: &mut impl Write, band: &[u8], width: u32) -> std::io::Result<()> {
+    if band.is_empty() {
+        return Ok(());
+    }
+    let rows = band.len() / (width * 3) as usize;
+    let mut tile = vec![0u8; TILE_BYTES];
+    for tx in 0..width.div_ceil(TILE_SIZE) {
+        tile.iter_mut().for_each(|b| *b = 0);
+        let x0 = tx * TILE_SIZE;
+        let columns = TILE_SIZE.min(width - x0) as usize;
+        for row in 0..rows {
+            let src = (row * width as usize + x0 as usize) * 3;
+            let dst = row * TILE_SIZE as usize * 3;
+            tile[dst..dst + columns * 3].copy_from_slice(&band[src..src + columns * 3]);
+        }
+        out.write_all(&tile)?;
+    }
+    Ok(())
+}
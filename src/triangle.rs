@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    hittable::{area_pdf, HitRecord, Hittable, HittableList},
+    interval::Interval,
+    material::{cut_out, Material},
+    normal_cone::NormalCone,
+    scene_export::SceneWriter,
+    stats::{self, Counter},
+    util::{random_f64_0_1, Ray, Vec3},
+    vec3::Float,
+};
+
+#[derive(Clone)]
+pub struct Triangle {
+    a: Vec3,
+    ab: Vec3,
+    ac: Vec3,
+    normal: Vec3,
+    // normals at a, b and c for smooth shading, None for a flat triangle
+    vertex_normals: Option<[Vec3; 3]>,
+    mat: Arc<dyn Material>,
+    // per-vertex motion over the shutter: a moves by velocity_a, b - a and c - a by the deltas
+    velocity_a: Vec3,
+    velocity_ab: Vec3,
+    velocity_ac: Vec3,
+    is_moving: bool,
+    bounding_box: AABB,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, mat: Arc<dyn Material>) -> Self {
+        let ab = b - a;
+        let ac = c - a;
+        let bounding_box =
+            AABB::new_two_boxes(AABB::new_two_points(a, b), AABB::new_two_points(a, c));
+        Self {
+            a,
+            ab,
+            ac,
+            normal: ab.cross(ac).unit(),
+            vertex_normals: None,
+            mat,
+            velocity_a: Vec3::zero(),
+            velocity_ab: Vec3::zero(),
+            velocity_ac: Vec3::zero(),
+            is_moving: false,
+            bounding_box,
+        }
+    }
+
+    // Deforming triangle: vertex `a` is at `a + velocity[0] * time` for time in [0, 1], etc.
+    pub fn new_moving(
+        a: Vec3,
+        b: Vec3,
+        c: Vec3,
+        velocity: [Vec3; 3],
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        let start = Self::new(a, b, c, mat.clone());
+        let end = Self::new(a + velocity[0], b + velocity[1], c + velocity[2], mat);
+        Self {
+            velocity_a: velocity[0],
+            velocity_ab: velocity[1] - velocity[0],
+            velocity_ac: velocity[2] - velocity[0],
+            is_moving: true,
+            bounding_box: AABB::new_two_boxes(start.bounding_box, end.bounding_box),
+            ..start
+        }
+    }
+
+    pub fn set_vertex_normals(&mut self, normals: [Vec3; 3]) {
+        self.vertex_normals = Some([normals[0].unit(), normals[1].unit(), normals[2].unit()]);
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        stats::count(Counter::TriangleTest, 1);
+        let (a, ab, ac, normal) = if self.is_moving {
+            let ab = self.ab + self.velocity_ab * r.time;
+            let ac = self.ac + self.velocity_ac * r.time;
+            (
+                self.a + self.velocity_a * r.time,
+                ab,
+                ac,
+                ab.cross(ac).unit(),
+            )
+        } else {
+            (self.a, self.ab, self.ac, self.normal)
+        };
+
+        // Moller-Trumbore
+        let pvec = r.b_direction.cross(ac);
+        let det = ab * pvec;
+
+        // No hit if the ray is parallel to the triangle.
+        if det.abs() < 1e-12 {
+            return false;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = r.a_origin - a;
+        let u = (tvec * pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return false;
+        }
+
+        let qvec = tvec.cross(ab);
+        let v = (r.b_direction * qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return false;
+        }
+
+        let t = (ac * qvec) * inv_det;
+        if !ray_t.contains(t) || cut_out(self.mat.as_ref(), u, v, r.at(t)) {
+            return false;
+        }
+
+        // barycentric coordinates double as texture coordinates
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.u = u;
+        rec.v = v;
+        rec.mat = &*self.mat;
+        rec.set_face_normal(r, &normal);
+
+        if let Some([na, nb, nc]) = self.vertex_normals {
+            let w = 1.0 - u - v;
+            let shading = (na * w + nb * u + nc * v).unit();
+            rec.set_shading_normal(&shading);
+
+            // Shadow terminator fix (Hanika 2021, "Hacking the Shadow Terminator"): rays leaving
+            // the front of a low-poly smooth mesh start out on the curved surface the vertex
+            // normals describe, so the flat neighbouring triangles don't shadow them.
+            if rec.front_face {
+                let p = rec.p;
+                let lift = |vertex: Vec3, n: Vec3| n * ((p - vertex) * n).min(0.0);
+                rec.lift = -(lift(a, na) * w + lift(a + ab, nb) * u + lift(a + ac, nc) * v);
+            }
+        }
+
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounding_box
+    }
+
+    fn normal_bounds(&self) -> NormalCone {
+        if self.is_moving || !self.mat.is_one_sided() {
+            NormalCone::everywhere()
+        } else {
+            NormalCone::new(self.normal, 0.0)
+        }
+    }
+
+    fn export(&self, out: &mut SceneWriter) -> Result<(), String> {
+        let a = self.a;
+        let velocity = self.is_moving.then(|| {
+            let va = self.velocity_a;
+            [va, va + self.velocity_ab, va + self.velocity_ac]
+        });
+        out.triangle(
+            [a, a + self.ab, a + self.ac],
+            self.vertex_normals,
+            velocity,
+            &self.mat,
+        )
+    }
+
+    // deforming triangles are left out, their corners depend on the time
+    fn collect_lights(&self, lights: &mut HittableList) {
+        if self.mat.is_emissive() && !self.is_moving {
+            lights.add(Arc::new(self.clone()));
+        }
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        let area = 0.5 * self.ab.cross(self.ac).length();
+        area_pdf(self, origin, direction, area)
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        // uniform over the triangle: the square root spreads the points evenly from a
+        let s = random_f64_0_1().sqrt();
+        let t = random_f64_0_1();
+        self.a + self.ab * (s * (1.0 - t)) + self.ac * (s * t) - origin
+    }
+}
@@ -1,10 +1,12 @@
-use rand::rngs::ThreadRng;
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 
 // pub use crate::aabb::BvhNode;
 pub use crate::ray::Ray;
 // pub use crate::sphere::Sphere;
-pub use crate::vec3::Vec3;
+pub use crate::vec3::{Float, Vec3};
 // pub use crate::world::Object;
 // use rand::{rngs::ThreadRng, Rng};
 
@@ -13,7 +15,7 @@ pub fn unit_vec(v: Vec3) -> Vec3 {
     v / v.length()
 }
 //自己实现的绝对值
-pub fn fabs(num: f64) -> f64 {
+pub fn fabs(num: Float) -> Float {
     if num < 0.0 {
         -num
     } else {
@@ -21,7 +23,7 @@ pub fn fabs(num: f64) -> f64 {
     }
 }
 //自己实现的取两数最小值
-pub fn fmin(v1: f64, v2: f64) -> f64 {
+pub fn fmin(v1: Float, v2: Float) -> Float {
     if v1 > v2 {
         v2
     } else {
@@ -29,7 +31,7 @@ pub fn fmin(v1: f64, v2: f64) -> f64 {
     }
 }
 //自己实现的取两数最大值
-pub fn fmax(v1: f64, v2: f64) -> f64 {
+pub fn fmax(v1: Float, v2: Float) -> Float {
     if v1 > v2 {
         v1
     } else {
@@ -38,26 +40,26 @@ pub fn fmax(v1: f64, v2: f64) -> f64 {
 }
 
 //折射模块，计算的是反射比，ratio为折射率之比
-pub fn reflectance(cos_theta: f64, ratio: f64) -> f64 {
+pub fn reflectance(cos_theta: Float, ratio: Float) -> Float {
     let mut r0 = (1.0 - ratio) / (1.0 + ratio);
     r0 = r0 * r0;
-    r0 + (1.0 - r0) * f64::powf(1.0 - cos_theta, 5.0)
+    r0 + (1.0 - r0) * Float::powf(1.0 - cos_theta, 5.0)
 }
 
 //ratio is etia / etia prime i.e the sphere is under the fraction
 //计算折射光线，v为入射光线，n为法线，ratio为折射率之比
-pub fn refract(v: Vec3, n: Vec3, ratio: f64) -> Vec3 {
+pub fn refract(v: Vec3, n: Vec3, ratio: Float) -> Vec3 {
     //v,n为单位向量
     //按道理应该不会有cos比1大
     let cos_theta = -v * n;
-    let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
-    let mut random: ThreadRng = rand::thread_rng();
-    if ratio * sin_theta >= 1.0 || reflectance(cos_theta, ratio) > random.gen::<f64>() {
+    let sin_theta = Float::sqrt(1.0 - cos_theta * cos_theta);
+    let mut random = rng();
+    if ratio * sin_theta >= 1.0 || reflectance(cos_theta, ratio) > random.gen::<Float>() {
         // total reflectance
         reflect(v, n)
     } else {
         let perp = (v + n * cos_theta) * ratio;
-        let para = -n * f64::sqrt(fabs(1.0 - perp.squared_length()));
+        let para = -n * Float::sqrt(fabs(1.0 - perp.squared_length()));
         perp + para
     }
 }
@@ -79,7 +81,7 @@ pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
 
 //计算单位球中一个随机单位向量
 pub fn random_in_unit_sphere() -> Vec3 {
-    let mut random: ThreadRng = rand::thread_rng();
+    let mut random = rng();
     loop {
         let p = Vec3::new(
             random.gen_range(-1.0..1.0),
@@ -99,7 +101,7 @@ pub fn random_in_unit_sphere() -> Vec3 {
 }
 
 pub fn random_positive_vec3() -> Vec3 {
-    let mut random: ThreadRng = rand::thread_rng();
+    let mut random = rng();
     Vec3::new(
         random.gen_range(0.0..1.0),
         random.gen_range(0.0..1.0),
@@ -107,8 +109,8 @@ pub fn random_positive_vec3() -> Vec3 {
     )
 }
 
-pub fn random_positive_vec3_ranged(x: f64, y: f64) -> Vec3 {
-    let mut random: ThreadRng = rand::thread_rng();
+pub fn random_positive_vec3_ranged(x: Float, y: Float) -> Vec3 {
+    let mut random = rng();
     Vec3::new(
         random.gen_range(x..y),
         random.gen_range(x..y),
@@ -117,30 +119,30 @@ pub fn random_positive_vec3_ranged(x: f64, y: f64) -> Vec3 {
 }
 
 //0-1中随机数字
-pub fn random_f64_0_1() -> f64 {
-    let mut random: ThreadRng = rand::thread_rng();
-    random.gen::<f64>()
+pub fn random_f64_0_1() -> Float {
+    let mut random = rng();
+    random.gen::<Float>()
 }
 
-pub fn random_f64_ranged(x: f64, y: f64) -> f64 {
-    let mut random: ThreadRng = rand::thread_rng();
+pub fn random_f64_ranged(x: Float, y: Float) -> Float {
+    let mut random = rng();
     random.gen_range(x..y)
 }
 
 // including x and y !!!
 pub fn random_i32_ranged(x: i32, y: i32) -> i32 {
-    random_f64_ranged(x as f64, (y + 1) as f64) as i32
+    random_f64_ranged(x as Float, (y + 1) as Float) as i32
 }
 
 //1-100随机数字
-pub fn random_f64_101() -> f64 {
-    let mut random: ThreadRng = rand::thread_rng();
+pub fn random_f64_101() -> Float {
+    let mut random = rng();
     random.gen_range(1.0..100.0)
 }
 
 //0-165随机向量，用于生成随机的场景数据
 pub fn random_cen_165() -> Vec3 {
-    let mut random: ThreadRng = rand::thread_rng();
+    let mut random = rng();
     Vec3::new(
         random.gen_range(0.0..165.0),
         random.gen_range(0.0..165.0),
@@ -150,7 +152,7 @@ pub fn random_cen_165() -> Vec3 {
 
 //单位圆盘中随机向量
 pub fn random_in_unit_disk() -> Vec3 {
-    let mut random: ThreadRng = rand::thread_rng();
+    let mut random = rng();
     loop {
         let p = Vec3::new(
             random.gen_range(-1.0..1.0),
@@ -170,7 +172,7 @@ pub fn random_in_unit_disk() -> Vec3 {
 }
 
 //0-1截断函数
-pub fn cut(x: f64) -> f64 {
+pub fn cut(x: Float) -> Float {
     if x > 0.99 {
         0.99
     } else if x < 0.0 {
@@ -184,11 +186,11 @@ pub fn cut(x: f64) -> f64 {
 
 //处理最近的光线交点(bvh版)
 //球版只留下了注释的一点点
-pub fn hittable(r: Ray, bvh_tree: &BvhNode) -> (f64, Object) {
-    //let mut t = f64::INFINITY;
+pub fn hittable(r: Ray, bvh_tree: &BvhNode) -> (Float, Object) {
+    //let mut t = Float::INFINITY;
     let t_min = 0.001;
     //let mut sphere = &Sphere::empty_sphere();
-    let (t, obj) = bvh_tree.hit(&r, t_min, f64::INFINITY);
+    let (t, obj) = bvh_tree.hit(&r, t_min, Float::INFINITY);
     (t, obj)
 }
 // for i in v {
@@ -210,3 +212,36 @@ pub fn hittable(r: Ray, bvh_tree: &BvhNode) -> (f64, Object) {
 
 
 */
+
+thread_local! {
+    // behind rng(): seeded from the OS until seed_rng() is called on the thread
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// The random numbers of the renderer, scene construction included. Each thread draws from
+// its own generator, so a render is repeatable when every thread seeds it the same way.
+pub struct TracerRng;
+
+impl RngCore for TracerRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|r| r.borrow_mut().next_u32())
+    }
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|r| r.borrow_mut().next_u64())
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|r| r.borrow_mut().fill_bytes(dest))
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        RNG.with(|r| r.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+pub fn rng() -> TracerRng {
+    TracerRng
+}
+
+// Restarts the generator of the calling thread from `seed`.
+pub fn seed_rng(seed: u64) {
+    RNG.with(|r| *r.borrow_mut() = StdRng::seed_from_u64(seed));
+}
@@ -1,10 +1,23 @@
 use crate::util::{fmax, fmin, random_f64_ranged};
 use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+
+// Scalar of all the geometry and shading math. Building with `--features f32` halves the size
+// of vectors, boxes and BVH nodes and doubles what fits in a SIMD register, at the price of
+// precision: self-intersection offsets and far-away geometry suffer first.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+
 #[derive(Clone, Debug, PartialEq, Copy)]
 pub struct Vec3 {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
 }
 
 // by Senpai
@@ -17,17 +30,17 @@ impl Neg for Vec3 {
 
 impl Mul for Vec3 {
     //相当于重载了 *，重载的是点乘（内积）
-    //用法： a = Vec3::new(1.0, 2.0, 3.0),b = Vec3::new(2.0, 3.0, 4.0),c:f64 = a * b = 20
-    type Output = f64;
-    fn mul(self, other: Vec3) -> f64 {
+    //用法： a = Vec3::new(1.0, 2.0, 3.0),b = Vec3::new(2.0, 3.0, 4.0),c:Float = a * b = 20
+    type Output = Float;
+    fn mul(self, other: Vec3) -> Float {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
 }
 
-impl Mul<f64> for Vec3 {
+impl Mul<Float> for Vec3 {
     type Output = Self;
 
-    fn mul(self, other: f64) -> Self {
+    fn mul(self, other: Float) -> Self {
         Self {
             x: self.x * other,
             y: self.y * other,
@@ -36,7 +49,7 @@ impl Mul<f64> for Vec3 {
     }
 }
 
-impl Mul<Vec3> for f64 {
+impl Mul<Vec3> for Float {
     type Output = Vec3;
 
     fn mul(self, other: Vec3) -> Vec3 {
@@ -48,12 +61,12 @@ impl Mul<Vec3> for f64 {
     }
 }
 
-impl Div<f64> for Vec3 {
+impl Div<Float> for Vec3 {
     //重载了标量除法 / 。
-    ///用法： a = Vec3::new(1.0, 2.0, 3.0)，b:f64  = 2.0, c:Vec3 = a / b = (0.5,1.0,1.5)
+    ///用法： a = Vec3::new(1.0, 2.0, 3.0)，b:Float  = 2.0, c:Vec3 = a / b = (0.5,1.0,1.5)
     type Output = Self;
 
-    fn div(self, other: f64) -> Self {
+    fn div(self, other: Float) -> Self {
         Self {
             x: self.x / other,
             y: self.y / other,
@@ -63,17 +76,17 @@ impl Div<f64> for Vec3 {
 }
 impl Vec3 {
     //取 x,y,z。理论上x,y,z是pub可以直接取，这样显得专业(TAT)
-    pub fn x(&self) -> f64 {
+    pub fn x(&self) -> Float {
         self.x
     }
-    pub fn y(&self) -> f64 {
+    pub fn y(&self) -> Float {
         self.y
     }
-    pub fn z(&self) -> f64 {
+    pub fn z(&self) -> Float {
         self.z
     }
 
-    pub fn lp(&self, index: u8) -> f64 {
+    pub fn lp(&self, index: u8) -> Float {
         if index == 0 {
             self.x
         } else if index == 1 {
@@ -85,7 +98,7 @@ impl Vec3 {
 
     //忘了是什么的缩写了，用来在循环中遍历向量
     //用法：a:Vec3 = Vec3::new(1.0,2.0,3.0) a.lp(0) = 1.0,a.lp(1) = 2.0,a.lp(2) = 3.0
-    pub fn mut_lp(&mut self, index: u8) -> &mut f64 {
+    pub fn mut_lp(&mut self, index: u8) -> &mut Float {
         if index == 0 {
             &mut self.x
         } else if index == 1 {
@@ -104,7 +117,7 @@ impl Vec3 {
     }
 
     //new 新建向量
-    pub fn new(x: f64, y: f64, z: f64) -> Self {
+    pub fn new(x: Float, y: Float, z: Float) -> Self {
         Self { x, y, z }
     }
     //取两个向量中最小的作为向量，用于AABB(book 2)
@@ -151,14 +164,14 @@ impl Vec3 {
 
     //平方长度
     //a = Vec3::new(1.0,2.0,3.0),a.squared_length() = 1 + 4 + 9 = 14
-    pub fn squared_length(&self) -> f64 {
+    pub fn squared_length(&self) -> Float {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
     //模长
-    //a = Vec3::new(1.0,2.0,3.0),a.length() = 1 + 4 + 9 = f64::sqrt(14) = ...
-    pub fn length(&self) -> f64 {
-        f64::sqrt(self.x * self.x + self.y * self.y + self.z * self.z)
+    //a = Vec3::new(1.0,2.0,3.0),a.length() = 1 + 4 + 9 = Float::sqrt(14) = ...
+    pub fn length(&self) -> Float {
+        Float::sqrt(self.x * self.x + self.y * self.y + self.z * self.z)
     }
 
     pub fn unit(&self) -> Self {
@@ -183,7 +196,7 @@ impl Vec3 {
         println!("x={},y={},z={}", self.x, self.y, self.z);
     }
 
-    pub fn random_ranged(x: f64, y: f64) -> Self {
+    pub fn random_ranged(x: Float, y: Float) -> Self {
         Self {
             x: random_f64_ranged(x, y),
             y: random_f64_ranged(x, y),
@@ -206,12 +219,12 @@ impl Add for Vec3 {
     }
 }
 
-impl Add<f64> for Vec3 {
+impl Add<Float> for Vec3 {
     //重载了标量加法,同标量乘法，f64只能放在右边
-    //a = Vec3::new(1.0, 2.0, 3.0),b:f64 = 2,c:Vec3 = a + b == Vec3::new(3.0,4.0,5.0)
+    //a = Vec3::new(1.0, 2.0, 3.0),b:Float = 2,c:Vec3 = a + b == Vec3::new(3.0,4.0,5.0)
     type Output = Self;
 
-    fn add(self, other: f64) -> Self {
+    fn add(self, other: Float) -> Self {
         Self {
             x: self.x + other,
             y: self.y + other,
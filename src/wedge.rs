@@ -0,0 +1,147 @@
+use image::RgbImage;
+
+use crate::camera::Camera;
+use crate::config;
+use crate::contact_sheet::{contact_sheet, positive_arg};
+use crate::hittable::HittableList;
+use crate::scene::{material_wedge, MATERIAL_WEDGE_PARAMS};
+use crate::vec3::Float;
+
+// One swept parameter, e.g. "fuzz=0:1:5" is fuzz in {0, 0.25, 0.5, 0.75, 1}.
+pub struct WedgeAxis {
+    pub name: String,
+    pub values: Vec<Float>,
+}
+
+impl WedgeAxis {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, range) = spec.split_once('=').ok_or(format!(
+            "expected <name>=<from>:<to>:<steps>, got \"{}\"",
+            spec
+        ))?;
+        let parts: Vec<&str> = range.split(':').collect();
+        if parts.len() != 3 {
+            return Err(format!("expected <from>:<to>:<steps>, got \"{}\"", range));
+        }
+        let from: Float = parts[0]
+            .parse()
+            .map_err(|_| format!("bad number \"{}\"", parts[0]))?;
+        let to: Float = parts[1]
+            .parse()
+            .map_err(|_| format!("bad number \"{}\"", parts[1]))?;
+        let steps: usize = parts[2]
+            .parse()
+            .map_err(|_| format!("bad step count \"{}\"", parts[2]))?;
+        if steps == 0 {
+            return Err("step count must be at least 1".to_owned());
+        }
+
+        let values = (0..steps)
+            .map(|i| {
+                if steps == 1 {
+                    from
+                } else {
+                    from + (to - from) * i as Float / (steps - 1) as Float
+                }
+            })
+            .collect();
+        Ok(Self {
+            name: name.to_owned(),
+            values,
+        })
+    }
+}
+
+// Renders `scene` once per value of `x` (columns) and `y` (rows) and composites the
+// labelled thumbnails into a single contact sheet.
+pub fn render_wedge<F>(
+    x: &WedgeAxis,
+    y: Option<&WedgeAxis>,
+    thread_limit: u32,
+    scene: F,
+) -> RgbImage
+where
+    F: Fn(&[(&str, Float)]) -> (Camera, HittableList),
+{
+    let rows: Vec<Option<Float>> = match y {
+        Some(y) => y.values.iter().map(|v| Some(*v)).collect(),
+        None => vec![None],
+    };
+
+    let mut cells = vec![];
+    for row in rows.iter() {
+        for xv in x.values.iter() {
+            let mut params = vec![(x.name.as_str(), *xv)];
+            if let (Some(y), Some(yv)) = (y, row) {
+                params.push((y.name.as_str(), *yv));
+            }
+            let label = params
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, trim_number(*value)))
+                .collect::<Vec<_>>()
+                .join(" ");
+            println!("wedge: {}", label);
+
+            let (mut cam, world) = scene(&params);
+            cam.thread_limit = thread_limit;
+            cells.push((cam.render(&world), label));
+        }
+    }
+    contact_sheet(&cells, x.values.len())
+}
+
+// `wedge <name>=<from>:<to>:<steps> [<name>=<from>:<to>:<steps>] [--size W] [--spp N]`
+// sweeps the knobs of scene::material_wedge (fuzz, ior, light).
+pub fn run(args: &[String], thread_limit: u32) -> Result<RgbImage, String> {
+    let mut axes = vec![];
+    let mut size = 128;
+    let mut spp = 64;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--size" | "--spp" => {
+                let value = positive_arg(args, i)?;
+                if args[i] == "--size" {
+                    size = value;
+                } else {
+                    spp = value;
+                }
+                i += 1;
+            }
+            arg if arg.contains('=') => axes.push(WedgeAxis::parse(arg)?),
+            // the user-wide settings, see Config::merge_args
+            arg => match config::FLAGS.iter().find(|(flag, _)| *flag == arg) {
+                Some((_, true)) => i += 1,
+                Some((_, false)) => {}
+                None => return Err(format!("unknown argument \"{}\"", arg)),
+            },
+        }
+        i += 1;
+    }
+    if axes.is_empty() || axes.len() > 2 {
+        return Err("wedge takes one or two <name>=<from>:<to>:<steps> axes".to_owned());
+    }
+    if let Some(axis) = axes
+        .iter()
+        .find(|axis| !MATERIAL_WEDGE_PARAMS.contains(&axis.name.as_str()))
+    {
+        return Err(format!(
+            "unknown parameter \"{}\" ({})",
+            axis.name,
+            MATERIAL_WEDGE_PARAMS.join(", ")
+        ));
+    }
+
+    Ok(render_wedge(
+        &axes[0],
+        axes.get(1),
+        thread_limit,
+        |params| material_wedge(params, size, spp),
+    ))
+}
+
+// 0.5 -> "0.5", 2 -> "2", 0.3333333 -> "0.333"
+pub fn trim_number(value: Float) -> String {
+    let s = format!("{:.3}", value);
+    s.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
@@ -0,0 +1,94 @@
+// Golden-image regression tests: the scenes of scene.rs are rendered small with a fixed seed
+// and compared with their references in tests/golden, so that a refactor of the BVH or the
+// materials can't change the output unnoticed.
+//
+// After a change that is meant to alter the output, rewrite the references with
+//     UPDATE_GOLDEN=1 cargo test --test golden
+// and look at them before committing. They come from the default (f64) build: the f32
+// feature rounds differently, which sends the paths elsewhere, so they are skipped there.
+//
+// `earth` and `final_scene` are left out, their earth texture goes through the JPEG decoder of
+// whichever OpenCV is installed.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const WIDTH: u32 = 64;
+const SPP: u32 = 32;
+const SEED: u64 = 1;
+// mean absolute difference per channel, out of 255; a render with the same seed repeats
+// exactly, this leaves room for floating point that differs between platforms
+const TOLERANCE: f64 = 1.0;
+
+fn render(scene: &str) -> PathBuf {
+    let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let status = Command::new(env!("CARGO_BIN_EXE_ray_tracer"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        // no raytracer.toml there, so the user's config stays out of it
+        .env("XDG_CONFIG_HOME", &output_dir)
+        .args(["--builtin", scene])
+        .args(["--width", &WIDTH.to_string(), "--spp", &SPP.to_string()])
+        .args(["--seed", &SEED.to_string()])
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .stdout(Stdio::null())
+        .status()
+        .expect("cannot run the renderer");
+    assert!(status.success(), "rendering {} failed", scene);
+    output_dir.join(format!("{}.png", scene))
+}
+
+fn check(scene: &str) {
+    let rendered = render(scene);
+    let reference = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.png", scene));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::copy(&rendered, &reference).unwrap();
+        return;
+    }
+
+    let new = image::open(&rendered).unwrap().to_rgb8();
+    let old = image::open(&reference)
+        .unwrap_or_else(|e| panic!("{}: {} (UPDATE_GOLDEN=1 writes it)", reference.display(), e))
+        .to_rgb8();
+    assert_eq!(new.dimensions(), old.dimensions(), "{} changed size", scene);
+    let total: f64 = new
+        .as_raw()
+        .iter()
+        .zip(old.as_raw())
+        .map(|(a, b)| (*a as f64 - *b as f64).abs())
+        .sum();
+    let difference = total / new.as_raw().len() as f64;
+    assert!(
+        difference <= TOLERANCE,
+        "{} is off its reference by {:.2} per channel (at most {}), see {}",
+        scene,
+        difference,
+        TOLERANCE,
+        rendered.display()
+    );
+}
+
+macro_rules! golden {
+    ($($scene:ident),*) => {
+        $(
+            #[test]
+            #[cfg_attr(feature = "f32", ignore)]
+            fn $scene() {
+                check(stringify!($scene));
+            }
+        )*
+    };
+}
+
+golden!(
+    bouncing_spheres,
+    checkered_spheres,
+    perlin_spheres,
+    quads,
+    simple_light,
+    cornell_box,
+    cornell_smoke
+);